@@ -0,0 +1,949 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fmt::Write,
+    fs,
+    path::PathBuf,
+    process::Command,
+};
+
+use toml::{Table, Value};
+
+fn main() {
+    // Short commit hash shown on the splash screen, "unknown" when not building from a git checkout
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=DEEJ_BUILD_HASH={hash}");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    board_config();
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Chip {
+    Esp32c3,
+    Esp32,
+    Esp32s3,
+}
+
+impl Chip {
+    fn from_features() -> Self {
+        if feature("esp32") {
+            Chip::Esp32
+        } else if feature("esp32s3") {
+            Chip::Esp32s3
+        } else {
+            Chip::Esp32c3
+        }
+    }
+
+    /// ADC1 pins of the chip, each has a variant in AnyAnalogPin
+    fn analog_pins(self) -> std::ops::RangeInclusive<i64> {
+        match self {
+            Chip::Esp32c3 => 0..=4,
+            Chip::Esp32 => 32..=39,
+            Chip::Esp32s3 => 1..=10,
+        }
+    }
+
+    /// Nominal input voltage (mV) of a raw reading of 4095 with `attenuation`
+    fn full_scale_mv(self, attenuation: &str) -> u16 {
+        match (self, attenuation) {
+            (Chip::Esp32c3, "Attenuation0dB") => 750,
+            (Chip::Esp32c3, "Attenuation2p5dB") => 1050,
+            (Chip::Esp32c3, "Attenuation6dB") => 1300,
+            (Chip::Esp32c3, _) => 2500,
+            (Chip::Esp32, "Attenuation0dB") => 1100,
+            (Chip::Esp32, "Attenuation2p5dB") => 1500,
+            (Chip::Esp32, "Attenuation6dB") => 2200,
+            (Chip::Esp32, _) => 3900,
+            (Chip::Esp32s3, "Attenuation0dB") => 950,
+            (Chip::Esp32s3, "Attenuation2p5dB") => 1250,
+            (Chip::Esp32s3, "Attenuation6dB") => 1750,
+            (Chip::Esp32s3, _) => 3100,
+        }
+    }
+
+    /// Defaults of the keys left out of board.toml, see the table in src/board.rs
+    fn defaults(self) -> Table {
+        let defaults = match self {
+            Chip::Esp32c3 => {
+                r#"
+                pots.pins = [0, 1, 2, 3]
+                pots.calibration = "curve"
+                display = { sda = 6, scl = 7, sck = 6, mosi = 7, dc = 10, cs = 5, res = 4 }
+                buttons.page = 9
+                serial = { uart = 0, baud = 115200, tx = 21, rx = 20 }
+                i2c.frequency = 400
+                "#
+            }
+            Chip::Esp32 => {
+                r#"
+                pots.pins = [32, 33, 34, 35]
+                pots.calibration = "none"
+                display = { sda = 21, scl = 22, sck = 14, mosi = 13, dc = 27, cs = 15, res = 26 }
+                buttons.page = 0
+                serial = { uart = 0, baud = 115200, tx = 1, rx = 3 }
+                i2c.frequency = 400
+                "#
+            }
+            Chip::Esp32s3 => {
+                r#"
+                pots.pins = [1, 2, 3, 4]
+                pots.calibration = "none"
+                display = { sda = 8, scl = 9, sck = 12, mosi = 11, dc = 13, cs = 10, res = 14 }
+                buttons.page = 0
+                i2c.frequency = 400
+                "#
+            }
+        };
+        defaults.parse().unwrap()
+    }
+}
+
+/// Pins taken by the chip or by features that are not configurable in board.toml
+fn fixed_pins(chip: Chip) -> Vec<(&'static str, i64)> {
+    let mut pins = Vec::new();
+    if chip == Chip::Esp32s3 {
+        pins.extend([("USB-OTG", 19), ("USB-OTG", 20)]);
+    }
+    if feature("usb-serial-jtag") {
+        pins.extend([
+            ("feature `usb-serial-jtag`", 18),
+            ("feature `usb-serial-jtag`", 19),
+        ]);
+    }
+    if feature("feedback") {
+        pins.push(("feature `feedback`", 10));
+    }
+    if feature("leds") || feature("status-led") {
+        pins.push(("the WS2812 features", 8));
+    }
+    pins
+}
+
+fn feature(name: &str) -> bool {
+    let var = format!("CARGO_FEATURE_{}", name.to_uppercase().replace('-', "_"));
+    env::var_os(var).is_some()
+}
+
+/// Value of `section.key` in board.toml, or in the defaults of the chip
+fn lookup<'a>(
+    board: &'a Table,
+    defaults: &'a Table,
+    section: &str,
+    key: &str,
+) -> Option<&'a Value> {
+    [board, defaults]
+        .into_iter()
+        .find_map(|table| table.get(section)?.get(key))
+}
+
+fn pin(board: &Table, defaults: &Table, section: &str, key: &str) -> i64 {
+    lookup(board, defaults, section, key)
+        .and_then(Value::as_integer)
+        .unwrap_or_else(|| panic!("board.toml: `{section}.{key}` has to be a GPIO number"))
+}
+
+/// ADC1 pin `section.pin` and the ratio `section.divider` of the resistor divider in front of it,
+/// read at 11 dB by `feature`
+fn divider_pin(
+    board: &Table,
+    defaults: &Table,
+    chip: Chip,
+    section: &str,
+    feature: &str,
+) -> (i64, f64) {
+    let pin = lookup(board, defaults, section, "pin")
+        .and_then(Value::as_integer)
+        .unwrap_or_else(|| {
+            panic!("board.toml: feature `{feature}` needs `{section}.pin`, a GPIO number")
+        });
+    if !chip.analog_pins().contains(&pin) {
+        panic!(
+            "board.toml: GPIO{pin} of `{section}.pin` is not one of the ADC1 pins {:?}",
+            chip.analog_pins()
+        );
+    }
+    let divider = match lookup(board, defaults, section, "divider") {
+        None => 2.0,
+        Some(Value::Integer(divider)) if *divider >= 1 => *divider as f64,
+        Some(Value::Float(divider)) if *divider >= 1.0 => *divider,
+        Some(_) => panic!("board.toml: `{section}.divider` has to be a ratio of 1 or more"),
+    };
+    (pin, divider)
+}
+
+/// How the `battery` feature reads the charge
+#[derive(Clone, Copy)]
+enum Gauge {
+    /// ADC1 pin and divider ratio
+    Divider(i64, f64),
+    /// MAX17048 on the I2C bus
+    Max17048,
+}
+
+/// Reads the wiring from board.toml, or the file in DEEJ_BOARD, and generates `board.rs` for the
+/// firmware and `channels.rs` for the library in OUT_DIR
+fn board_config() {
+    let path = env::var("DEEJ_BOARD").unwrap_or_else(|_| "board.toml".to_owned());
+    println!("cargo:rerun-if-env-changed=DEEJ_BOARD");
+    println!("cargo:rerun-if-changed={path}");
+    let board: Table = match fs::read_to_string(&path) {
+        Ok(text) => text
+            .parse()
+            .unwrap_or_else(|err| panic!("Invalid {path}: {err}")),
+        Err(_) => Table::new(),
+    };
+    let chip = Chip::from_features();
+    let defaults = chip.defaults();
+
+    let pots: Vec<i64> = lookup(&board, &defaults, "pots", "pins")
+        .and_then(Value::as_array)
+        .and_then(|pins| pins.iter().map(Value::as_integer).collect())
+        .expect("board.toml: `pots.pins` has to be a list of GPIO numbers");
+    if pots.is_empty() {
+        panic!("board.toml: `pots.pins` needs at least one pin");
+    }
+    if let Some(pin) = pots.iter().find(|pin| !chip.analog_pins().contains(pin)) {
+        panic!(
+            "board.toml: GPIO{pin} in `pots.pins` is not one of the ADC1 pins {:?}",
+            chip.analog_pins()
+        );
+    }
+    if feature("adc-dma") && pots.len() > 4 {
+        panic!("board.toml: `adc-dma` scans at most 4 pots");
+    }
+
+    let attenuation = match lookup(&board, &defaults, "pots", "attenuation") {
+        None => "Attenuation0dB",
+        Some(Value::Integer(0)) => "Attenuation0dB",
+        Some(Value::Float(db)) if *db == 2.5 => "Attenuation2p5dB",
+        Some(Value::Integer(6)) => "Attenuation6dB",
+        Some(Value::Integer(11)) => "Attenuation11dB",
+        Some(_) => panic!("board.toml: `pots.attenuation` has to be 0, 2.5, 6 or 11"),
+    };
+    if feature("adc-dma") && attenuation != "Attenuation0dB" {
+        panic!("board.toml: `adc-dma` only supports `pots.attenuation = 0`");
+    }
+
+    // One scheme for all pots or one per pot, only the ESP32-C3 has calibration in esp-hal
+    let calibration: Vec<&str> = match lookup(&board, &defaults, "pots", "calibration") {
+        Some(Value::String(scheme)) => vec![scheme.as_str(); pots.len()],
+        Some(Value::Array(schemes)) if schemes.len() == pots.len() => schemes
+            .iter()
+            .map(Value::as_str)
+            .collect::<Option<_>>()
+            .expect("board.toml: `pots.calibration` has to list a scheme per pot"),
+        _ => panic!("board.toml: `pots.calibration` has to be a scheme or a list with one per pot"),
+    };
+    let mut cal_types = Vec::new();
+    for scheme in &calibration {
+        let cal_type = match *scheme {
+            "none" => None,
+            "basic" => Some("AdcCalBasic"),
+            "line" => Some("AdcCalLine"),
+            "curve" => Some("AdcCalCurve"),
+            _ => panic!(
+                "board.toml: `pots.calibration` schemes are \"none\", \"basic\", \"line\" or \"curve\""
+            ),
+        };
+        if cal_type.is_some() && chip != Chip::Esp32c3 {
+            panic!("board.toml: only the ESP32-C3 supports `pots.calibration` other than \"none\"");
+        }
+        cal_types.push(cal_type);
+    }
+    // Compiles in the AnyAnalogPin variants of the schemes in use
+    for scheme in ["basic", "line", "curve"] {
+        if calibration.contains(&scheme) {
+            println!("cargo:rustc-cfg=feature=\"adc-cal-{scheme}\"");
+        }
+    }
+    if feature("adc-dma") && calibration.iter().any(|scheme| *scheme != "curve") {
+        panic!("board.toml: `adc-dma` calibrates every pot with the \"curve\" scheme");
+    }
+
+    // Headless builds leave the pins of `display` to the other devices
+    let headless = !feature("display");
+    // Same as enabling the display-spi and display-128x32 features
+    let spi = !headless
+        && match lookup(&board, &defaults, "display", "interface") {
+            None => feature("display-spi"),
+            Some(Value::String(interface)) if interface == "i2c" => feature("display-spi"),
+            Some(Value::String(interface)) if interface == "spi" => true,
+            Some(_) => panic!("board.toml: `display.interface` has to be \"i2c\" or \"spi\""),
+        };
+    if spi {
+        println!("cargo:rustc-cfg=feature=\"display-spi\"");
+    }
+    match lookup(&board, &defaults, "display", "size") {
+        None => (),
+        Some(Value::String(size)) if size == "128x64" => (),
+        Some(Value::String(size)) if size == "128x32" => {
+            println!("cargo:rustc-cfg=feature=\"display-128x32\"")
+        }
+        Some(_) => panic!("board.toml: `display.size` has to be \"128x64\" or \"128x32\""),
+    }
+
+    let display_keys: &[&str] = if headless {
+        &[]
+    } else if spi {
+        &["sck", "mosi", "dc", "cs", "res"]
+    } else {
+        &["sda", "scl"]
+    };
+    let display_pins: Vec<(&str, i64)> = display_keys
+        .iter()
+        .map(|key| (*key, pin(&board, &defaults, "display", key)))
+        .collect();
+    let page_button = pin(&board, &defaults, "buttons", "page");
+    // No default, the boards only have the BOOT button
+    let profile_button = feature("profiles").then(|| {
+        lookup(&board, &defaults, "buttons", "profile")
+            .and_then(Value::as_integer)
+            .expect("board.toml: feature `profiles` needs `buttons.profile`, a GPIO number")
+    });
+    let host_button = feature("host-switch").then(|| {
+        lookup(&board, &defaults, "buttons", "host")
+            .and_then(Value::as_integer)
+            .expect("board.toml: feature `host-switch` needs `buttons.host`, a GPIO number")
+    });
+    // One button per key of rust_deej::hid::ConsumerKey::ORDER
+    let media_keys = feature("media-keys").then(|| {
+        lookup(&board, &defaults, "buttons", "media")
+            .and_then(Value::as_array)
+            .and_then(|pins| pins.iter().map(Value::as_integer).collect::<Option<Vec<i64>>>())
+            .filter(|pins| (1..=4).contains(&pins.len()))
+            .expect("board.toml: feature `media-keys` needs `buttons.media`, a list of 1-4 GPIO numbers")
+    });
+    // Rows are driven low one at a time and the columns read with their pull-ups
+    let keypad = feature("keypad").then(|| {
+        let pins = |key| -> Vec<i64> {
+            lookup(&board, &defaults, "keypad", key)
+                .and_then(Value::as_array)
+                .and_then(|pins| pins.iter().map(Value::as_integer).collect())
+                .filter(|pins: &Vec<i64>| !pins.is_empty())
+                .unwrap_or_else(|| {
+                    panic!(
+                        "board.toml: feature `keypad` needs `keypad.{key}`, a list of GPIO numbers"
+                    )
+                })
+        };
+        (pins("rows"), pins("cols"))
+    });
+    // A, B and the push switch of the rotary encoder
+    let encoder = feature("settings").then(|| {
+        ["a", "b", "push"].map(|key| {
+            lookup(&board, &defaults, "encoder", key)
+                .and_then(Value::as_integer)
+                .unwrap_or_else(|| {
+                    panic!("board.toml: feature `settings` needs `encoder.{key}`, a GPIO number")
+                })
+        })
+    });
+    let supply = feature("supply-monitor")
+        .then(|| divider_pin(&board, &defaults, chip, "supply", "supply-monitor"));
+    let battery = feature("battery").then(|| {
+        match lookup(&board, &defaults, "battery", "gauge") {
+            None => (),
+            Some(Value::String(gauge)) if gauge == "divider" => (),
+            Some(Value::String(gauge)) if gauge == "max17048" => return Gauge::Max17048,
+            Some(_) => panic!("board.toml: `battery.gauge` has to be \"divider\" or \"max17048\""),
+        }
+        let (pin, divider) = divider_pin(&board, &defaults, chip, "battery", "battery");
+        Gauge::Divider(pin, divider)
+    });
+    // MCP23017 address and the expander pins (0-15) of the mute button and the LED of each channel
+    let expander = feature("expander").then(|| {
+        let address = match lookup(&board, &defaults, "expander", "address") {
+            None => 0x20,
+            Some(Value::Integer(address)) if (0x20..=0x27).contains(address) => *address,
+            Some(_) => panic!("board.toml: `expander.address` has to be 0x20-0x27"),
+        };
+        // Either list can be left out, e.g. for LEDs only
+        let pins = |key| -> Vec<i64> {
+            lookup(&board, &defaults, "expander", key)
+                .map_or(Some(Vec::new()), |pins| {
+                    pins.as_array()?.iter().map(Value::as_integer).collect()
+                })
+                .filter(|pins: &Vec<i64>| {
+                    pins.len() <= pots.len() && pins.iter().all(|pin| (0..16).contains(pin))
+                })
+                .unwrap_or_else(|| {
+                    panic!(
+                        "board.toml: `expander.{key}` has to be a list of expander pins 0-15, at \
+                         most one per channel"
+                    )
+                })
+        };
+        let (buttons, leds) = (pins("buttons"), pins("leds"));
+        let mut used = HashSet::new();
+        if let Some(pin) = buttons.iter().chain(&leds).find(|pin| !used.insert(**pin)) {
+            panic!("board.toml: expander pin {pin} is used twice in `expander`");
+        }
+        (address, buttons, leds)
+    });
+    // Driver and I2C address of the accelerometer turning the display
+    let accelerometer = feature("auto-rotate").then(|| {
+        let (driver, addresses) = match lookup(&board, &defaults, "accelerometer", "chip") {
+            Some(Value::String(chip)) if chip == "mpu6050" => ("Mpu6050", [0x68, 0x69]),
+            Some(Value::String(chip)) if chip == "lis3dh" => ("Lis3dh", [0x18, 0x19]),
+            _ => panic!(
+                "board.toml: feature `auto-rotate` needs `accelerometer.chip`, \"mpu6050\" or \
+                 \"lis3dh\""
+            ),
+        };
+        let address = match lookup(&board, &defaults, "accelerometer", "address") {
+            None => addresses[0],
+            Some(Value::Integer(address)) if addresses.contains(address) => *address,
+            Some(_) => panic!(
+                "board.toml: `accelerometer.address` of the {} has to be {:#04x} or {:#04x}",
+                driver.to_uppercase(),
+                addresses[0],
+                addresses[1]
+            ),
+        };
+        (driver, address)
+    });
+    // Enable (PWM) and phase pins of the H-bridge of each fader, a fader per pot
+    let motors = feature("motorized-faders").then(|| {
+        let pins = |key| -> Vec<i64> {
+            lookup(&board, &defaults, "motor", key)
+                .and_then(Value::as_array)
+                .and_then(|pins| pins.iter().map(Value::as_integer).collect())
+                .filter(|pins: &Vec<i64>| pins.len() == pots.len())
+                .unwrap_or_else(|| {
+                    panic!(
+                        "board.toml: feature `motorized-faders` needs `motor.{key}`, a GPIO number \
+                         per pot"
+                    )
+                })
+        };
+        // A LEDC channel per motor
+        if pots.len() > 6 {
+            panic!("board.toml: feature `motorized-faders` drives at most 6 motors");
+        }
+        (pins("enable"), pins("phase"))
+    });
+    // Slider cap of each pot
+    let touch_pins = feature("touch-sense").then(|| {
+        lookup(&board, &defaults, "touch", "pins")
+            .and_then(Value::as_array)
+            .and_then(|pins| pins.iter().map(Value::as_integer).collect())
+            .filter(|pins: &Vec<i64>| pins.len() == pots.len())
+            .expect("board.toml: feature `touch-sense` needs `touch.pins`, a GPIO number per pot")
+    });
+    // Every I2C device shares I2C0 through crate::I2C_BUS, the ESP32-C3 has no second I2C. An I2C
+    // display brings the bus along, next to an SPI display it is on the pins of `i2c`. Setting
+    // them without a sensor of the firmware leaves the bus to other devices, e.g. a GPIO expander
+    let i2c_display = !spi && !headless;
+    let devices = [
+        i2c_display.then_some(("the display", 0x3c)),
+        matches!(battery, Some(Gauge::Max17048)).then_some(("the MAX17048", 0x36)),
+        feature("ambient-light").then_some(("the BH1750", 0x23)),
+        expander
+            .as_ref()
+            .map(|(address, ..)| ("the MCP23017", *address)),
+        accelerometer.map(|(_, address)| ("the accelerometer", address)),
+    ];
+    let i2c_bus = if i2c_display {
+        Some((display_pins[0].1, display_pins[1].1))
+    } else if devices.iter().any(Option::is_some)
+        || lookup(&board, &defaults, "i2c", "sda").is_some()
+    {
+        Some((
+            pin(&board, &defaults, "i2c", "sda"),
+            pin(&board, &defaults, "i2c", "scl"),
+        ))
+    } else {
+        None
+    };
+    if i2c_bus.is_some() {
+        println!("cargo:rustc-cfg=feature=\"i2c-bus\"");
+    }
+    // Fast mode for the SSD1306, a frame takes a quarter of the time it takes at 100 kHz. The I2C
+    // of the ESP32s goes up to 800 kHz with strong pull-ups
+    let i2c_frequency = lookup(&board, &defaults, "i2c", "frequency")
+        .and_then(Value::as_integer)
+        .filter(|frequency| (10..=800).contains(frequency))
+        .expect("board.toml: `i2c.frequency` has to be 10-800 kHz, e.g. 100 or 400");
+    // Two devices at the same address only show up as garbled readings
+    let mut addresses = HashMap::new();
+    for (device, address) in devices.into_iter().flatten() {
+        if let Some(other) = addresses.insert(address, device) {
+            panic!("board.toml: {other} and {device} are both at I2C address {address:#04x}");
+        }
+    }
+
+    // The ESP32-S3 talks to the host over USB-OTG instead
+    if chip == Chip::Esp32s3 && board.contains_key("serial") {
+        panic!("board.toml: the ESP32-S3 talks to the host over USB-OTG, remove `serial`");
+    }
+    let serial = (chip != Chip::Esp32s3).then(|| {
+        let uart = match lookup(&board, &defaults, "serial", "uart").and_then(Value::as_integer) {
+            Some(uart @ (0 | 1)) => uart,
+            _ => panic!("board.toml: `serial.uart` has to be 0 or 1"),
+        };
+        let baud = lookup(&board, &defaults, "serial", "baud")
+            .and_then(Value::as_integer)
+            .filter(|baud| (1200..=5_000_000).contains(baud))
+            .expect("board.toml: `serial.baud` has to be a baud rate, e.g. 9600, 115200 or 921600");
+        let tx = pin(&board, &defaults, "serial", "tx");
+        let rx = pin(&board, &defaults, "serial", "rx");
+        (uart, baud, tx, rx)
+    });
+    // The RTIC app binds the interrupt of the UART at compile time
+    if serial.is_some_and(|(uart, ..)| uart == 1) {
+        println!("cargo:rustc-cfg=feature=\"host-uart1\"");
+    }
+
+    let mut used = HashMap::new();
+    let named_pins = pots
+        .iter()
+        .map(|pin| ("`pots.pins`", *pin))
+        .chain(display_pins.iter().map(|(_, pin)| ("`display`", *pin)))
+        .chain([("`buttons.page`", page_button)])
+        .chain(profile_button.map(|pin| ("`buttons.profile`", pin)))
+        .chain(host_button.map(|pin| ("`buttons.host`", pin)))
+        .chain(
+            media_keys
+                .iter()
+                .flatten()
+                .map(|pin| ("`buttons.media`", *pin)),
+        )
+        .chain(keypad.iter().flat_map(|(rows, cols)| {
+            let rows = rows.iter().map(|pin| ("`keypad.rows`", *pin));
+            rows.chain(cols.iter().map(|pin| ("`keypad.cols`", *pin)))
+        }))
+        .chain(encoder.iter().flatten().map(|pin| ("`encoder`", *pin)))
+        .chain(motors.iter().flat_map(|(enable, phase)| {
+            let enable = enable.iter().map(|pin| ("`motor.enable`", *pin));
+            enable.chain(phase.iter().map(|pin| ("`motor.phase`", *pin)))
+        }))
+        .chain(
+            touch_pins
+                .iter()
+                .flatten()
+                .map(|pin| ("`touch.pins`", *pin)),
+        )
+        .chain(supply.map(|(pin, _)| ("`supply.pin`", pin)))
+        .chain(battery.and_then(|gauge| match gauge {
+            Gauge::Divider(pin, _) => Some(("`battery.pin`", pin)),
+            Gauge::Max17048 => None,
+        }))
+        .chain(
+            i2c_bus
+                .filter(|_| !i2c_display)
+                .into_iter()
+                .flat_map(|(sda, scl)| [("`i2c`", sda), ("`i2c`", scl)]),
+        )
+        .chain(
+            serial
+                .iter()
+                .flat_map(|(_, _, tx, rx)| [("`serial`", *tx), ("`serial`", *rx)]),
+        )
+        .chain(fixed_pins(chip));
+    for (name, pin) in named_pins {
+        if let Some(other) = used.insert(pin, name) {
+            panic!("board.toml: GPIO{pin} is used by both {other} and {name}");
+        }
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    let mut channels = String::new();
+    writeln!(channels, "pub const INPUT_COUNT: usize = {};", pots.len()).unwrap();
+    writeln!(
+        channels,
+        "/// GPIO of each channel, on the ESP32-C3 this is also the ADC1 channel\n\
+         pub const POT_PINS: [u8; INPUT_COUNT] = {pots:?};"
+    )
+    .unwrap();
+    let full_scale: Vec<u16> = cal_types
+        .iter()
+        .map(|cal_type| match cal_type {
+            Some(_) => 0,
+            None => chip.full_scale_mv(attenuation),
+        })
+        .collect();
+    writeln!(
+        channels,
+        "/// Voltage (mV) of a raw reading of 4095 on each channel, from `pots.attenuation`. 0 for the\n\
+         /// calibrated channels, whose readings already are millivolts\n\
+         pub const POT_FULL_SCALE_MV: [u16; INPUT_COUNT] = {full_scale:?};"
+    )
+    .unwrap();
+    let (keypad_rows, keypad_cols) = keypad
+        .as_ref()
+        .map_or((0, 0), |(rows, cols)| (rows.len(), cols.len()));
+    writeln!(
+        channels,
+        "/// Rows and columns of the key matrix with `keypad`\n\
+         pub const KEYPAD_ROWS: usize = {keypad_rows};\n\
+         pub const KEYPAD_COLS: usize = {keypad_cols};"
+    )
+    .unwrap();
+    let media_key_count = media_keys.as_ref().map_or(0, Vec::len);
+    writeln!(
+        channels,
+        "/// Buttons of `media-keys`\n\
+         pub const MEDIA_KEY_COUNT: usize = {media_key_count};"
+    )
+    .unwrap();
+    let supply_full_scale = supply.map_or(0, |(_, divider)| {
+        (chip.full_scale_mv("Attenuation11dB") as f64 * divider).round() as u32
+    });
+    writeln!(
+        channels,
+        "/// Supply voltage (mV) of a raw reading of 4095 on `supply.pin` with `supply-monitor`, from\n\
+         /// `supply.divider`\n\
+         pub const SUPPLY_FULL_SCALE_MV: u32 = {supply_full_scale};"
+    )
+    .unwrap();
+    let battery_full_scale = match battery {
+        Some(Gauge::Divider(_, divider)) => {
+            (chip.full_scale_mv("Attenuation11dB") as f64 * divider).round() as u32
+        }
+        _ => 0,
+    };
+    writeln!(
+        channels,
+        "/// Battery voltage (mV) of a raw reading of 4095 on `battery.pin` with `battery`, from\n\
+         /// `battery.divider`. 0 with the MAX17048, which measures the cell itself\n\
+         pub const BATTERY_FULL_SCALE_MV: u32 = {battery_full_scale};"
+    )
+    .unwrap();
+    let (expander_address, expander_buttons, expander_leds) =
+        expander.unwrap_or((0x20, Vec::new(), Vec::new()));
+    let channel_pins = |pins: Vec<i64>| -> Vec<Option<i64>> {
+        (0..pots.len())
+            .map(|channel| pins.get(channel).copied())
+            .collect()
+    };
+    writeln!(
+        channels,
+        "/// I2C address of the MCP23017 with `expander`, from `expander.address`\n\
+         pub const EXPANDER_ADDRESS: u8 = {expander_address:#04x};\n\
+         /// Expander pin of the mute button of each channel, from `expander.buttons`\n\
+         pub const EXPANDER_BUTTONS: [Option<u8>; INPUT_COUNT] = {:?};\n\
+         /// Expander pin of the LED of each channel, from `expander.leds`\n\
+         pub const EXPANDER_LEDS: [Option<u8>; INPUT_COUNT] = {:?};",
+        channel_pins(expander_buttons),
+        channel_pins(expander_leds),
+    )
+    .unwrap();
+    writeln!(
+        channels,
+        "/// Clock (kHz) of the I2C bus, from `i2c.frequency`\n\
+         pub const I2C_FREQUENCY: u32 = {i2c_frequency};"
+    )
+    .unwrap();
+    fs::write(out_dir.join("channels.rs"), channels).unwrap();
+
+    let mut generated = String::new();
+    // The I2C display is set up on the pins of i2c_bus!
+    if spi {
+        writeln!(
+            generated,
+            "/// GPIO numbers of the SPI display\npub mod display_pins {{"
+        )
+        .unwrap();
+        for (key, pin) in &display_pins {
+            writeln!(
+                generated,
+                "    pub const {}: u8 = {pin};",
+                key.to_uppercase()
+            )
+            .unwrap();
+        }
+        writeln!(generated, "}}").unwrap();
+    }
+
+    writeln!(
+        generated,
+        "/// Enables the pots in `$adc_config` and registers them in the channel order of board.toml.\n\
+         /// Evaluates to `[AnyAnalogPin; INPUT_COUNT]`\n\
+         macro_rules! pots {{\n    ($io:ident, $adc_config:ident) => {{{{\n\
+         \x20       let mut registry = rust_deej::channels::ChannelRegistry::new();"
+    )
+    .unwrap();
+    for (channel, (pin, cal_type)) in pots.iter().zip(&cal_types).enumerate() {
+        let enable_pin = match cal_type {
+            None => "enable_pin".to_owned(),
+            Some(cal_type) => format!(
+                "enable_pin_with_cal::<_, esp_hal::adc::{cal_type}<esp_hal::peripherals::ADC1>>"
+            ),
+        };
+        writeln!(
+            generated,
+            "        registry\n\
+             \x20           .register(\n\
+             \x20               {channel},\n\
+             \x20               {pin},\n\
+             \x20               rust_deej::AnyAnalogPin::from($adc_config.{enable_pin}(\n\
+             \x20                   $io.pins.gpio{pin}.into_analog(),\n\
+             \x20                   esp_hal::adc::Attenuation::{attenuation},\n\
+             \x20               )),\n\
+             \x20           )\n\
+             \x20           .unwrap(); // Clashes are already rejected by build.rs"
+        )
+        .unwrap();
+    }
+    writeln!(
+        generated,
+        "        registry.finish().unwrap()\n    }}}};\n}}\npub(crate) use pots;"
+    )
+    .unwrap();
+
+    // The I2C display gets a proxy of crate::I2C_BUS, which has to be set first
+    let display_args = if spi {
+        let pins: String = display_pins
+            .iter()
+            .map(|(_, pin)| format!("            $io.pins.gpio{pin},\n"))
+            .collect();
+        format!("            $peripherals.SPI2,\n{pins}            $clocks,\n")
+    } else {
+        "            $crate::I2C_BUS.proxy(),\n".to_owned()
+    };
+    if !headless {
+        writeln!(
+            generated,
+            "/// Sets up the display with [crate::new_display]\n\
+             macro_rules! display {{\n    ($peripherals:ident, $io:ident, $clocks:expr, $delay:expr) => {{\n\
+             \x20       $crate::new_display(\n{display_args}\
+             \x20           $delay,\n        )\n    }};\n}}\npub(crate) use display;"
+        )
+        .unwrap();
+    }
+    if let Some((sda, scl)) = i2c_bus {
+        writeln!(
+            generated,
+            "/// I2C0 on SDA GPIO{sda} and SCL GPIO{scl} at [rust_deej::globals::I2C_FREQUENCY], for\n\
+             /// [crate::I2C_BUS]\n\
+             macro_rules! i2c_bus {{\n    ($peripherals:ident, $io:ident, $clocks:expr) => {{\n\
+             \x20       esp_hal::i2c::I2C::new(\n\
+             \x20           $peripherals.I2C0,\n\
+             \x20           $io.pins.gpio{sda},\n\
+             \x20           $io.pins.gpio{scl},\n\
+             \x20           rust_deej::globals::I2C_FREQUENCY.kHz(),\n\
+             \x20           $clocks,\n\
+             \x20       )\n    }};\n}}\npub(crate) use i2c_bus;"
+        )
+        .unwrap();
+    }
+    if let Some((sda, scl)) = i2c_bus.filter(|_| i2c_display) {
+        writeln!(
+            generated,
+            "/// SDA and SCL as open-drain outputs, for rust_deej::i2c_bus::clear_bus when the display\n\
+             /// stops answering. Only used by the RTIC app\n\
+             macro_rules! i2c_pins {{\n    ($io:ident) => {{(\n\
+             \x20       $io.pins.gpio{sda}.into_open_drain_output(),\n\
+             \x20       $io.pins.gpio{scl}.into_open_drain_output(),\n\
+             \x20   )}};\n}}\n\
+             #[cfg_attr(feature = \"embassy\", allow(unused_imports))]\n\
+             pub(crate) use i2c_pins;"
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        generated,
+        "/// Button that cycles the display pages, pulled up. Only used by the RTIC app\n\
+         #[cfg_attr(feature = \"embassy\", allow(dead_code))]\n\
+         pub type PageButton = esp_hal::gpio::GpioPin<esp_hal::gpio::Input<esp_hal::gpio::PullUp>, {page_button}>;\n\
+         macro_rules! page_button {{\n    ($io:ident) => {{\n        $io.pins.gpio{page_button}.into_pull_up_input()\n    }};\n}}\n\
+         #[cfg_attr(feature = \"embassy\", allow(unused_imports))]\n\
+         pub(crate) use page_button;"
+    )
+    .unwrap();
+    if let Some(profile_button) = profile_button {
+        writeln!(
+            generated,
+            "/// Button that cycles the profiles, pulled up\n\
+             pub type ProfileButton = esp_hal::gpio::GpioPin<esp_hal::gpio::Input<esp_hal::gpio::PullUp>, {profile_button}>;\n\
+             macro_rules! profile_button {{\n    ($io:ident) => {{\n        $io.pins.gpio{profile_button}.into_pull_up_input()\n    }};\n}}\n\
+             pub(crate) use profile_button;"
+        )
+        .unwrap();
+    }
+    if let Some(host_button) = host_button {
+        writeln!(
+            generated,
+            "/// Button that switches the stream between the two PCs, pulled up\n\
+             pub type HostButton = esp_hal::gpio::GpioPin<esp_hal::gpio::Input<esp_hal::gpio::PullUp>, {host_button}>;\n\
+             macro_rules! host_button {{\n    ($io:ident) => {{\n        $io.pins.gpio{host_button}.into_pull_up_input()\n    }};\n}}\n\
+             pub(crate) use host_button;"
+        )
+        .unwrap();
+    }
+    if let Some(pins) = &media_keys {
+        let pins: String = pins
+            .iter()
+            .map(|pin| format!("        $io.pins.gpio{pin}.into_pull_up_input().degrade(),\n"))
+            .collect();
+        writeln!(
+            generated,
+            "/// Buttons of `media-keys` in the order of rust_deej::hid::ConsumerKey::ORDER, pulled up\n\
+             pub type MediaKeyPins = [esp_hal::gpio::AnyPin<esp_hal::gpio::Input<esp_hal::gpio::PullUp>>; rust_deej::globals::MEDIA_KEY_COUNT];\n\
+             macro_rules! media_keys {{\n    ($io:ident) => {{[\n{pins}    ]}};\n}}\n\
+             pub(crate) use media_keys;"
+        )
+        .unwrap();
+    }
+    if let Some((rows, cols)) = &keypad {
+        let rows: String = rows
+            .iter()
+            .map(|pin| {
+                format!("            $io.pins.gpio{pin}.into_push_pull_output().degrade(),\n")
+            })
+            .collect();
+        let cols: String = cols
+            .iter()
+            .map(|pin| format!("            $io.pins.gpio{pin}.into_pull_up_input().degrade(),\n"))
+            .collect();
+        writeln!(
+            generated,
+            "/// Row outputs of the key matrix, driven low while their row is read\n\
+             pub type KeypadRows = [esp_hal::gpio::AnyPin<esp_hal::gpio::Output<esp_hal::gpio::PushPull>>; rust_deej::globals::KEYPAD_ROWS];\n\
+             /// Column inputs of the key matrix, pulled up\n\
+             pub type KeypadCols = [esp_hal::gpio::AnyPin<esp_hal::gpio::Input<esp_hal::gpio::PullUp>>; rust_deej::globals::KEYPAD_COLS];\n\
+             /// Evaluates to `(KeypadRows, KeypadCols)` on the pins of board.toml\n\
+             macro_rules! keypad {{\n    ($io:ident) => {{(\n        [\n{rows}        ],\n        [\n{cols}        ],\n    )}};\n}}\n\
+             pub(crate) use keypad;"
+        )
+        .unwrap();
+    }
+    if let Some((enable, phase)) = &motors {
+        let outputs = |pins: &[i64]| -> String {
+            pins.iter()
+                .map(|pin| {
+                    format!("            $io.pins.gpio{pin}.into_push_pull_output().degrade(),\n")
+                })
+                .collect()
+        };
+        writeln!(
+            generated,
+            "/// Evaluates to the enable and the phase pins of the H-bridge of each fader, both\n\
+             /// `[AnyPin<Output<PushPull>>; INPUT_COUNT]`, for rust_deej::motor::h_bridges\n\
+             macro_rules! motor_pins {{\n    ($io:ident) => {{(\n        [\n{}        ],\n        [\n{}        ],\n    )}};\n}}\n\
+             pub(crate) use motor_pins;",
+            outputs(enable),
+            outputs(phase),
+        )
+        .unwrap();
+    }
+    if let Some(pins) = &touch_pins {
+        let pins: String = pins
+            .iter()
+            .map(|pin| format!("        $io.pins.gpio{pin}.into_open_drain_output().degrade(),\n"))
+            .collect();
+        writeln!(
+            generated,
+            "/// Open-drain pins of the slider caps\n\
+             pub type TouchPins = [esp_hal::gpio::AnyPin<esp_hal::gpio::Output<esp_hal::gpio::OpenDrain>>; rust_deej::globals::INPUT_COUNT];\n\
+             macro_rules! touch_pins {{\n    ($io:ident) => {{[\n{pins}    ]}};\n}}\n\
+             pub(crate) use touch_pins;"
+        )
+        .unwrap();
+    }
+    if let Some([a, b, push]) = encoder {
+        writeln!(
+            generated,
+            "/// A and B outputs and the push switch of the rotary encoder, pulled up\n\
+             pub type Encoder = (\n    esp_hal::gpio::GpioPin<esp_hal::gpio::Input<esp_hal::gpio::PullUp>, {a}>,\n    esp_hal::gpio::GpioPin<esp_hal::gpio::Input<esp_hal::gpio::PullUp>, {b}>,\n    esp_hal::gpio::GpioPin<esp_hal::gpio::Input<esp_hal::gpio::PullUp>, {push}>,\n);\n\
+             macro_rules! encoder {{\n    ($io:ident) => {{(\n        $io.pins.gpio{a}.into_pull_up_input(),\n        $io.pins.gpio{b}.into_pull_up_input(),\n        $io.pins.gpio{push}.into_pull_up_input(),\n    )}};\n}}\n\
+             pub(crate) use encoder;"
+        )
+        .unwrap();
+    }
+    if let Some((pin, _)) = supply {
+        writeln!(
+            generated,
+            "/// Enables `supply.pin` in `$adc_config`, evaluates to a `rust_deej::AnyAnalogPin`\n\
+             macro_rules! supply_pin {{\n    ($io:ident, $adc_config:ident) => {{\n\
+             \x20       rust_deej::AnyAnalogPin::from($adc_config.enable_pin(\n\
+             \x20           $io.pins.gpio{pin}.into_analog(),\n\
+             \x20           esp_hal::adc::Attenuation::Attenuation11dB,\n\
+             \x20       ))\n    }};\n}}\npub(crate) use supply_pin;"
+        )
+        .unwrap();
+    }
+    let gauge = match battery {
+        None => None,
+        Some(Gauge::Divider(pin, _)) => Some((
+            "rust_deej::battery::DividerGauge".to_owned(),
+            format!(
+                "rust_deej::battery::DividerGauge::new(rust_deej::AnyAnalogPin::from(\n\
+                 \x20           $adc_config.enable_pin(\n\
+                 \x20               $io.pins.gpio{pin}.into_analog(),\n\
+                 \x20               esp_hal::adc::Attenuation::Attenuation11dB,\n\
+                 \x20           ),\n\
+                 \x20       ))"
+            ),
+        )),
+        Some(Gauge::Max17048) => Some((
+            "rust_deej::battery::Max17048<rust_deej::i2c_bus::I2cProxy<esp_hal::i2c::I2C<'static, esp_hal::peripherals::I2C0>>>"
+                .to_owned(),
+            "rust_deej::battery::Max17048::new($crate::I2C_BUS.proxy())".to_owned(),
+        )),
+    };
+    if let Some((gauge_type, gauge)) = gauge {
+        writeln!(
+            generated,
+            "/// Fuel gauge or divider of `battery` in board.toml\n\
+             pub type BatteryGauge = {gauge_type};\n\
+             /// Sets up [BatteryGauge], the divider is enabled in `$adc_config` and the MAX17048 gets a\n\
+             /// proxy of [crate::I2C_BUS]\n\
+             macro_rules! battery_gauge {{\n    ($io:ident, $adc_config:ident) => {{\n\
+             \x20       {gauge}\n    }};\n}}\npub(crate) use battery_gauge;"
+        )
+        .unwrap();
+    }
+    if let Some((driver, address)) = accelerometer {
+        writeln!(
+            generated,
+            "/// Accelerometer of `accelerometer` in board.toml\n\
+             pub type Accelerometer = rust_deej::orientation::{driver}<rust_deej::i2c_bus::I2cProxy<esp_hal::i2c::I2C<'static, esp_hal::peripherals::I2C0>>>;\n\
+             /// Sets up [Accelerometer] at {address:#04x} with a proxy of [crate::I2C_BUS]\n\
+             macro_rules! accelerometer {{\n    () => {{\n\
+             \x20       rust_deej::orientation::{driver}::new($crate::I2C_BUS.proxy(), {address:#04x})\n    }};\n}}\n\
+             pub(crate) use accelerometer;"
+        )
+        .unwrap();
+    }
+    if let Some((uart, baud, tx, rx)) = serial {
+        writeln!(
+            generated,
+            "/// UART talking to the host, TX on GPIO{tx} and RX on GPIO{rx}\n\
+             pub type HostUartPeripheral = esp_hal::peripherals::UART{uart};\n\
+             /// Interrupt of [HostUartPeripheral]\n\
+             #[cfg_attr(not(feature = \"embassy\"), allow(dead_code))]\n\
+             pub const HOST_UART_INTERRUPT: esp_hal::peripherals::Interrupt = esp_hal::peripherals::Interrupt::UART{uart};\n\
+             /// Baud rate the host is configured with\n\
+             pub const HOST_BAUD: u32 = {baud};\n\
+             /// Sets up [HostUartPeripheral] at [HOST_BAUD] on the pins of board.toml\n\
+             macro_rules! host_uart {{\n    ($peripherals:ident, $io:ident, $clocks:expr) => {{\n\
+             \x20       esp_hal::Uart::new_with_config(\n\
+             \x20           $peripherals.UART{uart},\n\
+             \x20           esp_hal::uart::config::Config {{\n\
+             \x20               baudrate: $crate::board::HOST_BAUD,\n\
+             \x20               ..Default::default()\n\
+             \x20           }},\n\
+             \x20           Some(esp_hal::uart::TxRxPins::new_tx_rx(\n\
+             \x20               $io.pins.gpio{tx}.into_push_pull_output(),\n\
+             \x20               $io.pins.gpio{rx}.into_floating_input(),\n\
+             \x20           )),\n\
+             \x20           $clocks,\n\
+             \x20       )\n    }};\n}}\npub(crate) use host_uart;"
+        )
+        .unwrap();
+    }
+    fs::write(out_dir.join("board.rs"), generated).unwrap();
+}
@@ -0,0 +1,174 @@
+//! Renders [DisplayState] in an embedded-graphics simulator window, so the layout can be iterated
+//! on without flashing the hardware. Run with `cargo simulator`, add `-- --script` to sweep the
+//! channels on their own instead of using the keyboard.
+//!
+//! Left / Right select a channel, Up / Down move it, Space cycles the screens, U toggles the units.
+
+use std::{
+    cell::RefCell,
+    convert::Infallible,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use embedded_graphics_simulator::{
+    sdl2::Keycode, BinaryColorTheme, OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent,
+    Window,
+};
+use rust_deej::{
+    activity::ChannelActivity,
+    events::Event,
+    globals::{DISPLAY_UPDATE_PERIOD, INPUT_COUNT},
+    units::Units,
+    DisplayFlush, DisplayState, DisplayStatus,
+};
+use ssd1306::prelude::DisplayRotation;
+
+/// Size of the SSD1306 panel, see [rust_deej::DISPLAY_SIZE]
+#[cfg(not(feature = "display-128x32"))]
+const SIZE: Size = Size::new(128, 64);
+#[cfg(feature = "display-128x32")]
+const SIZE: Size = Size::new(128, 32);
+
+/// Volume change per key press
+const STEP: u16 = 5;
+
+/// Window that is only updated on [DisplayFlush::flush], like the real panel
+struct Preview {
+    display: SimulatorDisplay<BinaryColor>,
+    /// Shared with the main loop, which reads the keyboard events from it
+    window: Rc<RefCell<Window>>,
+    on: bool,
+}
+
+impl DrawTarget for Preview {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.display.draw_iter(pixels)
+    }
+}
+
+impl OriginDimensions for Preview {
+    fn size(&self) -> Size {
+        self.display.size()
+    }
+}
+
+impl DisplayFlush for Preview {
+    type Error = Infallible;
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let mut window = self.window.borrow_mut();
+        if self.on {
+            window.update(&self.display);
+        } else {
+            window.update(&SimulatorDisplay::new(SIZE));
+        }
+        Ok(())
+    }
+
+    fn set_display_on(&mut self, on: bool) -> Result<(), Self::Error> {
+        self.on = on;
+        self.flush()
+    }
+
+    // The simulator has no contrast, inversion or rotation, the state is still tracked by
+    // DisplayState
+    fn set_invert(&mut self, _invert: bool) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_contrast(&mut self, _contrast: u8) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_rotation(&mut self, _rotation: DisplayRotation) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Triangle wave 0-100 with a different phase for each channel
+fn scripted_volumes(elapsed: Duration) -> [u16; INPUT_COUNT] {
+    let mut volumes = [0; INPUT_COUNT];
+    for (idx, volume) in volumes.iter_mut().enumerate() {
+        let phase = (elapsed.as_millis() as u64 / 40 + idx as u64 * 50) % 200;
+        *volume = if phase < 100 { phase } else { 200 - phase } as u16;
+    }
+    volumes
+}
+
+fn main() {
+    let script = std::env::args().any(|arg| arg == "--script");
+
+    let settings = OutputSettingsBuilder::new()
+        .theme(BinaryColorTheme::OledBlue)
+        .scale(4)
+        .build();
+    let window = Rc::new(RefCell::new(Window::new("rust-deej", &settings)));
+    let preview = Preview {
+        display: SimulatorDisplay::new(SIZE),
+        window: window.clone(),
+        on: true,
+    };
+
+    let mut display = DisplayState::new(preview);
+    display.set_title("Volumes");
+    display.ready();
+    display.draw().unwrap();
+
+    let start = Instant::now();
+    let mut volumes = [50; INPUT_COUNT];
+    let mut selected = 0;
+    let mut units = Units::Percent;
+    let mut activity = ChannelActivity::new();
+    loop {
+        let mut changed = DisplayStatus::NotChanged;
+        let events: Vec<_> = window.borrow_mut().events().collect();
+        for event in events {
+            match event {
+                SimulatorEvent::Quit => return,
+                SimulatorEvent::KeyDown { keycode, .. } => match keycode {
+                    Keycode::Left => selected = (selected + INPUT_COUNT - 1) % INPUT_COUNT,
+                    Keycode::Right => selected = (selected + 1) % INPUT_COUNT,
+                    Keycode::Up => volumes[selected] = (volumes[selected] + STEP).min(100),
+                    Keycode::Down => volumes[selected] = volumes[selected].saturating_sub(STEP),
+                    Keycode::Space => changed = changed.or(display.next_screen()),
+                    Keycode::U => {
+                        units = match units {
+                            Units::Percent => Units::Decibel,
+                            Units::Decibel => Units::Percent,
+                        };
+                        changed = changed.or(display.set_units(units));
+                    }
+                    _ => (),
+                },
+                _ => (),
+            }
+        }
+
+        if script {
+            volumes = scripted_volumes(start.elapsed());
+        }
+        let now_ms = start.elapsed().as_millis() as u64;
+        // Stand in for the pot values, only the changes count for the focus
+        for (channel, &value) in volumes.iter().enumerate() {
+            activity.handle(&Event::InputChanged { channel, value }, now_ms);
+        }
+        let changed = changed
+            .or(display.set_volumes(&volumes))
+            .or(display.set_focus(activity.focused(now_ms)))
+            .or(display.tick(now_ms))
+            .or(display.animate());
+        if let DisplayStatus::Changed = changed {
+            display.draw().unwrap();
+        }
+        thread::sleep(Duration::from_millis(DISPLAY_UPDATE_PERIOD as u64));
+    }
+}
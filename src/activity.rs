@@ -0,0 +1,142 @@
+//! When each channel was last moved, kept by a task from the [Event::InputChanged]s it reads.
+//! Keep-alives wait while nobody touches a channel, and the channel moved last on its own is the
+//! focused one zoomed on the display and highlighted on the LEDs.
+
+use crate::{
+    events::Event,
+    globals::{FOCUS_TIME, INPUT_COUNT},
+};
+
+/// Activity of one channel
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ChannelState {
+    /// Latest position (0-1023), `None` before the first sample
+    pub position: Option<u16>,
+    /// Time (ms) of the latest change after the first sample, `None` while the channel has not
+    /// been touched
+    pub changed_at: Option<u64>,
+}
+
+impl ChannelState {
+    /// Whether the channel changed after `since_ms`
+    pub fn changed_since(&self, since_ms: u64) -> bool {
+        self.changed_at.is_some_and(|at| at > since_ms)
+    }
+}
+
+/// [ChannelState] of every channel
+#[derive(Default)]
+pub struct ChannelActivity {
+    channels: [ChannelState; INPUT_COUNT],
+}
+
+impl ChannelActivity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the position of an [Event::InputChanged] read at `now_ms`, other events are
+    /// ignored
+    pub fn handle(&mut self, event: &Event, now_ms: u64) {
+        let Event::InputChanged { channel, value } = *event else {
+            return;
+        };
+        let Some(state) = self.channels.get_mut(channel) else {
+            return;
+        };
+        if state.position.is_some_and(|position| position != value) {
+            state.changed_at = Some(now_ms);
+        }
+        state.position = Some(value);
+    }
+
+    pub fn channels(&self) -> &[ChannelState; INPUT_COUNT] {
+        &self.channels
+    }
+
+    /// Latest positions (0-1023), 0 before the first sample
+    pub fn positions(&self) -> [u16; INPUT_COUNT] {
+        self.channels.map(|state| state.position.unwrap_or(0))
+    }
+
+    /// Whether any channel changed after `since_ms`
+    pub fn changed_since(&self, since_ms: u64) -> bool {
+        self.channels
+            .iter()
+            .any(|state| state.changed_since(since_ms))
+    }
+
+    /// Channel changed last, until it has been still for [FOCUS_TIME]. Only a channel that alone
+    /// changed at that time is focused, none while several sliders are moved together.
+    pub fn focused(&self, now_ms: u64) -> Option<usize> {
+        let latest = self
+            .channels
+            .iter()
+            .filter_map(|state| state.changed_at)
+            .max()
+            .filter(|changed_at| now_ms.saturating_sub(*changed_at) < FOCUS_TIME)?;
+        let mut moved =
+            (0..INPUT_COUNT).filter(|&idx| self.channels[idx].changed_at == Some(latest));
+        match (moved.next(), moved.next()) {
+            (Some(channel), None) => Some(channel),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(channel: usize, value: u16) -> Event {
+        Event::InputChanged { channel, value }
+    }
+
+    #[test]
+    fn first_sample_is_not_a_touch() {
+        let mut activity = ChannelActivity::new();
+        activity.handle(&input(0, 500), 10);
+        activity.handle(&input(1, 300), 10);
+        activity.handle(&input(INPUT_COUNT, 300), 10);
+        activity.handle(&Event::LinkUp, 10);
+        assert_eq!(activity.positions()[..2], [500, 300]);
+        assert!(!activity.changed_since(0));
+        assert_eq!(activity.focused(10), None);
+
+        activity.handle(&input(1, 310), 100);
+        assert_eq!(
+            activity.channels()[1],
+            ChannelState {
+                position: Some(310),
+                changed_at: Some(100)
+            }
+        );
+        assert!(activity.changed_since(99));
+        assert!(!activity.changed_since(100));
+    }
+
+    #[test]
+    fn focus_follows_the_latest_change() {
+        let mut activity = ChannelActivity::new();
+        activity.handle(&input(0, 500), 0);
+        activity.handle(&input(1, 500), 0);
+        activity.handle(&input(0, 600), 100);
+        assert_eq!(activity.focused(100), Some(0));
+        activity.handle(&input(1, 400), 200);
+        assert_eq!(activity.focused(200), Some(1));
+        assert_eq!(activity.focused(200 + FOCUS_TIME - 1), Some(1));
+        assert_eq!(activity.focused(200 + FOCUS_TIME), None);
+    }
+
+    #[test]
+    fn sliders_moved_together_are_not_focused() {
+        let mut activity = ChannelActivity::new();
+        activity.handle(&input(0, 500), 0);
+        activity.handle(&input(1, 500), 0);
+        activity.handle(&input(0, 600), 100);
+        activity.handle(&input(1, 600), 100);
+        assert_eq!(activity.focused(100), None);
+        activity.handle(&input(1, 700), 200);
+        assert_eq!(activity.focused(200), Some(1));
+    }
+}
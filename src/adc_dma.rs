@@ -0,0 +1,165 @@
+use core::ptr::{addr_of, addr_of_mut};
+
+use esp_hal::{
+    adc::{AdcCalCurve, AdcCalScheme, Attenuation, ADC},
+    dma::gdma::Gdma,
+    peripherals::ADC1,
+};
+
+use crate::globals::{ADC_DMA_BUFFER_LEN, ADC_DMA_SAMPLE_RATE, INPUT_COUNT, POT_PINS};
+
+/// ADC controller clock, APB / (ADC_CLKM_DIV + 1)
+const ADC_CLKM_HZ: u32 = 80_000_000 / (ADC_CLKM_DIV + 1);
+const ADC_CLKM_DIV: u32 = 15;
+/// GDMA peripheral id of the SAR ADC
+const GDMA_PERI_ADC: u8 = 8;
+/// Conversion results are 32 bit words, data in bits 0-11 and the channel in bits 13-15
+const DATA_MASK: u32 = 0xfff;
+const CHANNEL_SHIFT: u32 = 13;
+const CHANNEL_MASK: u32 = 0x7;
+
+/// GDMA linked list item, see the GDMA chapter of the ESP32-C3 TRM
+#[repr(C)]
+struct Descriptor {
+    /// Buffer size in bits 0-11, received length in 12-23, owner in 31
+    flags: u32,
+    buffer: *mut u8,
+    next: *const Descriptor,
+}
+
+static mut BUFFER: [u32; ADC_DMA_BUFFER_LEN] = [0; ADC_DMA_BUFFER_LEN];
+/// Points to itself, so the ADC keeps overwriting the buffer from the start
+static mut DESCRIPTOR: Descriptor = Descriptor {
+    flags: 0,
+    buffer: core::ptr::null_mut(),
+    next: core::ptr::null(),
+};
+
+/// Scans the [POT_PINS] in the background with the ADC digital controller and GDMA channel 0.
+///
+/// The ADC fills the buffer at [ADC_DMA_SAMPLE_RATE] conversions per second, so
+/// [ContinuousAdc::averages] averages the latest [ADC_DMA_BUFFER_LEN] / [INPUT_COUNT] conversions of
+/// each channel without the CPU waiting for any of them. The pins have to be put into analog mode
+/// and `adc` created with them enabled, as for the oneshot reads.
+pub struct ContinuousAdc {
+    _adc: ADC<'static, ADC1>,
+    _dma: Gdma<'static>,
+    cal: AdcCalCurve<ADC1>,
+}
+
+impl ContinuousAdc {
+    /// Starts the conversions, `adc` and `dma` are only taken so nothing else uses them
+    pub fn new(adc: ADC<'static, ADC1>, dma: Gdma<'static>) -> Self {
+        let cal = AdcCalCurve::new_cal(Attenuation::Attenuation0dB);
+        let saradc = unsafe { &*esp32c3::APB_SARADC::ptr() };
+        let gdma = unsafe { &*esp32c3::DMA::ptr() };
+
+        // Only this struct touches the statics and there is a single ADC1 to create it with
+        unsafe {
+            let descriptor = &mut *addr_of_mut!(DESCRIPTOR);
+            let size = (ADC_DMA_BUFFER_LEN * 4) as u32;
+            descriptor.flags = size | (1 << 31);
+            descriptor.buffer = addr_of_mut!(BUFFER) as *mut u8;
+            descriptor.next = addr_of!(DESCRIPTOR);
+        }
+
+        saradc
+            .ctrl()
+            .modify(|_, w| w.start_force().clear_bit().start().clear_bit());
+        saradc.ctrl2().modify(|_, w| w.timer_en().clear_bit());
+        saradc.clkm_conf().modify(|_, w| unsafe {
+            w.clk_sel()
+                .bits(2)
+                .clkm_div_num()
+                .bits(ADC_CLKM_DIV as u8)
+                .clkm_div_a()
+                .bits(0)
+                .clkm_div_b()
+                .bits(1)
+                .clk_en()
+                .set_bit()
+        });
+
+        // One pattern item per pot: ADC1 channel (the GPIO number) in bits 2-4, attenuation (0 dB)
+        // in bits 0-1. The first item is in the highest bits.
+        let pattern = POT_PINS.iter().enumerate().fold(0u32, |table, (idx, pin)| {
+            table | ((*pin as u32) << 2) << (6 * (3 - idx as u32))
+        });
+        saradc.sar_patt_tab1().write(|w| unsafe { w.bits(pattern) });
+        saradc.ctrl().modify(|_, w| unsafe {
+            w.sar_patt_len()
+                .bits(INPUT_COUNT as u8 - 1)
+                .sar_patt_p_clear()
+                .set_bit()
+                .sar_clk_div()
+                .bits(1)
+        });
+        saradc
+            .ctrl()
+            .modify(|_, w| w.sar_patt_p_clear().clear_bit());
+
+        // EOF after a full buffer moves the DMA to the next descriptor, which is the same one
+        saradc.dma_conf().modify(|_, w| unsafe {
+            w.apb_adc_eof_num()
+                .bits(ADC_DMA_BUFFER_LEN as u16)
+                .apb_adc_reset_fsm()
+                .set_bit()
+        });
+        saradc
+            .dma_conf()
+            .modify(|_, w| w.apb_adc_reset_fsm().clear_bit());
+
+        gdma.in_conf0_ch0().modify(|_, w| w.in_rst().set_bit());
+        gdma.in_conf0_ch0().modify(|_, w| w.in_rst().clear_bit());
+        gdma.in_peri_sel_ch0()
+            .modify(|_, w| unsafe { w.peri_in_sel().bits(GDMA_PERI_ADC) });
+        gdma.in_link_ch0().modify(|_, w| unsafe {
+            w.inlink_addr()
+                .bits(addr_of!(DESCRIPTOR) as u32)
+                .inlink_start()
+                .set_bit()
+        });
+
+        saradc.dma_conf().modify(|_, w| w.apb_adc_trans().set_bit());
+        saradc.ctrl2().modify(|_, w| unsafe {
+            w.timer_target()
+                .bits((ADC_CLKM_HZ / ADC_DMA_SAMPLE_RATE) as u16)
+                .timer_en()
+                .set_bit()
+        });
+
+        Self {
+            _adc: adc,
+            _dma: dma,
+            cal,
+        }
+    }
+
+    /// Average of the latest conversions of each channel, calibrated like
+    /// [crate::ReadAnalog::read_multi_sample]
+    pub fn averages(&self) -> [u16; INPUT_COUNT] {
+        let mut sums = [0u32; INPUT_COUNT];
+        let mut counts = [0u32; INPUT_COUNT];
+        // The DMA keeps writing while this reads, single words are still written atomically
+        let buffer = unsafe { &*addr_of!(BUFFER) };
+        for word in buffer
+            .iter()
+            .map(|word| unsafe { core::ptr::read_volatile(word) })
+        {
+            let channel = ((word >> CHANNEL_SHIFT) & CHANNEL_MASK) as u8;
+            if let Some(idx) = POT_PINS.iter().position(|pin| *pin == channel) {
+                sums[idx] += word & DATA_MASK;
+                counts[idx] += 1;
+            }
+        }
+
+        let mut averages = [0; INPUT_COUNT];
+        for (average, (sum, count)) in averages.iter_mut().zip(sums.iter().zip(counts)) {
+            if count == 0 {
+                continue;
+            }
+            *average = self.cal.adc_val((sum / count) as u16);
+        }
+        averages
+    }
+}
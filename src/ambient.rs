@@ -0,0 +1,104 @@
+//! Display contrast that follows the ambient light, measured by a BH1750 on the I2C bus of the
+//! display, see [crate::i2c_bus]. Readable in daylight without glaring in a dark room.
+
+use embedded_hal_027::blocking::i2c::{Read, Write};
+
+use crate::{
+    globals::{AMBIENT_CONTRAST, AMBIENT_CONTRAST_STEP},
+    interpolate,
+    log::debug,
+};
+
+/// The sensor did not acknowledge
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SensorError;
+
+/// BH1750 ambient light sensor with ADDR tied low
+pub struct Bh1750<I> {
+    i2c: I,
+}
+
+impl<I: Write + Read> Bh1750<I> {
+    const ADDRESS: u8 = 0x23;
+    const POWER_ON: u8 = 0x01;
+    /// 1 lx resolution, a new measurement every 120 ms
+    const CONTINUOUS_HIGH_RES: u8 = 0x10;
+
+    pub fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    /// Starts measuring continuously, the first result is ready after 180 ms
+    pub fn start(&mut self) -> Result<(), SensorError> {
+        for command in [Self::POWER_ON, Self::CONTINUOUS_HIGH_RES] {
+            self.i2c
+                .write(Self::ADDRESS, &[command])
+                .map_err(|_| SensorError)?;
+        }
+        Ok(())
+    }
+
+    /// Latest measurement in lx
+    pub fn read_lux(&mut self) -> Result<u32, SensorError> {
+        let mut value = [0; 2];
+        self.i2c
+            .read(Self::ADDRESS, &mut value)
+            .map_err(|_| SensorError)?;
+        // 1.2 counts per lx
+        Ok(u16::from_be_bytes(value) as u32 * 5 / 6)
+    }
+}
+
+/// Smooths the readings and picks the contrast for them from [AMBIENT_CONTRAST]
+#[derive(Default)]
+pub struct AmbientLight {
+    lux: Option<u32>,
+    contrast: Option<u8>,
+}
+
+impl AmbientLight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the new contrast when it moved by at least [AMBIENT_CONTRAST_STEP], smaller steps
+    /// would only make the display flicker as the light changes
+    pub fn update(&mut self, lux: u32) -> Option<u8> {
+        // Follows a quarter of the way per reading, a passing shadow does not dim the display
+        let lux = match self.lux {
+            Some(previous) => (previous * 3 + lux) / 4,
+            None => lux,
+        };
+        self.lux = Some(lux);
+        let contrast = interpolate(&AMBIENT_CONTRAST, lux);
+        if self
+            .contrast
+            .is_some_and(|previous| previous.abs_diff(contrast) < AMBIENT_CONTRAST_STEP)
+        {
+            return None;
+        }
+        debug!("Ambient light {} lx, contrast {}", lux, contrast);
+        self.contrast = Some(contrast);
+        self.contrast
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contrast_follows_the_light() {
+        let mut ambient = AmbientLight::new();
+        assert_eq!(ambient.update(0), Some(AMBIENT_CONTRAST[0].1));
+        // Too small a step
+        assert_eq!(ambient.update(1), None);
+        let last = AMBIENT_CONTRAST[AMBIENT_CONTRAST.len() - 1];
+        let mut contrast = None;
+        for _ in 0..50 {
+            contrast = ambient.update(last.0 * 2).or(contrast);
+        }
+        assert_eq!(contrast, Some(last.1));
+    }
+}
@@ -0,0 +1,197 @@
+use embedded_hal_027::adc::OneShot;
+use enum_dispatch::enum_dispatch;
+#[cfg(all(feature = "esp32c3", feature = "adc-cal-basic"))]
+use esp_hal::adc::AdcCalBasic;
+#[cfg(all(feature = "esp32c3", feature = "adc-cal-curve"))]
+use esp_hal::adc::AdcCalCurve;
+#[cfg(all(feature = "esp32c3", feature = "adc-cal-line"))]
+use esp_hal::adc::AdcCalLine;
+use esp_hal::{
+    adc::{AdcPin, ADC},
+    gpio::{Analog, GpioPin},
+    peripherals::ADC1,
+};
+
+#[cfg(feature = "oversampling")]
+use crate::oversampling;
+use crate::{
+    globals::INPUT_COUNT,
+    log::trace,
+    sampling::{AdcError, AnalogSource, Reading},
+};
+
+/// Readings are raw, the zero cutoff of the channel is applied by [crate::sampling::Sampler]
+#[enum_dispatch]
+pub trait ReadAnalog {
+    fn read(&mut self, adc: &mut ADC<ADC1>) -> Result<u16, AdcError>;
+    /// Average and spread of `sample_size` readings, see [Reading]
+    fn read_multi_sample(
+        &mut self,
+        adc: &mut ADC<ADC1>,
+        sample_size: u32,
+    ) -> Result<Reading, AdcError>;
+    /// Starts a conversion or returns the result of the running one
+    fn try_read(&mut self, adc: &mut ADC<ADC1>) -> nb::Result<u16, AdcError>;
+    /// Reading with `extra_bits` more resolution, see [oversampling]
+    #[cfg(feature = "oversampling")]
+    fn read_oversampled(&mut self, adc: &mut ADC<ADC1>, extra_bits: u32) -> Result<u32, AdcError>;
+}
+
+/// Allows storage for all implemented analog pins. Currently **only** supports ADC1 pins, which
+/// are GPIO0-4 on the ESP32-C3. The pots are put in channel order by [crate::channels::ChannelRegistry].
+///
+/// Each pin can be read raw or with one of the calibration schemes of esp-hal, selected per pin with
+/// `pots.calibration` in board.toml. Only the schemes in use are compiled in, the curve fitting one
+/// is the most accurate but also the largest and slowest to set up.
+///
+/// Regardless of the enum variant actually stored the analog input value can be read by using [AnyAnalogPin]
+#[cfg(feature = "esp32c3")]
+#[enum_dispatch(ReadAnalog)]
+pub enum AnyAnalogPin {
+    A0(AdcPin<GpioPin<Analog, 0>, ADC1>),
+    A1(AdcPin<GpioPin<Analog, 1>, ADC1>),
+    A2(AdcPin<GpioPin<Analog, 2>, ADC1>),
+    A3(AdcPin<GpioPin<Analog, 3>, ADC1>),
+    A4(AdcPin<GpioPin<Analog, 4>, ADC1>),
+    #[cfg(feature = "adc-cal-basic")]
+    A0Basic(AdcPin<GpioPin<Analog, 0>, ADC1, AdcCalBasic<ADC1>>),
+    #[cfg(feature = "adc-cal-basic")]
+    A1Basic(AdcPin<GpioPin<Analog, 1>, ADC1, AdcCalBasic<ADC1>>),
+    #[cfg(feature = "adc-cal-basic")]
+    A2Basic(AdcPin<GpioPin<Analog, 2>, ADC1, AdcCalBasic<ADC1>>),
+    #[cfg(feature = "adc-cal-basic")]
+    A3Basic(AdcPin<GpioPin<Analog, 3>, ADC1, AdcCalBasic<ADC1>>),
+    #[cfg(feature = "adc-cal-basic")]
+    A4Basic(AdcPin<GpioPin<Analog, 4>, ADC1, AdcCalBasic<ADC1>>),
+    #[cfg(feature = "adc-cal-line")]
+    A0Line(AdcPin<GpioPin<Analog, 0>, ADC1, AdcCalLine<ADC1>>),
+    #[cfg(feature = "adc-cal-line")]
+    A1Line(AdcPin<GpioPin<Analog, 1>, ADC1, AdcCalLine<ADC1>>),
+    #[cfg(feature = "adc-cal-line")]
+    A2Line(AdcPin<GpioPin<Analog, 2>, ADC1, AdcCalLine<ADC1>>),
+    #[cfg(feature = "adc-cal-line")]
+    A3Line(AdcPin<GpioPin<Analog, 3>, ADC1, AdcCalLine<ADC1>>),
+    #[cfg(feature = "adc-cal-line")]
+    A4Line(AdcPin<GpioPin<Analog, 4>, ADC1, AdcCalLine<ADC1>>),
+    #[cfg(feature = "adc-cal-curve")]
+    A0Curve(AdcPin<GpioPin<Analog, 0>, ADC1, AdcCalCurve<ADC1>>),
+    #[cfg(feature = "adc-cal-curve")]
+    A1Curve(AdcPin<GpioPin<Analog, 1>, ADC1, AdcCalCurve<ADC1>>),
+    #[cfg(feature = "adc-cal-curve")]
+    A2Curve(AdcPin<GpioPin<Analog, 2>, ADC1, AdcCalCurve<ADC1>>),
+    #[cfg(feature = "adc-cal-curve")]
+    A3Curve(AdcPin<GpioPin<Analog, 3>, ADC1, AdcCalCurve<ADC1>>),
+    #[cfg(feature = "adc-cal-curve")]
+    A4Curve(AdcPin<GpioPin<Analog, 4>, ADC1, AdcCalCurve<ADC1>>),
+}
+
+/// ADC1 pins of the ESP32. esp-hal has no calibration for its ADC, so the readings are raw.
+#[cfg(feature = "esp32")]
+#[enum_dispatch(ReadAnalog)]
+pub enum AnyAnalogPin {
+    A32(AdcPin<GpioPin<Analog, 32>, ADC1>),
+    A33(AdcPin<GpioPin<Analog, 33>, ADC1>),
+    A34(AdcPin<GpioPin<Analog, 34>, ADC1>),
+    A35(AdcPin<GpioPin<Analog, 35>, ADC1>),
+    A36(AdcPin<GpioPin<Analog, 36>, ADC1>),
+    A37(AdcPin<GpioPin<Analog, 37>, ADC1>),
+    A38(AdcPin<GpioPin<Analog, 38>, ADC1>),
+    A39(AdcPin<GpioPin<Analog, 39>, ADC1>),
+}
+
+/// ADC1 pins of the ESP32-S3, read without calibration like on the ESP32
+#[cfg(feature = "esp32s3")]
+#[enum_dispatch(ReadAnalog)]
+pub enum AnyAnalogPin {
+    A1(AdcPin<GpioPin<Analog, 1>, ADC1>),
+    A2(AdcPin<GpioPin<Analog, 2>, ADC1>),
+    A3(AdcPin<GpioPin<Analog, 3>, ADC1>),
+    A4(AdcPin<GpioPin<Analog, 4>, ADC1>),
+    A5(AdcPin<GpioPin<Analog, 5>, ADC1>),
+    A6(AdcPin<GpioPin<Analog, 6>, ADC1>),
+    A7(AdcPin<GpioPin<Analog, 7>, ADC1>),
+    A8(AdcPin<GpioPin<Analog, 8>, ADC1>),
+    A9(AdcPin<GpioPin<Analog, 9>, ADC1>),
+    A10(AdcPin<GpioPin<Analog, 10>, ADC1>),
+}
+
+/// Any pin the ADC can read, calibrated or not depending on the chip
+impl<T, Cal> ReadAnalog for AdcPin<T, ADC1, Cal>
+where
+    for<'d> ADC<'d, ADC1>: OneShot<ADC1, u16, Self, Error = ()>,
+{
+    fn read(&mut self, adc: &mut ADC<ADC1>) -> Result<u16, AdcError> {
+        nb::block!(adc.read(self)).map_err(|_| AdcError)
+    }
+
+    fn read_multi_sample(
+        &mut self,
+        adc: &mut ADC<ADC1>,
+        sample_size: u32,
+    ) -> Result<Reading, AdcError> {
+        let mut sum = 0u32;
+        let (mut min, mut max) = (u16::MAX, 0);
+        for _ in 0..sample_size {
+            let x = self.read(adc)?;
+            sum += x as u32;
+            min = min.min(x);
+            max = max.max(x);
+        }
+        let average = (sum / sample_size) as u16; // adc.read returns u16 so the average of u16 should never be larger than u16 --> no overflow
+        trace!("ADC average {} spread {}", average, max.saturating_sub(min));
+        Ok(Reading {
+            average,
+            spread: max.saturating_sub(min),
+        })
+    }
+
+    fn try_read(&mut self, adc: &mut ADC<ADC1>) -> nb::Result<u16, AdcError> {
+        adc.read(self).map_err(|err| err.map(|_| AdcError))
+    }
+
+    #[cfg(feature = "oversampling")]
+    fn read_oversampled(&mut self, adc: &mut ADC<ADC1>, extra_bits: u32) -> Result<u32, AdcError> {
+        oversampling::oversample(extra_bits, || {
+            nb::block!(adc.read(self)).map_err(|_| AdcError)
+        })
+    }
+}
+
+/// The pots and the ADC they are read with, borrowed for a single [crate::sampling::Sampler::sample]
+pub struct Pots<'a, 'd> {
+    pub adc: &'a mut ADC<'d, ADC1>,
+    pub pins: &'a mut [AnyAnalogPin; INPUT_COUNT],
+}
+
+impl AnalogSource for Pots<'_, '_> {
+    fn read(&mut self, channel: usize, samples: u32) -> Result<Reading, AdcError> {
+        self.pins[channel].read_multi_sample(self.adc, samples)
+    }
+}
+
+/// Same as [ReadAnalog::read_multi_sample] but yields to the executor while a conversion is running
+#[cfg(feature = "embassy")]
+pub async fn read_multi_sample_async<P: ReadAnalog>(
+    pin: &mut P,
+    adc: &mut ADC<'_, ADC1>,
+    sample_size: u32,
+) -> Result<Reading, AdcError> {
+    let mut sum = 0u32;
+    let (mut min, mut max) = (u16::MAX, 0);
+    for _ in 0..sample_size {
+        let x = loop {
+            match pin.try_read(adc) {
+                Ok(x) => break x,
+                Err(nb::Error::WouldBlock) => embassy_futures::yield_now().await,
+                Err(nb::Error::Other(err)) => return Err(err),
+            }
+        };
+        sum += x as u32;
+        min = min.min(x);
+        max = max.max(x);
+    }
+    Ok(Reading {
+        average: (sum / sample_size) as u16,
+        spread: max.saturating_sub(min),
+    })
+}
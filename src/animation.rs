@@ -0,0 +1,116 @@
+use crate::{
+    fixed::{ease_out, lerp, Q16},
+    globals::INPUT_COUNT,
+};
+
+/// Percentage points a bar moves per frame with [Easing::Linear]
+const LINEAR_STEP: u16 = 8;
+/// Frames a bar takes to reach a new value with [Easing::EaseOut]
+const EASE_OUT_FRAMES: u16 = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Easing {
+    /// Bars jump straight to the new value
+    Off,
+    /// Bars move at a constant speed
+    Linear,
+    /// Bars move fast at first and slow down near the new value, see [ease_out]
+    EaseOut,
+}
+
+/// Interpolates the drawn bar fill (0-100) toward the latest values, one step per frame
+pub struct BarAnimator {
+    easing: Easing,
+    shown: [u16; INPUT_COUNT],
+    target: [u16; INPUT_COUNT],
+    /// Where each bar was when its target last changed
+    start: [u16; INPUT_COUNT],
+    /// Frames each bar has moved since its target last changed
+    frame: [u16; INPUT_COUNT],
+}
+
+impl BarAnimator {
+    pub fn new(easing: Easing) -> Self {
+        Self {
+            easing,
+            shown: [0; INPUT_COUNT],
+            target: [0; INPUT_COUNT],
+            start: [0; INPUT_COUNT],
+            frame: [0; INPUT_COUNT],
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.easing != Easing::Off
+    }
+
+    /// With [Easing::Off] the new values are shown immediately
+    pub fn set_target(&mut self, target: &[u16; INPUT_COUNT]) {
+        for (channel, target) in target.iter().enumerate() {
+            if *target != self.target[channel] {
+                self.start[channel] = self.shown[channel];
+                self.frame[channel] = 0;
+            }
+        }
+        self.target = *target;
+        if !self.is_enabled() {
+            self.shown = *target;
+        }
+    }
+
+    /// Moves every bar one frame closer to its target. Returns true if any bar moved.
+    pub fn step(&mut self) -> bool {
+        let mut moved = false;
+        for channel in 0..INPUT_COUNT {
+            let (shown, target) = (self.shown[channel], self.target[channel]);
+            if shown == target {
+                continue;
+            }
+            self.shown[channel] = match self.easing {
+                Easing::Off => target,
+                Easing::Linear if shown < target => (shown + LINEAR_STEP).min(target),
+                Easing::Linear => shown.saturating_sub(LINEAR_STEP).max(target),
+                Easing::EaseOut => {
+                    self.frame[channel] += 1;
+                    let t = ease_out(Q16::from_ratio(
+                        self.frame[channel] as u32,
+                        EASE_OUT_FRAMES as u32,
+                    ));
+                    lerp(self.start[channel] as u32, target as u32, t) as u16
+                }
+            };
+            moved = true;
+        }
+        moved
+    }
+
+    pub fn shown(&self) -> &[u16; INPUT_COUNT] {
+        &self.shown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ease_out_slows_down_and_arrives() {
+        let mut animator = BarAnimator::new(Easing::EaseOut);
+        animator.set_target(&[100; INPUT_COUNT]);
+        let mut previous = 0;
+        let mut steps = std::vec::Vec::new();
+        while animator.step() {
+            let shown = animator.shown()[0];
+            steps.push(shown - previous);
+            previous = shown;
+        }
+        assert_eq!(steps.len(), EASE_OUT_FRAMES as usize);
+        assert_eq!(previous, 100);
+        assert!(steps.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        // A new target starts over from where the bar is
+        animator.set_target(&[40; INPUT_COUNT]);
+        assert!(animator.step());
+        assert!(animator.shown()[0] < 100 && animator.shown()[0] > 40);
+    }
+}
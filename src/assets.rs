@@ -0,0 +1,71 @@
+use embedded_graphics::{image::ImageRaw, pixelcolor::BinaryColor};
+
+/// Width and height of the built-in icons
+pub const ICON_SIZE: u32 = 8;
+
+/// 8x8 bitmap, one byte per row, most significant bit is the leftmost pixel
+pub type IconBitmap = [u8; ICON_SIZE as usize];
+
+/// One byte per row, most significant bit is the leftmost pixel
+const SPEAKER: IconBitmap = [0x08, 0x1a, 0xf9, 0xf9, 0xf9, 0xf9, 0x1a, 0x08];
+const MIC: IconBitmap = [0x38, 0x38, 0x38, 0xba, 0x82, 0x7c, 0x10, 0x7c];
+const BROWSER: IconBitmap = [0x3c, 0x5a, 0xff, 0x99, 0x99, 0xff, 0x5a, 0x3c];
+const GAME: IconBitmap = [0x00, 0x7e, 0xdd, 0x8b, 0xdf, 0xff, 0xe7, 0xc3];
+
+pub const LOGO_WIDTH: u32 = 32;
+/// Four mixer sliders, 32x16 px. Four bytes per row.
+#[rustfmt::skip]
+const LOGO: [u8; 64] = [
+    0x00, 0x00, 0x00, 0x00,
+    0x08, 0x08, 0x08, 0x08,
+    0x08, 0x08, 0x08, 0x08,
+    0x08, 0x3e, 0x08, 0x08,
+    0x08, 0x3e, 0x08, 0x08,
+    0x08, 0x3e, 0x08, 0x3e,
+    0x08, 0x08, 0x08, 0x3e,
+    0x08, 0x08, 0x08, 0x3e,
+    0x3e, 0x08, 0x08, 0x08,
+    0x3e, 0x08, 0x08, 0x08,
+    0x3e, 0x08, 0x3e, 0x08,
+    0x08, 0x08, 0x3e, 0x08,
+    0x08, 0x08, 0x3e, 0x08,
+    0x08, 0x08, 0x08, 0x08,
+    0x08, 0x08, 0x08, 0x08,
+    0x00, 0x00, 0x00, 0x00,
+];
+
+/// Shown on the splash screen
+pub fn logo() -> ImageRaw<'static, BinaryColor> {
+    ImageRaw::new(&LOGO, LOGO_WIDTH)
+}
+
+/// Default icons of the channels, e.g. master volume is usually the first channel
+const DEFAULT_ICONS: [Icon; 4] = [Icon::Speaker, Icon::Browser, Icon::Game, Icon::Mic];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Icon {
+    Speaker,
+    Mic,
+    Browser,
+    Game,
+    /// Uploaded by the host, e.g. the icon of the application mapped to the channel
+    Custom(IconBitmap),
+}
+
+impl Icon {
+    pub fn image(&self) -> ImageRaw<'_, BinaryColor> {
+        let data: &[u8] = match self {
+            Icon::Speaker => &SPEAKER,
+            Icon::Mic => &MIC,
+            Icon::Browser => &BROWSER,
+            Icon::Game => &GAME,
+            Icon::Custom(bitmap) => bitmap,
+        };
+        ImageRaw::new(data, ICON_SIZE)
+    }
+
+    /// Icon of channel `idx` in the default set, `None` for channels past the set
+    pub fn default_for(idx: usize) -> Option<Icon> {
+        DEFAULT_ICONS.get(idx).copied()
+    }
+}
@@ -0,0 +1,183 @@
+//! Charge of the battery of the wireless builds, from a MAX17048 fuel gauge or the cell voltage
+//! through a divider on an ADC1 pin, see `battery` in board.toml. It is shown next to a battery
+//! icon in the title bar and the chip goes to deep sleep once the cell is empty.
+
+use embedded_hal_027::blocking::i2c::WriteRead;
+#[cfg(feature = "hal")]
+use esp_hal::{adc::ADC, peripherals::ADC1};
+
+use crate::{
+    globals::{BATTERY_EMPTY_PERCENT, BATTERY_EMPTY_READINGS},
+    interpolate,
+    log::info,
+};
+#[cfg(feature = "hal")]
+use crate::{
+    globals::{BATTERY_FULL_SCALE_MV, BATTERY_SAMPLES},
+    AnyAnalogPin, ReadAnalog,
+};
+
+/// Cell voltage (mV) of a LiPo at a light load and its charge (%), interpolated in between
+const DISCHARGE_CURVE: [(u32, u8); 12] = [
+    (3300, 0),
+    (3500, 5),
+    (3600, 10),
+    (3700, 20),
+    (3750, 30),
+    (3790, 40),
+    (3830, 50),
+    (3870, 60),
+    (3920, 70),
+    (3980, 80),
+    (4060, 90),
+    (4150, 100),
+];
+
+/// Charge (0-100 %) of a LiPo cell at `millivolts`, see [DISCHARGE_CURVE]
+pub fn percent(millivolts: u32) -> u8 {
+    interpolate(&DISCHARGE_CURVE, millivolts)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BatteryReading {
+    pub millivolts: u32,
+    /// 0-100
+    pub percent: u8,
+}
+
+/// The gauge did not answer or the ADC did not return a reading
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GaugeError;
+
+/// MAX17048 fuel gauge, which tracks the charge of the cell itself
+pub struct Max17048<I> {
+    i2c: I,
+}
+
+impl<I: WriteRead> Max17048<I> {
+    const ADDRESS: u8 = 0x36;
+    /// Cell voltage in 78.125 uV steps
+    const VCELL: u8 = 0x02;
+    /// Charge in 1/256 %
+    const SOC: u8 = 0x04;
+
+    pub fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    pub fn read(&mut self) -> Result<BatteryReading, GaugeError> {
+        let vcell = self.register(Self::VCELL)?;
+        let soc = self.register(Self::SOC)?;
+        Ok(BatteryReading {
+            millivolts: vcell as u32 * 78_125 / 1_000_000,
+            // Reads a bit over 100 % right after charging
+            percent: (soc >> 8).min(100) as u8,
+        })
+    }
+
+    fn register(&mut self, register: u8) -> Result<u16, GaugeError> {
+        let mut value = [0; 2];
+        self.i2c
+            .write_read(Self::ADDRESS, &[register], &mut value)
+            .map_err(|_| GaugeError)?;
+        Ok(u16::from_be_bytes(value))
+    }
+}
+
+/// Cell voltage through a divider on an ADC1 pin, the charge is estimated with [percent]
+#[cfg(feature = "hal")]
+pub struct DividerGauge {
+    pin: AnyAnalogPin,
+}
+
+#[cfg(feature = "hal")]
+impl DividerGauge {
+    pub fn new(pin: AnyAnalogPin) -> Self {
+        Self { pin }
+    }
+}
+
+/// Gauge of `battery` in board.toml. `adc` is only used by [DividerGauge]
+#[cfg(feature = "hal")]
+pub trait ReadBattery {
+    fn read_battery(&mut self, adc: &mut ADC<ADC1>) -> Result<BatteryReading, GaugeError>;
+}
+
+#[cfg(feature = "hal")]
+impl<I: WriteRead> ReadBattery for Max17048<I> {
+    fn read_battery(&mut self, _adc: &mut ADC<ADC1>) -> Result<BatteryReading, GaugeError> {
+        self.read()
+    }
+}
+
+#[cfg(feature = "hal")]
+impl ReadBattery for DividerGauge {
+    fn read_battery(&mut self, adc: &mut ADC<ADC1>) -> Result<BatteryReading, GaugeError> {
+        let reading = self
+            .pin
+            .read_multi_sample(adc, BATTERY_SAMPLES)
+            .map_err(|_| GaugeError)?;
+        let millivolts = reading.average as u32 * BATTERY_FULL_SCALE_MV / 4095;
+        Ok(BatteryReading {
+            millivolts,
+            percent: percent(millivolts),
+        })
+    }
+}
+
+/// Decides when the cell is empty
+#[derive(Default)]
+pub struct BatteryMonitor {
+    /// Readings in a row at [BATTERY_EMPTY_PERCENT] or below
+    empty_readings: u8,
+}
+
+impl BatteryMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true once [BATTERY_EMPTY_READINGS] readings in a row were at
+    /// [BATTERY_EMPTY_PERCENT] or below, the chip should go to sleep then
+    pub fn is_empty(&mut self, reading: BatteryReading) -> bool {
+        if reading.percent > BATTERY_EMPTY_PERCENT {
+            self.empty_readings = 0;
+            return false;
+        }
+        self.empty_readings = self.empty_readings.saturating_add(1);
+        if self.empty_readings < BATTERY_EMPTY_READINGS {
+            return false;
+        }
+        info!("Battery empty at {} mV", reading.millivolts);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charge_from_voltage() {
+        assert_eq!(percent(3000), 0);
+        assert_eq!(percent(3550), 7);
+        assert_eq!(percent(3830), 50);
+        assert_eq!(percent(4300), 100);
+
+        let reading = |percent| BatteryReading {
+            millivolts: 3300,
+            percent,
+        };
+        let mut monitor = BatteryMonitor::new();
+        for _ in 1..BATTERY_EMPTY_READINGS {
+            assert!(!monitor.is_empty(reading(BATTERY_EMPTY_PERCENT)));
+        }
+        // A single reading above starts over
+        assert!(!monitor.is_empty(reading(50)));
+        for _ in 1..BATTERY_EMPTY_READINGS {
+            assert!(!monitor.is_empty(reading(0)));
+        }
+        assert!(monitor.is_empty(reading(0)));
+    }
+}
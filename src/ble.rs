@@ -0,0 +1,107 @@
+use core::cell::{Cell, RefCell};
+
+use bleps::{
+    ad_structure::{
+        create_advertising_data, AdStructure, BR_EDR_NOT_SUPPORTED, LE_GENERAL_DISCOVERABLE,
+    },
+    attribute_server::{AttributeServer, NotificationData, WorkResult},
+    gatt, Ble, HciConnector,
+};
+use esp_hal::peripherals::BT;
+use esp_wifi::{ble::controller::BleConnector, EspWifiInitialization};
+
+use crate::{protocol::Frame, status_led::StatusEvent};
+
+pub const BLE_DEVICE_NAME: &str = "rust-deej";
+
+/// Everything needed to bring up the BLE stack. Created in init and consumed by the idle task.
+pub struct BleLink {
+    pub init: EspWifiInitialization,
+    pub bluetooth: BT,
+}
+
+impl BleLink {
+    /// Advertises as [BLE_DEVICE_NAME] and serves the values characteristic forever.
+    ///
+    /// `poll` is called continuously. Every frame it returns is sent as a notification to the
+    /// connected central and can also be read from the characteristic.
+    ///
+    /// `on_event` is told when advertising starts and when the central has connected. bleps does
+    /// not report new connections, so the central counts as connected once it reads the values.
+    pub fn run(
+        &mut self,
+        mut poll: impl FnMut() -> Option<Frame>,
+        mut on_event: impl FnMut(StatusEvent),
+    ) -> ! {
+        let connector = BleConnector::new(&self.init, &mut self.bluetooth);
+        let hci = HciConnector::new(connector, esp_wifi::current_millis);
+        let mut ble = Ble::new(&hci);
+
+        loop {
+            advertise(&mut ble);
+            on_event(StatusEvent::Pairing);
+
+            let last_frame: RefCell<Option<Frame>> = RefCell::new(None);
+            let was_read = Cell::new(false);
+            let mut read_values = |_offset: usize, data: &mut [u8]| {
+                was_read.set(true);
+                let frame = last_frame.borrow();
+                let bytes = frame.as_ref().map(|f| f.as_bytes()).unwrap_or_default();
+                let len = bytes.len().min(data.len());
+                data[..len].copy_from_slice(&bytes[..len]);
+                len
+            };
+
+            gatt!([service {
+                uuid: "7a9e0001-3c4b-4d5e-9f60-2d1e0c0b0a09",
+                characteristics: [characteristic {
+                    name: "values",
+                    uuid: "7a9e0002-3c4b-4d5e-9f60-2d1e0c0b0a09",
+                    notify: true,
+                    read: read_values,
+                },],
+            },]);
+
+            let mut srv = AttributeServer::new(&mut ble, &mut gatt_attributes);
+
+            let mut connected = false;
+            loop {
+                let work = match poll() {
+                    Some(frame) => {
+                        let result = srv.do_work_with_notification(Some(NotificationData::new(
+                            values_handle,
+                            frame.as_bytes(),
+                        )));
+                        last_frame.replace(Some(frame));
+                        result
+                    }
+                    None => srv.do_work(),
+                };
+
+                if !connected && was_read.get() {
+                    connected = true;
+                    on_event(StatusEvent::HostConnected);
+                }
+
+                // Start advertising again so the host can reconnect
+                if let Ok(WorkResult::GotDisconnected) = work {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn advertise(ble: &mut Ble<'_>) {
+    ble.init().unwrap();
+    ble.cmd_set_le_advertising_parameters().unwrap();
+    ble.cmd_set_le_advertising_data(
+        create_advertising_data(&[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::CompleteLocalName(BLE_DEVICE_NAME),
+        ])
+        .unwrap(),
+    )
+    .unwrap();
+    ble.cmd_set_le_advertise_enable(true).unwrap();
+}
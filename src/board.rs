@@ -0,0 +1,65 @@
+//! Pins and peripherals of each supported chip. The apps take them through the macros here, so
+//! the rest of the firmware does not have to know which chip it runs on.
+//!
+//! The wiring is read from board.toml at build time. Keys left out of it get these defaults:
+//!
+//! |             | ESP32-C3       | ESP32              | ESP32-S3               |
+//! |-------------|----------------|--------------------|------------------------|
+//! | Pots        | GPIO0-3        | GPIO32-35          | GPIO1-4                |
+//! | Calibration | curve          | none               | none                   |
+//! | I2C display | SDA 6, SCL 7   | SDA 21, SCL 22     | SDA 8, SCL 9           |
+//! | SPI display | 6, 7, 10, 5, 4 | 14, 13, 27, 15, 26 | 12, 11, 13, 10, 14     |
+//! | Page button | GPIO9 (BOOT)   | GPIO0 (BOOT)       | GPIO0 (BOOT)           |
+//! | Profile     | -              | -                  | -                      |
+//! | Host switch | -              | -                  | -                      |
+//! | Keypad      | -              | -                  | -                      |
+//! | Media keys  | -              | -                  | -                      |
+//! | Encoder     | -              | -                  | -                      |
+//! | Supply      | -              | -                  | -                      |
+//! | Battery     | -              | -                  | -                      |
+//! | Host serial | UART0 21/20    | UART0 1/3          | USB-OTG CDC, GPIO19/20 |
+//!
+//! The SPI pins are SCK, MOSI, DC, CS and RES. The host UART pins are TX and RX and it runs at
+//! 115200 baud. The pots can be on any ADC1 pin in any order: GPIO0-4 on the ESP32-C3, GPIO32-39
+//! on the ESP32 and GPIO1-10 on the ESP32-S3. The profile and host switch buttons, the keypad, the
+//! media keys and the encoder have to be set in board.toml when building with `profiles`,
+//! `host-switch`, `keypad`, `media-keys` and `settings`, the supply divider with `supply-monitor` and the battery gauge with `battery`.
+//! The I2C devices share the bus of an I2C display, next to an SPI display it is set with `i2c`.
+//! The mute buttons and LEDs of `expander` are on the pins of the MCP23017, set with `expander`.
+//! The accelerometer of `auto-rotate` is picked with `accelerometer`. The H-bridges of
+//! `motorized-faders` are on the pins of `motor` and the slider caps of `touch-sense` on the pins of
+//! `touch`.
+
+// pots!, display!, PageButton, page_button!, with an SPI display display_pins, with `profiles`
+// ProfileButton and profile_button!, with `host-switch` HostButton and host_button!, with `keypad`
+// KeypadRows, KeypadCols and keypad!, with `media-keys` MediaKeyPins and media_keys!, with
+// `settings` Encoder and encoder!, with `supply-monitor` supply_pin!, with `battery` BatteryGauge
+// and battery_gauge!, with an I2C bus i2c_bus!, with `auto-rotate` Accelerometer and
+// accelerometer!, with `motorized-faders` motor_pins!, with `touch-sense` touch_pins! and except on
+// the ESP32-S3 HostUartPeripheral and host_uart! generated by build.rs from board.toml
+include!(concat!(env!("OUT_DIR"), "/board.rs"));
+
+/// RTC_CNTL_OPTION1_REG, its bit 0 (FORCE_DOWNLOAD_BOOT) is kept over a software reset and makes
+/// the ROM bootloader wait for a flasher instead of starting the firmware
+#[cfg(feature = "esp32c3")]
+const RTC_CNTL_OPTION1: *mut u32 = 0x6000_8128 as *mut u32;
+#[cfg(feature = "esp32s3")]
+const RTC_CNTL_OPTION1: *mut u32 = 0x6000_812c as *mut u32;
+
+/// Resets the chip into the ROM download mode, like a reset with the BOOT button held. The flasher
+/// clears the bit again when it resets the chip after flashing.
+#[cfg(not(feature = "esp32"))]
+pub fn reboot_to_bootloader() {
+    unsafe { RTC_CNTL_OPTION1.write_volatile(1) };
+    esp_hal::reset::software_reset();
+}
+
+/// The USB-OTG peripheral on D+ GPIO20 and D- GPIO19, for the CDC serial port to the host
+#[cfg(feature = "esp32s3")]
+macro_rules! usb {
+    ($peripherals:ident, $io:ident) => {
+        esp_hal::otg_fs::Usb::new($peripherals.USB0, $io.pins.gpio20, $io.pins.gpio19)
+    };
+}
+#[cfg(feature = "esp32s3")]
+pub(crate) use usb;
@@ -0,0 +1,79 @@
+/// Number of consecutive equal samples needed before a button state change is accepted
+pub const DEBOUNCE_SAMPLES: u8 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ButtonEvent {
+    Pressed,
+    Released,
+}
+
+/// Debounces a button that is sampled periodically, e.g. every 10 ms.
+#[derive(Default)]
+pub struct Debouncer {
+    pressed: bool,
+    counter: u8,
+}
+
+impl Debouncer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give the raw state of the button. Returns an event once the new state has been stable for [DEBOUNCE_SAMPLES].
+    pub fn update(&mut self, pressed: bool) -> Option<ButtonEvent> {
+        if pressed == self.pressed {
+            self.counter = 0;
+            return None;
+        }
+
+        self.counter += 1;
+        if self.counter < DEBOUNCE_SAMPLES {
+            return None;
+        }
+
+        self.counter = 0;
+        self.pressed = pressed;
+        Some(if pressed {
+            ButtonEvent::Pressed
+        } else {
+            ButtonEvent::Released
+        })
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+/// Detects a button held down for a given time. Fires once per press.
+pub struct LongPress {
+    hold_time: u64,
+    pressed_since: Option<u64>,
+    fired: bool,
+}
+
+impl LongPress {
+    pub fn new(hold_time_ms: u32) -> Self {
+        Self {
+            hold_time: hold_time_ms as u64,
+            pressed_since: None,
+            fired: false,
+        }
+    }
+
+    /// Give the raw state of the button and current time. Returns true once when the button has been held long enough.
+    pub fn update(&mut self, pressed: bool, now_ms: u64) -> bool {
+        if !pressed {
+            self.pressed_since = None;
+            self.fired = false;
+            return false;
+        }
+
+        let since = *self.pressed_since.get_or_insert(now_ms);
+        if !self.fired && now_ms.saturating_sub(since) >= self.hold_time {
+            self.fired = true;
+            return true;
+        }
+        false
+    }
+}
@@ -0,0 +1,112 @@
+//! Maps the pots to the slider channels at runtime, so the channel order can follow the physical
+//! sliders instead of the GPIO numbers.
+
+use crate::globals::INPUT_COUNT;
+
+/// Why a pot could not be registered to a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegistryError {
+    /// The channel is not below [INPUT_COUNT]
+    ChannelOutOfRange(usize),
+    /// The channel already has a pot
+    ChannelTaken(usize),
+    /// The GPIO is already registered to another channel
+    PinTaken(u8),
+    /// [ChannelRegistry::finish] was called before the channel got a pot
+    Missing(usize),
+}
+
+/// Pots by channel, registered in any order. The firmware stores [crate::AnyAnalogPin] here so any
+/// ADC1 pin of the chip can be on any channel.
+pub struct ChannelRegistry<P> {
+    slots: [Option<(u8, P)>; INPUT_COUNT],
+}
+
+impl<P> Default for ChannelRegistry<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P> ChannelRegistry<P> {
+    pub fn new() -> Self {
+        Self {
+            slots: core::array::from_fn(|_| None),
+        }
+    }
+
+    /// Puts the pot on `gpio` on `channel`
+    pub fn register(&mut self, channel: usize, gpio: u8, pin: P) -> Result<(), RegistryError> {
+        if self.channel_of(gpio).is_some() {
+            return Err(RegistryError::PinTaken(gpio));
+        }
+        match self.slots.get_mut(channel) {
+            None => Err(RegistryError::ChannelOutOfRange(channel)),
+            Some(Some(_)) => Err(RegistryError::ChannelTaken(channel)),
+            Some(slot) => {
+                *slot = Some((gpio, pin));
+                Ok(())
+            }
+        }
+    }
+
+    /// GPIO of the pot on `channel`, if one is registered
+    pub fn gpio(&self, channel: usize) -> Option<u8> {
+        self.slots.get(channel)?.as_ref().map(|(gpio, _)| *gpio)
+    }
+
+    /// Channel the pot on `gpio` is registered to
+    pub fn channel_of(&self, gpio: u8) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| matches!(slot, Some((registered, _)) if *registered == gpio))
+    }
+
+    /// The pots in channel order, once every channel has one
+    pub fn finish(self) -> Result<[P; INPUT_COUNT], RegistryError> {
+        if let Some(channel) = self.slots.iter().position(Option::is_none) {
+            return Err(RegistryError::Missing(channel));
+        }
+        Ok(self.slots.map(|slot| slot.unwrap().1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pots_come_out_in_channel_order() {
+        let mut registry = ChannelRegistry::new();
+        for channel in (0..INPUT_COUNT).rev() {
+            registry
+                .register(channel, 10 + channel as u8, channel * 100)
+                .unwrap();
+        }
+        assert_eq!(registry.gpio(0), Some(10));
+        assert_eq!(registry.channel_of(11), Some(1));
+
+        let pots = registry.finish().unwrap();
+        assert!(pots
+            .iter()
+            .enumerate()
+            .all(|(channel, pot)| *pot == channel * 100));
+    }
+
+    #[test]
+    fn rejects_clashes_and_gaps() {
+        let mut registry = ChannelRegistry::new();
+        registry.register(0, 3, ()).unwrap();
+        assert_eq!(
+            registry.register(0, 4, ()),
+            Err(RegistryError::ChannelTaken(0))
+        );
+        assert_eq!(registry.register(1, 3, ()), Err(RegistryError::PinTaken(3)));
+        assert_eq!(
+            registry.register(INPUT_COUNT, 5, ()),
+            Err(RegistryError::ChannelOutOfRange(INPUT_COUNT))
+        );
+        assert_eq!(registry.finish().err(), Some(RegistryError::Missing(1)));
+    }
+}
@@ -0,0 +1,289 @@
+//! Debug command line on the serial port, for a person at a terminal rather than the host program.
+//! Commands and their replies start with [CLI_PREFIX] so they never look like frames or
+//! [crate::protocol::HostCommand]s, e.g. `!set contrast 128`. Words are case insensitive.
+
+use heapless::String;
+
+use crate::{
+    diagnostics,
+    globals::INPUT_COUNT,
+    log::debug,
+    pages::VERSION,
+    sampling::Taper,
+    serial::Line,
+    settings::{DisplayTimeout, Settings, SERIAL_PERIOD_RANGE},
+    style::ThemeName,
+    text::{end_line, write_truncated},
+};
+
+/// Starts every command and every reply
+pub const CLI_PREFIX: char = '!';
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+/// Ctrl-U
+const KILL_LINE: u8 = 0x15;
+
+pub const HELP: &str = "!commands: help, raw, config, set <name> <value>, cal, reboot\r\n\
+                        !set timeout <s|never|off>, contrast <0-255>, period <ms>,\r\n\
+                        !set invert <ch> <on|off>, taper <ch> <linear|audio>,\r\n\
+                        !set theme <dark|light|compact|segmented>\r\n";
+
+/// Reply to every command that worked and has nothing else to say
+pub const OK: &str = "!ok\r\n";
+
+/// Text of the replies, cut like [write_truncated] when one does not fit
+pub type Reply = String<{ 112 + 40 * INPUT_COUNT }>;
+
+/// Applies the editing keys of a terminal to `line` while it is typed. Backspace and delete remove
+/// the last character and Ctrl-U clears the line. Returns false for any other byte.
+pub fn edit(line: &mut Line, byte: u8) -> bool {
+    match byte {
+        BACKSPACE | DELETE => {
+            line.pop();
+            true
+        }
+        KILL_LINE => {
+            line.clear();
+            true
+        }
+        _ => false,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CliCommand {
+    Help,
+    /// Raw reading and voltage of every channel
+    Raw,
+    /// Current [Settings] and build
+    Config,
+    Set(Setting),
+    /// Measures the zero cutoffs again, with the pots at zero
+    Calibrate,
+    Reboot,
+}
+
+/// Value of one of the [Settings]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Setting {
+    DisplayTimeout(DisplayTimeout),
+    Contrast(u8),
+    SerialPeriod(u32),
+    Inverted(usize, bool),
+    Taper(usize, Taper),
+    Theme(ThemeName),
+}
+
+impl Setting {
+    pub fn apply(self, settings: &mut Settings) {
+        match self {
+            Setting::DisplayTimeout(timeout) => settings.display_timeout = timeout,
+            Setting::Contrast(contrast) => settings.contrast = contrast,
+            Setting::SerialPeriod(period) => settings.serial_period = period,
+            Setting::Inverted(channel, inverted) => settings.inverted[channel] = inverted,
+            Setting::Taper(channel, taper) => settings.tapers[channel] = taper,
+            Setting::Theme(theme) => settings.theme = theme,
+        }
+    }
+}
+
+/// Command of a line that starts with [CLI_PREFIX], `Err` with the reply to anything else after
+/// the prefix. `None` for lines without the prefix.
+pub fn parse(line: &str) -> Option<Result<CliCommand, &'static str>> {
+    let command = line.strip_prefix(CLI_PREFIX)?;
+    let command = parse_words(command);
+    debug!("CLI command {}", line);
+    Some(command)
+}
+
+fn parse_words(line: &str) -> Result<CliCommand, &'static str> {
+    let mut words = line.split_ascii_whitespace();
+    let command = words.next().ok_or("!error empty command\r\n")?;
+    let is = |word: &str| command.eq_ignore_ascii_case(word);
+    let command = if is("help") {
+        CliCommand::Help
+    } else if is("raw") {
+        CliCommand::Raw
+    } else if is("config") {
+        CliCommand::Config
+    } else if is("cal") {
+        CliCommand::Calibrate
+    } else if is("reboot") {
+        CliCommand::Reboot
+    } else if is("set") {
+        CliCommand::Set(parse_setting(&mut words).ok_or("!error bad setting, see help\r\n")?)
+    } else {
+        return Err("!error unknown command, see help\r\n");
+    };
+    match words.next() {
+        Some(_) => Err("!error too many words\r\n"),
+        None => Ok(command),
+    }
+}
+
+fn parse_setting<'a>(words: &mut impl Iterator<Item = &'a str>) -> Option<Setting> {
+    let name = words.next()?;
+    let is = |word: &str| name.eq_ignore_ascii_case(word);
+    let setting = if is("timeout") {
+        Setting::DisplayTimeout(DisplayTimeout::parse(words.next()?)?)
+    } else if is("contrast") {
+        Setting::Contrast(words.next()?.parse().ok()?)
+    } else if is("period") {
+        let period = words.next()?.parse().ok()?;
+        Setting::SerialPeriod(Some(period).filter(|p| SERIAL_PERIOD_RANGE.contains(p))?)
+    } else if is("invert") {
+        let channel = parse_channel(words.next()?)?;
+        let value = words.next()?;
+        let inverted = if value.eq_ignore_ascii_case("on") {
+            true
+        } else if value.eq_ignore_ascii_case("off") {
+            false
+        } else {
+            return None;
+        };
+        Setting::Inverted(channel, inverted)
+    } else if is("taper") {
+        let channel = parse_channel(words.next()?)?;
+        let value = words.next()?;
+        let taper = if value.eq_ignore_ascii_case("linear") {
+            Taper::Linear
+        } else if value.eq_ignore_ascii_case("audio") {
+            Taper::Audio
+        } else {
+            return None;
+        };
+        Setting::Taper(channel, taper)
+    } else if is("theme") {
+        Setting::Theme(ThemeName::parse(words.next()?)?)
+    } else {
+        return None;
+    };
+    Some(setting)
+}
+
+fn parse_channel(word: &str) -> Option<usize> {
+    word.parse().ok().filter(|channel| *channel < INPUT_COUNT)
+}
+
+/// `!raw <channel>:<raw reading>/<mV> ...`
+pub fn encode_raw(raw_values: &[u16; INPUT_COUNT]) -> Reply {
+    let mut reply = Reply::new();
+    write_truncated(&mut reply, format_args!("!raw")).ok();
+    for (channel, raw) in raw_values.iter().enumerate() {
+        write_truncated(
+            &mut reply,
+            format_args!(
+                " {}:{}/{}mV",
+                channel,
+                raw,
+                diagnostics::millivolts(channel, *raw)
+            ),
+        )
+        .ok();
+    }
+    end_line(&mut reply);
+    reply
+}
+
+/// A line for the build and one for the settings, then one per channel
+pub fn encode_config(settings: &Settings) -> Reply {
+    let mut reply = Reply::new();
+    write_truncated(
+        &mut reply,
+        format_args!("!version {} channels {}\r\n", VERSION, INPUT_COUNT),
+    )
+    .ok();
+    match settings.display_timeout {
+        DisplayTimeout::After(secs) => {
+            write_truncated(&mut reply, format_args!("!timeout {}", secs))
+        }
+        DisplayTimeout::Never => write_truncated(&mut reply, format_args!("!timeout never")),
+        DisplayTimeout::AlwaysOff => write_truncated(&mut reply, format_args!("!timeout off")),
+    }
+    .ok();
+    write_truncated(
+        &mut reply,
+        format_args!(
+            " contrast {} period {} theme ",
+            settings.contrast, settings.serial_period
+        ),
+    )
+    .ok();
+    for c in settings.theme.name().chars() {
+        write_truncated(&mut reply, format_args!("{}", c.to_ascii_lowercase())).ok();
+    }
+    write_truncated(&mut reply, format_args!("\r\n")).ok();
+    for channel in 0..INPUT_COUNT {
+        write_truncated(
+            &mut reply,
+            format_args!(
+                "!ch{} invert {} taper {}",
+                channel,
+                if settings.inverted[channel] {
+                    "on"
+                } else {
+                    "off"
+                },
+                match settings.tapers[channel] {
+                    Taper::Linear => "linear",
+                    Taper::Audio => "audio",
+                }
+            ),
+        )
+        .ok();
+        end_line(&mut reply);
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_and_replies() {
+        assert_eq!(parse("HELLO"), None);
+        assert_eq!(parse("!help"), Some(Ok(CliCommand::Help)));
+        assert_eq!(
+            parse("!SET timeout never"),
+            Some(Ok(CliCommand::Set(Setting::DisplayTimeout(
+                DisplayTimeout::Never
+            ))))
+        );
+        assert_eq!(
+            parse("!set taper 1 audio"),
+            Some(Ok(CliCommand::Set(Setting::Taper(1, Taper::Audio))))
+        );
+        assert_eq!(
+            parse("!set theme LIGHT"),
+            Some(Ok(CliCommand::Set(Setting::Theme(ThemeName::Light))))
+        );
+        assert!(matches!(parse("!set period 5"), Some(Err(_))));
+        assert!(matches!(parse("!set invert 99 on"), Some(Err(_))));
+        assert!(matches!(parse("!raw now"), Some(Err(_))));
+        assert!(matches!(parse("!"), Some(Err(_))));
+
+        let mut settings = Settings::DEFAULT;
+        Setting::Inverted(2, true).apply(&mut settings);
+        let config = encode_config(&settings);
+        assert!(config.contains("!ch2 invert on"));
+        assert!(config.contains("theme dark\r\n"));
+        assert!(config.lines().all(|line| line.starts_with(CLI_PREFIX)));
+
+        assert!(encode_raw(&[0; INPUT_COUNT]).starts_with("!raw 0:0/0mV 1:0/0mV"));
+    }
+
+    #[test]
+    fn line_editing() {
+        let mut line = Line::new();
+        for byte in b"!rax\x7fw" {
+            if !edit(&mut line, *byte) {
+                line.push(*byte as char).unwrap();
+            }
+        }
+        assert_eq!(line, "!raw");
+        assert!(edit(&mut line, KILL_LINE));
+        assert!(line.is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+//! Statistics of the raw ADC readings shown on [crate::pages::Screen::Diagnostics], so the zero
+//! cutoffs and [crate::globals::MAX_ANALOG_VALUE] can be set and bad wiring found without a
+//! debugger.
+
+use crate::globals::{INPUT_COUNT, POT_FULL_SCALE_MV};
+
+/// Change of a raw reading that is shown right away, smaller ones wait for another change
+const SHOWN_CHANGE: u16 = 2;
+/// Each sample moves the noise estimate by 1/2^n of the difference
+const NOISE_SMOOTHING: u8 = 3;
+/// Fraction bits of the noise estimate
+const NOISE_FRACTION_BITS: u8 = 4;
+
+/// Voltage (mV) at the pin of a raw reading of `channel`. Readings of calibrated channels already
+/// are millivolts, the others are scaled by the nominal range of `pots.attenuation`.
+pub fn millivolts(channel: usize, raw: u16) -> u16 {
+    match POT_FULL_SCALE_MV[channel] {
+        0 => raw,
+        full_scale => (raw as u32 * full_scale as u32 / 4095) as u16,
+    }
+}
+
+/// Readings of a single channel since boot
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ChannelStats {
+    /// Latest raw reading
+    pub raw: u16,
+    pub min: u16,
+    pub max: u16,
+    /// Average change between samples in 1/2^[NOISE_FRACTION_BITS] units. Grows while the pot is
+    /// moved and settles to the noise when it is left alone.
+    noise: u16,
+}
+
+impl ChannelStats {
+    /// Average change between consecutive raw readings
+    pub fn noise(&self) -> u16 {
+        self.noise >> NOISE_FRACTION_BITS
+    }
+
+    fn update(&mut self, raw: u16) {
+        let change = (raw.abs_diff(self.raw) as i32) << NOISE_FRACTION_BITS;
+        let noise = self.noise as i32;
+        self.noise = (noise + ((change - noise) >> NOISE_SMOOTHING)) as u16;
+        self.raw = raw;
+        self.min = self.min.min(raw);
+        self.max = self.max.max(raw);
+    }
+}
+
+/// [ChannelStats] of every channel
+pub struct AdcStats {
+    channels: [ChannelStats; INPUT_COUNT],
+    /// Stats last reported as changed, so slow drifts are shown as well
+    shown: [ChannelStats; INPUT_COUNT],
+    started: bool,
+}
+
+impl AdcStats {
+    pub fn new() -> Self {
+        Self {
+            channels: [ChannelStats::default(); INPUT_COUNT],
+            shown: [ChannelStats::default(); INPUT_COUNT],
+            started: false,
+        }
+    }
+
+    pub fn channels(&self) -> &[ChannelStats; INPUT_COUNT] {
+        &self.channels
+    }
+
+    /// Give the raw readings of every sample, returns true when the shown stats changed
+    pub fn update(&mut self, raw_values: &[u16; INPUT_COUNT]) -> bool {
+        for (stats, raw) in self.channels.iter_mut().zip(raw_values) {
+            if self.started {
+                stats.update(*raw);
+            } else {
+                *stats = ChannelStats {
+                    raw: *raw,
+                    min: *raw,
+                    max: *raw,
+                    noise: 0,
+                };
+            }
+        }
+        self.started = true;
+
+        let changed = self.channels.iter().zip(&self.shown).any(|(new, old)| {
+            new.raw.abs_diff(old.raw) > SHOWN_CHANGE
+                || new.min != old.min
+                || new.max != old.max
+                || new.noise() != old.noise()
+        });
+        if changed {
+            self.shown = self.channels;
+        }
+        changed
+    }
+}
+
+impl Default for AdcStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_range_and_noise() {
+        let mut stats = AdcStats::new();
+        assert!(stats.update(&[500; INPUT_COUNT]));
+        assert!(!stats.update(&[500; INPUT_COUNT]));
+
+        for sample in 0..40 {
+            let raw = if sample % 2 == 0 { 490 } else { 510 };
+            stats.update(&[raw; INPUT_COUNT]);
+        }
+        let channel = stats.channels()[0];
+        assert_eq!((channel.min, channel.max), (490, 510));
+        // Settles just below the change of 20 between the samples
+        assert!((17..=20).contains(&channel.noise()), "{}", channel.noise());
+
+        // Noise fades once the readings are steady, the range is kept
+        for _ in 0..80 {
+            stats.update(&[510; INPUT_COUNT]);
+        }
+        let channel = stats.channels()[0];
+        assert_eq!((channel.noise(), channel.min, channel.max), (0, 490, 510));
+    }
+}
@@ -0,0 +1,688 @@
+//! Same firmware on the embassy executor: sampling, the display and serial are async tasks passing
+//! the latest values to each other through signals. The wireless links, LEDs and the other
+//! optional peripherals are only wired up in the RTIC app.
+//!
+//! This is the only app on the ESP32 and the ESP32-S3, RTIC has no Xtensa backend. The S3 talks to
+//! the host over its USB-OTG port instead of UART0.
+
+use core::cell::Cell;
+#[cfg(feature = "esp32s3")]
+use core::ptr::addr_of_mut;
+
+#[cfg(feature = "defmt")]
+use defmt_rtt as _; // Global logger
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, select4, Either, Either4};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    channel::Channel,
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+#[cfg(not(feature = "esp32s3"))]
+use embedded_io_async::Read;
+use esp_backtrace as _; // Exception handling
+#[cfg(feature = "esp32s3")]
+use esp_hal::otg_fs::{Usb, UsbBus};
+#[cfg(feature = "esp32c3")]
+use esp_hal::systimer::SystemTimer;
+#[cfg(any(feature = "esp32", feature = "esp32s3"))]
+use esp_hal::timer::TimerGroup;
+use esp_hal::{
+    adc::{AdcConfig, ADC},
+    clock::ClockControl,
+    embassy,
+    peripherals::{Peripherals, ADC1},
+    prelude::*,
+    Delay, IO,
+};
+#[cfg(not(feature = "esp32s3"))]
+use esp_hal::{
+    interrupt::{self, Priority},
+    uart::{UartRx, UartTx},
+};
+#[cfg(feature = "esp32s3")]
+use usb_device::prelude::*;
+#[cfg(any(feature = "gamepad", feature = "keypad", feature = "media-keys"))]
+use usbd_hid::hid_class::HIDClass;
+#[cfg(feature = "usb-midi")]
+use usbd_midi::UsbMidiClass;
+#[cfg(feature = "esp32s3")]
+use usbd_serial::SerialPort;
+
+#[cfg(any(feature = "gamepad", feature = "keypad", feature = "media-keys"))]
+use rust_deej::hid;
+#[cfg(feature = "usb-midi")]
+use rust_deej::midi;
+use rust_deej::{
+    activity::ChannelActivity,
+    cli::{self, CliCommand},
+    events::Event,
+    globals::{
+        CHANNEL_CONFIGS, DISPLAY_OFF_DELAY, DISPLAY_ON_TIME, DISPLAY_RETRY_DELAY,
+        DISPLAY_RETRY_MAX_DELAY, DISPLAY_UPDATE_PERIOD, INPUT_COUNT, NOISE_FLOOR_SAMPLES,
+        OUTPUT_COUNT, SELF_TEST_FAIL_TIME, SELF_TEST_TIME, SERIAL_CHANGE_THRESHOLD,
+        SERIAL_KEEP_ALIVE_PERIOD, SERIAL_UPDATE_PERIOD, SPLASH_TIME, VIRTUAL_CHANNELS,
+    },
+    motion::MotionDetector,
+    numerics::wire_to_percent,
+    pages::Screen,
+    protocol::{self, Frame, HostCommand, ProtocolMode, CAPABILITIES},
+    read_multi_sample_async,
+    reset::BootInfo,
+    roles,
+    sampling::{AdcError, NoiseFloor, Reading, Sampler},
+    self_test::SelfTest,
+    serial::{LineReader, LinkMonitor, LinkState, SerialGate},
+    settings::DisplayTimeout,
+    AnyAnalogPin, DisplayPower, DisplayState, DisplayStatus, PanicMessage, Ssd1306Display,
+};
+#[cfg(feature = "keypad")]
+use rust_deej::{
+    buttons::ButtonEvent,
+    globals::{KEYPAD_SCAN_PERIOD, KEYPAD_SETTLE_TIME},
+    keypad::{KeyEvent, Keypad},
+};
+#[cfg(feature = "media-keys")]
+use rust_deej::{
+    globals::{MEDIA_KEY_COUNT, MEDIA_KEY_SCAN_PERIOD},
+    hid::{MediaKeyEvent, MediaKeys},
+};
+
+#[cfg(not(feature = "esp32s3"))]
+use crate::board::HostUartPeripheral;
+use crate::{board, DisplayInterface};
+
+type Display = DisplayState<'static, Ssd1306Display<DisplayInterface>>;
+
+/// Espressif's VID with the PID of their CDC examples
+#[cfg(feature = "esp32s3")]
+const USB_VID_PID: UsbVidPid = UsbVidPid(0x303a, 0x4001);
+/// How often (ms) the USB stack is polled, the host expects answers within a few ms
+#[cfg(feature = "esp32s3")]
+const USB_POLL_PERIOD: u64 = 1;
+
+/// Latest values sent to the host, 0-1023
+static OUTPUT_VALUES: Mutex<CriticalSectionRawMutex, Cell<[u16; OUTPUT_COUNT]>> =
+    Mutex::new(Cell::new([0; OUTPUT_COUNT]));
+/// Whether a host is listening, updated by `serial` on every tick
+static LINK_STATE: Mutex<CriticalSectionRawMutex, Cell<LinkState>> =
+    Mutex::new(Cell::new(LinkState::Unknown));
+/// Latest sample for the display, older ones are skipped
+static SAMPLES: Signal<CriticalSectionRawMutex, Sample> = Signal::new();
+
+/// What the display shows of a sample
+struct Sample {
+    /// Volumes (0-100) after the channel roles
+    volumes: [u16; INPUT_COUNT],
+    /// Pot positions (0-100) before the channel roles
+    positions: [u16; INPUT_COUNT],
+    /// Pot values (0-1023) before the channel roles, the display focuses the one moved last
+    values: [u16; INPUT_COUNT],
+    raw_values: [u16; INPUT_COUNT],
+    disconnected: [bool; INPUT_COUNT],
+}
+/// Host commands that change the display
+static DISPLAY_COMMANDS: Channel<CriticalSectionRawMutex, HostCommand, 4> = Channel::new();
+/// Presses and releases of the keypad for `serial`, which sends their shortcuts
+#[cfg(feature = "keypad")]
+static KEY_EVENTS: Channel<CriticalSectionRawMutex, KeyEvent, 8> = Channel::new();
+/// Presses and releases of the media keys for `serial`, which sends their consumer reports
+#[cfg(feature = "media-keys")]
+static MEDIA_KEY_EVENTS: Channel<CriticalSectionRawMutex, MediaKeyEvent, 8> = Channel::new();
+
+#[main]
+async fn main(spawner: Spawner) {
+    #[cfg(feature = "defmt")]
+    defmt::info!("rust-deej {}", rust_deej::pages::VERSION);
+
+    let peripherals = Peripherals::take();
+    let system = peripherals.SYSTEM.split();
+    let io = IO::new(peripherals.GPIO, peripherals.IO_MUX);
+
+    let mut adc_config = AdcConfig::new();
+
+    let mut pots = board::pots!(io, adc_config);
+
+    let mut adc = ADC::new(peripherals.ADC1, adc_config);
+
+    let clocks = ClockControl::max(system.clock_control).freeze();
+    #[cfg(feature = "esp32c3")]
+    embassy::init(&clocks, SystemTimer::new(peripherals.SYSTIMER));
+    #[cfg(any(feature = "esp32", feature = "esp32s3"))]
+    embassy::init(&clocks, TimerGroup::new(peripherals.TIMG0, &clocks).timer0);
+    let mut delay = Delay::new(&clocks);
+
+    #[cfg(feature = "i2c-bus")]
+    crate::I2C_BUS.set(board::i2c_bus!(peripherals, io, &clocks));
+    let display = board::display!(peripherals, io, &clocks, &mut delay);
+
+    let mut display_state = DisplayState::new(display);
+    display_state.set_title("Volumes");
+    display_state.ready();
+
+    // Previous boot ended in a panic, `serial` tells the host and the splash screen shows it. Same
+    // for every reset, crashes, watchdogs and brown-outs get a banner
+    let panic = rust_deej::panic_persist::take();
+    let boot = rust_deej::reset::read(panic.is_some());
+    display_state.set_boot(boot);
+
+    // Checked before the tasks use the pots, `serial` sends the result to the host
+    let readings = read_pots(&mut adc, &mut pots, &Sampler::new(&CHANNEL_CONFIGS)).await;
+    let timer_runs = {
+        let start = Instant::now();
+        delay.delay_ms(1u32);
+        Instant::now() != start
+    };
+    let self_test = SelfTest::new(display_state.check_display(), &readings, timer_runs);
+    display_state.set_self_test(self_test);
+
+    display_state.show_screen(Screen::Splash);
+    display_state.draw_async().await.unwrap();
+    Timer::after_millis(SPLASH_TIME as u64).await;
+    display_state.show_screen(Screen::SelfTest);
+    display_state.draw_async().await.unwrap();
+    let self_test_time = if self_test.passed() {
+        SELF_TEST_TIME
+    } else {
+        SELF_TEST_FAIL_TIME
+    };
+    Timer::after_millis(self_test_time as u64).await;
+    display_state.show_screen(Screen::Volumes);
+    display_state.draw_async().await.unwrap();
+
+    spawner.must_spawn(sample(adc, pots));
+    spawner.must_spawn(update_display(display_state));
+    #[cfg(feature = "keypad")]
+    {
+        let (rows, cols) = board::keypad!(io);
+        spawner.must_spawn(keypad(rows, cols));
+    }
+    #[cfg(feature = "media-keys")]
+    spawner.must_spawn(media_keys(board::media_keys!(io)));
+
+    // esp_println logs to UART0 as well, at the baud rate set here when the host is on UART0
+    #[cfg(not(feature = "esp32s3"))]
+    {
+        let mut uart = board::host_uart!(peripherals, io, &clocks);
+        uart.set_rx_fifo_full_threshold(1).unwrap();
+        interrupt::enable(board::HOST_UART_INTERRUPT, Priority::Priority1).unwrap();
+        let (tx, rx) = uart.split();
+        spawner.must_spawn(serial(tx, rx, panic, boot, self_test));
+    }
+    #[cfg(feature = "esp32s3")]
+    spawner.must_spawn(serial(
+        board::usb!(peripherals, io),
+        panic,
+        Some(boot),
+        Some(self_test),
+    ));
+}
+
+/// Reads the pots every [MotionDetector::sample_period]
+#[embassy_executor::task]
+async fn sample(mut adc: ADC<'static, ADC1>, mut pots: [AnyAnalogPin; INPUT_COUNT]) {
+    let mut sampler = Sampler::new(&CHANNEL_CONFIGS);
+    let mut motion = MotionDetector::new();
+
+    // Zero cutoff of the pots that are at zero at boot, from their noise
+    let mut noise_floor = NoiseFloor::new();
+    for _ in 0..NOISE_FLOOR_SAMPLES {
+        noise_floor.add(&read_pots(&mut adc, &mut pots, &sampler).await);
+    }
+    sampler.set_zero_cutoffs(&noise_floor.cutoffs());
+
+    loop {
+        let readings = read_pots(&mut adc, &mut pots, &sampler).await;
+        let raw_values = readings.map(|reading| reading.map_or(0, |reading| reading.average));
+        let values = sampler.process_readings(&readings);
+        let outputs = roles::apply(&CHANNEL_CONFIGS, VIRTUAL_CHANNELS, &values);
+        OUTPUT_VALUES.lock(|o| o.set(outputs));
+        SAMPLES.signal(Sample {
+            volumes: roles::channel_values(&CHANNEL_CONFIGS, &outputs).map(wire_to_percent),
+            positions: sampler.positions(),
+            values,
+            raw_values,
+            disconnected: sampler.disconnected(),
+        });
+
+        let now_ms = Instant::now().as_millis();
+        motion.update(&values, now_ms);
+        Timer::after_millis(motion.sample_period(now_ms) as u64).await;
+    }
+}
+
+/// Same as [Sampler::read] with the async reads
+async fn read_pots(
+    adc: &mut ADC<'static, ADC1>,
+    pots: &mut [AnyAnalogPin; INPUT_COUNT],
+    sampler: &Sampler,
+) -> [Result<Reading, AdcError>; INPUT_COUNT] {
+    let mut readings = [Err(AdcError); INPUT_COUNT];
+    for (idx, input) in pots.iter_mut().enumerate() {
+        readings[idx] = read_multi_sample_async(input, adc, sampler.samples(idx)).await;
+    }
+    readings
+}
+
+/// When the display dims after it was drawn, never with [DisplayTimeout::Never]
+fn dim_at(timeout: DisplayTimeout) -> Instant {
+    match timeout {
+        DisplayTimeout::After(secs) => Instant::now() + Duration::from_secs(secs as u64),
+        DisplayTimeout::Never | DisplayTimeout::AlwaysOff => Instant::MAX,
+    }
+}
+
+/// Redraws on new samples, host commands and animation frames. Dims the display after the
+/// timeout, [DISPLAY_ON_TIME] until the host sets another, and turns it off after
+/// [DISPLAY_OFF_DELAY]. A display that went offline is set up again on a change once
+/// [DISPLAY_RETRY_DELAY] has passed, twice as long after every attempt it does not answer.
+#[embassy_executor::task]
+async fn update_display(mut display: Display) {
+    let mut frames = Ticker::every(Duration::from_millis(DISPLAY_UPDATE_PERIOD as u64));
+    let mut timeout = DisplayTimeout::After(DISPLAY_ON_TIME);
+    let mut off_at = dim_at(timeout);
+    let mut retry_delay = DISPLAY_RETRY_DELAY;
+    let mut retry_at = Instant::MIN;
+    let mut activity = ChannelActivity::new();
+    // Pot values reported to the activity, none at first so every channel is reported with the
+    // first sample
+    let mut last_values = [u16::MAX; INPUT_COUNT];
+    loop {
+        let animated = display.is_animated();
+        let next_frame = async {
+            if animated {
+                frames.next().await
+            } else {
+                core::future::pending().await
+            }
+        };
+        let changed = match select4(
+            SAMPLES.wait(),
+            DISPLAY_COMMANDS.receive(),
+            next_frame,
+            Timer::at(off_at),
+        )
+        .await
+        {
+            Either4::First(sample) => {
+                let now_ms = Instant::now().as_millis();
+                // Same threshold as the serial gate, a jittering pot does not keep the focus
+                for (channel, (&value, last)) in
+                    sample.values.iter().zip(&mut last_values).enumerate()
+                {
+                    if value.abs_diff(*last) >= SERIAL_CHANGE_THRESHOLD {
+                        *last = value;
+                        activity.handle(&Event::InputChanged { channel, value }, now_ms);
+                    }
+                }
+                display
+                    .set_volumes(&sample.volumes)
+                    .or(display.set_focus(activity.focused(now_ms)))
+                    .or(display.set_positions(&sample.positions))
+                    .or(display.set_raw_values(&sample.raw_values))
+                    .or(display.set_disconnected(&sample.disconnected))
+                    .or(display.set_status(LINK_STATE.lock(Cell::get).label()))
+                    .or(display.tick(now_ms))
+            }
+            Either4::Second(HostCommand::DisplayTimeout(new)) => {
+                timeout = new;
+                DisplayStatus::Changed
+            }
+            Either4::Second(command) => display.handle_host_command(command),
+            Either4::Third(()) => display.animate(),
+            Either4::Fourth(()) => match display.dim_or_turn_off() {
+                DisplayPower::Dimmed => {
+                    off_at = Instant::now() + Duration::from_secs(DISPLAY_OFF_DELAY as u64);
+                    DisplayStatus::NotChanged
+                }
+                // Now playing page is shown instead, drawing it restarts the timer
+                DisplayPower::On => DisplayStatus::Changed,
+                DisplayPower::Off => {
+                    off_at = Instant::MAX;
+                    DisplayStatus::NotChanged
+                }
+            },
+        };
+        if let DisplayStatus::Changed = changed {
+            if display.is_offline() {
+                // Waiting here would hold up the serial task sending host commands
+                if Instant::now() < retry_at {
+                    continue;
+                }
+                if !display.reinit() {
+                    retry_delay = (retry_delay * 2).min(DISPLAY_RETRY_MAX_DELAY);
+                    retry_at = Instant::now() + Duration::from_millis(retry_delay as u64);
+                    continue;
+                }
+            }
+            if display.keep_off(timeout) {
+                off_at = Instant::MAX;
+                continue;
+            }
+            display.draw_async().await.unwrap();
+            off_at = dim_at(timeout);
+            if display.is_offline() {
+                retry_delay = DISPLAY_RETRY_DELAY;
+                retry_at = Instant::now() + Duration::from_millis(retry_delay as u64);
+            }
+        }
+    }
+}
+
+/// Protocol state of the host link, shared by the UART and the USB variants of `serial`
+struct HostLink {
+    serial_gate: SerialGate,
+    line_reader: LineReader,
+    protocol_mode: ProtocolMode,
+    monitor: LinkMonitor,
+}
+
+impl HostLink {
+    fn new() -> Self {
+        Self {
+            serial_gate: SerialGate::new(
+                SERIAL_CHANGE_THRESHOLD,
+                SERIAL_KEEP_ALIVE_PERIOD / SERIAL_UPDATE_PERIOD,
+            ),
+            line_reader: LineReader::new(),
+            protocol_mode: ProtocolMode::default(),
+            monitor: LinkMonitor::new(),
+        }
+    }
+
+    /// Frame to send on this tick, if the values have changed or the keep-alive period has passed.
+    /// Also publishes the [LinkState] for the display.
+    fn frame(&mut self) -> Option<Frame> {
+        let now_ms = Instant::now().as_millis();
+        LINK_STATE.lock(|state| state.set(self.monitor.state(now_ms)));
+        if !self.monitor.should_send(now_ms) {
+            return None;
+        }
+        let values = OUTPUT_VALUES.lock(Cell::get);
+        self.serial_gate
+            .should_send(&values)
+            .then(|| protocol::encode(self.protocol_mode, &values))
+    }
+
+    /// Handles the commands in the bytes received from the host, replies are passed to `write`
+    async fn receive(&mut self, bytes: &[u8], mut write: impl FnMut(&[u8])) {
+        // Any traffic counts, even empty lines
+        self.monitor.received(Instant::now().as_millis());
+        for byte in bytes {
+            let Some(line) = self.line_reader.push(*byte) else {
+                continue;
+            };
+            // Settings and the raw readings are only shared between the tasks of the RTIC app
+            if let Some(command) = cli::parse(&line) {
+                match command {
+                    Ok(CliCommand::Help) => write(cli::HELP.as_bytes()),
+                    Ok(CliCommand::Reboot) => esp_hal::reset::software_reset(),
+                    Ok(_) => write(b"!error not supported by the embassy app\r\n"),
+                    Err(error) => write(error.as_bytes()),
+                }
+                continue;
+            }
+            match protocol::parse_command(&line) {
+                Some(HostCommand::Hello) => write(protocol::encode_hello(CAPABILITIES).as_bytes()),
+                Some(HostCommand::SetMode(mode)) => self.protocol_mode = mode,
+                // Firmware updates need the Wi-Fi stack, which is only in the RTIC app
+                Some(HostCommand::Ota) => (),
+                Some(HostCommand::Bootloader) => {
+                    #[cfg(not(feature = "esp32"))]
+                    board::reboot_to_bootloader();
+                }
+                Some(command) => DISPLAY_COMMANDS.send(command).await,
+                None => (),
+            }
+        }
+    }
+}
+
+/// Sends the values to the host when they have changed or the keep-alive period has passed and
+/// handles the commands sent by the host. `panic` is the report of a panic before the reset, sent
+/// first, followed by `boot` and `self_test`.
+#[cfg(not(feature = "esp32s3"))]
+#[embassy_executor::task]
+async fn serial(
+    mut tx: UartTx<'static, HostUartPeripheral>,
+    mut rx: UartRx<'static, HostUartPeripheral>,
+    panic: Option<PanicMessage>,
+    boot: BootInfo,
+    self_test: SelfTest,
+) {
+    if let Some(message) = panic {
+        tx.write_bytes(protocol::encode_panic(&message).as_bytes())
+            .ok();
+    }
+    tx.write_bytes(protocol::encode_reset(&boot).as_bytes())
+        .ok();
+    tx.write_bytes(protocol::encode_self_test(&self_test).as_bytes())
+        .ok();
+    let mut ticker = Ticker::every(Duration::from_millis(SERIAL_UPDATE_PERIOD as u64));
+    let mut link = HostLink::new();
+    let mut buf = [0u8; 16];
+    loop {
+        match select(ticker.next(), rx.read(&mut buf)).await {
+            Either::First(()) => {
+                if let Some(frame) = link.frame() {
+                    tx.write_bytes(frame.as_bytes()).ok();
+                }
+            }
+            Either::Second(Ok(len)) => {
+                link.receive(&buf[..len], |reply| {
+                    tx.write_bytes(reply).ok();
+                })
+                .await
+            }
+            Either::Second(Err(_)) => (),
+        }
+    }
+}
+
+/// Same as the UART variant but over the USB-OTG CDC port. Frames are dropped and the host is
+/// shown as gone while it has not opened the port. `panic` is the report of a panic before the
+/// reset, `boot` the reset itself and `self_test` the result of the boot self-test, all sent once it
+/// is open. With `usb-midi` the MIDI messages go out on a USB-MIDI port
+/// instead, with `gamepad` the values are also reported as the axes of a HID gamepad, with
+/// `keypad` the keys send their shortcuts as a HID keyboard and with `media-keys` the buttons send
+/// consumer control reports.
+#[cfg(feature = "esp32s3")]
+#[embassy_executor::task]
+async fn serial(
+    usb: Usb<'static>,
+    mut panic: Option<PanicMessage>,
+    mut boot: Option<BootInfo>,
+    mut self_test: Option<SelfTest>,
+) {
+    static mut EP_MEMORY: [u32; 1024] = [0; 1024];
+    // The task is spawned once, so nothing else uses the endpoint memory
+    let usb_bus = UsbBus::new(usb, unsafe { &mut *addr_of_mut!(EP_MEMORY) });
+    let mut port = SerialPort::new(&usb_bus);
+    #[cfg(feature = "usb-midi")]
+    let mut midi_port = UsbMidiClass::new(&usb_bus, 1, 1).unwrap();
+    #[cfg(feature = "gamepad")]
+    let mut gamepad = HIDClass::new(
+        &usb_bus,
+        hid::GAMEPAD_REPORT_DESCRIPTOR,
+        SERIAL_UPDATE_PERIOD as u8,
+    );
+    #[cfg(feature = "keypad")]
+    let mut keyboard = HIDClass::new(
+        &usb_bus,
+        hid::KEYBOARD_REPORT_DESCRIPTOR,
+        KEYPAD_SCAN_PERIOD as u8,
+    );
+    #[cfg(feature = "media-keys")]
+    let mut consumer = HIDClass::new(
+        &usb_bus,
+        hid::CONSUMER_REPORT_DESCRIPTOR,
+        MEDIA_KEY_SCAN_PERIOD as u8,
+    );
+    let builder = UsbDeviceBuilder::new(&usb_bus, USB_VID_PID)
+        .strings(&[StringDescriptors::default()
+            .manufacturer("rust-deej")
+            .product("rust-deej")])
+        .unwrap();
+    // The host tells the CDC, MIDI and HID functions of a composite device apart by their
+    // interface associations
+    #[cfg(any(
+        feature = "usb-midi",
+        feature = "gamepad",
+        feature = "keypad",
+        feature = "media-keys"
+    ))]
+    let mut usb_dev = builder.composite_with_iads().build();
+    #[cfg(not(any(
+        feature = "usb-midi",
+        feature = "gamepad",
+        feature = "keypad",
+        feature = "media-keys"
+    )))]
+    let mut usb_dev = builder.device_class(usbd_serial::USB_CLASS_CDC).build();
+
+    let mut ticker = Ticker::every(Duration::from_millis(SERIAL_UPDATE_PERIOD as u64));
+    let mut usb_poll = Ticker::every(Duration::from_millis(USB_POLL_PERIOD));
+    let mut link = HostLink::new();
+    let mut buf = [0u8; 16];
+    #[cfg(feature = "gamepad")]
+    let mut last_report = None;
+    // Keyboard report not taken by the host yet, the next key event waits for it
+    #[cfg(feature = "keypad")]
+    let mut pending_key = None;
+    // Same for the consumer reports of the media keys
+    #[cfg(feature = "media-keys")]
+    let mut pending_media_key = None;
+    loop {
+        match select(ticker.next(), usb_poll.next()).await {
+            Either::First(()) => {
+                // Games read the axes whether or not anything has the CDC port open
+                #[cfg(feature = "gamepad")]
+                {
+                    let report = hid::gamepad_report(&OUTPUT_VALUES.lock(Cell::get));
+                    // Sent again on the next tick when the host has not taken the previous one yet
+                    if last_report != Some(report) && gamepad.push_raw_input(&report).is_ok() {
+                        last_report = Some(report);
+                    }
+                }
+                // A DAW reads the MIDI port without ever opening the CDC port
+                #[cfg(feature = "usb-midi")]
+                link.monitor
+                    .set_port_open(port.dtr() || link.protocol_mode == ProtocolMode::Midi);
+                #[cfg(not(feature = "usb-midi"))]
+                link.monitor.set_port_open(port.dtr());
+                if port.dtr() {
+                    if let Some(message) = panic.take() {
+                        port.write(protocol::encode_panic(&message).as_bytes()).ok();
+                    }
+                    if let Some(boot) = boot.take() {
+                        port.write(protocol::encode_reset(&boot).as_bytes()).ok();
+                    }
+                    if let Some(test) = self_test.take() {
+                        port.write(protocol::encode_self_test(&test).as_bytes())
+                            .ok();
+                    }
+                }
+                match link.frame() {
+                    #[cfg(feature = "usb-midi")]
+                    Some(Frame::Midi(messages)) => {
+                        for packet in midi::usb_packets(&messages) {
+                            midi_port.send_bytes(packet).ok();
+                        }
+                    }
+                    Some(frame) => {
+                        port.write(frame.as_bytes()).ok();
+                    }
+                    None => (),
+                }
+            }
+            Either::Second(()) => {
+                let polled = usb_dev.poll(&mut [
+                    &mut port,
+                    #[cfg(feature = "usb-midi")]
+                    &mut midi_port,
+                    #[cfg(feature = "gamepad")]
+                    &mut gamepad,
+                    #[cfg(feature = "keypad")]
+                    &mut keyboard,
+                    #[cfg(feature = "media-keys")]
+                    &mut consumer,
+                ]);
+                #[cfg(feature = "keypad")]
+                {
+                    if pending_key.is_none() {
+                        pending_key = KEY_EVENTS.try_receive().ok().map(|key| {
+                            let pressed = key.event == ButtonEvent::Pressed;
+                            hid::keyboard_report(pressed.then(|| key.shortcut()))
+                        });
+                    }
+                    if let Some(report) = pending_key {
+                        if keyboard.push_raw_input(&report).is_ok() {
+                            pending_key = None;
+                        }
+                    }
+                }
+                #[cfg(feature = "media-keys")]
+                {
+                    if pending_media_key.is_none() {
+                        pending_media_key =
+                            MEDIA_KEY_EVENTS.try_receive().ok().map(|key| key.report());
+                    }
+                    if let Some(report) = pending_media_key {
+                        if consumer.push_raw_input(&report).is_ok() {
+                            pending_media_key = None;
+                        }
+                    }
+                }
+                if !polled {
+                    continue;
+                }
+                if let Ok(len) = port.read(&mut buf) {
+                    link.receive(&buf[..len], |reply| {
+                        port.write(reply).ok();
+                    })
+                    .await;
+                }
+            }
+        }
+    }
+}
+
+/// Scans the key matrix every [KEYPAD_SCAN_PERIOD] and queues the presses and releases for `serial`
+#[cfg(feature = "keypad")]
+#[embassy_executor::task]
+async fn keypad(mut rows: board::KeypadRows, cols: board::KeypadCols) {
+    for row in rows.iter_mut() {
+        row.set_high().unwrap();
+    }
+    let mut keypad = Keypad::new();
+    let mut ticker = Ticker::every(Duration::from_millis(KEYPAD_SCAN_PERIOD));
+    loop {
+        ticker.next().await;
+        for (idx, row) in rows.iter_mut().enumerate() {
+            row.set_low().unwrap();
+            Timer::after_micros(KEYPAD_SETTLE_TIME).await;
+            let pressed = core::array::from_fn(|col| cols[col].is_low().unwrap());
+            row.set_high().unwrap();
+            for event in keypad.update_row(idx, pressed) {
+                KEY_EVENTS.send(event).await;
+            }
+        }
+    }
+}
+
+/// Reads the media keys every [MEDIA_KEY_SCAN_PERIOD] and queues the presses and releases for
+/// `serial`
+#[cfg(feature = "media-keys")]
+#[embassy_executor::task]
+async fn media_keys(pins: board::MediaKeyPins) {
+    let mut keys = MediaKeys::<MEDIA_KEY_COUNT>::new();
+    let mut ticker = Ticker::every(Duration::from_millis(MEDIA_KEY_SCAN_PERIOD));
+    loop {
+        ticker.next().await;
+        let pressed = core::array::from_fn(|idx| pins[idx].is_low().unwrap());
+        for event in keys.update(pressed) {
+            MEDIA_KEY_EVENTS.send(event).await;
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Rotary encoder with a push switch, navigates the settings menu. The A and B outputs are read on
+//! every edge and decoded into detents, so no step is lost however fast it is turned.
+
+use crate::globals::ENCODER_STEPS_PER_DETENT;
+
+/// Change of the position for each transition from the previous A/B state (high bits of the index)
+/// to the new one (low bits). Invalid transitions, e.g. a missed edge, count as no change.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Turns the raw states of the A and B outputs into detents, positive clockwise
+pub struct QuadratureDecoder {
+    state: u8,
+    steps: i8,
+}
+
+impl QuadratureDecoder {
+    /// Both outputs are high at a detent with the pull-ups
+    pub fn new() -> Self {
+        Self {
+            state: 0b11,
+            steps: 0,
+        }
+    }
+
+    /// Give the states of A and B after an edge, returns the detents turned since the last call
+    pub fn update(&mut self, a: bool, b: bool) -> i32 {
+        let state = (a as u8) << 1 | b as u8;
+        self.steps += TRANSITIONS[(self.state << 2 | state) as usize];
+        self.state = state;
+
+        let detents = self.steps / ENCODER_STEPS_PER_DETENT;
+        self.steps %= ENCODER_STEPS_PER_DETENT;
+        detents as i32
+    }
+}
+
+impl Default for QuadratureDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// States of A and B through a full cycle clockwise, starting after the rest state
+    const CLOCKWISE: [(bool, bool); 4] =
+        [(false, true), (false, false), (true, false), (true, true)];
+
+    fn turn(
+        decoder: &mut QuadratureDecoder,
+        states: impl IntoIterator<Item = (bool, bool)>,
+    ) -> i32 {
+        states.into_iter().map(|(a, b)| decoder.update(a, b)).sum()
+    }
+
+    #[test]
+    fn counts_detents_in_both_directions() {
+        let cycle = 4 / ENCODER_STEPS_PER_DETENT as i32;
+        let mut decoder = QuadratureDecoder::new();
+        assert_eq!(
+            turn(&mut decoder, CLOCKWISE.into_iter().cycle().take(8)),
+            2 * cycle
+        );
+
+        // Same states the other way round
+        let counter_clockwise = CLOCKWISE.into_iter().rev().skip(1).chain([(true, true)]);
+        assert_eq!(turn(&mut decoder, counter_clockwise), -cycle);
+
+        // Bounce on one output moves back and forth without adding up
+        let bounce = [(false, true), (true, true), (false, true), (true, true)];
+        assert_eq!(turn(&mut decoder, bounce), 0);
+    }
+}
@@ -0,0 +1,10 @@
+//! Errors of the firmware itself. The drivers of the peripherals have their own, e.g.
+//! [crate::sampling::AdcError].
+
+/// What went wrong in the firmware rather than on a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeejError {
+    /// Text did not fit its buffer. What fit is kept, ending in [crate::text::ELLIPSIS].
+    Format,
+}
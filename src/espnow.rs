@@ -0,0 +1,47 @@
+use esp_hal::peripherals::WIFI;
+use esp_wifi::{
+    esp_now::{EspNow, BROADCAST_ADDRESS},
+    EspWifiInitialization,
+};
+
+use crate::{
+    globals::OUTPUT_COUNT,
+    protocol::{decode_binary, encode_binary},
+};
+
+/// Link between a remote unit reading the pots and a dongle unit connected to the PC.
+///
+/// Values are sent as [binary frames](crate::protocol::encode_binary) in both roles.
+pub struct EspNowLink {
+    pub init: EspWifiInitialization,
+    pub wifi: WIFI,
+}
+
+impl EspNowLink {
+    /// Remote role. `poll` is called continuously and every value set it returns is broadcast.
+    pub fn run_remote(&mut self, mut poll: impl FnMut() -> Option<[u16; OUTPUT_COUNT]>) -> ! {
+        let mut esp_now = EspNow::new(&self.init, &mut self.wifi).unwrap();
+        loop {
+            let Some(values) = poll() else {
+                continue;
+            };
+            // Nobody may be listening, a lost frame is replaced by the next one
+            if let Ok(waiter) = esp_now.send(&BROADCAST_ADDRESS, &encode_binary(&values)) {
+                let _ = waiter.wait();
+            }
+        }
+    }
+
+    /// Dongle role. `on_values` is called with the values of every valid frame received.
+    pub fn run_dongle(&mut self, mut on_values: impl FnMut(&[u16; OUTPUT_COUNT])) -> ! {
+        let mut esp_now = EspNow::new(&self.init, &mut self.wifi).unwrap();
+        loop {
+            if let Some(values) = esp_now
+                .receive()
+                .and_then(|received| decode_binary(received.get_data()))
+            {
+                on_values(&values);
+            }
+        }
+    }
+}
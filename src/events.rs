@@ -0,0 +1,147 @@
+//! Events passed between the tasks of the RTIC app. The producers, e.g. idle with the samples and
+//! the gestures or the interrupts receiving from the host, [EventBus::publish] them. Every
+//! [Consumer] task reads all of them in order with [EventBus::next], at its own pace.
+
+use heapless::Deque;
+
+use crate::{
+    gestures::Gesture, globals::EVENT_QUEUE_LEN, protocol::HostCommand, status_led::StatusEvent,
+};
+
+/// [EventBus] of the RTIC app
+pub type Events = EventBus<EVENT_QUEUE_LEN>;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// Value (0-1023) of a pot changed, before the channel roles
+    InputChanged { channel: usize, value: u16 },
+    /// Gesture of the page button
+    ButtonPressed(Gesture),
+    /// Command from the host for the consumers, e.g. the names and icons for the display
+    HostMessage(HostCommand),
+    /// A host started talking to the mixer
+    LinkUp,
+    /// The host closed the port or went silent, see [crate::serial::LinkMonitor]
+    LinkDown,
+}
+
+impl Event {
+    /// What the status LED shows for the event
+    pub fn status_event(&self) -> Option<StatusEvent> {
+        match self {
+            Event::LinkUp => Some(StatusEvent::HostConnected),
+            Event::LinkDown => Some(StatusEvent::HostDisconnected),
+            _ => None,
+        }
+    }
+}
+
+/// Task reading the [EventBus]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Consumer {
+    Display,
+    Serial,
+    Leds,
+}
+
+impl Consumer {
+    const COUNT: usize = 3;
+}
+
+/// Queue of [Event]s every [Consumer] reads. An event is kept until all of them have read it, a
+/// consumer more than `N` events behind misses the oldest ones.
+pub struct EventBus<const N: usize> {
+    events: Deque<Event, N>,
+    /// Events at the back of [EventBus::events] each consumer has not read yet
+    unread: [usize; Consumer::COUNT],
+}
+
+impl<const N: usize> Default for EventBus<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> EventBus<N> {
+    pub const fn new() -> Self {
+        Self {
+            events: Deque::new(),
+            unread: [0; Consumer::COUNT],
+        }
+    }
+
+    /// Queues `event` for every consumer. Drops the oldest event when the queue is full.
+    pub fn publish(&mut self, event: Event) {
+        if self.events.is_full() {
+            self.events.pop_front();
+        }
+        // A full queue has room for the new event again
+        self.events.push_back(event).ok();
+        for unread in &mut self.unread {
+            *unread = (*unread + 1).min(self.events.len());
+        }
+    }
+
+    /// Oldest event `consumer` has not read yet
+    pub fn next(&mut self, consumer: Consumer) -> Option<Event> {
+        let unread = &mut self.unread[consumer as usize];
+        if *unread == 0 {
+            return None;
+        }
+        let event = self.events.iter().nth(self.events.len() - *unread).cloned();
+        *unread -= 1;
+        // Events every consumer has read are not needed anymore
+        let needed = self.unread.iter().copied().max().unwrap_or(0);
+        while self.events.len() > needed {
+            self.events.pop_front();
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(value: u16) -> Event {
+        Event::InputChanged { channel: 0, value }
+    }
+
+    #[test]
+    fn every_consumer_reads_every_event() {
+        let mut bus = EventBus::<4>::new();
+        bus.publish(Event::LinkUp);
+        bus.publish(Event::ButtonPressed(Gesture::Tap));
+        assert_eq!(bus.next(Consumer::Display), Some(Event::LinkUp));
+        assert_eq!(bus.next(Consumer::Leds), Some(Event::LinkUp));
+        bus.publish(Event::LinkDown);
+        assert_eq!(
+            bus.next(Consumer::Display),
+            Some(Event::ButtonPressed(Gesture::Tap))
+        );
+        assert_eq!(bus.next(Consumer::Display), Some(Event::LinkDown));
+        assert_eq!(bus.next(Consumer::Display), None);
+        assert_eq!(bus.next(Consumer::Serial), Some(Event::LinkUp));
+        // Only what the serial task and the LEDs have not read is kept
+        assert_eq!(bus.events.len(), 2);
+        assert_eq!(
+            bus.next(Consumer::Leds),
+            Some(Event::ButtonPressed(Gesture::Tap))
+        );
+    }
+
+    #[test]
+    fn slow_consumer_misses_the_oldest() {
+        let mut bus = EventBus::<3>::new();
+        for value in 0..5 {
+            bus.publish(input(value));
+            assert_eq!(bus.next(Consumer::Display), Some(input(value)));
+            assert_eq!(bus.next(Consumer::Leds), Some(input(value)));
+        }
+        assert_eq!(bus.next(Consumer::Serial), Some(input(2)));
+        assert_eq!(bus.next(Consumer::Serial), Some(input(3)));
+        assert_eq!(bus.next(Consumer::Serial), Some(input(4)));
+        assert_eq!(bus.next(Consumer::Serial), None);
+        assert!(bus.events.is_empty());
+    }
+}
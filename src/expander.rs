@@ -0,0 +1,169 @@
+//! Mute buttons and LEDs on an MCP23017 GPIO expander on [crate::i2c_bus], so a build with a
+//! button and an LED per channel does not run out of pins on the ESP32-C3. The button of a channel
+//! toggles its mute and its LED is lit while the channel is muted, here or on the PC.
+
+use embedded_hal_027::blocking::i2c::{Write, WriteRead};
+
+use crate::{
+    buttons::{ButtonEvent, Debouncer},
+    globals::INPUT_COUNT,
+    log::debug,
+};
+
+/// The expander did not acknowledge
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExpanderError;
+
+/// MCP23017 in its default register layout, port B follows port A. Pins 0-7 are GPA0-7 and pins
+/// 8-15 GPB0-7, bit `n` of the levels is pin `n`.
+pub struct Mcp23017<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: Write + WriteRead> Mcp23017<I> {
+    /// Set bits are inputs
+    const IODIR: u8 = 0x00;
+    /// Set bits enable the 100 kOhm pull-up
+    const GPPU: u8 = 0x0c;
+    const GPIO: u8 = 0x12;
+    const OLAT: u8 = 0x14;
+
+    /// `address` is 0x20 plus the levels of A2-A0
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    /// Makes the pins of `inputs` inputs with pull-ups and every other pin an output driven low
+    pub fn configure(&mut self, inputs: u16) -> Result<(), ExpanderError> {
+        // Low before they turn into outputs, so no LED flashes
+        self.write(Self::OLAT, 0)?;
+        self.write(Self::GPPU, inputs)?;
+        self.write(Self::IODIR, inputs)
+    }
+
+    /// Levels of every pin, the outputs read back as driven
+    pub fn read_pins(&mut self) -> Result<u16, ExpanderError> {
+        let mut levels = [0; 2];
+        self.i2c
+            .write_read(self.address, &[Self::GPIO], &mut levels)
+            .map_err(|_| ExpanderError)?;
+        Ok(u16::from_le_bytes(levels))
+    }
+
+    /// Drives the outputs, the bits of the inputs are ignored
+    pub fn write_outputs(&mut self, levels: u16) -> Result<(), ExpanderError> {
+        self.write(Self::OLAT, levels)
+    }
+
+    fn write(&mut self, register: u8, value: u16) -> Result<(), ExpanderError> {
+        let [a, b] = value.to_le_bytes();
+        self.i2c
+            .write(self.address, &[register, a, b])
+            .map_err(|_| ExpanderError)
+    }
+}
+
+/// Mute of each channel, toggled by its button and shown by its LED
+pub struct MuteButtons {
+    /// Expander pin of the button of each channel
+    pins: [Option<u8>; INPUT_COUNT],
+    /// Expander pin of the LED of each channel
+    leds: [Option<u8>; INPUT_COUNT],
+    buttons: [Debouncer; INPUT_COUNT],
+    muted: [bool; INPUT_COUNT],
+}
+
+impl MuteButtons {
+    /// Takes the pins of the buttons and the LEDs, e.g. [crate::globals::EXPANDER_BUTTONS] and
+    /// [crate::globals::EXPANDER_LEDS]
+    pub fn new(pins: [Option<u8>; INPUT_COUNT], leds: [Option<u8>; INPUT_COUNT]) -> Self {
+        Self {
+            pins,
+            leds,
+            buttons: core::array::from_fn(|_| Debouncer::new()),
+            muted: [false; INPUT_COUNT],
+        }
+    }
+
+    /// Pins of the buttons, for [Mcp23017::configure]
+    pub fn inputs(&self) -> u16 {
+        pin_mask(&self.pins, &[true; INPUT_COUNT])
+    }
+
+    /// Give the levels read from the expander, a pressed button pulls its pin low
+    pub fn update(&mut self, levels: u16) {
+        for (channel, (button, pin)) in self.buttons.iter_mut().zip(self.pins).enumerate() {
+            if let Some(pin) = pin {
+                if button.update(levels & 1 << pin == 0) == Some(ButtonEvent::Pressed) {
+                    self.muted[channel] = !self.muted[channel];
+                    debug!("Channel {} muted: {}", channel, self.muted[channel]);
+                }
+            }
+        }
+    }
+
+    /// Sends the muted channels as 0, like the muted channels of a profile
+    pub fn apply(&self, values: &mut [u16; INPUT_COUNT]) {
+        for (value, muted) in values.iter_mut().zip(self.muted) {
+            if muted {
+                *value = 0;
+            }
+        }
+    }
+
+    /// Output levels lighting the LED of each channel muted here or in `host_mutes`
+    pub fn leds(&self, host_mutes: &[bool; INPUT_COUNT]) -> u16 {
+        let muted: [bool; INPUT_COUNT] =
+            core::array::from_fn(|channel| self.muted[channel] || host_mutes[channel]);
+        pin_mask(&self.leds, &muted)
+    }
+}
+
+/// Bits of the pins of the channels in `selected`
+fn pin_mask(pins: &[Option<u8>; INPUT_COUNT], selected: &[bool; INPUT_COUNT]) -> u16 {
+    pins.iter()
+        .zip(selected)
+        .filter_map(|(pin, selected)| pin.filter(|_| *selected))
+        .fold(0, |mask, pin| mask | 1 << pin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buttons::DEBOUNCE_SAMPLES;
+
+    #[test]
+    fn button_toggles_the_mute_of_its_channel() {
+        // Button of the first channel on GPA0 and its LED on GPB0
+        let mut pins = [None; INPUT_COUNT];
+        pins[0] = Some(0);
+        let mut leds = [None; INPUT_COUNT];
+        leds[0] = Some(8);
+        let mut buttons = MuteButtons::new(pins, leds);
+        let released = buttons.inputs();
+        assert_eq!(released, 0x0001);
+
+        let press = |buttons: &mut MuteButtons| {
+            for levels in [released & !1, released] {
+                for _ in 0..DEBOUNCE_SAMPLES {
+                    buttons.update(levels);
+                }
+            }
+        };
+        press(&mut buttons);
+        let mut values = [512; INPUT_COUNT];
+        buttons.apply(&mut values);
+        assert_eq!(values[0], 0);
+        assert_eq!(buttons.leds(&[false; INPUT_COUNT]), 0x0100);
+
+        press(&mut buttons);
+        let mut values = [512; INPUT_COUNT];
+        buttons.apply(&mut values);
+        assert_eq!(values, [512; INPUT_COUNT]);
+        assert_eq!(buttons.leds(&[false; INPUT_COUNT]), 0);
+        // Lit for a mute on the PC as well
+        assert_eq!(buttons.leds(&[true; INPUT_COUNT]), 0x0100);
+    }
+}
@@ -0,0 +1,56 @@
+use embedded_hal_027::digital::v2::OutputPin;
+
+use crate::globals::{FEEDBACK_DETENTS, FEEDBACK_PULSE_TIME, INPUT_COUNT};
+
+/// Pulses an active buzzer or a vibration motor (through a transistor) when a channel arrives at
+/// one of the [FEEDBACK_DETENTS]
+pub struct Feedback<P> {
+    pin: P,
+    /// Volumes 0-100 the detents are compared against, small jitter is ignored.
+    /// `None` until the first sample so the pots do not click at boot.
+    volumes: Option<[u16; INPUT_COUNT]>,
+    pulse_until: Option<u64>,
+}
+
+impl<P: OutputPin> Feedback<P> {
+    pub fn new(mut pin: P) -> Self {
+        pin.set_low().ok();
+        Self {
+            pin,
+            volumes: None,
+            pulse_until: None,
+        }
+    }
+
+    /// Call after every sample. The pulse ends on the first call after [FEEDBACK_PULSE_TIME].
+    pub fn update(&mut self, volumes: &[u16; INPUT_COUNT], now_ms: u64) {
+        if self.pulse_until.is_some_and(|until_ms| now_ms >= until_ms) {
+            self.pin.set_low().ok();
+            self.pulse_until = None;
+        }
+
+        let Some(previous_volumes) = &mut self.volumes else {
+            self.volumes = Some(*volumes);
+            return;
+        };
+        let mut arrived = false;
+        for (previous, vol) in previous_volumes.iter_mut().zip(volumes) {
+            if vol.abs_diff(*previous) > 1 {
+                arrived |= FEEDBACK_DETENTS
+                    .iter()
+                    .any(|detent| reaches(*previous, *vol, *detent));
+                *previous = *vol;
+            }
+        }
+
+        if arrived {
+            self.pin.set_high().ok();
+            self.pulse_until = Some(now_ms + FEEDBACK_PULSE_TIME);
+        }
+    }
+}
+
+/// Moving onto or over `detent` counts, moving away from it does not
+fn reaches(previous: u16, volume: u16, detent: u16) -> bool {
+    (previous < detent && volume >= detent) || (previous > detent && volume <= detent)
+}
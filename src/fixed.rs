@@ -0,0 +1,212 @@
+//! Fixed-point math for the curves of the firmware: the taper of the pots, the gamma of the LEDs
+//! and the easing of the bars. The ESP32-C3 has no FPU, so fractions are kept as [Q16] and curves
+//! as a few points interpolated in between, see [Curve].
+
+/// Bits of [Q16] below the point
+const FRACTION_BITS: u32 = 16;
+
+/// Unsigned Q16.16 number, 16 bits before and 16 bits after the point. Products are worked out
+/// in `u64` and saturate at [Q16::MAX] instead of wrapping.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct Q16(u32);
+
+impl Q16 {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1 << FRACTION_BITS);
+    pub const MAX: Self = Self(u32::MAX);
+
+    pub const fn from_int(value: u16) -> Self {
+        Self((value as u32) << FRACTION_BITS)
+    }
+
+    /// `numerator / denominator` rounded to the nearest, [Q16::MAX] if it does not fit or
+    /// `denominator` is 0
+    pub const fn from_ratio(numerator: u32, denominator: u32) -> Self {
+        if denominator == 0 {
+            return Self::MAX;
+        }
+        let denominator = denominator as u64;
+        Self::saturate(
+            ((numerator as u64) << FRACTION_BITS).saturating_add(denominator / 2) / denominator,
+        )
+    }
+
+    const fn saturate(bits: u64) -> Self {
+        if bits > u32::MAX as u64 {
+            Self::MAX
+        } else {
+            Self(bits as u32)
+        }
+    }
+
+    /// Integer part, rounded down
+    pub const fn floor(self) -> u32 {
+        self.0 >> FRACTION_BITS
+    }
+
+    /// Rounded to the nearest integer, halves up
+    pub const fn round(self) -> u32 {
+        ((self.0 as u64 + (1 << (FRACTION_BITS - 1))) >> FRACTION_BITS) as u32
+    }
+
+    /// Product rounded to the nearest [Q16]
+    pub const fn mul(self, other: Self) -> Self {
+        let product = self.0 as u64 * other.0 as u64;
+        Self::saturate((product + (1 << (FRACTION_BITS - 1))) >> FRACTION_BITS)
+    }
+
+    /// `value` times `self`, rounded to the nearest integer
+    pub const fn scale(self, value: u32) -> u32 {
+        let product = self.0 as u64 * value as u64 + (1 << (FRACTION_BITS - 1));
+        let scaled = product >> FRACTION_BITS;
+        if scaled > u32::MAX as u64 {
+            u32::MAX
+        } else {
+            scaled as u32
+        }
+    }
+
+    pub const fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    /// Moves `1/2^shift` of the way toward `target`, the step of an exponential moving average
+    pub const fn approach(self, target: Self, shift: u8) -> Self {
+        if target.0 >= self.0 {
+            Self(self.0 + ((target.0 - self.0) >> shift))
+        } else {
+            Self(self.0 - ((self.0 - target.0) >> shift))
+        }
+    }
+}
+
+/// Piecewise-linear curve through points sorted by x. Clamped to the first and last point outside
+/// of them.
+pub struct Curve<'a>(&'a [(Q16, Q16)]);
+
+impl<'a> Curve<'a> {
+    /// `points` must be sorted by x and hold at least one point
+    pub const fn new(points: &'a [(Q16, Q16)]) -> Self {
+        assert!(!points.is_empty());
+        Self(points)
+    }
+
+    pub fn eval(&self, x: Q16) -> Q16 {
+        let points = self.0;
+        let Some(upper) = points.iter().position(|(point_x, _)| x < *point_x) else {
+            return points[points.len() - 1].1;
+        };
+        if upper == 0 {
+            return points[0].1;
+        }
+        let (low_x, low_y) = points[upper - 1];
+        let (high_x, high_y) = points[upper];
+        // Fraction of the way from the lower point to the upper one
+        let t = Q16::from_ratio(x.0 - low_x.0, high_x.0 - low_x.0);
+        Q16(lerp(low_y.0, high_y.0, t))
+    }
+}
+
+/// Value `t` of the way from `from` to `to`, rounded to the nearest. `t` is clamped to 0-1.
+pub fn lerp(from: u32, to: u32, t: Q16) -> u32 {
+    let t = t.min(Q16::ONE);
+    if to >= from {
+        from + t.scale(to - from)
+    } else {
+        from - t.scale(from - to)
+    }
+}
+
+/// `x^2.2` at every eighth of the range, how much brighter an LED looks for its duty
+const GAMMA_POINTS: [(Q16, Q16); 9] = [
+    (Q16::ZERO, Q16::ZERO),
+    (Q16::from_ratio(1, 8), Q16(676)),
+    (Q16::from_ratio(2, 8), Q16(3104)),
+    (Q16::from_ratio(3, 8), Q16(7574)),
+    (Q16::from_ratio(4, 8), Q16(14263)),
+    (Q16::from_ratio(5, 8), Q16(23303)),
+    (Q16::from_ratio(6, 8), Q16(34803)),
+    (Q16::from_ratio(7, 8), Q16(48854)),
+    (Q16::ONE, Q16::ONE),
+];
+
+/// Duty of an LED color channel that looks `value` (0-255) bright
+pub fn gamma(value: u8) -> u8 {
+    let x = Q16::from_ratio(value as u32, u8::MAX as u32);
+    Curve::new(&GAMMA_POINTS).eval(x).scale(u8::MAX as u32) as u8
+}
+
+/// Starts fast and slows down to a stop, `1 - (1 - t)^2`. `t` is clamped to 0-1.
+pub fn ease_out(t: Q16) -> Q16 {
+    let rest = Q16::ONE.saturating_sub(t);
+    Q16::ONE.saturating_sub(rest.mul(rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn q16_arithmetic() {
+        assert_eq!(Q16::from_ratio(1, 2), Q16(1 << 15));
+        assert_eq!(Q16::from_ratio(1023, 1023), Q16::ONE);
+        assert_eq!(Q16::from_ratio(1, 0), Q16::MAX);
+        assert_eq!(Q16::from_ratio(u32::MAX, 1), Q16::MAX);
+        assert_eq!(Q16::from_int(3).mul(Q16::from_ratio(1, 2)).round(), 2);
+        assert_eq!(Q16::from_int(3).mul(Q16::from_ratio(1, 2)).floor(), 1);
+        assert_eq!(Q16::MAX.mul(Q16::from_int(2)), Q16::MAX);
+        assert_eq!(Q16::from_ratio(1, 3).scale(3), 1);
+        assert_eq!(Q16::ONE.scale(u32::MAX), u32::MAX);
+
+        let mut average = Q16::ZERO;
+        for _ in 0..64 {
+            average = average.approach(Q16::from_int(1000), 2);
+        }
+        assert_eq!(average.round(), 1000);
+        assert_eq!(Q16::from_int(8).approach(Q16::ZERO, 1), Q16::from_int(4));
+
+        assert_eq!(lerp(10, 20, Q16::from_ratio(1, 4)), 13);
+        assert_eq!(lerp(20, 10, Q16::from_ratio(1, 4)), 17);
+        assert_eq!(lerp(20, 10, Q16::from_int(5)), 10);
+    }
+
+    #[test]
+    fn curves_are_monotonic_and_keep_their_ends() {
+        let falling = [(Q16::ZERO, Q16::ONE), (Q16::ONE, Q16::ZERO)];
+        for curve in [Curve::new(&GAMMA_POINTS), Curve::new(&falling)] {
+            let ends = (curve.eval(Q16::ZERO), curve.eval(Q16::ONE));
+            assert_eq!(curve.eval(Q16::from_int(2)), ends.1);
+            let mut previous = ends.0;
+            for step in 0..=1000 {
+                let y = curve.eval(Q16::from_ratio(step, 1000));
+                assert!(if ends.0 < ends.1 {
+                    previous <= y
+                } else {
+                    previous >= y
+                });
+                previous = y;
+            }
+            assert_eq!(previous, ends.1);
+        }
+        assert_eq!(
+            Curve::new(&falling).eval(Q16::from_ratio(1, 4)),
+            Q16::from_ratio(3, 4)
+        );
+    }
+
+    #[test]
+    fn gamma_and_easing() {
+        assert_eq!(gamma(0), 0);
+        assert_eq!(gamma(255), 255);
+        // 0.5^2.2 = 0.218
+        assert_eq!(gamma(128), 56);
+        for value in 1..=255 {
+            assert!(gamma(value) >= gamma(value - 1));
+        }
+
+        assert_eq!(ease_out(Q16::ZERO), Q16::ZERO);
+        assert_eq!(ease_out(Q16::ONE), Q16::ONE);
+        assert_eq!(ease_out(Q16::from_int(2)), Q16::ONE);
+        assert_eq!(ease_out(Q16::from_ratio(1, 2)), Q16::from_ratio(3, 4));
+    }
+}
@@ -0,0 +1,301 @@
+//! Copy of the frame that is sent to the panel a page at a time. The SSD1306 driver sends the
+//! whole area touched since the last flush in one go, which is most of a 128x64 frame for a full
+//! redraw. [Framebuffer] keeps the frame itself and hands the driver one page of 8 rows per
+//! [DisplayFlush::flush_chunk], so the task sending it can let other tasks in between. It also
+//! keeps the frame as it was last sent: a page drawn again with the same pixels, e.g. a whole row
+//! redrawn for a value that moved a bar by less than a pixel, is not sent at all.
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+use ssd1306::prelude::DisplayRotation;
+
+use crate::{DisplayFlush, DisplaySink};
+
+/// Rows of a page, as the SSD1306 lays out its memory
+const PAGE_HEIGHT: u32 = 8;
+/// Pixels of the largest panel, 128x64
+const MAX_PIXELS: usize = 128 * 64;
+
+pub struct Framebuffer<D> {
+    display: D,
+    size: Size,
+    /// Row after row, a bit per pixel
+    pixels: [u8; MAX_PIXELS / 8],
+    /// Bit `n` is set while page `n` has not been sent since it was drawn to
+    dirty: u16,
+    /// Pixels as they were last sent
+    sent: [u8; MAX_PIXELS / 8],
+    /// Bit `n` is set while the panel may not show `sent` on page `n`, so it is sent even if it
+    /// did not change
+    stale: u16,
+}
+
+impl<D: DisplaySink> Framebuffer<D> {
+    pub fn new(display: D) -> Self {
+        let size = display.bounding_box().size;
+        Self {
+            display,
+            size,
+            pixels: [0; MAX_PIXELS / 8],
+            dirty: 0,
+            sent: [0; MAX_PIXELS / 8],
+            stale: u16::MAX,
+        }
+    }
+
+    fn pages(&self) -> u32 {
+        self.size.height.div_ceil(PAGE_HEIGHT)
+    }
+
+    /// Every page is sent on the next flush, whatever was sent before
+    fn mark_all_dirty(&mut self) {
+        self.dirty = ((1u32 << self.pages()) - 1) as u16;
+        self.stale = self.dirty;
+    }
+
+    /// Bytes of [Framebuffer::pixels] that hold `page`
+    fn page_bytes(&self, page: u32) -> core::ops::Range<usize> {
+        let top = page * PAGE_HEIGHT;
+        let bottom = (top + PAGE_HEIGHT).min(self.size.height);
+        let width = self.size.width as usize;
+        top as usize * width / 8..(bottom as usize * width).div_ceil(8)
+    }
+}
+
+/// Pixel at `point` of a frame `width` pixels wide
+fn pixel_at(pixels: &[u8], width: u32, point: Point) -> BinaryColor {
+    let index = point.y as usize * width as usize + point.x as usize;
+    BinaryColor::from(pixels[index / 8] & 1 << (index % 8) != 0)
+}
+
+impl<D: DisplaySink> OriginDimensions for Framebuffer<D> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl<D> DrawTarget for Framebuffer<D>
+where
+    D: DisplaySink + DrawTarget<Error = <D as DisplayFlush>::Error>,
+{
+    type Color = BinaryColor;
+    type Error = <D as DisplayFlush>::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let area = self.bounding_box();
+        for Pixel(point, color) in pixels.into_iter().filter(|p| area.contains(p.0)) {
+            let index = point.y as usize * self.size.width as usize + point.x as usize;
+            let mask = 1 << (index % 8);
+            if color.is_on() {
+                self.pixels[index / 8] |= mask;
+            } else {
+                self.pixels[index / 8] &= !mask;
+            }
+            self.dirty |= 1 << (point.y as u32 / PAGE_HEIGHT);
+        }
+        Ok(())
+    }
+}
+
+impl<D> DisplayFlush for Framebuffer<D>
+where
+    D: DisplaySink + DrawTarget<Error = <D as DisplayFlush>::Error>,
+{
+    type Error = <D as DisplayFlush>::Error;
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        while !self.flush_chunk()? {}
+        Ok(())
+    }
+
+    /// Sends the topmost page drawn to since it was last sent. Pages drawn with the pixels they
+    /// already had are skipped.
+    fn flush_chunk(&mut self) -> Result<bool, Self::Error> {
+        let page = loop {
+            if self.dirty == 0 {
+                return Ok(true);
+            }
+            let page = self.dirty.trailing_zeros();
+            self.dirty &= !(1 << page);
+            let bytes = self.page_bytes(page);
+            if self.stale & 1 << page != 0 || self.pixels[bytes.clone()] != self.sent[bytes] {
+                break page;
+            }
+        };
+        let top = page * PAGE_HEIGHT;
+        let area = Rectangle::new(
+            Point::new(0, top as i32),
+            Size::new(self.size.width, PAGE_HEIGHT.min(self.size.height - top)),
+        );
+        let (pixels, width) = (&self.pixels, self.size.width);
+        self.display
+            .fill_contiguous(&area, area.points().map(|p| pixel_at(pixels, width, p)))?;
+        // Only the page was touched, so that is all the driver sends
+        self.display.flush()?;
+        let bytes = self.page_bytes(page);
+        self.sent[bytes.clone()].copy_from_slice(&self.pixels[bytes]);
+        self.stale &= !(1 << page);
+        Ok(self.dirty == 0)
+    }
+
+    fn set_display_on(&mut self, on: bool) -> Result<(), Self::Error> {
+        self.display.set_display_on(on)
+    }
+
+    fn set_invert(&mut self, invert: bool) -> Result<(), Self::Error> {
+        self.display.set_invert(invert)
+    }
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<(), Self::Error> {
+        self.display.set_contrast(contrast)
+    }
+
+    fn set_rotation(&mut self, rotation: DisplayRotation) -> Result<(), Self::Error> {
+        // The driver keeps the rotation even if the panel did not answer
+        let result = self.display.set_rotation(rotation);
+        // The frame is drawn again for the new size
+        self.size = self.display.bounding_box().size;
+        self.pixels.fill(0);
+        self.mark_all_dirty();
+        result
+    }
+
+    /// The panel memory is lost, every page is sent again
+    fn init(&mut self) -> Result<(), Self::Error> {
+        self.display.init()?;
+        self.mark_all_dirty();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_graphics::primitives::PrimitiveStyle;
+
+    /// 128x64 panel that keeps the rows of each flush
+    #[derive(Default)]
+    struct Panel {
+        touched: Option<Rectangle>,
+        flushed: std::vec::Vec<Rectangle>,
+    }
+
+    impl OriginDimensions for Panel {
+        fn size(&self) -> Size {
+            Size::new(128, 64)
+        }
+    }
+
+    impl DrawTarget for Panel {
+        type Color = BinaryColor;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+        where
+            I: IntoIterator<Item = Pixel<BinaryColor>>,
+        {
+            for Pixel(point, _) in pixels {
+                let pixel = Rectangle::new(point, Size::new(1, 1));
+                self.touched = Some(match self.touched {
+                    Some(area) => envelope(area, pixel),
+                    None => pixel,
+                });
+            }
+            Ok(())
+        }
+    }
+
+    fn envelope(a: Rectangle, b: Rectangle) -> Rectangle {
+        let top_left = a.top_left.component_min(b.top_left);
+        let bottom_right = a
+            .bottom_right()
+            .unwrap()
+            .component_max(b.bottom_right().unwrap());
+        Rectangle::with_corners(top_left, bottom_right)
+    }
+
+    impl DisplayFlush for Panel {
+        type Error = Infallible;
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            self.flushed.extend(self.touched.take());
+            Ok(())
+        }
+
+        fn set_display_on(&mut self, _on: bool) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_invert(&mut self, _invert: bool) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_contrast(&mut self, _contrast: u8) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_rotation(&mut self, _rotation: DisplayRotation) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sends_a_page_per_chunk() {
+        let mut frame = Framebuffer::new(Panel::default());
+        assert_eq!(frame.flush_chunk(), Ok(true));
+        // Rows 6-17 are on pages 0-2
+        Rectangle::new(Point::new(10, 6), Size::new(20, 12))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(&mut frame)
+            .unwrap();
+        assert_eq!(frame.flush_chunk(), Ok(false));
+        assert_eq!(frame.flush_chunk(), Ok(false));
+        assert_eq!(frame.flush_chunk(), Ok(true));
+        let rows: std::vec::Vec<_> = frame
+            .display
+            .flushed
+            .iter()
+            .map(|area| (area.top_left.y, area.size))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (0, Size::new(128, 8)),
+                (8, Size::new(128, 8)),
+                (16, Size::new(128, 8))
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_unchanged_pages() {
+        let mut frame = Framebuffer::new(Panel::default());
+        let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+        let bar = Rectangle::new(Point::new(10, 6), Size::new(20, 4));
+        bar.into_styled(fill).draw(&mut frame).unwrap();
+        frame.flush().unwrap();
+        assert_eq!(frame.display.flushed.len(), 2);
+
+        // Same pixels again, nothing is sent
+        bar.into_styled(fill).draw(&mut frame).unwrap();
+        assert_eq!(frame.flush_chunk(), Ok(true));
+        assert_eq!(frame.display.flushed.len(), 2);
+
+        // Whole bar drawn again with a pixel more on page 1
+        bar.into_styled(fill).draw(&mut frame).unwrap();
+        Pixel(Point::new(40, 9), BinaryColor::On)
+            .draw(&mut frame)
+            .unwrap();
+        frame.flush().unwrap();
+        assert_eq!(frame.display.flushed[2].top_left.y, 8);
+        assert_eq!(frame.display.flushed.len(), 3);
+
+        // The panel lost its memory, every page is sent
+        frame.init().unwrap();
+        frame.flush().unwrap();
+        assert_eq!(frame.display.flushed.len(), 3 + 8);
+    }
+}
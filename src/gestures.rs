@@ -0,0 +1,230 @@
+//! Taps, double taps, holds and hold-repeats of a single button, so one button can do several
+//! things. The detected [Gesture]s are published as [crate::events::Event::ButtonPressed] for the
+//! task carrying out their [ButtonAction]s.
+
+use crate::{
+    buttons::{ButtonEvent, Debouncer},
+    log::debug,
+    pages::Screen,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gesture {
+    /// Pressed and released once. Sent after the double tap window when double taps are in use
+    Tap,
+    /// Second tap within the double tap window
+    DoubleTap,
+    /// Held down for the hold time, sent once per press
+    Hold,
+    /// Still held, sent every repeat period after [Gesture::Hold]
+    Repeat,
+}
+
+/// What a gesture does
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ButtonAction {
+    /// Cycles the display pages
+    NextPage,
+    /// Jumps to a page, e.g. [Screen::Volumes] to get back from the others
+    ShowScreen(Screen),
+    /// Sends every channel as 0 until toggled again
+    ToggleMute,
+}
+
+/// Action of each gesture, `None` ignores it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GestureActions {
+    pub tap: Option<ButtonAction>,
+    pub double_tap: Option<ButtonAction>,
+    pub hold: Option<ButtonAction>,
+    pub repeat: Option<ButtonAction>,
+}
+
+impl GestureActions {
+    pub fn action(&self, gesture: Gesture) -> Option<ButtonAction> {
+        match gesture {
+            Gesture::Tap => self.tap,
+            Gesture::DoubleTap => self.double_tap,
+            Gesture::Hold => self.hold,
+            Gesture::Repeat => self.repeat,
+        }
+    }
+}
+
+/// Timing (ms) of the gestures
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GestureTiming {
+    /// Longest time from a release to the next press that makes a double tap, 0 sends every tap
+    /// right away
+    pub double_tap_window: u64,
+    pub hold_time: u64,
+    /// 0 sends no [Gesture::Repeat]
+    pub repeat_period: u64,
+}
+
+impl GestureTiming {
+    /// Taps are only held back for a double tap and holds only repeat when `actions` use them
+    pub const fn for_actions(self, actions: &GestureActions) -> Self {
+        Self {
+            double_tap_window: if actions.double_tap.is_some() {
+                self.double_tap_window
+            } else {
+                0
+            },
+            hold_time: self.hold_time,
+            repeat_period: if actions.repeat.is_some() {
+                self.repeat_period
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// Turns the raw states of a button sampled periodically into gestures. A tap followed by a hold
+/// counts as the hold alone.
+pub struct GestureDetector {
+    timing: GestureTiming,
+    debouncer: Debouncer,
+    pressed_since: Option<u64>,
+    /// Release of a tap that can still become a double tap
+    tap_released_at: Option<u64>,
+    held: bool,
+    next_repeat: u64,
+}
+
+impl GestureDetector {
+    pub fn new(timing: GestureTiming) -> Self {
+        Self {
+            timing,
+            debouncer: Debouncer::new(),
+            pressed_since: None,
+            tap_released_at: None,
+            held: false,
+            next_repeat: 0,
+        }
+    }
+
+    /// Give the raw state of the button and the current time, returns the gesture it completes
+    pub fn update(&mut self, pressed: bool, now_ms: u64) -> Option<Gesture> {
+        let gesture = self.detect(pressed, now_ms);
+        if let Some(gesture) = gesture {
+            debug!("Button {}", gesture);
+        }
+        gesture
+    }
+
+    fn detect(&mut self, pressed: bool, now_ms: u64) -> Option<Gesture> {
+        match self.debouncer.update(pressed) {
+            Some(ButtonEvent::Pressed) => {
+                self.pressed_since = Some(now_ms);
+                self.held = false;
+            }
+            Some(ButtonEvent::Released) => {
+                self.pressed_since = None;
+                if self.held {
+                    return None;
+                }
+                if self.tap_released_at.take().is_some() {
+                    return Some(Gesture::DoubleTap);
+                }
+                if self.timing.double_tap_window == 0 {
+                    return Some(Gesture::Tap);
+                }
+                self.tap_released_at = Some(now_ms);
+            }
+            None => (),
+        }
+
+        match self.pressed_since {
+            Some(since) if !self.held && now_ms.saturating_sub(since) >= self.timing.hold_time => {
+                self.held = true;
+                self.tap_released_at = None;
+                self.next_repeat = now_ms + self.timing.repeat_period;
+                Some(Gesture::Hold)
+            }
+            Some(_) if self.held && self.timing.repeat_period > 0 && now_ms >= self.next_repeat => {
+                self.next_repeat += self.timing.repeat_period;
+                Some(Gesture::Repeat)
+            }
+            Some(_) => None,
+            None => {
+                let released_at = self.tap_released_at?;
+                if now_ms.saturating_sub(released_at) < self.timing.double_tap_window {
+                    return None;
+                }
+                self.tap_released_at = None;
+                Some(Gesture::Tap)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buttons::DEBOUNCE_SAMPLES;
+
+    const TIMING: GestureTiming = GestureTiming {
+        double_tap_window: 300,
+        hold_time: 800,
+        repeat_period: 200,
+    };
+
+    /// Gestures of `presses` given as (pressed, duration in ms), sampled every 10 ms
+    fn gestures(detector: &mut GestureDetector, presses: &[(bool, u64)]) -> Vec<(u64, Gesture)> {
+        let mut now = 0;
+        let mut gestures = Vec::new();
+        for (pressed, duration) in presses {
+            for _ in 0..duration / 10 {
+                now += 10;
+                gestures.extend(detector.update(*pressed, now).map(|g| (now, g)));
+            }
+        }
+        gestures
+    }
+
+    #[test]
+    fn tap_double_tap_and_hold() {
+        let debounce = DEBOUNCE_SAMPLES as u64 * 10;
+        let mut detector = GestureDetector::new(TIMING);
+        let found = gestures(&mut detector, &[(true, 100), (false, 500)]);
+        assert_eq!(
+            found.iter().map(|(_, g)| *g).collect::<Vec<_>>(),
+            [Gesture::Tap]
+        );
+        // Held back until the window has passed
+        assert_eq!(found[0].0, 100 + debounce + 300);
+
+        let found = gestures(
+            &mut detector,
+            &[(true, 100), (false, 100), (true, 100), (false, 500)],
+        );
+        assert_eq!(
+            found.iter().map(|(_, g)| *g).collect::<Vec<_>>(),
+            [Gesture::DoubleTap]
+        );
+
+        let found = gestures(&mut detector, &[(true, 1300), (false, 500)]);
+        assert_eq!(
+            found.iter().map(|(_, g)| *g).collect::<Vec<_>>(),
+            [Gesture::Hold, Gesture::Repeat, Gesture::Repeat]
+        );
+
+        // Without a double tap action taps are sent on the release
+        let actions = GestureActions {
+            tap: Some(ButtonAction::NextPage),
+            double_tap: None,
+            hold: None,
+            repeat: None,
+        };
+        let mut detector = GestureDetector::new(TIMING.for_actions(&actions));
+        let found = gestures(&mut detector, &[(true, 100), (false, 100), (true, 1300)]);
+        assert_eq!(
+            found.iter().map(|(_, g)| *g).collect::<Vec<_>>(),
+            [Gesture::Tap, Gesture::Hold]
+        );
+        assert_eq!(found[0].0, 100 + debounce);
+    }
+}
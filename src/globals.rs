@@ -1,6 +1,273 @@
+use crate::{
+    animation::Easing,
+    gestures::{ButtonAction, GestureActions, GestureTiming},
+    hid::{self, Shortcut},
+    layout::BarOrientation,
+    midi,
+    profiles::Profile,
+    roles::{self, VirtualChannel},
+    sampling::{ChannelConfig, Snap},
+};
+
+/// Frame period (ms) of the bar animation
 pub const DISPLAY_UPDATE_PERIOD: u32 = 50;
-pub const SERIAL_UPDATE_PERIOD: u32 = 500;
+/// How long (ms) the splash screen is shown at boot
+pub const SPLASH_TIME: u32 = 1000;
+/// How long (ms) the self-test checklist is shown after the splash screen when every check passed
+pub const SELF_TEST_TIME: u32 = 1000;
+/// Same when a check failed, long enough to read which one
+pub const SELF_TEST_FAIL_TIME: u32 = 5000;
+/// Readings averaged per pot for the self-test
+pub const SELF_TEST_SAMPLES: u32 = 16;
+/// Show the focused channel full screen on the display while it stays in focus, see [FOCUS_TIME]
+pub const ZOOM_VIEW: bool = true;
+/// How long (ms) the arrow showing the direction of the latest change stays next to a bar
+pub const TREND_TIME: u64 = 1000;
+/// How long (ms) the channel moved last stays in focus, zoomed on the display and its LEDs
+/// brighter than the others
+pub const FOCUS_TIME: u64 = 3000;
+/// Speed (px/s) of the lines on [crate::pages::Screen::NowPlaying] that are wider than the display
+pub const NOW_PLAYING_SCROLL_SPEED: u64 = 20;
+/// Pixels a channel name too long for the zoom view scrolls by per animation frame
+pub const NAME_SCROLL_STEP: u32 = 1;
+/// How long (ms) the volumes reported by the host are marked on the bars after the latest report
+pub const HOST_VOLUMES_TIMEOUT: u64 = 5000;
+/// How long (ms) the bars keep showing the audio levels streamed by the host after the latest
+/// report, the host sends them at about 10 Hz
+pub const LEVELS_TIMEOUT: u64 = 1000;
+/// Contrast of the display when it is on
+pub const DISPLAY_CONTRAST: u8 = 0x5f;
+/// Contrast after the display has been idle for [DISPLAY_ON_TIME]
+pub const DISPLAY_DIM_CONTRAST: u8 = 0x00;
+/// Wait (ms) before the display is set up again after a transfer to it failed, doubled after
+/// every attempt it does not answer
+pub const DISPLAY_RETRY_DELAY: u32 = 100;
+/// Longest wait (ms) between two attempts at setting up the display again
+pub const DISPLAY_RETRY_MAX_DELAY: u32 = 10_000;
+/// With `supply-monitor`, contrast cap while the supply is below [SUPPLY_LOW_MV]
+pub const SUPPLY_LOW_CONTRAST: u8 = 0x10;
+/// With `supply-monitor`, supply voltage (mV) below which LOW V is shown and the display dimmed.
+/// USB should deliver at least 4750 mV, long cables and weak batteries sag below that
+pub const SUPPLY_LOW_MV: u32 = 4500;
+/// With `supply-monitor`, how far (mV) the supply has to rise above [SUPPLY_LOW_MV] to count as
+/// fine again, so a supply right at the threshold does not flicker
+pub const SUPPLY_HYSTERESIS_MV: u32 = 150;
+/// With `supply-monitor`, how often (ms) the supply is read
+pub const SUPPLY_SAMPLE_PERIOD: u64 = 500;
+/// With `supply-monitor`, readings averaged per supply reading
+pub const SUPPLY_SAMPLES: u32 = 16;
+/// With `battery`, how often (ms) the charge is read
+pub const BATTERY_SAMPLE_PERIOD: u64 = 5000;
+/// With `battery`, readings averaged per reading of the divider
+pub const BATTERY_SAMPLES: u32 = 16;
+/// With `battery`, charge (%) at which the chip goes to deep sleep to protect the cell. It wakes up
+/// again on reset, e.g. from the power switch
+pub const BATTERY_EMPTY_PERCENT: u8 = 3;
+/// With `battery`, readings in a row at [BATTERY_EMPTY_PERCENT] or below before the chip sleeps,
+/// so a voltage dip under load does not put it to sleep
+pub const BATTERY_EMPTY_READINGS: u8 = 3;
+/// With `ambient-light`, display contrast by ambient light (lx), interpolated in between. It
+/// replaces the contrast of the settings while the sensor answers
+pub const AMBIENT_CONTRAST: [(u32, u8); 5] = [
+    (0, 0x01),
+    (10, 0x20),
+    (100, 0x60),
+    (1000, 0xc0),
+    (10000, 0xff),
+];
+/// With `ambient-light`, smallest change of the contrast that is sent to the display
+pub const AMBIENT_CONTRAST_STEP: u8 = 8;
+/// With `ambient-light`, how often (ms) the light is read
+pub const AMBIENT_SAMPLE_PERIOD: u64 = 1000;
+/// With `auto-rotate`, gravity (mg) along the X or Y axis of the accelerometer that counts as that
+/// edge of the display being up. About 45 degrees, so lying flat keeps the rotation
+pub const ORIENTATION_THRESHOLD_MG: i32 = 700;
+/// With `auto-rotate`, readings in a row that have to agree before the picture is turned
+pub const ORIENTATION_READINGS: u8 = 4;
+/// With `auto-rotate`, how often (ms) the accelerometer is read
+pub const ORIENTATION_SAMPLE_PERIOD: u64 = 500;
+/// With `motorized-faders`, proportional, integral and derivative gain of the loop moving a fader,
+/// in % of motor duty per 100 counts of error in the 0-1023 range
+pub const MOTOR_GAINS: (i32, i32, i32) = (30, 1, 20);
+/// With `motorized-faders`, limit of the summed error of the integral gain, so a long move does
+/// not overshoot
+pub const MOTOR_INTEGRAL_LIMIT: i32 = 2000;
+/// With `motorized-faders`, smallest duty (%) that gets a fader motor moving
+pub const MOTOR_MIN_DUTY: i32 = 30;
+/// With `motorized-faders`, how close (in the 0-1023 range) a fader has to get to its target. A
+/// step of the volumes reported by the host is about 10
+pub const MOTOR_DEADBAND: u16 = 15;
+/// With `motorized-faders`, how long (ms) a moving fader may not get closer to its target before
+/// it counts as held by a hand
+pub const MOTOR_STALL_TIME: u64 = 300;
+/// With `motorized-faders`, how long (ms) a held fader has to stay still before the motor takes
+/// over again
+pub const MOTOR_RELEASE_TIME: u64 = 1000;
+/// With `motorized-faders`, PWM frequency (kHz) of the motors, above hearing
+pub const MOTOR_PWM_FREQUENCY: u32 = 20;
+/// With `touch-sense`, how much longer (%) than without a finger a cap has to take to charge to
+/// count as touched
+pub const TOUCH_THRESHOLD_PERCENT: u32 = 30;
+/// With `touch-sense`, charge times of a cap summed per reading
+pub const TOUCH_SAMPLES: u32 = 4;
+/// With `touch-sense`, polls after which a cap counts as charged, about 0.5 ms. Limits how long the
+/// interrupts are off for a cap that is not connected
+pub const TOUCH_MAX_COUNT: u32 = 5000;
+/// How long (s) the display stays on without changes before it is dimmed
+pub const DISPLAY_ON_TIME: u32 = 10;
+/// How long (s) the display stays dimmed before it is turned off
+pub const DISPLAY_OFF_DELAY: u32 = 30;
+/// How often (ms) the pots are sampled when a wireless link drives the sampling
+pub const SAMPLE_PERIOD: u32 = 50;
+/// How often (ms) the pots are sampled while they are moved
+pub const SAMPLE_PERIOD_MOVING: u32 = 10;
+/// How often (ms) the pots are sampled after they have been still for [MOTION_SETTLE_TIME]
+pub const SAMPLE_PERIOD_IDLE: u32 = 200;
+/// How long (ms) the pots have to stay still before sampling slows down
+pub const MOTION_SETTLE_TIME: u64 = 3000;
+/// Smallest change (in the 0-1023 serial range) on any channel that counts as moving
+pub const MOTION_THRESHOLD: u16 = 8;
+/// With `oversampling`, bits of resolution added to the 12 bit readings. Takes 4^n readings per sample.
+pub const OVERSAMPLING_BITS: u32 = 2;
+/// With `adc-dma`, ADC conversions per second over all channels
+pub const ADC_DMA_SAMPLE_RATE: u32 = 20_000;
+/// With `adc-dma`, conversions averaged per sample over all channels
+pub const ADC_DMA_BUFFER_LEN: usize = 256;
+/// With `light-sleep`, how long (ms) the pots have to stay still before sleeping between samples
+pub const SLEEP_AFTER: u64 = 60 * 1000;
+/// With `light-sleep`, how often (ms) the pots are sampled while sleeping
+pub const SLEEP_WAKE_PERIOD: u64 = 200;
+/// How often (ms) the serial task checks whether a new frame needs to be sent
+pub const SERIAL_UPDATE_PERIOD: u32 = 20;
+/// Smallest change (in the 0-1023 serial range) on any channel that causes a new frame to be sent immediately
+pub const SERIAL_CHANGE_THRESHOLD: u16 = 2;
+/// When nothing changes a keep-alive frame is still sent this often (ms)
+pub const SERIAL_KEEP_ALIVE_PERIOD: u32 = 5000;
+/// The host counts as gone after sending nothing for this long (ms). Hosts that want the
+/// indicator send something, even an empty line, more often. The plain deej host never writes to
+/// the port, so it is never shown as gone
+pub const HOST_TIMEOUT: u64 = 3000;
+/// Hold the frames back while the host is gone instead of writing them to a port nobody reads
+pub const STOP_SENDING_WHEN_DISCONNECTED: bool = false;
+#[cfg(not(any(feature = "esp32", feature = "esp32s3")))]
 pub const MAX_ANALOG_VALUE: u16 = 770;
-/// Analog input never really is zero. This value is cutoff, meaning everything under it is interpreted as zero volume
+/// Analog input never really is zero. This value is cutoff, meaning everything under it is interpreted as zero volume.
+/// Default of [ChannelConfig::zero_cutoff], pots at zero at boot get their own from the measured noise.
+#[cfg(not(any(feature = "esp32", feature = "esp32s3")))]
 pub const ZERO_CUTOFF: u16 = 35;
-pub const INPUT_COUNT: usize = 4;
+/// Same pot wiring as on the ESP32-C3, but the readings are raw 12 bit values of about 0-1100 mV
+#[cfg(feature = "esp32")]
+pub const MAX_ANALOG_VALUE: u16 = 2870;
+#[cfg(feature = "esp32")]
+pub const ZERO_CUTOFF: u16 = 130;
+/// Raw 12 bit readings like on the ESP32, 0 dB covers about 0-950 mV on the S3
+#[cfg(feature = "esp32s3")]
+pub const MAX_ANALOG_VALUE: u16 = 3320;
+#[cfg(feature = "esp32s3")]
+pub const ZERO_CUTOFF: u16 = 150;
+/// Samples of each channel taken at boot to measure its noise floor, 0 keeps [ZERO_CUTOFF] for all
+pub const NOISE_FLOOR_SAMPLES: u32 = 20;
+/// Highest zero cutoff measured at boot. A channel reading above it is not at zero and keeps [ZERO_CUTOFF]
+pub const MAX_ZERO_CUTOFF: u16 = MAX_ANALOG_VALUE / 8;
+/// Readings at or above this are a channel stuck at the rail, e.g. a wiper shorted to 3.3 V
+pub const RAIL_VALUE: u16 = MAX_ANALOG_VALUE + MAX_ANALOG_VALUE / 8;
+/// Spread of the readings of one sample at which a channel counts as floating, i.e. no wiper
+pub const FLOATING_SPREAD: u16 = MAX_ANALOG_VALUE / 4;
+/// Bad samples in a row before a channel is shown as disconnected, and good ones before it is back
+pub const DISCONNECT_SAMPLES: u8 = 10;
+// INPUT_COUNT and POT_PINS, generated by build.rs from `pots.pins` in board.toml
+include!(concat!(env!("OUT_DIR"), "/channels.rs"));
+/// Below 3 % reports 0 and above 97 % reports 100 %, unless overridden in [CHANNEL_CONFIGS]
+pub const SNAP_ZONES: Snap = Snap { low: 3, high: 97 };
+/// Read strategy and [Role] of each channel, e.g. heavier filtering for a noisy pot or a master pot
+pub const CHANNEL_CONFIGS: [ChannelConfig; INPUT_COUNT] = [ChannelConfig {
+    snap: SNAP_ZONES,
+    ..ChannelConfig::DEFAULT
+}; INPUT_COUNT];
+/// Outputs sent after the channels, e.g. `VirtualChannel::Min(1, 2)` for a group that follows the
+/// lower of two sliders
+pub const VIRTUAL_CHANNELS: &[VirtualChannel] = &[];
+/// Values in every frame sent to the host, one per channel, one more per [roles::Role::Crossfade],
+/// one per [VIRTUAL_CHANNELS] and the index of the active profile with `profiles`
+pub const OUTPUT_COUNT: usize =
+    roles::output_count(&CHANNEL_CONFIGS, VIRTUAL_CHANNELS) + cfg!(feature = "profiles") as usize;
+/// Cycled with the profile button, the first one is active at boot. The host tells them apart by
+/// the index, change the order or mute channels with e.g. `Profile::named("Game").with_muted(3)`.
+/// `Profile::named("DAW").with_midi()` sends MIDI Control Changes instead of deej frames
+pub const PROFILES: &[Profile] = &[
+    Profile::named("Work"),
+    Profile::named("Game"),
+    Profile::named("Stream"),
+];
+/// MIDI channel (0-15, shown as 1-16 by most DAWs) of the Control Change messages sent in
+/// [crate::protocol::ProtocolMode::Midi]
+pub const MIDI_CHANNEL: u8 = 0;
+/// Controller (0-119) of each channel of the frame in MIDI mode. 20-31 are not assigned by the MIDI
+/// spec, so they do not clash with what the DAW already maps
+pub const MIDI_CONTROLLERS: [u8; OUTPUT_COUNT] = midi::consecutive_controllers(20);
+/// With `keypad`, how often (ms) the key matrix is scanned. Keys are debounced over
+/// [crate::buttons::DEBOUNCE_SAMPLES] scans
+pub const KEYPAD_SCAN_PERIOD: u64 = 5;
+/// With `keypad`, how long (us) a row is driven before its columns are read
+pub const KEYPAD_SETTLE_TIME: u64 = 10;
+/// With `keypad`, shortcut sent by each key by row and column, e.g.
+/// `Shortcut::key(0x10).with(hid::MOD_CTRL | hid::MOD_SHIFT)` for Ctrl+Shift+M. The F13-F24 keys
+/// of the default are free to bind in OBS, Discord and the like
+pub const KEYPAD_SHORTCUTS: [[Shortcut; KEYPAD_COLS]; KEYPAD_ROWS] = hid::function_keys();
+/// With `media-keys`, how often (ms) the buttons are read. They are debounced over
+/// [crate::buttons::DEBOUNCE_SAMPLES] reads
+pub const MEDIA_KEY_SCAN_PERIOD: u64 = 10;
+/// Length of the LED strip segment of each channel, channels are chained on one strip
+pub const LEDS_PER_CHANNEL: usize = 4;
+/// Brightness of the LED strip and the status LED, 0-255
+pub const LED_BRIGHTNESS: u8 = 32;
+/// How often (ms) the LED strip and the status LED are updated
+pub const LED_UPDATE_PERIOD: u32 = 50;
+/// Events a task of the RTIC app can fall behind by before it misses the oldest. The LED task reads
+/// them every [LED_UPDATE_PERIOD], while the pots move that is 5 samples of [INPUT_COUNT] changes.
+pub const EVENT_QUEUE_LEN: usize = 32;
+/// Length (ms) of one breath of the status LED while no host is connected
+pub const STATUS_BREATHE_PERIOD: u64 = 4000;
+/// Period (ms) of the red blinking of the status LED after an error
+pub const STATUS_BLINK_PERIOD: u64 = 500;
+/// How long (ms) an error is shown on the status LED
+pub const STATUS_ERROR_TIME: u64 = 3000;
+/// Volumes (0-100) that give a feedback pulse when a channel reaches them
+pub const FEEDBACK_DETENTS: [u16; 3] = [0, 50, 100];
+/// Length (ms) of the feedback pulse, rounded up to the next sample
+pub const FEEDBACK_PULSE_TIME: u64 = 30;
+/// How long (s) the panic screen is shown before the chip resets, 0 halts instead
+pub const PANIC_RESET_DELAY: u32 = 10;
+/// How long (s) the sampling loop and the serial task may go without checking in with the task
+/// watchdog before it resets the chip
+pub const WATCHDOG_TIMEOUT: u32 = 5;
+/// What the gestures of the page button do, e.g. mute on a tap, next page on a double tap and
+/// [crate::pages::Screen::Info] on a hold. Taps are only held back to tell them from a double tap
+/// when `double_tap` has an action
+pub const PAGE_BUTTON_ACTIONS: GestureActions = GestureActions {
+    tap: Some(ButtonAction::NextPage),
+    double_tap: None,
+    hold: None,
+    repeat: None,
+};
+/// Timing (ms) of the gestures of the page button
+pub const GESTURE_TIMING: GestureTiming = GestureTiming {
+    double_tap_window: 300,
+    hold_time: 800,
+    repeat_period: 200,
+};
+/// With `settings`, quadrature steps from one detent of the encoder to the next, 4 for most
+/// encoders and 2 for the ones with half as many detents
+pub const ENCODER_STEPS_PER_DETENT: i8 = 4;
+/// With `settings`, how long (ms) the push switch of the encoder is ignored after a push
+pub const ENCODER_PUSH_DEBOUNCE: u64 = 50;
+/// How long (ms) the BOOT button has to be held to start a firmware update
+pub const OTA_BUTTON_HOLD_TIME: u32 = 3000;
+/// How often (ms) the display contents are moved by a pixel or two to prevent burn-in
+pub const SCREENSAVER_SHIFT_PERIOD: u64 = 3 * 60 * 1000;
+/// Invert the display every other cycle of shifts so lit pixels wear evenly
+pub const SCREENSAVER_INVERT: bool = false;
+/// How the volume bars move to a new value, animation frames are drawn every [DISPLAY_UPDATE_PERIOD] ms
+pub const BAR_EASING: Easing = Easing::Off;
+/// Direction of the volume bars, `Auto` draws vertical bars when the display is rotated
+pub const BAR_ORIENTATION: BarOrientation = BarOrientation::Auto;
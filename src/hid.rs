@@ -0,0 +1,256 @@
+use heapless::Vec;
+
+use crate::{
+    buttons::{ButtonEvent, Debouncer},
+    globals::OUTPUT_COUNT,
+};
+
+/// Consumer page usages sent by the auxiliary buttons
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum ConsumerKey {
+    PlayPause = 0xCD,
+    NextTrack = 0xB5,
+    PreviousTrack = 0xB6,
+    Mute = 0xE2,
+}
+
+impl ConsumerKey {
+    /// Key of each pin of `buttons.media` in board.toml, a board with fewer buttons has the first
+    /// ones
+    pub const ORDER: [ConsumerKey; 4] = [
+        ConsumerKey::PlayPause,
+        ConsumerKey::NextTrack,
+        ConsumerKey::PreviousTrack,
+        ConsumerKey::Mute,
+    ];
+}
+
+/// Consumer control collection with a single 16 bit usage per report
+pub const CONSUMER_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x0C, // Usage Page (Consumer)
+    0x09, 0x01, // Usage (Consumer Control)
+    0xA1, 0x01, // Collection (Application)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x03, //   Logical Maximum (0x3FF)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x2A, 0xFF, 0x03, //   Usage Maximum (0x3FF)
+    0x75, 0x10, //   Report Size (16)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x00, //   Input (Data, Array, Absolute)
+    0xC0, // End Collection
+];
+
+pub type ConsumerReport = [u8; 2];
+
+/// Report for a pressed key. `None` is the release report that has to follow every press.
+pub fn consumer_report(key: Option<ConsumerKey>) -> ConsumerReport {
+    key.map_or(0, |k| k as u16).to_le_bytes()
+}
+
+/// Press or release of a media key
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MediaKeyEvent {
+    pub key: ConsumerKey,
+    pub event: ButtonEvent,
+}
+
+impl MediaKeyEvent {
+    /// Report for the host, a release is the empty report
+    pub fn report(&self) -> ConsumerReport {
+        consumer_report((self.event == ButtonEvent::Pressed).then_some(self.key))
+    }
+}
+
+/// Debounced state of the `N` buttons of `media-keys`, the first `N` of [ConsumerKey::ORDER]
+pub struct MediaKeys<const N: usize> {
+    buttons: [Debouncer; N],
+}
+
+impl<const N: usize> MediaKeys<N> {
+    pub fn new() -> Self {
+        Self {
+            buttons: core::array::from_fn(|_| Debouncer::new()),
+        }
+    }
+
+    /// Give the raw state of every button. Returns the buttons whose state changed, in order.
+    pub fn update(&mut self, pressed: [bool; N]) -> Vec<MediaKeyEvent, N> {
+        self.buttons
+            .iter_mut()
+            .zip(pressed)
+            .zip(ConsumerKey::ORDER)
+            .filter_map(|((button, pressed), key)| {
+                let event = button.update(pressed)?;
+                Some(MediaKeyEvent { key, event })
+            })
+            .collect()
+    }
+}
+
+impl<const N: usize> Default for MediaKeys<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Generic Desktop usage of the first gamepad axis, the others follow it: Y, Z, Rx, Ry, Rz, Slider, Dial
+const USAGE_X: u8 = 0x30;
+/// Axes a gamepad can have from X to Dial
+pub const MAX_GAMEPAD_AXES: usize = 8;
+
+#[cfg(feature = "gamepad")]
+const _: () = assert!(
+    OUTPUT_COUNT <= MAX_GAMEPAD_AXES,
+    "The gamepad has an axis per value of the frame, at most 8"
+);
+
+/// Gamepad collection with one 0-1023 axis per value of the frame, no buttons
+#[rustfmt::skip]
+pub const GAMEPAD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x05, // Usage (Game Pad)
+    0xA1, 0x01, // Collection (Application)
+    0x19, USAGE_X, //   Usage Minimum (X)
+    0x29, USAGE_X + OUTPUT_COUNT as u8 - 1, //   Usage Maximum
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x03, //   Logical Maximum (1023)
+    0x75, 0x10, //   Report Size (16)
+    0x95, OUTPUT_COUNT as u8, //   Report Count (OUTPUT_COUNT)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0xC0, // End Collection
+];
+
+pub type GamepadReport = [u8; OUTPUT_COUNT * 2];
+
+/// Report with the values (0-1023) of the frame as little-endian axes
+pub fn gamepad_report(values: &[u16; OUTPUT_COUNT]) -> GamepadReport {
+    let mut report = [0; OUTPUT_COUNT * 2];
+    for (axis, value) in report.chunks_exact_mut(2).zip(values) {
+        axis.copy_from_slice(&value.min(&1023).to_le_bytes());
+    }
+    report
+}
+
+/// Modifier bits of a keyboard report
+pub const MOD_CTRL: u8 = 0x01;
+pub const MOD_SHIFT: u8 = 0x02;
+pub const MOD_ALT: u8 = 0x04;
+pub const MOD_GUI: u8 = 0x08;
+
+/// Keyboard page usage of F13, F14-F24 follow it. No keyboard has them, so they are free to bind
+pub const KEY_F13: u8 = 0x68;
+
+/// Key sent with its modifiers held, e.g. Ctrl+Shift+M for a mic mute hotkey
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Shortcut {
+    /// `MOD_*` bits
+    pub modifiers: u8,
+    /// Keyboard page usage
+    pub key: u8,
+}
+
+impl Shortcut {
+    pub const fn key(key: u8) -> Self {
+        Self { modifiers: 0, key }
+    }
+
+    pub const fn with(mut self, modifier: u8) -> Self {
+        self.modifiers |= modifier;
+        self
+    }
+}
+
+/// F13-F24 for the first 12 keys of a `ROWS` by `COLS` matrix, then the same keys with Ctrl, Shift
+/// and both
+pub const fn function_keys<const ROWS: usize, const COLS: usize>() -> [[Shortcut; COLS]; ROWS] {
+    const LAYERS: [u8; 4] = [0, MOD_CTRL, MOD_SHIFT, MOD_CTRL | MOD_SHIFT];
+    let mut shortcuts = [[Shortcut::key(KEY_F13); COLS]; ROWS];
+    let mut idx = 0;
+    while idx < ROWS * COLS {
+        shortcuts[idx / COLS][idx % COLS] =
+            Shortcut::key(KEY_F13 + (idx % 12) as u8).with(LAYERS[idx / 12 % LAYERS.len()]);
+        idx += 1;
+    }
+    shortcuts
+}
+
+/// Boot protocol keyboard: modifier bits, a reserved byte and six keys
+#[rustfmt::skip]
+pub const KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xA1, 0x01, // Collection (Application)
+    0x05, 0x07, //   Usage Page (Keyboard)
+    0x19, 0xE0, //   Usage Minimum (Left Control)
+    0x29, 0xE7, //   Usage Maximum (Right GUI)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x01, //   Input (Constant)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x29, 0xFF, //   Usage Maximum (0xFF)
+    0x26, 0xFF, 0x00, //   Logical Maximum (0xFF)
+    0x95, 0x06, //   Report Count (6)
+    0x81, 0x00, //   Input (Data, Array, Absolute)
+    0xC0, // End Collection
+];
+
+pub type KeyboardReport = [u8; 8];
+
+/// Report holding `shortcut` down. `None` is the release report that has to follow every press.
+pub fn keyboard_report(shortcut: Option<Shortcut>) -> KeyboardReport {
+    let mut report = [0; 8];
+    if let Some(shortcut) = shortcut {
+        report[0] = shortcut.modifiers;
+        report[2] = shortcut.key;
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_keys_wrap_with_modifiers() {
+        let keys = function_keys::<3, 5>();
+        assert_eq!(keys[0][0], Shortcut::key(KEY_F13));
+        assert_eq!(keys[2][1], Shortcut::key(KEY_F13 + 11));
+        assert_eq!(keys[2][2], Shortcut::key(KEY_F13).with(MOD_CTRL));
+        assert_eq!(
+            keyboard_report(Some(keys[2][4])),
+            [MOD_CTRL, 0, KEY_F13 + 2, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(keyboard_report(None), [0; 8]);
+    }
+
+    #[test]
+    fn media_keys_report_debounced_presses() {
+        let mut keys = MediaKeys::<2>::new();
+        for _ in 1..crate::buttons::DEBOUNCE_SAMPLES {
+            assert!(keys.update([false, true]).is_empty());
+        }
+        let events = keys.update([false, true]);
+        assert_eq!(
+            events.as_slice(),
+            [MediaKeyEvent {
+                key: ConsumerKey::NextTrack,
+                event: ButtonEvent::Pressed
+            }]
+        );
+        assert_eq!(events[0].report(), [0xB5, 0]);
+        assert_eq!(
+            MediaKeyEvent {
+                key: ConsumerKey::Mute,
+                event: ButtonEvent::Released
+            }
+            .report(),
+            [0, 0]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! I2C bus shared by every device on it: the display, the MAX17048 of `battery`, the BH1750 of
+//! `ambient-light` and whatever else is wired to the pins, e.g. an external ADC or a GPIO expander.
+//! Each of them gets an [I2cProxy] and every transfer holds a critical section, so the tasks can
+//! use their devices without knowing about each other. The SSD1306 driver sends a frame in 16 byte
+//! chunks, so the critical sections stay short even while it is flushed.
+//!
+//! A glitch on the cable can leave a device in the middle of a byte, holding SDA low so nobody
+//! else can talk. [clear_bus] frees it before the bus is set up again.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embedded_hal_027::blocking::i2c::{Read, Write, WriteRead};
+// InputPin is behind the `unproven` feature of embedded-hal, which esp-hal enables
+#[cfg(feature = "hal")]
+use embedded_hal_027::{
+    blocking::delay::DelayUs,
+    digital::v2::{InputPin, OutputPin},
+};
+
+pub struct SharedI2c<I> {
+    bus: Mutex<RefCell<Option<I>>>,
+}
+
+impl<I> SharedI2c<I> {
+    pub const fn new() -> Self {
+        Self {
+            bus: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Hands over the bus, replacing the previous one. Called at boot and again by the panic
+    /// handler and after [clear_bus], which set the display up from scratch.
+    pub fn set(&self, bus: I) {
+        critical_section::with(|cs| self.bus.borrow_ref_mut(cs).replace(bus));
+    }
+
+    pub fn proxy(&'static self) -> I2cProxy<I> {
+        I2cProxy { shared: self }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut I) -> R) -> R {
+        critical_section::with(|cs| {
+            let mut bus = self.bus.borrow_ref_mut(cs);
+            f(bus.as_mut().expect("I2C bus used before it was set"))
+        })
+    }
+}
+
+impl<I> Default for SharedI2c<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handle of one device on a [SharedI2c]
+pub struct I2cProxy<I: 'static> {
+    shared: &'static SharedI2c<I>,
+}
+
+impl<I: Write> Write for I2cProxy<I> {
+    type Error = I::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.shared.with(|bus| bus.write(address, bytes))
+    }
+}
+
+impl<I: Read> Read for I2cProxy<I> {
+    type Error = I::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.shared.with(|bus| bus.read(address, buffer))
+    }
+}
+
+impl<I: WriteRead> WriteRead for I2cProxy<I> {
+    type Error = I::Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.shared
+            .with(|bus| bus.write_read(address, bytes, buffer))
+    }
+}
+
+/// Clocks SCL until a device stuck in the middle of a byte has shifted it out and lets go of SDA,
+/// then sends a STOP. `sda` and `scl` are the pins of the bus as open-drain outputs, taken from
+/// the I2C peripheral for the time being. Nine clocks are enough for the byte and its ACK.
+#[cfg(feature = "hal")]
+pub fn clear_bus<SDA, SCL>(sda: &mut SDA, scl: &mut SCL, delay: &mut impl DelayUs<u32>)
+where
+    SDA: InputPin + OutputPin,
+    SCL: OutputPin,
+{
+    // Half periods of 100 kHz, which every device keeps up with
+    const HALF_PERIOD_US: u32 = 5;
+    sda.set_high().ok();
+    scl.set_high().ok();
+    for _ in 0..9 {
+        if sda.is_high().unwrap_or(false) {
+            break;
+        }
+        scl.set_low().ok();
+        delay.delay_us(HALF_PERIOD_US);
+        scl.set_high().ok();
+        delay.delay_us(HALF_PERIOD_US);
+    }
+    // SDA rising while SCL is high
+    scl.set_low().ok();
+    sda.set_low().ok();
+    delay.delay_us(HALF_PERIOD_US);
+    scl.set_high().ok();
+    delay.delay_us(HALF_PERIOD_US);
+    sda.set_high().ok();
+    delay.delay_us(HALF_PERIOD_US);
+}
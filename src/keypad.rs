@@ -0,0 +1,63 @@
+//! Key matrix of the macro pad. Each row is driven low in turn and the columns of the pressed keys
+//! read low through their pull-ups. Every key is debounced on its own and its presses and releases
+//! are queued as [KeyEvent]s for the task sending the shortcuts.
+
+use heapless::Vec;
+
+use crate::{
+    buttons::{ButtonEvent, Debouncer},
+    globals::{KEYPAD_COLS, KEYPAD_ROWS, KEYPAD_SHORTCUTS},
+    hid::Shortcut,
+};
+
+/// Press or release of the key at `row` and `col`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyEvent {
+    pub row: usize,
+    pub col: usize,
+    pub event: ButtonEvent,
+}
+
+impl KeyEvent {
+    /// Shortcut of the key in [KEYPAD_SHORTCUTS]
+    pub fn shortcut(&self) -> Shortcut {
+        KEYPAD_SHORTCUTS[self.row][self.col]
+    }
+}
+
+/// Debounced state of every key
+pub struct Keypad {
+    keys: [[Debouncer; KEYPAD_COLS]; KEYPAD_ROWS],
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Self {
+            keys: core::array::from_fn(|_| core::array::from_fn(|_| Debouncer::new())),
+        }
+    }
+
+    /// Give the raw state of each key of `row` while it is driven. Returns the keys whose state
+    /// changed, in column order.
+    pub fn update_row(
+        &mut self,
+        row: usize,
+        pressed: [bool; KEYPAD_COLS],
+    ) -> Vec<KeyEvent, KEYPAD_COLS> {
+        self.keys[row]
+            .iter_mut()
+            .zip(pressed)
+            .enumerate()
+            .filter_map(|(col, (key, pressed))| {
+                let event = key.update(pressed)?;
+                Some(KeyEvent { row, col, event })
+            })
+            .collect()
+    }
+}
+
+impl Default for Keypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
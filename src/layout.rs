@@ -0,0 +1,271 @@
+use embedded_graphics::{
+    geometry::{Point, Size},
+    mono_font::MonoTextStyle,
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+};
+
+use crate::style::{TEXT_STYLE, TEXT_STYLE_SMALL};
+
+/// Height reserved for the title and status at the top of the display
+pub const TITLE_HEIGHT: u32 = 12;
+pub const MAX_ROW_HEIGHT: u32 = 12;
+pub const MAX_BAR_HEIGHT: u32 = 7;
+/// Space between the left edge of a column and the value label
+pub const LABEL_MARGIN: i32 = 2;
+/// More columns than this would leave no room for the bars, channels are paged instead
+pub const MAX_COLUMNS: usize = 2;
+/// Vertical bars are not made wider than this even when there is room
+pub const MAX_VERTICAL_BAR_WIDTH: u32 = 16;
+
+/// Size of the arrow shown next to a bar after its value changed
+pub const TREND_ARROW_SIZE: Size = Size::new(3, 3);
+
+/// Fonts tried in order, each with the smallest row height its text still fits in
+const FONTS: [(MonoTextStyle<'static, BinaryColor>, u32); 2] =
+    [(TEXT_STYLE, 10), (TEXT_STYLE_SMALL, 8)];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BarOrientation {
+    /// One row per channel with the label left of the bar
+    Horizontal,
+    /// One column per channel, bars grow upward with the value underneath
+    Vertical,
+    /// Vertical on displays that are taller than wide, e.g. rotated by 90 degrees
+    Auto,
+}
+
+/// Positions of the channel rows. Everything is relative to the top left corner of the display.
+#[derive(Clone, Copy)]
+pub struct Layout {
+    /// Never [BarOrientation::Auto]
+    pub orientation: BarOrientation,
+    pub text_style: MonoTextStyle<'static, BinaryColor>,
+    pub columns: usize,
+    pub rows_per_column: usize,
+    /// More than one when all channels do not fit on the display at once
+    pub pages: usize,
+    pub line_spacing: i32,
+    pub column_width: i32,
+    /// Only the value is shown in the label when channels are split into columns
+    pub compact_labels: bool,
+    /// Where the value starts when the index is replaced with an icon. Compact labels have no
+    /// index, so there is no room for an icon either. Center of the value in vertical layouts.
+    pub value_x_offset: i32,
+    /// Text baseline of the first row
+    pub vol_value_y_offset: i32,
+    pub vol_bar_x_offset: i32,
+    /// Top of the bar in the first row
+    pub vol_bar_y_offset: i32,
+    pub vol_bar_size: Size,
+}
+
+impl Layout {
+    /// `value_chars` is the length of the longest value label, e.g. 3 for `100`.
+    pub fn new(
+        size: Size,
+        channels: usize,
+        value_chars: usize,
+        orientation: BarOrientation,
+    ) -> Self {
+        match orientation {
+            BarOrientation::Horizontal => Self::horizontal(size, channels, value_chars),
+            BarOrientation::Vertical => Self::vertical(size, channels, value_chars),
+            BarOrientation::Auto if size.height > size.width => {
+                Self::vertical(size, channels, value_chars)
+            }
+            BarOrientation::Auto => Self::horizontal(size, channels, value_chars),
+        }
+    }
+
+    /// Prefers a single column with the regular font, then the small font, then the same with
+    /// [MAX_COLUMNS] columns. If the channels still do not fit they are split into pages.
+    fn horizontal(size: Size, channels: usize, value_chars: usize) -> Self {
+        let available = size.height.saturating_sub(TITLE_HEIGHT);
+        let rows_fit = |min_row_height: u32| (available / min_row_height).max(1) as usize;
+
+        let fitting = (1..=MAX_COLUMNS).find_map(|columns| {
+            FONTS
+                .iter()
+                .find(|(_, min_row_height)| rows_fit(*min_row_height) * columns >= channels)
+                .map(|(style, _)| (*style, columns, channels.div_ceil(columns), 1))
+        });
+        let (text_style, columns, rows_per_column, pages) = fitting.unwrap_or_else(|| {
+            let (style, min_row_height) = FONTS[FONTS.len() - 1];
+            let rows = rows_fit(min_row_height);
+            let pages = channels.div_ceil(rows * MAX_COLUMNS);
+            (style, MAX_COLUMNS, rows, pages)
+        });
+
+        let line_spacing = (available / rows_per_column.max(1) as u32).min(MAX_ROW_HEIGHT);
+        let column_width = (size.width / columns as u32) as i32;
+        let compact_labels = columns > 1;
+
+        // Label is "0: 100" or just "100" when compact, followed by a small gap
+        let index_digits = channels.saturating_sub(1).max(1).ilog10() as i32 + 1;
+        let value_chars = value_chars as i32;
+        let label_chars = if compact_labels {
+            value_chars
+        } else {
+            index_digits + 2 + value_chars
+        };
+        let label_gap = if compact_labels { 4 } else { 7 };
+        let char_width = text_style.font.character_size.width as i32;
+        let value_x_offset = if compact_labels {
+            LABEL_MARGIN
+        } else {
+            // Index followed by ": "
+            LABEL_MARGIN + (index_digits + 2) * char_width
+        };
+        let vol_bar_x_offset = LABEL_MARGIN + label_chars * char_width + label_gap;
+        let vol_bar_width = (column_width - vol_bar_x_offset - 3).max(1) as u32;
+        let vol_bar_height = MAX_BAR_HEIGHT.min(line_spacing.saturating_sub(3)).max(1);
+        // 2 px above the bottom of the row
+        let vol_value_y_offset = (TITLE_HEIGHT + line_spacing) as i32 - 2;
+
+        Self {
+            orientation: BarOrientation::Horizontal,
+            text_style,
+            columns,
+            rows_per_column,
+            pages,
+            line_spacing: line_spacing as i32,
+            column_width,
+            compact_labels,
+            value_x_offset,
+            vol_value_y_offset,
+            vol_bar_x_offset,
+            vol_bar_y_offset: vol_value_y_offset - vol_bar_height as i32,
+            vol_bar_size: Size::new(vol_bar_width, vol_bar_height),
+        }
+    }
+
+    /// All channels side by side with the regular font if the labels fit, then with the small
+    /// font. Otherwise as many as fit are shown per page.
+    fn vertical(size: Size, channels: usize, value_chars: usize) -> Self {
+        let available = size.height.saturating_sub(TITLE_HEIGHT);
+        let columns_fit = |style: &MonoTextStyle<'static, BinaryColor>| {
+            let label_width = value_chars as u32 * style.font.character_size.width + 1;
+            (size.width / label_width).max(1) as usize
+        };
+
+        let (text_style, columns, pages) = FONTS
+            .iter()
+            .find(|(style, _)| columns_fit(style) >= channels)
+            .map(|(style, _)| (*style, channels.max(1), 1))
+            .unwrap_or_else(|| {
+                let (style, _) = FONTS[FONTS.len() - 1];
+                let columns = columns_fit(&style);
+                (style, columns, channels.div_ceil(columns))
+            });
+
+        let column_width = (size.width / columns as u32) as i32;
+        let char_height = text_style.font.character_size.height;
+        let vol_bar_width = ((column_width - 4).max(1) as u32).min(MAX_VERTICAL_BAR_WIDTH);
+        let vol_bar_height = available.saturating_sub(char_height + 4).max(1);
+
+        Self {
+            orientation: BarOrientation::Vertical,
+            text_style,
+            columns,
+            rows_per_column: 1,
+            pages,
+            line_spacing: available as i32,
+            column_width,
+            compact_labels: true,
+            value_x_offset: column_width / 2,
+            // Value at the bottom of the column, bar above it
+            vol_value_y_offset: (TITLE_HEIGHT + available) as i32 - 2,
+            vol_bar_x_offset: (column_width - vol_bar_width as i32) / 2,
+            vol_bar_y_offset: TITLE_HEIGHT as i32 + 2,
+            vol_bar_size: Size::new(vol_bar_width, vol_bar_height),
+        }
+    }
+
+    /// Whether an icon of `size` fits in place of the index
+    pub fn icon_fits(&self, size: Size) -> bool {
+        self.orientation == BarOrientation::Horizontal
+            && size.width as i32 <= self.value_x_offset - LABEL_MARGIN
+            && size.height as i32 <= self.line_spacing - 2
+    }
+
+    /// Top left corner of the change arrow relative to the row origin, `None` when there is no room
+    pub fn trend_arrow_offset(&self) -> Option<Point> {
+        let width = TREND_ARROW_SIZE.width as i32;
+        match self.orientation {
+            // In the gap between the label and the bar, vertically centered on the bar
+            BarOrientation::Horizontal => Some(Point::new(
+                self.vol_bar_x_offset - width - 1,
+                self.vol_bar_y_offset
+                    + self
+                        .vol_bar_size
+                        .height
+                        .saturating_sub(TREND_ARROW_SIZE.height) as i32
+                        / 2,
+            )),
+            // Right of the top of the bar
+            _ => {
+                let x = self.vol_bar_x_offset + self.vol_bar_size.width as i32 + 1;
+                (x + width <= self.column_width).then_some(Point::new(x, self.vol_bar_y_offset))
+            }
+        }
+    }
+
+    pub fn channels_per_page(&self) -> usize {
+        self.columns * self.rows_per_column
+    }
+
+    pub fn page_of(&self, idx: usize) -> usize {
+        idx / self.channels_per_page()
+    }
+
+    /// Top left corner of the row of channel `idx`, rows are filled column by column.
+    /// `None` when the channel is not on `page`.
+    pub fn row_origin(&self, idx: usize, page: usize) -> Option<Point> {
+        if self.page_of(idx) != page {
+            return None;
+        }
+        let idx = idx % self.channels_per_page();
+        let column = (idx / self.rows_per_column) as i32;
+        let row = (idx % self.rows_per_column) as i32;
+        Some(Point::new(
+            column * self.column_width,
+            row * self.line_spacing,
+        ))
+    }
+
+    /// Area covered by the row at `row_origin`, see [Layout::row_origin]
+    pub fn row_area(&self, row_origin: Point) -> Rectangle {
+        Rectangle::new(
+            row_origin + Point::new(0, TITLE_HEIGHT as i32),
+            Size::new(self.column_width as u32, self.line_spacing as u32),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every channel gets a row fully inside the display on some page
+    fn assert_rows_fit(size: Size, orientation: BarOrientation) {
+        let layout = Layout::new(size, 4, 3, orientation);
+        let display = Rectangle::new(Point::zero(), size);
+        for idx in 0..4 {
+            let origin = layout.row_origin(idx, layout.page_of(idx)).unwrap();
+            let area = layout.row_area(origin);
+            assert!(display.contains(area.top_left), "{idx} {area:?}");
+            assert!(
+                display.contains(area.bottom_right().unwrap()),
+                "{idx} {area:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rows_fit() {
+        assert_rows_fit(Size::new(128, 64), BarOrientation::Horizontal);
+        assert_rows_fit(Size::new(128, 32), BarOrientation::Horizontal);
+        assert_rows_fit(Size::new(64, 128), BarOrientation::Auto);
+    }
+}
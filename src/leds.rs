@@ -0,0 +1,99 @@
+use esp_hal::rmt::Channel;
+use esp_hal_smartled::SmartLedsAdapter;
+use smart_leds::{brightness, SmartLedsWrite, RGB8};
+
+use crate::{
+    fixed::gamma,
+    globals::{INPUT_COUNT, LEDS_PER_CHANNEL, LED_BRIGHTNESS},
+    numerics::wire_to_percent,
+    scale_to_range,
+};
+
+pub const LED_COUNT: usize = INPUT_COUNT * LEDS_PER_CHANNEL;
+/// RMT pulses needed for the strip, 24 per LED and an end marker
+pub const LED_BUFFER_SIZE: usize = LED_COUNT * 24 + 1;
+
+pub type LedStrip = SmartLedsAdapter<Channel<0>, LED_BUFFER_SIZE>;
+
+/// First LED of a channel at zero volume
+const MUTE_COLOR: RGB8 = RGB8::new(0, 0, 255);
+/// Every LED of a channel muted on the PC
+const HOST_MUTE_COLOR: RGB8 = RGB8::new(255, 0, 255);
+const OFF: RGB8 = RGB8::new(0, 0, 0);
+/// Segments of the other channels are dimmed by this much while one is in focus
+const UNFOCUSED_DIVISOR: u8 = 4;
+
+/// Shows the level of every channel on its own segment of [LEDS_PER_CHANNEL] LEDs.
+/// Channel 0 is at the start of the strip.
+pub struct LedBar {
+    strip: LedStrip,
+    /// Colors currently on the strip, the strip is only written when they change
+    shown: [RGB8; LED_COUNT],
+}
+
+impl LedBar {
+    pub fn new(strip: LedStrip) -> Self {
+        Self {
+            strip,
+            // Anything but off so the first update is always written
+            shown: [MUTE_COLOR; LED_COUNT],
+        }
+    }
+
+    /// Give the positions of the pots (0-1023), from [crate::events::Event::InputChanged], the
+    /// channels the host reports as muted and the [crate::activity::ChannelActivity::focused]
+    /// channel, if any, which is highlighted
+    pub fn show(
+        &mut self,
+        positions: &[u16; INPUT_COUNT],
+        host_mutes: &[bool; INPUT_COUNT],
+        focused: Option<usize>,
+    ) {
+        let mut colors = [OFF; LED_COUNT];
+        for (channel, ((segment, position), muted)) in colors
+            .chunks_exact_mut(LEDS_PER_CHANNEL)
+            .zip(positions)
+            .zip(host_mutes)
+            .enumerate()
+        {
+            if *muted {
+                segment.fill(HOST_MUTE_COLOR);
+            } else {
+                segment_colors(wire_to_percent(*position), segment);
+            }
+            if focused.is_some_and(|focused| focused != channel) {
+                for led in segment {
+                    *led = RGB8::new(
+                        led.r / UNFOCUSED_DIVISOR,
+                        led.g / UNFOCUSED_DIVISOR,
+                        led.b / UNFOCUSED_DIVISOR,
+                    );
+                }
+            }
+        }
+        if colors == self.shown {
+            return;
+        }
+        self.strip
+            .write(brightness(colors.iter().cloned(), LED_BRIGHTNESS))
+            .unwrap(); // TODO propagate error?
+        self.shown = colors;
+    }
+}
+
+/// Lights LEDs in proportion to `volume` (0-100). Every lit LED is colored by its position on
+/// the segment, from green at the bottom to red at the top. The mix is gamma corrected, so the
+/// colors in between look as bright as the ends.
+fn segment_colors(volume: u16, segment: &mut [RGB8]) {
+    if volume == 0 {
+        segment[0] = MUTE_COLOR;
+        return;
+    }
+    let len = segment.len() as u16;
+    // Any volume above zero lights at least one LED
+    let lit = scale_to_range(volume, 0, 100, 0, len).max(1);
+    for (idx, led) in segment.iter_mut().take(lit as usize).enumerate() {
+        let red = scale_to_range(idx as u16, 0, (len - 1).max(1), 0, 255) as u8;
+        *led = RGB8::new(gamma(red), gamma(255 - red), 0);
+    }
+}
@@ -1,140 +1,695 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
+pub mod activity;
+#[cfg(feature = "adc-dma")]
+pub mod adc_dma;
+pub mod ambient;
+#[cfg(feature = "hal")]
+pub mod analog;
+pub mod animation;
+pub mod assets;
+pub mod battery;
+#[cfg(feature = "ble")]
+pub mod ble;
+pub mod buttons;
+pub mod channels;
+pub mod cli;
+pub mod diagnostics;
+pub mod encoder;
+pub mod error;
+#[cfg(any(feature = "espnow-remote", feature = "espnow-dongle"))]
+pub mod espnow;
+pub mod events;
+pub mod expander;
+#[cfg(feature = "feedback")]
+pub mod feedback;
+pub mod fixed;
+#[cfg(feature = "display")]
+pub mod framebuffer;
+pub mod gestures;
 pub mod globals;
+pub mod hid;
+pub mod i2c_bus;
+#[cfg(feature = "keypad")]
+pub mod keypad;
+pub mod layout;
+#[cfg(feature = "leds")]
+pub mod leds;
+mod log;
+pub mod menu;
+pub mod midi;
+#[cfg(all(feature = "esp32c3", not(feature = "embassy")))]
+pub mod mono;
+pub mod motion;
+pub mod motor;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod numerics;
+pub mod orientation;
+#[cfg(feature = "osc")]
+pub mod osc;
+#[cfg(feature = "ota")]
+pub mod ota;
+#[cfg(feature = "oversampling")]
+pub mod oversampling;
+pub mod pages;
+#[cfg(feature = "hal")]
+pub mod panic_persist;
+pub mod pipeline;
+#[cfg(feature = "light-sleep")]
+pub mod power;
+pub mod profiles;
+pub mod protocol;
+pub mod reset;
+pub mod roles;
+pub mod sampling;
+pub mod screensaver;
+pub mod self_test;
+pub mod serial;
+pub mod settings;
+pub mod status_led;
 pub mod style;
+pub mod supply;
+pub mod text;
+pub mod touch;
+pub mod units;
+pub mod watchdog;
+pub mod widgets;
+#[cfg(feature = "wifi")]
+pub mod wifi;
 
-use core::fmt::Write;
-use embedded_graphics::{
-    geometry::AnchorPoint,
-    pixelcolor::BinaryColor,
-    prelude::*,
-    primitives::Rectangle,
-    text::{Alignment, Text},
-};
-use embedded_hal_027::adc::Channel;
-use enum_dispatch::enum_dispatch;
-use esp_hal::{
-    adc::{AdcCalCurve, AdcCalScheme, AdcPin, ADC},
-    gpio::{Analog, GpioPin},
-    i2c::I2C,
-    peripherals::{ADC1, I2C0},
-    prelude::*,
-};
-use globals::{INPUT_COUNT, MAX_ANALOG_VALUE, ZERO_CUTOFF};
+#[cfg(any(
+    all(feature = "ble", feature = "wifi"),
+    all(feature = "ble", feature = "espnow-remote"),
+    all(feature = "ble", feature = "espnow-dongle"),
+    all(feature = "wifi", feature = "espnow-remote"),
+    all(feature = "wifi", feature = "espnow-dongle"),
+    all(feature = "espnow-remote", feature = "espnow-dongle"),
+))]
+compile_error!(
+    "Only one of the features `ble`, `wifi`, `espnow-remote` and `espnow-dongle` can be enabled"
+);
+
+#[cfg(any(
+    all(feature = "esp32c3", feature = "esp32"),
+    all(feature = "esp32c3", feature = "esp32s3"),
+    all(feature = "esp32", feature = "esp32s3"),
+))]
+compile_error!("Only one of the chip features `esp32c3`, `esp32` and `esp32s3` can be enabled");
+
+#[cfg(all(
+    feature = "hal",
+    not(any(feature = "esp32c3", feature = "esp32", feature = "esp32s3"))
+))]
+compile_error!("Select the chip with the `esp32c3`, `esp32` or `esp32s3` feature");
+
+#[cfg(all(
+    any(feature = "esp32", feature = "esp32s3"),
+    any(
+        feature = "leds",
+        feature = "status-led",
+        feature = "light-sleep",
+        feature = "adc-dma",
+        feature = "motorized-faders"
+    )
+))]
+compile_error!(
+    "Features `leds`, `status-led`, `light-sleep`, `adc-dma` and `motorized-faders` are only \
+     supported on the ESP32-C3"
+);
+
+#[cfg(all(feature = "mqtt", feature = "osc"))]
+compile_error!("Features `mqtt` and `osc` both replace the TCP stream, only one can be enabled");
+
+#[cfg(all(feature = "leds", feature = "status-led"))]
+compile_error!("Features `leds` and `status-led` both use GPIO8");
+
+#[cfg(all(
+    feature = "embassy",
+    any(
+        feature = "ble",
+        feature = "wifi",
+        feature = "espnow-remote",
+        feature = "espnow-dongle"
+    )
+))]
+compile_error!("The embassy time driver and the radio both use SYSTIMER alarm0");
+
+#[cfg(all(feature = "oversampling", feature = "adc-dma"))]
+compile_error!(
+    "Feature `oversampling` only applies to the blocking reads, `adc-dma` replaces them"
+);
+
+#[cfg(all(
+    any(feature = "supply-monitor", feature = "battery"),
+    feature = "adc-dma"
+))]
+compile_error!(
+    "Features `supply-monitor` and `battery` use blocking reads, `adc-dma` owns the ADC"
+);
+
+#[cfg(all(
+    feature = "battery",
+    not(any(feature = "ble", feature = "wifi", feature = "espnow-remote"))
+))]
+compile_error!("Feature `battery` is for the wireless builds, `ble`, `wifi` or `espnow-remote`");
+
+#[cfg(all(feature = "supply-monitor", feature = "embassy"))]
+compile_error!("Feature `supply-monitor` is only wired up in the RTIC app");
+
+#[cfg(all(feature = "ambient-light", feature = "embassy"))]
+compile_error!("Feature `ambient-light` is only wired up in the RTIC app");
+
+#[cfg(all(feature = "expander", feature = "embassy"))]
+compile_error!("Feature `expander` is only wired up in the RTIC app");
+
+#[cfg(all(feature = "auto-rotate", feature = "embassy"))]
+compile_error!("Feature `auto-rotate` is only wired up in the RTIC app");
+
+#[cfg(all(
+    feature = "motorized-faders",
+    any(feature = "embassy", feature = "espnow-dongle")
+))]
+compile_error!("Feature `motorized-faders` is only wired up in the RTIC app with pots of its own");
+
+#[cfg(all(feature = "touch-sense", feature = "embassy"))]
+compile_error!("Feature `touch-sense` is only wired up in the RTIC app");
+
+#[cfg(all(feature = "profiles", feature = "embassy"))]
+compile_error!("Feature `profiles` is only wired up in the RTIC app");
+
+#[cfg(all(feature = "settings", feature = "embassy"))]
+compile_error!("Feature `settings` is only wired up in the RTIC app");
+
+#[cfg(all(
+    feature = "display-slave",
+    any(
+        feature = "embassy",
+        not(feature = "display"),
+        feature = "ble",
+        feature = "wifi",
+        feature = "espnow-remote",
+        feature = "espnow-dongle"
+    )
+))]
+compile_error!(
+    "Feature `display-slave` is only wired up in the RTIC app with a display and a serial host"
+);
+
+#[cfg(all(
+    feature = "display-slave",
+    any(
+        feature = "adc-dma",
+        feature = "oversampling",
+        feature = "motorized-faders",
+        feature = "touch-sense",
+        feature = "profiles",
+        feature = "supply-monitor",
+        feature = "ambient-light",
+        feature = "expander",
+        feature = "auto-rotate",
+        feature = "light-sleep",
+        feature = "host-switch",
+        feature = "feedback"
+    )
+))]
+compile_error!(
+    "Features read along with the pots can not be combined with `display-slave`, it has none"
+);
+
+#[cfg(all(feature = "embassy", not(feature = "display")))]
+compile_error!("The embassy app always draws to the display, enable the `display` feature");
+
+#[cfg(all(
+    not(feature = "display"),
+    any(
+        feature = "settings",
+        feature = "ambient-light",
+        feature = "auto-rotate"
+    )
+))]
+compile_error!("Features `settings`, `ambient-light` and `auto-rotate` need the display");
+
+#[cfg(all(
+    feature = "usb-serial-jtag",
+    any(not(feature = "esp32c3"), feature = "embassy")
+))]
+compile_error!("Feature `usb-serial-jtag` is only wired up in the RTIC app on the ESP32-C3");
+
+#[cfg(all(
+    any(
+        feature = "usb-midi",
+        feature = "gamepad",
+        feature = "keypad",
+        feature = "media-keys"
+    ),
+    not(feature = "esp32s3")
+))]
+compile_error!(
+    "Features `usb-midi`, `gamepad`, `keypad` and `media-keys` need the USB-OTG peripheral of the \
+     ESP32-S3"
+);
+
+#[cfg(all(
+    feature = "host-switch",
+    any(
+        feature = "ble",
+        feature = "wifi",
+        feature = "espnow-remote",
+        feature = "espnow-dongle"
+    )
+))]
+compile_error!("Feature `host-switch` is only read along with the pots of the serial builds");
+
+use core::panic::PanicInfo;
+use globals::MAX_ANALOG_VALUE;
 use heapless::String;
-use ssd1306::{mode::BufferedGraphicsMode, prelude::*, Ssd1306};
-use style::{FILL_RECT_STYLE, OUTER_RECT_STYLE, TEXT_STYLE, TEXT_STYLE_BOLD};
-
-pub type Ssd1306Display = Ssd1306<
-    I2CInterface<I2C<'static, I2C0>>,
-    DisplaySize128x64,
-    BufferedGraphicsMode<DisplaySize128x64>,
->;
-
-#[enum_dispatch]
-pub trait ReadAnalog {
-    fn read(&mut self, adc: &mut ADC<ADC1>) -> u16;
-    fn read_multi_sample(&mut self, adc: &mut ADC<ADC1>, sample_size: u32) -> u16;
-}
+use numerics::{map_range, Rounding, WIRE_MAX};
 
-/// Allows storage for all implemented analog pins. Currently **only** supports ADC1 pins.
-///
-/// Regardless of the enum variant actually stored the analog input value can be read by using [AnyAnalogPin]
-#[enum_dispatch(ReadAnalog)]
-pub enum AnyAnalogPin {
-    AO(AdcPin<GpioPin<Analog, 0>, ADC1, AdcCalCurve<ADC1>>),
-    A1(AdcPin<GpioPin<Analog, 1>, ADC1, AdcCalCurve<ADC1>>),
-    A2(AdcPin<GpioPin<Analog, 2>, ADC1, AdcCalCurve<ADC1>>),
-    A3(AdcPin<GpioPin<Analog, 3>, ADC1, AdcCalCurve<ADC1>>),
-    A4(AdcPin<GpioPin<Analog, 4>, ADC1, AdcCalCurve<ADC1>>),
-}
+// Only DisplayState and the types it draws to use these, a headless build leaves them out
+#[cfg(feature = "display")]
+use {
+    animation::BarAnimator,
+    assets::Icon,
+    core::fmt::Debug,
+    diagnostics::AdcStats,
+    embedded_graphics::{
+        geometry::AnchorPoint,
+        image::Image,
+        pixelcolor::BinaryColor,
+        prelude::*,
+        primitives::{Rectangle, Triangle},
+        text::{Alignment, Text},
+    },
+    globals::{
+        BAR_EASING, BAR_ORIENTATION, DISPLAY_CONTRAST, DISPLAY_DIM_CONTRAST, HOST_VOLUMES_TIMEOUT,
+        INPUT_COUNT, LEVELS_TIMEOUT, NAME_SCROLL_STEP, NOW_PLAYING_SCROLL_SPEED,
+        SCREENSAVER_INVERT, SCREENSAVER_SHIFT_PERIOD, SUPPLY_LOW_CONTRAST, TREND_TIME, ZOOM_VIEW,
+    },
+    layout::{BarOrientation, Layout, LABEL_MARGIN, TITLE_HEIGHT, TREND_ARROW_SIZE},
+    log::{debug, info},
+    orientation::Rotation,
+    pages::{
+        ChannelName, DiagnosticsPage, InfoPage, MenuPage, NowPlaying, NowPlayingPage, Page,
+        PanicPage, Screen, SelfTestPage, SplashPage, ZoomPage,
+    },
+    protocol::HostCommand,
+    reset::BootInfo,
+    screensaver::Screensaver,
+    self_test::SelfTest,
+    settings::{DisplayTimeout, SettingsView},
+    ssd1306::{mode::BufferedGraphicsMode, prelude::*, Ssd1306},
+    style::{Theme, FILL_RECT_STYLE, OUTER_RECT_STYLE, TEXT_STYLE},
+    text::{format_truncated, write_truncated},
+    units::Units,
+    widgets::{BarStyle, VolumeBar},
+};
 
-impl<T, Cal> ReadAnalog for AdcPin<T, ADC1, Cal>
-where
-    T: Channel<ADC1, ID = u8>,
-    Cal: AdcCalScheme<ADC1>,
-{
-    fn read(&mut self, adc: &mut ADC<ADC1>) -> u16 {
-        match nb::block!(adc.read(self)).expect("Failed to read analog value") {
-            x if x < ZERO_CUTOFF => 0,
-            x => x,
+#[cfg(feature = "embassy")]
+pub use analog::read_multi_sample_async;
+#[cfg(feature = "hal")]
+pub use analog::{AnyAnalogPin, ReadAnalog};
+
+#[cfg(all(feature = "display", not(feature = "display-128x32")))]
+pub type DisplaySize = DisplaySize128x64;
+#[cfg(all(feature = "display", not(feature = "display-128x32")))]
+pub const DISPLAY_SIZE: DisplaySize = DisplaySize128x64;
+#[cfg(all(feature = "display", feature = "display-128x32"))]
+pub type DisplaySize = DisplaySize128x32;
+#[cfg(all(feature = "display", feature = "display-128x32"))]
+pub const DISPLAY_SIZE: DisplaySize = DisplaySize128x32;
+
+/// Display mounted sideways, with [BarOrientation::Auto] the bars are drawn vertically
+#[cfg(all(feature = "display", feature = "display-rotated"))]
+pub const DISPLAY_ROTATION: DisplayRotation = DisplayRotation::Rotate90;
+#[cfg(all(feature = "display", not(feature = "display-rotated")))]
+pub const DISPLAY_ROTATION: DisplayRotation = DisplayRotation::Rotate0;
+
+#[cfg(feature = "display")]
+impl From<DisplayRotation> for Rotation {
+    fn from(rotation: DisplayRotation) -> Self {
+        match rotation {
+            DisplayRotation::Rotate0 => Rotation::Rotate0,
+            DisplayRotation::Rotate90 => Rotation::Rotate90,
+            DisplayRotation::Rotate180 => Rotation::Rotate180,
+            DisplayRotation::Rotate270 => Rotation::Rotate270,
         }
     }
+}
 
-    fn read_multi_sample(&mut self, adc: &mut ADC<ADC1>, sample_size: u32) -> u16 {
-        let mut sum = 0u32;
-        for _ in 0..sample_size {
-            sum += match nb::block!(adc.read(self)).expect("Failed to read analog value") {
-                x if x < ZERO_CUTOFF => 0u32,
-                x => x as u32,
-            };
+#[cfg(feature = "display")]
+impl From<Rotation> for DisplayRotation {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Rotate0 => DisplayRotation::Rotate0,
+            Rotation::Rotate90 => DisplayRotation::Rotate90,
+            Rotation::Rotate180 => DisplayRotation::Rotate180,
+            Rotation::Rotate270 => DisplayRotation::Rotate270,
         }
-        (sum / sample_size) as u16 // adc.read returns u16 so the average of u16 should never be larger than u16 --> no overflow
     }
 }
 
+/// `DI` is the display interface, e.g. `I2CInterface` or `SPIInterface`
+#[cfg(feature = "display")]
+pub type Ssd1306Display<DI> = Ssd1306<DI, DisplaySize, BufferedGraphicsMode<DisplaySize>>;
+
+/// Operations [DisplayState] needs on top of [DrawTarget]. Implement this for other buffered panels
+/// or for the embedded-graphics simulator to reuse the rendering code.
+#[cfg(feature = "display")]
+pub trait DisplayFlush {
+    type Error: Debug;
+
+    /// Sends the buffered frame to the panel
+    fn flush(&mut self) -> Result<(), Self::Error>;
+    /// Sends part of the buffered frame, returns true once all of it is on the panel. The whole
+    /// frame at once unless the target can split it, like [framebuffer::Framebuffer].
+    fn flush_chunk(&mut self) -> Result<bool, Self::Error> {
+        self.flush().map(|()| true)
+    }
+    fn set_display_on(&mut self, on: bool) -> Result<(), Self::Error>;
+    fn set_invert(&mut self, invert: bool) -> Result<(), Self::Error>;
+    fn set_contrast(&mut self, contrast: u8) -> Result<(), Self::Error>;
+    /// Turns the picture, the size seen by [DrawTarget] swaps for 90 and 270 degrees
+    fn set_rotation(&mut self, rotation: DisplayRotation) -> Result<(), Self::Error>;
+    /// Sets the panel up again after it lost its state, e.g. power on a glitching cable. Only
+    /// turns it on unless the target has more to set up.
+    fn init(&mut self) -> Result<(), Self::Error> {
+        self.set_display_on(true)
+    }
+}
+
+#[cfg(feature = "display")]
+impl<DI: WriteOnlyDataCommand> DisplayFlush for Ssd1306Display<DI> {
+    type Error = <Self as DrawTarget>::Error;
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ssd1306::flush(self)
+    }
+
+    fn set_display_on(&mut self, on: bool) -> Result<(), Self::Error> {
+        Ssd1306::set_display_on(self, on)
+    }
+
+    fn set_invert(&mut self, invert: bool) -> Result<(), Self::Error> {
+        Ssd1306::set_invert(self, invert)
+    }
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<(), Self::Error> {
+        // Precharge period of Brightness::NORMAL
+        self.set_brightness(Brightness::custom(0x2, contrast))
+    }
+
+    fn set_rotation(&mut self, rotation: DisplayRotation) -> Result<(), Self::Error> {
+        Ssd1306::set_rotation(self, rotation)
+    }
+
+    fn init(&mut self) -> Result<(), Self::Error> {
+        Ssd1306::init(self)
+    }
+}
+
+/// Everything [DisplayState] draws to. Implemented for every [DisplayFlush] target, so tests can
+/// record the drawing and flushes with their own target instead of a panel.
+#[cfg(feature = "display")]
+pub trait DisplaySink: DrawTarget<Color = BinaryColor> + DisplayFlush {}
+
+#[cfg(feature = "display")]
+impl<D: DrawTarget<Color = BinaryColor> + DisplayFlush> DisplaySink for D {}
+
+/// [DisplayFlush] for the embassy build, the executor can run other tasks while the frame is sent
+#[cfg(all(feature = "display", feature = "embassy"))]
+#[allow(async_fn_in_trait)]
+pub trait DisplayFlushAsync: DisplayFlush {
+    async fn flush_async(&mut self) -> Result<(), Self::Error>;
+}
+
+/// ssd1306 0.8 only has a blocking interface, so the transfer still blocks the executor
+#[cfg(all(feature = "display", feature = "embassy"))]
+impl<DI: WriteOnlyDataCommand> DisplayFlushAsync for Ssd1306Display<DI> {
+    async fn flush_async(&mut self) -> Result<(), Self::Error> {
+        Ssd1306::flush(self)
+    }
+}
+
+/// Longest panic message shown and kept over the reset, longer ones are truncated
+pub const PANIC_MESSAGE_LEN: usize = 160;
+pub type PanicMessage = String<PANIC_MESSAGE_LEN>;
+
+/// Location and message of the panic
+pub fn panic_message(info: &PanicInfo) -> PanicMessage {
+    text::format_truncated(format_args!("{}", info))
+}
+
+/// Draws [PanicPage] for the panic handler. Errors are ignored, there is nothing left to do about them.
+#[cfg(feature = "display")]
+pub fn show_panic<D>(display: &mut D, message: &str)
+where
+    D: DisplaySink,
+{
+    let area = display.bounding_box();
+    display.clear(BinaryColor::Off).ok();
+    PanicPage { message }.draw(display, area).ok();
+    display.flush().ok();
+}
+
+/// Reading as the value sent to the host, rounded to the nearest
 pub fn scale_analog_input_to_1023(value: u16) -> u16 {
-    scale_to_range(value, 0, MAX_ANALOG_VALUE, 0, 1023)
+    map_range(value, 0..=MAX_ANALOG_VALUE, 0..=WIRE_MAX, Rounding::Nearest).unwrap_or(0)
 }
 
+/// Reading as a percentage, rounded to the nearest
 pub fn scale_analog_input_to_100(value: u16) -> u16 {
-    scale_to_range(value, 0, MAX_ANALOG_VALUE, 0, 100)
+    map_range(value, 0..=MAX_ANALOG_VALUE, 0..=100, Rounding::Nearest).unwrap_or(0)
 }
 
+/// `value` from `old_min..=old_max` to `new_min..=new_max`, rounded down like pixels that are only
+/// lit once reached. Clamped to the old range. See [map_range] for rounding to the nearest.
 pub fn scale_to_range(value: u16, old_min: u16, old_max: u16, new_min: u16, new_max: u16) -> u16 {
-    let old_range = old_max - old_min;
-    let new_range = new_max - new_min;
-    let value = value.min(old_max); // To ensure that the provided value is not larger than original max to prevent overflow
+    map_range(value, old_min..=old_max, new_min..=new_max, Rounding::Down).unwrap_or(new_min)
+}
 
-    ((value as u32 - old_min as u32) * new_range as u32 / old_range as u32 + new_min as u32) as u16
+/// Value of `x` on `curve`, points sorted by x and interpolated in between. Clamped to the first
+/// and last point outside the curve.
+pub fn interpolate(curve: &[(u32, u8)], x: u32) -> u8 {
+    let Some(upper) = curve.iter().position(|(point_x, _)| x < *point_x) else {
+        return curve[curve.len() - 1].1;
+    };
+    if upper == 0 {
+        return curve[0].1;
+    }
+    let (low_x, low_y) = curve[upper - 1];
+    let (high_x, high_y) = curve[upper];
+    let y = low_y as i32
+        + (x - low_x) as i32 * (high_y as i32 - low_y as i32) / (high_x - low_x) as i32;
+    y as u8
 }
 
+/// Text in the top right corner of the display. Longer text would overlap the title
+pub type StatusText = String<6>;
+
+/// Drawn in place of the bar of a channel the pot is not connected to, see [sampling::ChannelHealth]
+#[cfg(feature = "display")]
+const DISCONNECTED_TEXT: &str = "disconnected";
+/// Shown in place of the status while the supply is low
+#[cfg(feature = "display")]
+const SUPPLY_LOW_TEXT: &str = "LOW V";
+
 pub enum DisplayStatus {
     Changed,
     NotChanged,
 }
 
-pub struct DisplayState<'a> {
-    display: Ssd1306Display,
+impl DisplayStatus {
+    /// Changed if either `self` or `other` is changed
+    pub fn or(self, other: DisplayStatus) -> DisplayStatus {
+        match (self, other) {
+            (DisplayStatus::NotChanged, DisplayStatus::NotChanged) => DisplayStatus::NotChanged,
+            _ => DisplayStatus::Changed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DisplayPower {
+    On,
+    /// Still showing the values at [globals::DISPLAY_DIM_CONTRAST]
+    Dimmed,
+    Off,
+}
+
+/// What [Screen::Volumes] shows
+#[cfg(feature = "display")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum View {
+    /// All channels
+    Overview,
+    /// The focused channel, see [DisplayState::set_focus]
+    Zoomed { channel: usize },
+}
+
+/// Direction a channel was last moved to
+#[cfg(feature = "display")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Trend {
+    Up,
+    Down,
+}
+
+#[cfg(feature = "display")]
+pub struct DisplayState<'a, D> {
+    display: D,
     title: Option<&'a str>,
     title_position: Point,
+    status: Option<StatusText>,
+    status_position: Point,
     volumes: [u16; INPUT_COUNT],
+    /// Positions (0-100) of the pots, marked on the bars when a [roles::Role::Master] makes the
+    /// volumes lower than the positions
+    positions: [u16; INPUT_COUNT],
+    /// Actual volumes (0-100) reported by the host, marked on the bars so drift from the pots shows
+    host_volumes: [Option<u16>; INPUT_COUNT],
+    /// Time the [DisplayState::host_volumes] are dropped at unless the host reports them again
+    host_volumes_until: u64,
+    /// Channels muted on the PC, drawn crossed out
+    host_mutes: [bool; INPUT_COUNT],
+    /// Peak audio levels (0-100) streamed by the host, the bars show them instead of the volumes
+    /// with a tick at the volume
+    levels: Option<[u16; INPUT_COUNT]>,
+    /// Time the bars go back to the volumes unless the host streams [DisplayState::levels] again
+    levels_until: u64,
+    /// Bar fill drawn for [DisplayState::volumes], lags behind them when animated
+    animator: BarAnimator,
+    /// Drawn in place of the channel index when set
+    icons: [Option<Icon>; INPUT_COUNT],
+    /// Names the host gave the channels, shown on the zoomed channel
+    names: [Option<ChannelName>; INPUT_COUNT],
     ready_to_draw: bool,
-    vol_value_y_offset: i32,
-    line_spacing: i32,
-    vol_bar_x_offset: i32,
-    vol_bar_height: u32,
-    vol_bar_width: u32,
-    vol_bar_size: Size,
+    layout: Layout,
+    /// Page of channels currently shown when they do not all fit at once
+    channel_page: usize,
+    screen: Screen,
+    /// Screen to go back to when the pots move, set when [Screen::NowPlaying] was shown instead of
+    /// turning the display off
+    screen_before_idle: Option<Screen>,
+    /// Shown on [Screen::NowPlaying]
+    now_playing: Option<NowPlaying>,
+    /// Pixels the wide lines of [Screen::NowPlaying] have scrolled by at the latest tick
+    scroll: u32,
+    /// Pixels the name of the zoomed channel has scrolled by, a step per animation frame
+    name_scroll: u32,
+    view: View,
+    /// Latest reset, shown on [Screen::Splash] and [Screen::Info]
+    boot: Option<BootInfo>,
+    /// Shown on [Screen::SelfTest]
+    self_test: Option<SelfTest>,
+    /// The display did not answer [DisplayState::check_display], nothing is sent to it any more
+    missing: bool,
+    /// A transfer to the display failed, nothing is sent to it until [DisplayState::reinit] gets
+    /// an answer
+    offline: bool,
+    /// Direction of the latest change per channel and the time it is hidden at
+    trends: [Option<(Trend, u64)>; INPUT_COUNT],
+    /// Time of the latest [DisplayState::tick]
+    now_ms: u64,
+    units: Units,
+    theme: Theme,
+    /// Shown on [Screen::Diagnostics]
+    adc_stats: AdcStats,
+    /// Channels drawn as [DISCONNECTED_TEXT] instead of a bar
+    disconnected: [bool; INPUT_COUNT],
+    /// Shown on [Screen::Settings]
+    menu: SettingsView,
+    /// Contrast while the display is on, [DISPLAY_CONTRAST] unless changed in the settings
+    contrast: u8,
+    /// The supply sagged below [globals::SUPPLY_LOW_MV], see [DisplayState::set_supply_low]
+    supply_low: bool,
+    /// Charge (0-100 %) shown in the top left corner with `battery`
+    battery: Option<u8>,
+    /// Contrast for the ambient light with `ambient-light`, replaces [DisplayState::contrast]
+    ambient_contrast: Option<u8>,
+    screensaver: Screensaver,
+    power: DisplayPower,
+    /// Rows to redraw on the next [DisplayState::draw]
+    dirty_rows: [bool; INPUT_COUNT],
+    /// Set when something outside the rows changed, e.g. the title or the layout position
+    full_redraw: bool,
     top_left_point: Point,
 }
 
-impl<'a> DisplayState<'a> {
-    pub fn new(display: Ssd1306Display) -> Self {
-        let vol_bar_height = 7;
-        let vol_bar_width = 80;
+#[cfg(feature = "display")]
+impl<'a, D> DisplayState<'a, D>
+where
+    D: DisplaySink,
+    <D as DrawTarget>::Error: Debug,
+{
+    /// Layout is computed from the display size and [INPUT_COUNT], see [Layout::new]
+    pub fn new(display: D) -> Self {
+        let bounding_box = display.bounding_box();
+
         Self {
-            top_left_point: display.bounding_box().anchor_point(AnchorPoint::TopLeft),
-            title_position: display.bounding_box().anchor_point(AnchorPoint::TopCenter)
-                + Point::new(0, 8),
+            top_left_point: bounding_box.anchor_point(AnchorPoint::TopLeft),
+            title_position: bounding_box.anchor_point(AnchorPoint::TopCenter) + Point::new(0, 8),
+            status_position: bounding_box.anchor_point(AnchorPoint::TopRight) + Point::new(0, 8),
+            layout: Layout::new(
+                bounding_box.size,
+                INPUT_COUNT,
+                Units::default().max_label_len(),
+                BAR_ORIENTATION,
+            ),
+            channel_page: 0,
+            screen: Screen::default(),
+            screen_before_idle: None,
+            now_playing: None,
+            scroll: 0,
+            name_scroll: 0,
+            view: View::Overview,
+            boot: None,
+            self_test: None,
+            missing: false,
+            offline: false,
+            trends: [None; INPUT_COUNT],
+            now_ms: 0,
+            units: Units::default(),
+            theme: Theme::default(),
+            adc_stats: AdcStats::new(),
+            disconnected: [false; INPUT_COUNT],
+            menu: SettingsView::default(),
+            contrast: DISPLAY_CONTRAST,
+            supply_low: false,
+            battery: None,
+            ambient_contrast: None,
+            screensaver: Screensaver::new(SCREENSAVER_SHIFT_PERIOD, SCREENSAVER_INVERT),
+            power: DisplayPower::Off,
+            dirty_rows: [false; INPUT_COUNT],
+            full_redraw: true,
             display,
             volumes: Default::default(),
+            positions: Default::default(),
+            host_volumes: [None; INPUT_COUNT],
+            host_volumes_until: 0,
+            host_mutes: [false; INPUT_COUNT],
+            levels: None,
+            levels_until: 0,
+            animator: BarAnimator::new(BAR_EASING),
+            icons: [None; INPUT_COUNT],
+            names: core::array::from_fn(|_| None),
             ready_to_draw: false,
             title: None,
-            vol_value_y_offset: 22,
-            line_spacing: 12,
-            vol_bar_x_offset: 45,
-            vol_bar_height,
-            vol_bar_width,
-            vol_bar_size: Size::new(vol_bar_width, vol_bar_height),
+            status: None,
         }
     }
 
-    pub fn with_volumes(display: Ssd1306Display, volumes: [u16; INPUT_COUNT]) -> Self {
-        Self {
-            volumes,
-            ..Self::new(display)
-        }
+    pub fn with_volumes(display: D, volumes: [u16; INPUT_COUNT]) -> Self {
+        let mut state = Self::new(display);
+        state.volumes = volumes;
+        state.animator.set_target(&volumes);
+        state
+    }
+
+    /// The display drawn to, e.g. for tests to check what their [DisplaySink] recorded
+    pub fn display(&self) -> &D {
+        &self.display
     }
 
     /// Needs to be called to actually draw anything on the screen.
@@ -144,123 +699,988 @@ impl<'a> DisplayState<'a> {
 
     pub fn set_title(&mut self, title: &'a str) {
         self.title = Some(title);
+        self.full_redraw = true;
     }
 
     pub fn disable_title(&mut self) {
         self.title = None;
+        self.full_redraw = true;
+    }
+
+    /// Icon shown left of the bar of channel `idx`, `None` shows the index instead.
+    /// Icons are not shown when the channels are split into columns as there is no room for them.
+    pub fn set_icon(&mut self, idx: usize, icon: Option<Icon>) {
+        debug!("Icon of channel {} set: {}", idx, icon.is_some());
+        self.icons[idx] = icon;
+        self.dirty_rows[idx] = true;
+    }
+
+    /// Name of channel `idx` from the host, shown in place of `CH<n>` while the channel is zoomed
+    pub fn set_name(&mut self, idx: usize, name: Option<ChannelName>) -> DisplayStatus {
+        if self.names[idx] == name {
+            return DisplayStatus::NotChanged;
+        }
+        debug!("Name of channel {} set: {}", idx, name.is_some());
+        self.names[idx] = name;
+        if self.screen == Screen::Volumes
+            && matches!(self.view, View::Zoomed { channel } if channel == idx)
+        {
+            self.full_redraw = true;
+            return DisplayStatus::Changed;
+        }
+        DisplayStatus::NotChanged
+    }
+
+    /// Shows the name of `profile` as the title and its icons
+    pub fn set_profile(&mut self, profile: &'a profiles::Profile) -> DisplayStatus {
+        debug!("Profile {}", profile.name);
+        self.title = Some(profile.name);
+        self.icons = profile.icons;
+        self.full_redraw = true;
+        DisplayStatus::Changed
+    }
+
+    /// Assigns [Icon::default_for] to every channel
+    pub fn use_default_icons(&mut self) {
+        for (idx, icon) in self.icons.iter_mut().enumerate() {
+            *icon = Icon::default_for(idx);
+        }
+        self.full_redraw = true;
+    }
+
+    /// Short text shown in the top right corner, e.g. connection state. Truncated to fit [StatusText]
+    pub fn set_status(&mut self, status: Option<&str>) -> DisplayStatus {
+        if self.status.as_deref() == status {
+            return DisplayStatus::NotChanged;
+        }
+        self.status = status.map(|s| {
+            let mut text = StatusText::new();
+            for c in s.chars() {
+                if text.push(c).is_err() {
+                    break;
+                }
+            }
+            text
+        });
+        self.full_redraw = true;
+        DisplayStatus::Changed
+    }
+
+    /// While the supply is low [SUPPLY_LOW_TEXT] replaces the status and the contrast is lowered
+    /// to [SUPPLY_LOW_CONTRAST] so the display draws less current
+    pub fn set_supply_low(&mut self, low: bool) -> DisplayStatus {
+        if self.supply_low == low {
+            return DisplayStatus::NotChanged;
+        }
+        self.supply_low = low;
+        if self.power == DisplayPower::On {
+            self.set_contrast(self.on_contrast());
+        }
+        self.full_redraw = true;
+        DisplayStatus::Changed
+    }
+
+    /// Charge (0-100 %) of the battery, drawn as an icon and the percentage left of the title
+    pub fn set_battery(&mut self, percent: Option<u8>) -> DisplayStatus {
+        if self.battery == percent {
+            return DisplayStatus::NotChanged;
+        }
+        self.battery = percent;
+        self.full_redraw = true;
+        DisplayStatus::Changed
     }
 
     /// Give volumes in range 0-100
+    ///
+    /// Moving a pot leaves [Screen::NowPlaying] when it was shown instead of turning the display
+    /// off.
     pub fn set_volumes(&mut self, volumes: &[u16; INPUT_COUNT]) -> DisplayStatus {
         let mut changed = false;
 
         for (idx, vol) in volumes.iter().enumerate() {
             if vol.abs_diff(self.volumes[idx]) > 1 {
+                let trend = if *vol > self.volumes[idx] {
+                    Trend::Up
+                } else {
+                    Trend::Down
+                };
+                self.trends[idx] = Some((trend, self.now_ms + TREND_TIME));
                 self.volumes[idx] = *vol;
+                self.dirty_rows[idx] = true;
+                // Show the page of the channel that was moved
+                let page = self.layout.page_of(idx);
+                if page != self.channel_page {
+                    self.channel_page = page;
+                    self.full_redraw = true;
+                }
                 changed = true;
             }
         }
 
+        let mut screen_changed = DisplayStatus::NotChanged;
         if changed {
+            self.animator.set_target(&self.volumes);
+            if let Some(screen) = self.screen_before_idle.take() {
+                screen_changed = self.show_screen(screen);
+            }
+        }
+        if changed && self.screen == Screen::Volumes {
+            return DisplayStatus::Changed;
+        }
+        screen_changed
+    }
+
+    /// Give the [activity::ChannelActivity::focused] channel, it is zoomed to full screen while
+    /// [Screen::Volumes] is shown
+    pub fn set_focus(&mut self, focused: Option<usize>) -> DisplayStatus {
+        let view = match focused {
+            Some(channel) if ZOOM_VIEW && self.screen == Screen::Volumes => {
+                View::Zoomed { channel }
+            }
+            _ => View::Overview,
+        };
+        if self.view == view {
+            return DisplayStatus::NotChanged;
+        }
+        debug!("Focus {}", focused);
+        self.view = view;
+        self.name_scroll = 0;
+        self.full_redraw = true;
+        if self.screen == Screen::Volumes {
+            DisplayStatus::Changed
+        } else {
+            DisplayStatus::NotChanged
+        }
+    }
+
+    /// Give the positions of the pots in range 0-100, before [roles::apply]
+    pub fn set_positions(&mut self, positions: &[u16; INPUT_COUNT]) -> DisplayStatus {
+        let mut changed = false;
+        for (idx, (old, new)) in self.positions.iter_mut().zip(positions).enumerate() {
+            if old.abs_diff(*new) > 1 {
+                *old = *new;
+                self.dirty_rows[idx] = true;
+                changed = true;
+            }
+        }
+        if changed && self.screen == Screen::Volumes {
+            return DisplayStatus::Changed;
+        }
+        DisplayStatus::NotChanged
+    }
+
+    /// Actual volumes (0-100) of the sessions on the PC, see [protocol::HostCommand::HostVolumes].
+    /// They are dropped after [HOST_VOLUMES_TIMEOUT] without a new report.
+    pub fn set_host_volumes(&mut self, volumes: &[Option<u16>; INPUT_COUNT]) -> DisplayStatus {
+        self.host_volumes_until = self.now_ms + HOST_VOLUMES_TIMEOUT;
+        let mut changed = false;
+        for (idx, (old, new)) in self.host_volumes.iter_mut().zip(volumes).enumerate() {
+            if old != new {
+                *old = *new;
+                self.dirty_rows[idx] = true;
+                changed = true;
+            }
+        }
+        if changed && self.screen == Screen::Volumes {
+            return DisplayStatus::Changed;
+        }
+        DisplayStatus::NotChanged
+    }
+
+    /// Channels whose sessions are muted on the PC, see [protocol::HostCommand::HostMutes]
+    pub fn set_host_mutes(&mut self, mutes: &[bool; INPUT_COUNT]) -> DisplayStatus {
+        let mut changed = false;
+        for (idx, (old, new)) in self.host_mutes.iter_mut().zip(mutes).enumerate() {
+            if old != new {
+                debug!("Channel {} muted on the host: {}", idx, new);
+                *old = *new;
+                self.dirty_rows[idx] = true;
+                changed = true;
+            }
+        }
+        if changed && self.screen == Screen::Volumes {
+            return DisplayStatus::Changed;
+        }
+        DisplayStatus::NotChanged
+    }
+
+    /// Peak audio levels (0-100) of the sessions on the PC, see [protocol::HostCommand::Levels].
+    /// The bars show the volumes again after [LEVELS_TIMEOUT] without new levels.
+    pub fn set_levels(&mut self, levels: &[u16; INPUT_COUNT]) -> DisplayStatus {
+        self.levels_until = self.now_ms + LEVELS_TIMEOUT;
+        let old = self.levels.replace(*levels);
+        let mut changed = false;
+        for (idx, level) in levels.iter().enumerate() {
+            if old.map(|old| old[idx]) != Some(*level) {
+                self.dirty_rows[idx] = true;
+                changed = true;
+            }
+        }
+        if changed && self.screen == Screen::Volumes {
+            return DisplayStatus::Changed;
+        }
+        DisplayStatus::NotChanged
+    }
+
+    /// Switches the value labels between percent and dB. The bars get shorter to make room for the longer dB labels.
+    pub fn set_units(&mut self, units: Units) -> DisplayStatus {
+        if units == self.units {
+            return DisplayStatus::NotChanged;
+        }
+        debug!("Units {}", units);
+        self.units = units;
+        self.layout = Layout::new(
+            self.display.bounding_box().size,
+            INPUT_COUNT,
+            units.max_label_len(),
+            BAR_ORIENTATION,
+        );
+        self.full_redraw = true;
+        DisplayStatus::Changed
+    }
+
+    /// Turns the picture, e.g. after [orientation::OrientationDetector] found the display turned.
+    /// The title, the status and the rows are laid out again for the new size, with
+    /// [BarOrientation::Auto] the bars are vertical while the display stands on its short side.
+    pub fn set_rotation(&mut self, rotation: Rotation) -> DisplayStatus {
+        if self.missing {
+            return DisplayStatus::NotChanged;
+        }
+        debug!("Rotation {}", rotation);
+        // Laid out for the new size even if the display is offline, reinit applies it
+        let result = self.display.set_rotation(rotation.into());
+        self.check(result);
+        let bounding_box = self.display.bounding_box();
+        self.top_left_point = bounding_box.anchor_point(AnchorPoint::TopLeft);
+        self.title_position = bounding_box.anchor_point(AnchorPoint::TopCenter) + Point::new(0, 8);
+        self.status_position = bounding_box.anchor_point(AnchorPoint::TopRight) + Point::new(0, 8);
+        self.layout = Layout::new(
+            bounding_box.size,
+            INPUT_COUNT,
+            self.units.max_label_len(),
+            BAR_ORIENTATION,
+        );
+        // The new layout may have fewer pages
+        self.channel_page = 0;
+        self.full_redraw = true;
+        DisplayStatus::Changed
+    }
+
+    /// Raw ADC readings of every sample, their stats since boot are shown on [Screen::Diagnostics]
+    pub fn set_raw_values(&mut self, raw_values: &[u16; INPUT_COUNT]) -> DisplayStatus {
+        if self.adc_stats.update(raw_values) && self.screen == Screen::Diagnostics {
+            self.full_redraw = true;
             return DisplayStatus::Changed;
         }
         DisplayStatus::NotChanged
     }
 
+    /// Channels without a working pot, see [sampling::Sampler::disconnected]
+    pub fn set_disconnected(&mut self, disconnected: &[bool; INPUT_COUNT]) -> DisplayStatus {
+        let mut changed = false;
+        for (idx, (old, new)) in self.disconnected.iter_mut().zip(disconnected).enumerate() {
+            if old != new {
+                debug!("Channel {} disconnected: {}", idx, new);
+                *old = *new;
+                self.dirty_rows[idx] = true;
+                changed = true;
+            }
+        }
+        if changed && self.screen == Screen::Volumes {
+            return DisplayStatus::Changed;
+        }
+        DisplayStatus::NotChanged
+    }
+
+    /// Track playing on the PC, see [protocol::HostCommand::NowPlaying]
+    pub fn set_now_playing(&mut self, track: Option<NowPlaying>) -> DisplayStatus {
+        if track == self.now_playing {
+            return DisplayStatus::NotChanged;
+        }
+        debug!("Now playing {}", track);
+        self.now_playing = track;
+        if self.screen == Screen::NowPlaying {
+            self.full_redraw = true;
+            return DisplayStatus::Changed;
+        }
+        DisplayStatus::NotChanged
+    }
+
+    /// Shows what a command of the host changes on the display, e.g. the names and icons of the
+    /// channels. Commands for the rest of the firmware change nothing.
+    pub fn handle_host_command(&mut self, command: HostCommand) -> DisplayStatus {
+        match command {
+            HostCommand::Icon(channel, bitmap) => {
+                self.set_icon(channel, bitmap.map(Icon::Custom));
+                DisplayStatus::Changed
+            }
+            HostCommand::Name(channel, name) => self.set_name(channel, name),
+            HostCommand::SetUnits(units) => self.set_units(units),
+            HostCommand::HostMutes(mutes) => self.set_host_mutes(&mutes),
+            HostCommand::HostVolumes(volumes) => self.set_host_volumes(&volumes),
+            HostCommand::Levels(levels) => self.set_levels(&levels),
+            HostCommand::NowPlaying(track) => self.set_now_playing(track),
+            _ => DisplayStatus::NotChanged,
+        }
+    }
+
+    /// Switches to the next [Screen], called when the page button is pressed
+    pub fn next_screen(&mut self) -> DisplayStatus {
+        self.screen_before_idle = None;
+        self.show_screen(self.screen.next())
+    }
+
+    pub fn screen(&self) -> Screen {
+        self.screen
+    }
+
+    /// Items of the settings menu, see [crate::menu::MenuCursor::view]
+    pub fn set_menu(&mut self, menu: SettingsView) -> DisplayStatus {
+        if menu == self.menu {
+            return DisplayStatus::NotChanged;
+        }
+        self.menu = menu;
+        if self.screen == Screen::Settings {
+            self.full_redraw = true;
+            return DisplayStatus::Changed;
+        }
+        DisplayStatus::NotChanged
+    }
+
+    pub fn show_screen(&mut self, screen: Screen) -> DisplayStatus {
+        if screen == self.screen {
+            return DisplayStatus::NotChanged;
+        }
+        debug!("Screen {}", screen);
+        self.screen = screen;
+        self.full_redraw = true;
+        DisplayStatus::Changed
+    }
+
+    /// Adds a banner to [Screen::Splash] when the previous boot ended in a panic, a watchdog or a
+    /// brown-out, and the reset to [Screen::Info]
+    pub fn set_boot(&mut self, boot: BootInfo) {
+        self.boot = Some(boot);
+    }
+
+    /// Result of the boot self-test shown on [Screen::SelfTest]
+    pub fn set_self_test(&mut self, test: SelfTest) {
+        self.self_test = Some(test);
+    }
+
+    /// Whether the display acknowledges a command, for the boot self-test. Without a display the
+    /// drawing is skipped from then on, so the mixer keeps working.
+    pub fn check_display(&mut self) -> bool {
+        self.missing = self.display.set_display_on(true).is_err();
+        !self.missing
+    }
+
+    /// Whether a transfer to the display failed since it was last set up, see
+    /// [DisplayState::reinit]. The state is still kept up to date, only the drawing is skipped.
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Sets the display up again once it went offline, e.g. after the bus was cleared. Returns
+    /// whether it answered, everything is redrawn on the next [DisplayState::draw] then.
+    pub fn reinit(&mut self) -> bool {
+        // Init turns the panel on at its default contrast
+        let result = self.display.init().and_then(|()| match self.power {
+            DisplayPower::On => self.display.set_contrast(self.on_contrast()),
+            DisplayPower::Dimmed => self.display.set_contrast(DISPLAY_DIM_CONTRAST),
+            DisplayPower::Off => self.display.set_display_on(false),
+        });
+        if result.is_err() {
+            return false;
+        }
+        info!("Display online");
+        self.offline = false;
+        self.full_redraw = true;
+        true
+    }
+
+    /// Takes the display offline if `result` of a transfer to it failed, instead of panicking
+    /// over a glitch on the cable
+    fn check<T, E>(&mut self, result: Result<T, E>) -> Option<T> {
+        if result.is_err() && !self.offline {
+            info!("Display offline");
+            self.offline = true;
+        }
+        result.ok()
+    }
+
+    /// Whether nothing is sent to the display, it is missing or offline
+    fn unreachable(&self) -> bool {
+        self.missing || self.offline
+    }
+
+    /// Whether [DisplayState::animate] needs to be called periodically, for the bar animation or
+    /// a channel name that scrolls. Can change with every update, check it after each one.
+    pub fn is_animated(&self) -> bool {
+        self.animator.is_enabled() || self.name_scrolls()
+    }
+
+    /// Whether the zoomed channel has a name too long for its room, which scrolls while the
+    /// display is on
+    fn name_scrolls(&self) -> bool {
+        let View::Zoomed { channel } = self.view else {
+            return false;
+        };
+        let page = ZoomPage {
+            channel,
+            label: &self.units.format(self.volumes[channel]),
+            fill: 0,
+            icon: self.icons[channel].as_ref().map(Icon::image),
+            name: self.names[channel].as_deref(),
+            name_scroll: Some(self.name_scroll),
+            theme: self.theme,
+        };
+        self.screen == Screen::Volumes
+            && self.power == DisplayPower::On
+            && page
+                .name_label(self.page_area(Point::zero()))
+                .is_some_and(|label| label.scrolls())
+    }
+
+    /// Advances the bar animation and a scrolling channel name by one frame
+    pub fn animate(&mut self) -> DisplayStatus {
+        let scrolled = self.name_scrolls();
+        if scrolled {
+            self.name_scroll = self.name_scroll.wrapping_add(NAME_SCROLL_STEP);
+            self.full_redraw = true;
+        }
+        let previous = *self.animator.shown();
+        if !self.animator.step() || self.screen != Screen::Volumes {
+            return if scrolled {
+                DisplayStatus::Changed
+            } else {
+                DisplayStatus::NotChanged
+            };
+        }
+        for (dirty, (prev, shown)) in self
+            .dirty_rows
+            .iter_mut()
+            .zip(previous.iter().zip(self.animator.shown()))
+        {
+            *dirty |= prev != shown;
+        }
+        if let View::Zoomed { channel } = self.view {
+            self.full_redraw |= previous[channel] != self.animator.shown()[channel];
+        }
+        DisplayStatus::Changed
+    }
+
+    /// Call periodically. Hides change arrows after [TREND_TIME], scrolls the wide lines of
+    /// [Screen::NowPlaying] and moves the layout when the screensaver shift period has passed.
+    /// Only reports a change while the display is on, otherwise the changes are drawn on the next
+    /// draw.
+    pub fn tick(&mut self, now_ms: u64) -> DisplayStatus {
+        self.now_ms = now_ms;
+
+        let mut trend_expired = false;
+        for (idx, trend) in self.trends.iter_mut().enumerate() {
+            if trend.is_some_and(|(_, until_ms)| now_ms >= until_ms) {
+                *trend = None;
+                self.dirty_rows[idx] = true;
+                trend_expired = true;
+            }
+        }
+
+        let host_volumes_expired =
+            now_ms >= self.host_volumes_until && self.host_volumes.iter().any(Option::is_some);
+        if host_volumes_expired {
+            self.host_volumes = [None; INPUT_COUNT];
+            self.dirty_rows = [true; INPUT_COUNT];
+        }
+
+        let levels_expired = now_ms >= self.levels_until && self.levels.is_some();
+        if levels_expired {
+            info!("Host stopped streaming levels");
+            self.levels = None;
+            self.dirty_rows = [true; INPUT_COUNT];
+        }
+
+        if self.screensaver.update(now_ms) {
+            self.full_redraw = true;
+        }
+
+        let scroll = (now_ms * NOW_PLAYING_SCROLL_SPEED / 1000) as u32;
+        if self.screen == Screen::NowPlaying && scroll != self.scroll {
+            let page = NowPlayingPage {
+                track: self.now_playing.as_ref(),
+                scroll,
+            };
+            self.full_redraw |= page.scrolls(self.display.bounding_box().size.width);
+        }
+        self.scroll = scroll;
+
+        let redraw = self.full_redraw
+            || ((trend_expired || host_volumes_expired || levels_expired)
+                && self.screen == Screen::Volumes);
+        if redraw && self.power != DisplayPower::Off {
+            return DisplayStatus::Changed;
+        }
+        DisplayStatus::NotChanged
+    }
+
+    /// Redraws only the rows that changed unless the whole screen has to be redrawn. The SSD1306
+    /// driver only flushes the area of the framebuffer that was touched, so a single changed row
+    /// is a fraction of the I2C traffic of a full frame.
     #[allow(clippy::result_unit_err)]
     pub fn draw(&mut self) -> Result<(), ()> {
+        if self.unreachable() {
+            return Ok(());
+        }
+        self.render()?;
+        let result = self.display.flush();
+        self.check(result);
+        Ok(())
+    }
+
+    /// Same as [DisplayState::draw] without sending the frame, [DisplayState::flush_chunk] sends
+    /// it a part at a time
+    #[allow(clippy::result_unit_err)]
+    pub fn draw_frame(&mut self) -> Result<(), ()> {
+        if self.unreachable() {
+            return Ok(());
+        }
+        self.render()
+    }
+
+    /// Sends the next part of the frame drawn by [DisplayState::draw_frame], returns true once
+    /// the whole frame is on the panel or the display went offline
+    pub fn flush_chunk(&mut self) -> bool {
+        if self.unreachable() {
+            return true;
+        }
+        let result = self.display.flush_chunk();
+        self.check(result).unwrap_or(true)
+    }
+
+    /// Draws into the framebuffer of the display, see [DisplayState::draw]
+    fn render(&mut self) -> Result<(), ()> {
         if !self.ready_to_draw {
             return Err(());
         }
 
         // esp_println::println!("Drawing");
         self.turn_on();
-        self.display.clear(BinaryColor::Off).unwrap(); // TODO propagate error?
+        let result = self
+            .display
+            .set_invert(self.screensaver.inverted() != self.theme.inverted);
+        self.check(result);
+        let shift = self.screensaver.offset();
 
-        if let Some(title) = self.title {
-            Text::with_alignment(
-                title,
-                self.title_position,
-                TEXT_STYLE_BOLD,
-                Alignment::Center,
+        if self.full_redraw {
+            self.display.clear(BinaryColor::Off).unwrap(); // TODO propagate error?
+
+            // Splash and self-test cover the whole display
+            let show_title = !matches!(self.screen, Screen::Splash | Screen::SelfTest);
+            if let Some(title) = self.title.filter(|_| show_title) {
+                Text::with_alignment(
+                    title,
+                    self.title_position + shift,
+                    self.theme.title_style,
+                    Alignment::Center,
+                )
+                .draw(&mut self.display)
+                .unwrap();
+            }
+            let status = if self.supply_low {
+                Some(SUPPLY_LOW_TEXT)
+            } else {
+                self.status.as_deref()
+            };
+            if let Some(status) = status.filter(|_| show_title) {
+                Text::with_alignment(
+                    status,
+                    self.status_position + shift,
+                    TEXT_STYLE,
+                    Alignment::Right,
+                )
+                .draw(&mut self.display)
+                .unwrap();
+            }
+            if let Some(percent) = self.battery.filter(|_| show_title) {
+                self.draw_battery(percent, self.top_left_point + shift);
+            }
+        }
+
+        if self.screen != Screen::Volumes || self.view != View::Overview {
+            if self.full_redraw {
+                self.draw_page(shift);
+            }
+            self.full_redraw = false;
+            return Ok(());
+        }
+
+        for idx in 0..INPUT_COUNT {
+            if !self.full_redraw && !self.dirty_rows[idx] {
+                continue;
+            }
+            let Some(row_origin) = self.layout.row_origin(idx, self.channel_page) else {
+                continue;
+            };
+            let row_origin = self.top_left_point + shift + row_origin;
+
+            if !self.full_redraw {
+                self.display
+                    .fill_solid(&self.layout.row_area(row_origin), BinaryColor::Off)
+                    .unwrap();
+            }
+            self.draw_row(idx, row_origin);
+        }
+        self.full_redraw = false;
+        self.dirty_rows = [false; INPUT_COUNT];
+        Ok(())
+    }
+
+    /// Draws the content of screens other than the [Screen::Volumes] overview below the title
+    fn draw_page(&mut self, shift: Point) {
+        let area = self.page_area(shift);
+        match (self.screen, self.view) {
+            (Screen::Volumes, View::Zoomed { channel }) => ZoomPage {
+                channel,
+                label: &self.units.format(self.volumes[channel]),
+                fill: self.animator.shown()[channel],
+                icon: self.icons[channel].as_ref().map(Icon::image),
+                name: self.names[channel].as_deref(),
+                name_scroll: (self.power == DisplayPower::On).then_some(self.name_scroll),
+                theme: self.theme,
+            }
+            .draw(&mut self.display, area),
+            (Screen::Volumes, View::Overview) => Ok(()),
+            (Screen::Splash, _) => SplashPage {
+                reset: self.boot.map(|boot| boot.reason),
+            }
+            .draw(&mut self.display, area),
+            (Screen::Diagnostics, _) => DiagnosticsPage {
+                stats: self.adc_stats.channels(),
+            }
+            .draw(&mut self.display, area),
+            (Screen::Info, _) => InfoPage { boot: self.boot }.draw(&mut self.display, area),
+            (Screen::SelfTest, _) => match &self.self_test {
+                Some(test) => SelfTestPage { test }.draw(&mut self.display, area),
+                None => Ok(()),
+            },
+            (Screen::Settings, _) => MenuPage { view: &self.menu }.draw(&mut self.display, area),
+            (Screen::NowPlaying, _) => NowPlayingPage {
+                track: self.now_playing.as_ref(),
+                scroll: self.scroll,
+            }
+            .draw(&mut self.display, area),
+        }
+        .unwrap(); // TODO propagate error?
+    }
+
+    /// Area of the current page, below the title unless the page covers the whole display
+    fn page_area(&self, shift: Point) -> Rectangle {
+        let size = self.display.bounding_box().size;
+        if matches!(self.screen, Screen::Splash | Screen::SelfTest) {
+            Rectangle::new(self.top_left_point + shift, size)
+        } else {
+            Rectangle::new(
+                self.top_left_point + shift + Point::new(0, TITLE_HEIGHT as i32),
+                size - Size::new(0, TITLE_HEIGHT),
             )
-            .draw(&mut self.display)
-            .unwrap();
         }
+    }
+
+    /// Draws the label and bar of channel `idx` on top of what is already in the framebuffer
+    fn draw_row(&mut self, idx: usize, row_origin: Point) {
+        let p_val = self.units.format(self.volumes[idx]);
+        let p_val = if self.disconnected[idx] {
+            "--"
+        } else {
+            p_val.as_str()
+        };
         let mut s_buf: String<32> = String::new();
 
-        for (idx, p_val) in self.volumes.iter().enumerate() {
-            s_buf.clear();
-            write!(s_buf, "{}: {}", idx, p_val).expect("Format string failed, check buffer size");
+        let icon = self.icons[idx]
+            .as_ref()
+            .map(Icon::image)
+            .filter(|image| self.layout.icon_fits(image.size()));
 
-            Text::with_alignment(
-                &s_buf,
-                self.top_left_point
-                    + Point::new(2, self.vol_value_y_offset + self.line_spacing * idx as i32),
-                TEXT_STYLE,
-                Alignment::Left,
+        let label_x = if let Some(image) = &icon {
+            // Bottom of the icon is aligned with the bottom of the bar
+            Image::new(
+                image,
+                row_origin
+                    + Point::new(
+                        LABEL_MARGIN,
+                        self.layout.vol_value_y_offset - image.size().height as i32,
+                    ),
             )
             .draw(&mut self.display)
             .unwrap();
+            write_truncated(&mut s_buf, format_args!("{}", p_val)).ok();
+            self.layout.value_x_offset
+        } else {
+            if self.layout.compact_labels {
+                write_truncated(&mut s_buf, format_args!("{}", p_val))
+            } else {
+                write_truncated(&mut s_buf, format_args!("{}: {}", idx, p_val))
+            }
+            .ok();
+            LABEL_MARGIN
+        };
 
-            let mut b = Rectangle::new(
-                self.top_left_point
-                    + Point::new(
-                        self.vol_bar_x_offset,
-                        self.vol_value_y_offset - self.vol_bar_height as i32
-                            + self.line_spacing * idx as i32,
-                    ),
-                self.vol_bar_size,
-            )
-            .into_styled(OUTER_RECT_STYLE);
+        // Value is centered under vertical bars
+        let (label_x, alignment) = match self.layout.orientation {
+            BarOrientation::Vertical => (self.layout.value_x_offset, Alignment::Center),
+            _ => (label_x, Alignment::Left),
+        };
+        Text::with_alignment(
+            &s_buf,
+            row_origin + Point::new(label_x, self.layout.vol_value_y_offset),
+            self.layout.text_style,
+            alignment,
+        )
+        .draw(&mut self.display)
+        .unwrap();
 
-            b.primitive = Rectangle::new(
-                self.top_left_point
-                    + Point::new(
-                        self.vol_bar_x_offset,
-                        self.vol_value_y_offset - self.vol_bar_height as i32
-                            + self.line_spacing * idx as i32,
-                    ),
-                self.vol_bar_size,
-            );
+        if self.disconnected[idx] {
+            self.draw_disconnected(row_origin);
+            return;
+        }
 
-            Rectangle::new(
-                self.top_left_point
-                    + Point::new(
-                        self.vol_bar_x_offset,
-                        self.vol_value_y_offset - self.vol_bar_height as i32
-                            + self.line_spacing * idx as i32,
-                    ),
-                self.vol_bar_size,
-            )
+        let mut bar = VolumeBar::new(
+            row_origin + Point::new(self.layout.vol_bar_x_offset, self.layout.vol_bar_y_offset),
+            self.layout.vol_bar_size,
+            self.layout.orientation,
+            self.animator.shown()[idx],
+        );
+        bar.style = self.theme.bar_style;
+        if self.host_mutes[idx] {
+            // Muted on the PC
+            bar.style = BarStyle::CrossedOut;
+        } else if let Some(levels) = self.levels {
+            // VU meter while the host streams levels, the volume is only the marker then
+            bar.add_marker(bar.value);
+            bar.value = levels[idx];
+        } else {
+            // Pot position when the master scales the volume down
+            let position = self.positions[idx];
+            if position > self.volumes[idx] {
+                bar.add_marker(position);
+            }
+            // Volume the PC actually has
+            if let Some(host_volume) = self.host_volumes[idx] {
+                bar.add_marker(host_volume);
+            }
+        }
+        bar.draw(&mut self.display).unwrap();
+
+        if let (Some((trend, _)), Some(offset)) =
+            (self.trends[idx], self.layout.trend_arrow_offset())
+        {
+            let right = TREND_ARROW_SIZE.width as i32 - 1;
+            let bottom = TREND_ARROW_SIZE.height as i32 - 1;
+            let arrow = match trend {
+                Trend::Up => Triangle::new(
+                    Point::new(right / 2, 0),
+                    Point::new(0, bottom),
+                    Point::new(right, bottom),
+                ),
+                Trend::Down => Triangle::new(
+                    Point::new(0, 0),
+                    Point::new(right, 0),
+                    Point::new(right / 2, bottom),
+                ),
+            };
+            arrow
+                .translate(row_origin + offset)
+                .into_styled(FILL_RECT_STYLE)
+                .draw(&mut self.display)
+                .unwrap();
+        }
+    }
+
+    /// Battery icon filled up to `percent` and the percentage next to it, in the title row
+    fn draw_battery(&mut self, percent: u8, top_left: Point) {
+        Rectangle::new(top_left + Point::new(0, 1), Size::new(12, 7))
             .into_styled(OUTER_RECT_STYLE)
             .draw(&mut self.display)
             .unwrap();
+        // Terminal
+        Rectangle::new(top_left + Point::new(12, 3), Size::new(2, 3))
+            .into_styled(FILL_RECT_STYLE)
+            .draw(&mut self.display)
+            .unwrap();
+        let fill = scale_to_range(percent.min(100) as u16, 0, 100, 0, 8) as u32;
+        Rectangle::new(top_left + Point::new(2, 3), Size::new(fill, 3))
+            .into_styled(FILL_RECT_STYLE)
+            .draw(&mut self.display)
+            .unwrap();
+        let text: String<4> = format_truncated(format_args!("{}%", percent));
+        Text::new(&text, top_left + Point::new(16, 8), TEXT_STYLE)
+            .draw(&mut self.display)
+            .unwrap();
+    }
 
-            let fill_val = scale_to_range(*p_val, 0, 100, 0, self.vol_bar_width as u16);
-
-            Rectangle::new(
-                self.top_left_point
-                    + Point::new(
-                        self.vol_bar_x_offset,
-                        self.vol_value_y_offset - self.vol_bar_height as i32
-                            + self.line_spacing * idx as i32,
-                    ),
-                Size::new(fill_val as u32, self.vol_bar_height),
+    /// Draws [DISCONNECTED_TEXT] where the bar would be, or an empty bar when the text does not fit
+    fn draw_disconnected(&mut self, row_origin: Point) {
+        let bar_top_left =
+            row_origin + Point::new(self.layout.vol_bar_x_offset, self.layout.vol_bar_y_offset);
+        let text_width =
+            DISCONNECTED_TEXT.len() as u32 * self.layout.text_style.font.character_size.width;
+        if self.layout.orientation != BarOrientation::Vertical
+            && text_width <= self.layout.vol_bar_size.width
+        {
+            Text::with_alignment(
+                DISCONNECTED_TEXT,
+                Point::new(
+                    bar_top_left.x,
+                    row_origin.y + self.layout.vol_value_y_offset,
+                ),
+                self.layout.text_style,
+                Alignment::Left,
             )
-            .into_styled(FILL_RECT_STYLE)
             .draw(&mut self.display)
             .unwrap();
+        } else {
+            Rectangle::new(bar_top_left, self.layout.vol_bar_size)
+                .into_styled(OUTER_RECT_STYLE)
+                .draw(&mut self.display)
+                .unwrap();
         }
-        self.display.flush().unwrap(); // TODO propagate error?
-        Ok(())
     }
 
     pub fn turn_off(&mut self) {
-        self.display.set_display_on(false).unwrap(); // TODO propagate error?
+        if self.missing {
+            return;
+        }
+        // Offline, reinit leaves it off
+        if !self.offline {
+            let result = self.display.set_display_on(false);
+            self.check(result);
+        }
+        self.power = DisplayPower::Off;
     }
 
+    /// Also restores full contrast if the display was dimmed
     pub fn turn_on(&mut self) {
-        self.display.set_display_on(true).unwrap(); // TODO propagate error?
+        if self.power != DisplayPower::On {
+            self.set_contrast(self.on_contrast());
+        }
+        let result = self.display.set_display_on(true);
+        self.check(result);
+        self.power = DisplayPower::On;
+    }
+
+    pub fn set_contrast(&mut self, contrast: u8) {
+        if self.unreachable() {
+            return;
+        }
+        let result = self.display.set_contrast(contrast);
+        self.check(result);
+    }
+
+    /// Fonts, bars and colors of everything drawn from now on
+    pub fn set_theme(&mut self, theme: Theme) -> DisplayStatus {
+        if theme == self.theme {
+            return DisplayStatus::NotChanged;
+        }
+        self.theme = theme;
+        self.full_redraw = true;
+        DisplayStatus::Changed
+    }
+
+    /// Contrast while the display is on, applied right away unless it is dimmed or off
+    pub fn set_on_contrast(&mut self, contrast: u8) {
+        self.contrast = contrast;
+        if self.power == DisplayPower::On {
+            self.set_contrast(self.on_contrast());
+        }
+    }
+
+    /// Contrast for the ambient light, applied right away unless the display is dimmed or off
+    pub fn set_ambient_contrast(&mut self, contrast: Option<u8>) {
+        if self.ambient_contrast == contrast {
+            return;
+        }
+        self.ambient_contrast = contrast;
+        if self.power == DisplayPower::On {
+            self.set_contrast(self.on_contrast());
+        }
+    }
+
+    /// [DisplayState::contrast] or the one for the ambient light, capped while the supply is low
+    fn on_contrast(&self) -> u8 {
+        let contrast = self.ambient_contrast.unwrap_or(self.contrast);
+        if self.supply_low {
+            contrast.min(SUPPLY_LOW_CONTRAST)
+        } else {
+            contrast
+        }
+    }
+
+    /// With [DisplayTimeout::AlwaysOff] turns the display off and returns true, skip drawing then.
+    /// The settings menu is still shown so the timeout can be changed back.
+    pub fn keep_off(&mut self, timeout: DisplayTimeout) -> bool {
+        if timeout != DisplayTimeout::AlwaysOff || self.screen == Screen::Settings {
+            return false;
+        }
+        if self.power != DisplayPower::Off {
+            self.turn_off();
+            info!("Display {}", self.power);
+        }
+        true
+    }
+
+    /// First timeout dims the display, the next one turns it off. While the host reports a track
+    /// [Screen::NowPlaying] is shown instead and the display stays on, redraw when this returns
+    /// [DisplayPower::On].
+    pub fn dim_or_turn_off(&mut self) -> DisplayPower {
+        if self.power == DisplayPower::On && self.now_playing.is_some() {
+            if self.screen != Screen::NowPlaying {
+                self.screen_before_idle = Some(self.screen);
+                self.show_screen(Screen::NowPlaying);
+            }
+            return self.power;
+        }
+        match self.power {
+            DisplayPower::On => {
+                self.set_contrast(DISPLAY_DIM_CONTRAST);
+                self.power = DisplayPower::Dimmed;
+            }
+            DisplayPower::Dimmed | DisplayPower::Off => self.turn_off(),
+        }
+        info!("Display {}", self.power);
+        self.power
+    }
+}
+
+#[cfg(all(feature = "display", feature = "embassy"))]
+impl<'a, D> DisplayState<'a, D>
+where
+    D: DisplaySink + DisplayFlushAsync,
+    <D as DrawTarget>::Error: Debug,
+{
+    /// Same as [DisplayState::draw] but awaits the transfer to the panel
+    #[allow(clippy::result_unit_err)]
+    pub async fn draw_async(&mut self) -> Result<(), ()> {
+        if self.unreachable() {
+            return Ok(());
+        }
+        self.render()?;
+        let result = self.display.flush_async().await;
+        self.check(result);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_to_range_ends_and_middle() {
+        assert_eq!(scale_to_range(0, 0, 1023, 0, 100), 0);
+        assert_eq!(scale_to_range(1023, 0, 1023, 0, 100), 100);
+        assert_eq!(scale_to_range(512, 0, 1023, 0, 100), 50);
+        // Values outside the old range are clamped
+        assert_eq!(scale_analog_input_to_1023(MAX_ANALOG_VALUE + 100), 1023);
+        assert_eq!(scale_to_range(5, 10, 20, 0, 100), 0);
     }
 }
@@ -0,0 +1,32 @@
+// Log macros that go to defmt over RTT with the `defmt` feature, so they do not garble the
+// values sent over the serial port. Without the feature they compile to nothing.
+// Only `{}` placeholders are used so the messages are valid for both.
+
+#[cfg(feature = "defmt")]
+macro_rules! trace {
+    ($($arg:tt)*) => { defmt::trace!($($arg)*) };
+}
+#[cfg(feature = "defmt")]
+macro_rules! debug {
+    ($($arg:tt)*) => { defmt::debug!($($arg)*) };
+}
+#[cfg(feature = "defmt")]
+macro_rules! info {
+    ($($arg:tt)*) => { defmt::info!($($arg)*) };
+}
+
+// Arguments are still borrowed so values that are only logged do not cause unused warnings
+#[cfg(not(feature = "defmt"))]
+macro_rules! trace {
+    ($fmt:literal $(, $arg:expr)* $(,)?) => {{ $(let _ = &$arg;)* }};
+}
+#[cfg(not(feature = "defmt"))]
+macro_rules! debug {
+    ($fmt:literal $(, $arg:expr)* $(,)?) => {{ $(let _ = &$arg;)* }};
+}
+#[cfg(not(feature = "defmt"))]
+macro_rules! info {
+    ($fmt:literal $(, $arg:expr)* $(,)?) => {{ $(let _ = &$arg;)* }};
+}
+
+pub(crate) use {debug, info, trace};
@@ -1,183 +1,2088 @@
 #![no_std]
 #![no_main]
 #![feature(generic_arg_infer)]
+#![cfg_attr(feature = "embassy", feature(type_alias_impl_trait))]
 
-#[rtic::app(device=esp32c3, dispatchers = [FROM_CPU_INTR0])]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+mod board;
+
+use esp_hal::{clock::ClockControl, peripherals::Peripherals, prelude::*, Delay};
+use rust_deej::globals::PANIC_RESET_DELAY;
+
+#[cfg(feature = "display")]
+use esp_hal::IO;
+#[cfg(feature = "display")]
+use rust_deej::{Ssd1306Display, DISPLAY_ROTATION, DISPLAY_SIZE};
+#[cfg(feature = "display")]
+use ssd1306::{prelude::*, Ssd1306};
+
+#[cfg(feature = "display-spi")]
+use esp_hal::{
+    clock::Clocks,
+    gpio::{GpioPin, Output, PushPull, Unknown},
+    peripherals::SPI2,
+    spi::{master::Spi, FullDuplexMode, SpiMode},
+};
+#[cfg(feature = "i2c-bus")]
+use esp_hal::{i2c::I2C, peripherals::I2C0};
+#[cfg(all(feature = "display", not(feature = "display-spi")))]
+use rust_deej::i2c_bus::I2cProxy;
+#[cfg(feature = "i2c-bus")]
+use rust_deej::i2c_bus::SharedI2c;
+#[cfg(all(feature = "display", not(feature = "display-spi")))]
+use ssd1306::I2CDisplayInterface;
+
+/// I2C0 shared by the display and the other devices on it, set up with [board::i2c_bus] before
+/// the display. Each device gets its own [SharedI2c::proxy]
+#[cfg(feature = "i2c-bus")]
+static I2C_BUS: SharedI2c<I2C<'static, I2C0>> = SharedI2c::new();
+
+/// Set by the first panic, so a panic while drawing the panic screen does not start over
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+/// Replaces the panic handler of esp-backtrace to show the panic on the display as well
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    if PANICKED.swap(true, Ordering::Relaxed) {
+        loop {}
+    }
+    esp_println::println!("\n\n{}", info);
+    for address in esp_backtrace::arch::backtrace().into_iter().flatten() {
+        // Return addresses point to the instruction after the call
+        esp_println::println!("0x{:x}", address - 4);
+    }
+    let message = rust_deej::panic_message(info);
+    rust_deej::panic_persist::store(&message);
+    // Keep the tasks from touching the display while it is set up again
+    critical_section::with(|_| show_panic(&message));
+    loop {}
+}
+
+/// Proxy of [I2C_BUS]
+#[cfg(all(feature = "display", not(feature = "display-spi")))]
+type DisplayInterface = I2CInterface<I2cProxy<I2C<'static, I2C0>>>;
+/// SCK, MOSI, DC, CS and RES, see [board::display_pins]
+#[cfg(feature = "display-spi")]
+type DisplayInterface = SPIInterface<
+    Spi<'static, SPI2, FullDuplexMode>,
+    GpioPin<Output<PushPull>, { board::display_pins::DC }>,
+    GpioPin<Output<PushPull>, { board::display_pins::CS }>,
+>;
+
+/// Sets up the display on [I2C_BUS], which has to be set before
+#[cfg(all(feature = "display", not(feature = "display-spi")))]
+fn new_display(
+    i2c: I2cProxy<I2C<'static, I2C0>>,
+    _delay: &mut Delay,
+) -> Ssd1306Display<DisplayInterface> {
+    let mut display = Ssd1306::new(
+        I2CDisplayInterface::new(i2c),
+        DISPLAY_SIZE,
+        DISPLAY_ROTATION,
+    )
+    .into_buffered_graphics_mode();
+    // A display that does not answer is reported by the self-test
+    display.init().ok();
+    display
+}
+
+/// Sets up the display, see [DisplayInterface] for the pins
+#[cfg(feature = "display-spi")]
+#[allow(clippy::too_many_arguments)]
+fn new_display(
+    spi2: SPI2,
+    sck: GpioPin<Unknown, { board::display_pins::SCK }>,
+    mosi: GpioPin<Unknown, { board::display_pins::MOSI }>,
+    dc: GpioPin<Unknown, { board::display_pins::DC }>,
+    cs: GpioPin<Unknown, { board::display_pins::CS }>,
+    res: GpioPin<Unknown, { board::display_pins::RES }>,
+    clocks: &Clocks,
+    delay: &mut Delay,
+) -> Ssd1306Display<DisplayInterface> {
+    let spi = Spi::new(spi2, 8u32.MHz(), SpiMode::Mode0, clocks)
+        .with_sck(sck)
+        .with_mosi(mosi);
+    let interface = SPIInterface::new(spi, dc.into_push_pull_output(), cs.into_push_pull_output());
+    let mut display =
+        Ssd1306::new(interface, DISPLAY_SIZE, DISPLAY_ROTATION).into_buffered_graphics_mode();
+
+    // SPI modules have a reset pin that has to be toggled before init
+    display
+        .reset(&mut res.into_push_pull_output(), delay)
+        .unwrap();
+    // A display that does not answer is reported by the self-test
+    display.init().ok();
+    display
+}
+
+/// Frees [I2C_BUS] after a transfer to the display failed, a device may be holding SDA low, and
+/// sets it up again with stolen peripherals like [show_panic]. The devices keep their proxies.
+/// Holds a critical section, so no transfer starts in between.
+#[cfg(all(
+    feature = "display",
+    not(any(feature = "display-spi", feature = "embassy"))
+))]
+fn reset_i2c_bus(clocks: &esp_hal::clock::Clocks) {
+    critical_section::with(|_| {
+        let peripherals = unsafe { Peripherals::steal() };
+        let io = IO::new(peripherals.GPIO, peripherals.IO_MUX);
+        let (mut sda, mut scl) = board::i2c_pins!(io);
+        rust_deej::i2c_bus::clear_bus(&mut sda, &mut scl, &mut Delay::new(clocks));
+
+        // The pins went to clear_bus, so they are stolen once more
+        let peripherals = unsafe { Peripherals::steal() };
+        let io = IO::new(peripherals.GPIO, peripherals.IO_MUX);
+        I2C_BUS.set(board::i2c_bus!(peripherals, io, clocks));
+    })
+}
+
+/// Shows the panic on the display, esp-backtrace would only print it to serial. Resets the chip
+/// after [PANIC_RESET_DELAY] so the panic is reported on the next boot.
+///
+/// The display task may have been interrupted in the middle of a transfer, so the display is
+/// set up again from scratch with stolen peripherals. Without `display` only the reset is left.
+pub fn show_panic(message: &str) {
+    let peripherals = unsafe { Peripherals::steal() };
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+    let mut delay = Delay::new(&clocks);
+    // The task watchdog would reset the chip before the panic can be read
+    #[cfg(not(feature = "embassy"))]
+    esp_hal::timer::TimerGroup::new(peripherals.TIMG1, &clocks)
+        .wdt
+        .disable();
+
+    #[cfg(feature = "display")]
+    {
+        let io = IO::new(peripherals.GPIO, peripherals.IO_MUX);
+        // The bus may have been left in the middle of a transfer as well
+        #[cfg(not(feature = "display-spi"))]
+        I2C_BUS.set(board::i2c_bus!(peripherals, io, &clocks));
+        let mut display = board::display!(peripherals, io, &clocks, &mut delay);
+        rust_deej::show_panic(&mut display, message);
+    }
+    #[cfg(not(feature = "display"))]
+    let _ = message;
+
+    if PANIC_RESET_DELAY > 0 {
+        delay.delay_ms(PANIC_RESET_DELAY * 1000);
+        esp_hal::reset::software_reset();
+    }
+}
+
+/// Puts the chip into deep sleep until it is reset, e.g. from the power switch once the battery is
+/// charged. Like [show_panic] it sets up what it needs with stolen peripherals.
+#[cfg(feature = "battery")]
+pub fn power_off() -> ! {
+    let peripherals = unsafe { Peripherals::steal() };
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+    let mut delay = Delay::new(&clocks);
+    let mut rtc = esp_hal::rtc_cntl::Rtc::new(peripherals.LPWR);
+    rtc.sleep_deep(&[], &mut delay)
+}
+
+/// Body of the interrupt handlers of the host transports. `$read` reads a byte from the transport
+/// of the handler and `$reader` is its local `LineReader`. The RTIC app binds UART0 or UART1
+/// depending on `serial.uart` of board.toml and with `usb-serial-jtag` also USB_DEVICE, every
+/// handler expands this with its own context. Replies go out on every transport like the frames.
+#[cfg(not(feature = "embassy"))]
+macro_rules! receive_from_host {
+    ($cx:ident, $read:expr, $reader:ident) => {
+        // Any traffic counts, even empty lines
+        $cx.shared.host_link.lock(|l| l.received(now_ms()));
+        while let Ok(byte) = $cx.shared.host.lock($read) {
+            let Some(line) = $cx.local.$reader.push(byte) else {
+                continue;
+            };
+            if let Some(command) = cli::parse(&line) {
+                run_cli!($cx, command);
+                continue;
+            }
+            match protocol::parse_command(&line) {
+                Some(HostCommand::Hello) => {
+                    let hello = protocol::encode_hello(CAPABILITIES);
+                    $cx.shared.host.lock(|h| h.send(hello.as_bytes()));
+                    $cx.shared
+                        .status
+                        .lock(|s| s.handle(StatusEvent::HostConnected, now_ms()));
+                }
+                Some(HostCommand::SetMode(mode)) => $cx.shared.protocol_mode.lock(|m| *m = mode),
+                Some(HostCommand::Ota) => $cx.shared.ota_request.lock(|r| *r = true),
+                Some(HostCommand::Bootloader) => board::reboot_to_bootloader(),
+                Some(HostCommand::HostMutes(mutes)) => {
+                    $cx.shared.host_mutes.lock(|m| *m = mutes);
+                    publish_event(
+                        &mut $cx.shared.events,
+                        Event::HostMessage(HostCommand::HostMutes(mutes)),
+                    );
+                }
+                Some(HostCommand::HostVolumes(volumes)) => {
+                    $cx.shared.fader_targets.lock(|t| t.set_volumes(&volumes));
+                    publish_event(
+                        &mut $cx.shared.events,
+                        Event::HostMessage(HostCommand::HostVolumes(volumes)),
+                    );
+                }
+                // Only ever shown on the display, by `handle_events`
+                Some(
+                    command @ (HostCommand::Icon(..)
+                    | HostCommand::Name(..)
+                    | HostCommand::SetUnits(_)
+                    | HostCommand::Levels(_)
+                    | HostCommand::NowPlaying(_)),
+                ) => publish_event(&mut $cx.shared.events, Event::HostMessage(command)),
+                Some(HostCommand::DisplayTimeout(timeout)) => {
+                    let changed = $cx.shared.settings.lock(|s| {
+                        let changed = s.display_timeout != timeout;
+                        s.display_timeout = timeout;
+                        changed.then_some(*s)
+                    });
+                    // Kept like the changes made in the settings menu
+                    #[cfg(feature = "settings")]
+                    if let Some(settings) = changed {
+                        rust_deej::settings::save(&mut esp_storage::FlashStorage::new(), &settings)
+                            .ok();
+                    }
+                    #[cfg(not(feature = "settings"))]
+                    let _ = changed;
+                    // Applies the timeout and restarts the timer
+                    redraw();
+                }
+                Some(HostCommand::Faders(enabled)) => {
+                    $cx.shared.fader_targets.lock(|t| t.set_enabled(enabled))
+                }
+                None => $cx
+                    .shared
+                    .status
+                    .lock(|s| s.handle(StatusEvent::Error, now_ms())),
+            }
+        }
+    };
+}
+
+/// Carries out a command of the debug [cli] received by [receive_from_host], `$command` is the
+/// result of [cli::parse]. The replies go out on every transport.
+#[cfg(not(feature = "embassy"))]
+macro_rules! run_cli {
+    ($cx:ident, $command:expr) => {
+        let mut send = |reply: &str| $cx.shared.host.lock(|h| h.send(reply.as_bytes()));
+        match $command {
+            Err(error) => send(error),
+            Ok(CliCommand::Help) => send(cli::HELP),
+            Ok(CliCommand::Raw) => {
+                let raw_values = $cx.shared.raw_input_values.lock(|r| *r);
+                send(&cli::encode_raw(&raw_values));
+            }
+            Ok(CliCommand::Config) => {
+                let settings = $cx.shared.settings.lock(|s| *s);
+                send(&cli::encode_config(&settings));
+            }
+            Ok(CliCommand::Set(setting)) => {
+                let settings = $cx.shared.settings.lock(|s| {
+                    setting.apply(s);
+                    *s
+                });
+                // Kept like the changes made in the settings menu
+                #[cfg(feature = "settings")]
+                rust_deej::settings::save(&mut esp_storage::FlashStorage::new(), &settings).ok();
+                #[cfg(feature = "display")]
+                $cx.shared.display.lock(|d| {
+                    d.set_on_contrast(settings.contrast);
+                    d.set_theme(settings.theme.theme());
+                });
+                #[cfg(not(feature = "display"))]
+                let _ = settings;
+                // Applies the display timeout, the rest applies from the next sample or frame
+                redraw();
+                send(cli::OK);
+            }
+            // The pots have to be at zero, they are measured over the next samples
+            #[cfg(not(feature = "oversampling"))]
+            Ok(CliCommand::Calibrate) => {
+                $cx.shared.calibrate.lock(|c| *c = true);
+                send(cli::OK);
+            }
+            #[cfg(feature = "oversampling")]
+            Ok(CliCommand::Calibrate) => send("!error oversampling has no zero cutoffs\r\n"),
+            Ok(CliCommand::Reboot) => esp_hal::reset::software_reset(),
+        }
+    };
+}
+
+/// Runs on the embassy executor instead of RTIC
+#[cfg(feature = "embassy")]
+mod embassy_app;
+
+#[cfg(not(feature = "embassy"))]
+// FROM_CPU_INTR3 is left to the radio, which yields its tasks on it
+#[rtic::app(device=esp32c3, dispatchers = [FROM_CPU_INTR0, FROM_CPU_INTR1, FROM_CPU_INTR2])]
 mod app {
 
-    use esp_backtrace as _; // Panic handling
+    use core::cell::Cell;
+    #[cfg(feature = "defmt")]
+    use defmt_rtt as _; // Global logger
+    use esp_backtrace as _; // Exception handling
     use esp_hal::{
-        adc::{AdcConfig, Attenuation, ADC},
-        clock::ClockControl,
-        i2c::I2C,
-        peripherals::{Peripherals, ADC1, TIMG0, TIMG1},
+        adc::{AdcConfig, ADC},
+        clock::{ClockControl, Clocks},
+        ledc::{timer::Timer as LedcTimer, LowSpeed, LEDC},
+        peripherals::{Peripherals, TIMG1},
         prelude::*,
-        timer::{Timer0, TimerGroup},
-        Delay, Timer, IO,
+        systimer::SystemTimer,
+        timer::{TimerGroup, Wdt},
+        Delay, Uart, IO,
     };
-    use esp_println::println;
+    use rtic_monotonics::Monotonic;
 
     use rust_deej::{
-        globals::{INPUT_COUNT, SERIAL_UPDATE_PERIOD},
-        scale_analog_input_to_100, scale_analog_input_to_1023, AnyAnalogPin, DisplayState,
-        DisplayStatus, ReadAnalog,
+        activity::ChannelActivity,
+        cli::{self, CliCommand},
+        events::{Consumer, Event, Events},
+        gestures::{ButtonAction, GestureDetector},
+        globals::{
+            GESTURE_TIMING, INPUT_COUNT, LED_UPDATE_PERIOD, OUTPUT_COUNT, PAGE_BUTTON_ACTIONS,
+            SAMPLE_PERIOD_MOVING, SERIAL_CHANGE_THRESHOLD, SERIAL_KEEP_ALIVE_PERIOD,
+            VIRTUAL_CHANNELS, WATCHDOG_TIMEOUT,
+        },
+        mono::{self, Mono},
+        motor::FaderTargets,
+        numerics::wire_to_percent,
+        protocol::{self, HostCommand, ProtocolMode, CAPABILITIES},
+        self_test::SelfTest,
+        serial::{ActiveHost, FanOut, LineReader, LinkMonitor, LinkState, Transport},
+        settings::Settings,
+        status_led::{StatusEvent, StatusIndicator},
+        watchdog::{TaskWatchdog, WatchedTask},
+        AnyAnalogPin, DisplayStatus,
     };
-    use ssd1306::{
-        prelude::{DisplaySize128x64, *},
-        I2CDisplayInterface, Ssd1306,
+
+    use crate::board;
+
+    #[cfg(feature = "display")]
+    use esp_hal::systimer::{Alarm, Periodic};
+    #[cfg(feature = "display")]
+    use rust_deej::{
+        framebuffer::Framebuffer,
+        globals::{
+            DISPLAY_OFF_DELAY, DISPLAY_RETRY_DELAY, DISPLAY_RETRY_MAX_DELAY, DISPLAY_UPDATE_PERIOD,
+            SELF_TEST_FAIL_TIME, SELF_TEST_TIME, SPLASH_TIME,
+        },
+        mono::Instant,
+        pages::Screen,
+        settings::DisplayTimeout,
+        DisplayPower, DisplayState, Ssd1306Display,
     };
 
+    #[cfg(feature = "display")]
+    use crate::DisplayInterface;
+
+    #[cfg(any(
+        feature = "ble",
+        feature = "wifi",
+        feature = "espnow-remote",
+        feature = "espnow-dongle"
+    ))]
+    use esp_hal::Rng;
+    #[cfg(any(
+        feature = "ble",
+        feature = "wifi",
+        feature = "espnow-remote",
+        feature = "espnow-dongle"
+    ))]
+    use esp_wifi::EspWifiInitFor;
+    #[cfg(feature = "ble")]
+    use rust_deej::ble::BleLink;
+    #[cfg(any(feature = "espnow-remote", feature = "espnow-dongle"))]
+    use rust_deej::espnow::EspNowLink;
+    #[cfg(any(feature = "ble", feature = "wifi", feature = "espnow-remote"))]
+    use rust_deej::globals::SAMPLE_PERIOD;
+    #[cfg(feature = "wifi")]
+    use rust_deej::wifi::{WifiLink, WifiState};
+
+    #[cfg(not(feature = "ble"))]
+    type BleLink = ();
+    #[cfg(not(feature = "wifi"))]
+    type WifiLink = ();
+    #[cfg(not(any(feature = "espnow-remote", feature = "espnow-dongle")))]
+    type EspNowLink = ();
+
+    #[cfg(feature = "ota")]
+    use rust_deej::{buttons::LongPress, globals::OTA_BUTTON_HOLD_TIME};
+
+    /// BOOT button starts a firmware update when held
+    #[cfg(feature = "ota")]
+    type OtaButton = LongPress;
+    #[cfg(not(feature = "ota"))]
+    type OtaButton = ();
+
+    #[cfg(feature = "profiles")]
+    use rust_deej::{globals::PROFILES, profiles::Profiles};
+
+    /// Cycles the profiles
+    #[cfg(feature = "profiles")]
+    type ProfileButton = board::ProfileButton;
+    #[cfg(not(feature = "profiles"))]
+    type ProfileButton = ();
+
+    /// Switches the stream between the two PCs
+    #[cfg(feature = "host-switch")]
+    type HostButton = board::HostButton;
+    #[cfg(not(feature = "host-switch"))]
+    type HostButton = ();
+
+    /// Decides which frames go out, to each of the two PCs with `host-switch`
+    #[cfg(feature = "host-switch")]
+    type Gate = rust_deej::serial::HostSwitch;
+    #[cfg(not(feature = "host-switch"))]
+    type Gate = rust_deej::serial::SerialGate;
+
+    #[cfg(feature = "settings")]
+    use esp_hal::gpio::Event;
+    #[cfg(feature = "settings")]
+    use rust_deej::{
+        encoder::QuadratureDecoder,
+        globals::ENCODER_PUSH_DEBOUNCE,
+        menu::{MenuCursor, MenuEvent},
+    };
+
+    #[cfg(any(feature = "leds", feature = "status-led"))]
+    use esp_hal::rmt::Rmt;
+    #[cfg(any(feature = "leds", feature = "status-led"))]
+    use esp_hal_smartled::SmartLedsAdapter;
+    #[cfg(feature = "leds")]
+    use rust_deej::leds::{LedBar, LED_BUFFER_SIZE};
+
+    #[cfg(not(feature = "leds"))]
+    type LedBar = ();
+
+    #[cfg(feature = "status-led")]
+    use rust_deej::status_led::{StatusLed, STATUS_LED_BUFFER_SIZE};
+
+    #[cfg(not(feature = "status-led"))]
+    type StatusLed = ();
+
+    /// UART0 or UART1 on the pins of board.toml
+    type HostUart = Uart<'static, board::HostUartPeripheral>;
+
+    #[cfg(feature = "usb-serial-jtag")]
+    type UsbPort = esp_hal::usb_serial_jtag::UsbSerialJtag<'static>;
+    #[cfg(not(feature = "usb-serial-jtag"))]
+    type UsbPort = ();
+
+    /// Buzzer or vibration motor on GPIO10
+    #[cfg(feature = "feedback")]
+    type Feedback = rust_deej::feedback::Feedback<
+        esp_hal::gpio::GpioPin<esp_hal::gpio::Output<esp_hal::gpio::PushPull>, 10>,
+    >;
+    #[cfg(not(feature = "feedback"))]
+    type Feedback = ();
+
+    /// Pots are scanned in the background and only the averages are read
+    #[cfg(feature = "adc-dma")]
+    type Adc = rust_deej::adc_dma::ContinuousAdc;
+    #[cfg(not(feature = "adc-dma"))]
+    type Adc = ADC<'static, esp_hal::peripherals::ADC1>;
+
+    /// Divider on `supply.pin` of board.toml, read along with the pots
+    #[cfg(feature = "supply-monitor")]
+    type SupplyPin = AnyAnalogPin;
+    #[cfg(not(feature = "supply-monitor"))]
+    type SupplyPin = ();
+    #[cfg(not(any(
+        feature = "adc-dma",
+        feature = "oversampling",
+        feature = "display-slave"
+    )))]
+    use rust_deej::analog::Pots;
+    #[cfg(not(feature = "oversampling"))]
+    use rust_deej::{globals::CHANNEL_CONFIGS, sampling::Reading};
+    #[cfg(not(any(feature = "oversampling", feature = "display-slave")))]
+    use rust_deej::{
+        globals::NOISE_FLOOR_SAMPLES,
+        sampling::{NoiseFloor, Sampler},
+    };
+    #[cfg(not(any(feature = "adc-dma", feature = "display-slave")))]
+    use rust_deej::{globals::SELF_TEST_SAMPLES, ReadAnalog};
+    #[cfg(feature = "oversampling")]
+    use rust_deej::{
+        globals::{CHANNEL_CONFIGS, OVERSAMPLING_BITS},
+        oversampling,
+    };
+    #[cfg(feature = "supply-monitor")]
+    use rust_deej::{
+        globals::{SUPPLY_SAMPLES, SUPPLY_SAMPLE_PERIOD},
+        supply::{self, SupplyMonitor},
+    };
+
+    /// Fuel gauge or divider of `battery` in board.toml
+    #[cfg(feature = "battery")]
+    type BatteryGauge = board::BatteryGauge;
+    #[cfg(not(feature = "battery"))]
+    type BatteryGauge = ();
+    #[cfg(feature = "battery")]
+    use rust_deej::{
+        battery::{BatteryMonitor, ReadBattery},
+        globals::BATTERY_SAMPLE_PERIOD,
+    };
+
+    /// BH1750 on [crate::I2C_BUS]
+    #[cfg(feature = "ambient-light")]
+    type AmbientSensor = rust_deej::ambient::Bh1750<
+        rust_deej::i2c_bus::I2cProxy<esp_hal::i2c::I2C<'static, esp_hal::peripherals::I2C0>>,
+    >;
+    #[cfg(not(feature = "ambient-light"))]
+    type AmbientSensor = ();
+    #[cfg(feature = "ambient-light")]
+    use rust_deej::{ambient::AmbientLight, globals::AMBIENT_SAMPLE_PERIOD};
+
+    /// MCP23017 on [crate::I2C_BUS] with the mute buttons and their LEDs
+    #[cfg(feature = "expander")]
+    type Expander = rust_deej::expander::Mcp23017<
+        rust_deej::i2c_bus::I2cProxy<esp_hal::i2c::I2C<'static, esp_hal::peripherals::I2C0>>,
+    >;
+    #[cfg(not(feature = "expander"))]
+    type Expander = ();
+    #[cfg(feature = "expander")]
+    use rust_deej::{
+        expander::MuteButtons,
+        globals::{EXPANDER_BUTTONS, EXPANDER_LEDS},
+    };
+
+    /// MPU6050 or LIS3DH of `accelerometer` in board.toml
+    #[cfg(feature = "auto-rotate")]
+    type Accelerometer = board::Accelerometer;
+    #[cfg(not(feature = "auto-rotate"))]
+    type Accelerometer = ();
+    #[cfg(feature = "auto-rotate")]
+    use rust_deej::{
+        globals::ORIENTATION_SAMPLE_PERIOD,
+        orientation::{OrientationDetector, ReadGravity},
+        DISPLAY_ROTATION,
+    };
+
+    /// H-bridges of `motor` in board.toml and the loops moving the faders
+    #[cfg(feature = "motorized-faders")]
+    type Faders = rust_deej::motor::Faders<rust_deej::motor::HBridge>;
+    #[cfg(not(feature = "motorized-faders"))]
+    type Faders = ();
+
+    /// Slider caps of `touch` in board.toml
+    #[cfg(feature = "touch-sense")]
+    type TouchPads = rust_deej::touch::TouchPads<
+        esp_hal::gpio::AnyPin<esp_hal::gpio::Output<esp_hal::gpio::OpenDrain>>,
+    >;
+    #[cfg(not(feature = "touch-sense"))]
+    type TouchPads = ();
+
+    #[cfg(feature = "display")]
+    type Display = DisplayState<'static, Framebuffer<Ssd1306Display<DisplayInterface>>>;
+    #[cfg(not(feature = "display"))]
+    type Display = ();
+
+    #[cfg(feature = "light-sleep")]
+    use esp_hal::rtc_cntl::Rtc;
+    #[cfg(feature = "light-sleep")]
+    use rust_deej::power::PowerManager;
+    #[cfg(not(feature = "light-sleep"))]
+    type PowerManager = ();
+
     #[shared]
     struct Shared {
         raw_input_values: [u16; INPUT_COUNT],
-        display: DisplayState<'static>,
-        display_on_time: u32,
-        timer0: Timer<Timer0<TIMG0>>,
+        /// Values sent to the host, 0-1023
+        output_values: [u16; OUTPUT_COUNT],
+        display: Display,
+        /// Changed in the menu with `settings`
+        settings: Settings,
+        /// When `turn_display_off` dims the display, pushed back by every draw
+        #[cfg(feature = "display")]
+        display_off_at: Option<Instant>,
+        /// Fires `animate_display`, its interrupt is enabled by `update_display` while the display
+        /// is animated
+        #[cfg(feature = "display")]
+        animation_alarm: Alarm<Periodic, 1>,
+        /// Milliseconds between the samples of idle, paced by `pace_sampling`
+        sample_period: u32,
+        /// Set by `pace_sampling` when idle is due to take the next sample
+        sample_due: bool,
+        protocol_mode: ProtocolMode,
+        /// Set when the host asks for a firmware update
+        ota_request: bool,
+        /// Channels the host reports as muted
+        host_mutes: [bool; INPUT_COUNT],
+        /// Volumes the host reports, for `move_faders`
+        fader_targets: FaderTargets,
+        /// Whether a host is listening, from the traffic it sends
+        host_link: LinkMonitor,
+        /// Written by the serial tasks and read on their interrupts
+        host: FanOut<HostUart, UsbPort>,
+        /// PC that gets the stream with `host-switch`
+        active_host: ActiveHost,
+        status: StatusIndicator,
+        /// Published by idle and the host, read by `handle_events`, `send_to_serial` and
+        /// `update_leds`
+        events: Events,
+        /// Every channel is sent as 0, toggled with [ButtonAction::ToggleMute]
+        muted: bool,
+        /// Set by [CliCommand::Calibrate], idle measures the zero cutoffs again
+        calibrate: bool,
+        /// The TIMG1 watchdog is fed once the sampling loop and the serial task have checked in
+        watchdog: (TaskWatchdog, Wdt<TIMG1>),
     }
 
     #[local]
     struct Local {
-        adc: ADC<'static, ADC1>,
+        adc: Adc,
         pots: [AnyAnalogPin; INPUT_COUNT],
+        supply_pin: SupplyPin,
+        battery_gauge: BatteryGauge,
+        ambient_sensor: AmbientSensor,
+        expander: Expander,
+        accelerometer: Accelerometer,
+        faders: Faders,
+        touch_pads: TouchPads,
         delay: Delay,
-        timer1: Timer<Timer0<TIMG1>>,
+        serial_gate: Gate,
+        line_reader: LineReader,
+        #[cfg(feature = "usb-serial-jtag")]
+        usb_line_reader: LineReader,
+        #[cfg(feature = "settings")]
+        encoder: board::Encoder,
+        led_bar: LedBar,
+        status_led: StatusLed,
+        ble_link: BleLink,
+        wifi_link: WifiLink,
+        espnow_link: EspNowLink,
+        boot_button: board::PageButton,
+        ota_button: OtaButton,
+        profile_button: ProfileButton,
+        host_button: HostButton,
+        feedback: Feedback,
+        power: PowerManager,
+        /// For `recover_display` to set the I2C bus up again
+        #[cfg(feature = "display")]
+        clocks: &'static Clocks<'static>,
+    }
+
+    /// Milliseconds since boot
+    fn now_ms() -> u64 {
+        SystemTimer::now() / (SystemTimer::TICKS_PER_SECOND / 1000)
+    }
+
+    /// Has `update_display` draw what changed, there is nothing to draw without `display`
+    fn redraw() {
+        #[cfg(feature = "display")]
+        update_display::spawn().ok();
+    }
+
+    /// Takes `flag`, sleeping until the next interrupt when it was not set. The interrupts are off
+    /// from the check to the sleep, a pending one still wakes the core, so the task setting the
+    /// flag can not slip in between and leave the core asleep.
+    fn take_or_sleep(flag: &mut impl rtic::Mutex<T = bool>) -> bool {
+        critical_section::with(|_| {
+            let set = flag.lock(core::mem::take);
+            if !set {
+                unsafe { core::arch::asm!("wfi") };
+            }
+            set
+        })
     }
 
-    #[init]
-    fn init(_: init::Context) -> (Shared, Local) {
+    /// The LEDC of the motors keeps references to the clocks and its timer
+    #[init(local = [
+        clocks: Option<Clocks<'static>> = None,
+        ledc: Option<LEDC<'static>> = None,
+        motor_timer: Option<LedcTimer<'static, LowSpeed>> = None,
+    ])]
+    fn init(cx: init::Context) -> (Shared, Local) {
+        #[cfg(feature = "defmt")]
+        defmt::info!("rust-deej {}", rust_deej::pages::VERSION);
+
         let peripherals = Peripherals::take();
         let system = peripherals.SYSTEM.split();
         let io = IO::new(peripherals.GPIO, peripherals.IO_MUX);
 
         let mut adc_config = AdcConfig::new();
 
-        let pot0 = adc_config
-            .enable_pin_with_cal(io.pins.gpio0.into_analog(), Attenuation::Attenuation0dB);
-        let pot1 = adc_config
-            .enable_pin_with_cal(io.pins.gpio1.into_analog(), Attenuation::Attenuation0dB);
-        let pot2 = adc_config
-            .enable_pin_with_cal(io.pins.gpio2.into_analog(), Attenuation::Attenuation0dB);
-        let pot3 = adc_config
-            .enable_pin_with_cal(io.pins.gpio3.into_analog(), Attenuation::Attenuation0dB);
-
-        let adc = ADC::new(peripherals.ADC1, adc_config);
-
-        let clocks = ClockControl::max(system.clock_control).freeze();
-        let delay = Delay::new(&clocks);
-
-        let i2c = I2C::new(
-            peripherals.I2C0,
-            io.pins.gpio6,
-            io.pins.gpio7,
-            100u32.kHz(),
-            &clocks,
+        #[cfg_attr(any(feature = "adc-dma", feature = "display-slave"), allow(unused_mut))]
+        let mut pots = board::pots!(io, adc_config);
+        #[cfg(feature = "supply-monitor")]
+        let supply_pin = board::supply_pin!(io, adc_config);
+        #[cfg(not(feature = "supply-monitor"))]
+        let supply_pin = ();
+        #[cfg(feature = "battery")]
+        let battery_gauge = board::battery_gauge!(io, adc_config);
+        #[cfg(not(feature = "battery"))]
+        let battery_gauge = ();
+
+        #[cfg_attr(any(feature = "adc-dma", feature = "display-slave"), allow(unused_mut))]
+        let mut adc = ADC::new(peripherals.ADC1, adc_config);
+        #[cfg(feature = "adc-dma")]
+        let adc = Adc::new(adc, esp_hal::dma::gdma::Gdma::new(peripherals.DMA));
+
+        let clocks: &'static Clocks = cx
+            .local
+            .clocks
+            .insert(ClockControl::max(system.clock_control).freeze());
+        let mut delay = Delay::new(clocks);
+        // Alarm 0 is left to the radio
+        let systimer = SystemTimer::new(peripherals.SYSTIMER);
+        mono::start(systimer.alarm2);
+
+        #[cfg(feature = "i2c-bus")]
+        crate::I2C_BUS.set(board::i2c_bus!(peripherals, io, clocks));
+        #[cfg(feature = "display")]
+        let display = board::display!(peripherals, io, clocks, &mut delay);
+        #[cfg(feature = "ambient-light")]
+        let ambient_sensor = {
+            let mut sensor = rust_deej::ambient::Bh1750::new(crate::I2C_BUS.proxy());
+            // A missing sensor leaves the contrast of the settings
+            sensor.start().ok();
+            sensor
+        };
+        #[cfg(not(feature = "ambient-light"))]
+        let ambient_sensor = ();
+        // Set up by idle for the pins of its MuteButtons
+        #[cfg(feature = "expander")]
+        let expander = rust_deej::expander::Mcp23017::new(
+            crate::I2C_BUS.proxy(),
+            rust_deej::globals::EXPANDER_ADDRESS,
         );
+        #[cfg(not(feature = "expander"))]
+        let expander = ();
+        #[cfg(feature = "auto-rotate")]
+        let accelerometer = {
+            let mut accelerometer = board::accelerometer!();
+            // A missing sensor leaves the display in DISPLAY_ROTATION
+            accelerometer.start().ok();
+            accelerometer
+        };
+        #[cfg(not(feature = "auto-rotate"))]
+        let accelerometer = ();
+        #[cfg(feature = "motorized-faders")]
+        let faders = {
+            let (enable, phase) = board::motor_pins!(io);
+            let ledc = cx.local.ledc.insert(LEDC::new(peripherals.LEDC, clocks));
+            Faders::new(rust_deej::motor::h_bridges(
+                ledc,
+                cx.local.motor_timer,
+                enable,
+                phase,
+            ))
+        };
+        #[cfg(not(feature = "motorized-faders"))]
+        let faders = ();
+        #[cfg(feature = "touch-sense")]
+        let touch_pads = TouchPads::new(board::touch_pins!(io));
+        #[cfg(not(feature = "touch-sense"))]
+        let touch_pads = ();
+
+        // Kept in flash with `settings`
+        #[cfg(feature = "settings")]
+        let settings =
+            rust_deej::settings::load(&mut esp_storage::FlashStorage::new()).unwrap_or_default();
+        #[cfg(not(feature = "settings"))]
+        let settings = Settings::DEFAULT;
+
+        #[cfg(feature = "display")]
+        let mut display_state = {
+            let mut display_state = DisplayState::new(Framebuffer::new(display));
+            display_state.set_title("Volumes");
+            display_state.set_on_contrast(settings.contrast);
+            display_state.set_theme(settings.theme.theme());
+            #[cfg(feature = "profiles")]
+            display_state.set_profile(&PROFILES[0]);
+            display_state.ready();
+            display_state
+        };
+        #[cfg(not(feature = "display"))]
+        let display_state = ();
+
+        // esp_println logs to UART0 as well, at the baud rate set here when the host is on UART0
+        let mut host_uart = board::host_uart!(peripherals, io, clocks);
+        host_uart.set_rx_fifo_full_threshold(1).unwrap();
+        host_uart.listen_rx_fifo_full();
+
+        #[cfg(feature = "usb-serial-jtag")]
+        let usb_port = {
+            let mut usb_port = UsbPort::new(peripherals.USB_DEVICE);
+            usb_port.listen_rx_packet_recv_interrupt();
+            usb_port
+        };
+        #[cfg(not(feature = "usb-serial-jtag"))]
+        let usb_port = ();
+        let mut host = FanOut(host_uart, usb_port);
+
+        // Previous boot ended in a panic, tell the host and show it on the splash screen
+        let panic = rust_deej::panic_persist::take();
+        if let Some(message) = &panic {
+            host.send(protocol::encode_panic(message).as_bytes());
+        }
+        // Same for every reset, crashes, watchdogs and brown-outs get a banner
+        let boot = rust_deej::reset::read(panic.is_some());
+        host.send(protocol::encode_reset(&boot).as_bytes());
+        #[cfg(feature = "display")]
+        display_state.set_boot(boot);
+
+        // Checked before anything else uses the pots and the timers
+        #[cfg(feature = "adc-dma")]
+        let readings = {
+            // Averages of the background scan once it has gone round
+            delay.delay_ms(SAMPLE_PERIOD_MOVING);
+            adc.averages().map(|raw| Ok(Reading::steady(raw)))
+        };
+        #[cfg(not(any(feature = "adc-dma", feature = "display-slave")))]
+        let readings: [_; INPUT_COUNT] =
+            core::array::from_fn(|idx| pots[idx].read_multi_sample(&mut adc, SELF_TEST_SAMPLES));
+        // Nothing is wired to the pins of a display slave
+        #[cfg(feature = "display-slave")]
+        let readings: [_; INPUT_COUNT] = core::array::from_fn(|_| Ok(Reading::steady(0)));
+        let timer_runs = {
+            let start = Mono::now();
+            delay.delay_ms(1u32);
+            Mono::now() != start
+        };
+        #[cfg(feature = "display")]
+        let display_answers = display_state.check_display();
+        // Nothing to check without a display
+        #[cfg(not(feature = "display"))]
+        let display_answers = true;
+        let self_test = SelfTest::new(display_answers, &readings, timer_runs);
+        host.send(protocol::encode_self_test(&self_test).as_bytes());
+
+        #[cfg(feature = "display")]
+        {
+            display_state.set_self_test(self_test);
+            display_state.show_screen(Screen::Splash);
+            display_state.draw().unwrap();
+            delay.delay_ms(SPLASH_TIME);
+            display_state.show_screen(Screen::SelfTest);
+            display_state.draw().unwrap();
+            delay.delay_ms(if self_test.passed() {
+                SELF_TEST_TIME
+            } else {
+                SELF_TEST_FAIL_TIME
+            });
+            display_state.show_screen(Screen::Volumes);
+            display_state.draw().unwrap();
+        }
 
-        let interface = I2CDisplayInterface::new(i2c);
-        let mut display = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
-            .into_buffered_graphics_mode();
-        display.init().unwrap();
+        // Started once the boot screens are done, the dongle only samples when the remote sends
+        let task_watchdog = TaskWatchdog::new(if cfg!(feature = "espnow-dongle") {
+            &[WatchedTask::Serial]
+        } else {
+            &[WatchedTask::Sampling, WatchedTask::Serial]
+        });
+        let mut wdt = TimerGroup::new(peripherals.TIMG1, clocks).wdt;
+        wdt.start(WATCHDOG_TIMEOUT.secs());
+
+        // Animation frames are only needed while the bars are animated or a name scrolls
+        #[cfg(feature = "display")]
+        let animation_alarm = {
+            let animation_alarm = systimer.alarm1.into_periodic();
+            animation_alarm.set_period((DISPLAY_UPDATE_PERIOD * 1000).micros());
+            animation_alarm.enable_interrupt(display_state.is_animated());
+            animation_alarm
+        };
+
+        #[cfg(feature = "leds")]
+        let led_bar = {
+            let rmt = Rmt::new(peripherals.RMT, 80u32.MHz(), clocks).unwrap();
+            LedBar::new(SmartLedsAdapter::new(
+                rmt.channel0,
+                io.pins.gpio8,
+                [0u32; LED_BUFFER_SIZE],
+                clocks,
+            ))
+        };
+        #[cfg(not(feature = "leds"))]
+        let led_bar = ();
+
+        // Onboard LED of the DevKits, same pin as the LED strip
+        #[cfg(feature = "status-led")]
+        let status_led = {
+            let rmt = Rmt::new(peripherals.RMT, 80u32.MHz(), clocks).unwrap();
+            StatusLed::new(SmartLedsAdapter::new(
+                rmt.channel0,
+                io.pins.gpio8,
+                [0u32; STATUS_LED_BUFFER_SIZE],
+                clocks,
+            ))
+        };
+        #[cfg(not(feature = "status-led"))]
+        let status_led = ();
+
+        if cfg!(any(feature = "leds", feature = "status-led")) {
+            update_leds::spawn().ok();
+        }
+        send_to_serial::spawn().ok();
+
+        #[cfg(any(
+            feature = "ble",
+            feature = "wifi",
+            feature = "espnow-remote",
+            feature = "espnow-dongle"
+        ))]
+        let radio_init = esp_wifi::initialize(
+            if cfg!(feature = "ble") {
+                EspWifiInitFor::Ble
+            } else {
+                EspWifiInitFor::Wifi
+            },
+            systimer.alarm0,
+            Rng::new(peripherals.RNG),
+            system.radio_clock_control,
+            clocks,
+        )
+        .unwrap();
+
+        #[cfg(feature = "ble")]
+        let ble_link = BleLink {
+            init: radio_init,
+            bluetooth: peripherals.BT,
+        };
+        #[cfg(not(feature = "ble"))]
+        let ble_link = ();
+
+        #[cfg(feature = "wifi")]
+        let wifi_link = WifiLink {
+            init: radio_init,
+            wifi: peripherals.WIFI,
+        };
+        #[cfg(not(feature = "wifi"))]
+        let wifi_link = ();
 
-        let pots = [
-            AnyAnalogPin::from(pot0),
-            AnyAnalogPin::from(pot1),
-            AnyAnalogPin::from(pot2),
-            AnyAnalogPin::from(pot3),
-        ];
+        #[cfg(any(feature = "espnow-remote", feature = "espnow-dongle"))]
+        let espnow_link = EspNowLink {
+            init: radio_init,
+            wifi: peripherals.WIFI,
+        };
+        #[cfg(not(any(feature = "espnow-remote", feature = "espnow-dongle")))]
+        let espnow_link = ();
 
-        let display_on_time: u32 = 10;
+        // Pressing the BOOT button cycles the display pages
+        let boot_button = board::page_button!(io);
+        #[cfg(feature = "profiles")]
+        let profile_button = board::profile_button!(io);
+        #[cfg(not(feature = "profiles"))]
+        let profile_button = ();
+        #[cfg(feature = "host-switch")]
+        let host_button = board::host_button!(io);
+        #[cfg(not(feature = "host-switch"))]
+        let host_button = ();
+        // Every edge of the encoder is handled by read_encoder
+        #[cfg(feature = "settings")]
+        let encoder = {
+            let mut encoder = board::encoder!(io);
+            encoder.0.listen(Event::AnyEdge);
+            encoder.1.listen(Event::AnyEdge);
+            encoder.2.listen(Event::AnyEdge);
+            encoder
+        };
 
-        let timer_group0 = TimerGroup::new(peripherals.TIMG0, &clocks);
-        let mut timer0 = timer_group0.timer0;
-        timer0.listen();
+        #[cfg(feature = "feedback")]
+        let feedback = Feedback::new(io.pins.gpio10.into_push_pull_output());
+        #[cfg(not(feature = "feedback"))]
+        let feedback = ();
 
-        let timer_group1 = TimerGroup::new(peripherals.TIMG1, &clocks);
-        let mut timer1 = timer_group1.timer0;
-        timer1.listen();
-        timer1.start(SERIAL_UPDATE_PERIOD.millis());
+        #[cfg(feature = "light-sleep")]
+        let power = PowerManager::new(Rtc::new(peripherals.LPWR));
+        #[cfg(not(feature = "light-sleep"))]
+        let power = ();
 
-        let mut display_state = DisplayState::new(display);
-        display_state.set_title("Volumes");
-        display_state.ready();
+        #[cfg(feature = "ota")]
+        let ota_button = LongPress::new(OTA_BUTTON_HOLD_TIME);
+        #[cfg(not(feature = "ota"))]
+        let ota_button = ();
+
+        // Reaching this point means the image works, stop the bootloader from rolling back to the previous one
+        #[cfg(feature = "ota")]
+        rust_deej::ota::mark_valid(&mut esp_storage::FlashStorage::new()).ok();
+
+        let serial_gate = Gate::new(
+            SERIAL_CHANGE_THRESHOLD,
+            SERIAL_KEEP_ALIVE_PERIOD / settings.serial_period,
+        );
 
         (
             Shared {
                 raw_input_values: Default::default(),
+                output_values: Default::default(),
                 display: display_state,
-                display_on_time,
-                timer0,
+                settings,
+                #[cfg(feature = "display")]
+                display_off_at: None,
+                #[cfg(feature = "display")]
+                animation_alarm,
+                sample_period: SAMPLE_PERIOD_MOVING,
+                sample_due: false,
+                protocol_mode: ProtocolMode::default(),
+                ota_request: false,
+                host_mutes: [false; INPUT_COUNT],
+                fader_targets: FaderTargets::new(),
+                host_link: LinkMonitor::new(),
+                host,
+                active_host: ActiveHost::default(),
+                status: StatusIndicator::default(),
+                events: Events::new(),
+                muted: false,
+                calibrate: false,
+                watchdog: (task_watchdog, wdt),
             },
             Local {
                 adc,
                 pots,
+                supply_pin,
+                battery_gauge,
+                ambient_sensor,
+                expander,
+                accelerometer,
+                faders,
+                touch_pads,
                 delay,
-                timer1,
+                serial_gate,
+                line_reader: LineReader::new(),
+                #[cfg(feature = "usb-serial-jtag")]
+                usb_line_reader: LineReader::new(),
+                #[cfg(feature = "settings")]
+                encoder,
+                led_bar,
+                status_led,
+                ble_link,
+                wifi_link,
+                espnow_link,
+                boot_button,
+                ota_button,
+                profile_button,
+                host_button,
+                feedback,
+                power,
+                #[cfg(feature = "display")]
+                clocks,
             },
         )
     }
 
-    #[idle (shared = [raw_input_values, display], local=[adc,pots, delay])]
+    #[idle (shared = [raw_input_values, output_values, display, ota_request, host_mutes, host_link, active_host, protocol_mode, status, events, muted, settings, calibrate, watchdog, sample_period, sample_due], local=[adc, pots, supply_pin, battery_gauge, ambient_sensor, expander, accelerometer, touch_pads, delay, ble_link, wifi_link, espnow_link, boot_button, ota_button, profile_button, host_button, feedback, power])]
     fn idle(cx: idle::Context) -> ! {
         let idle::LocalResources {
-            adc, pots, delay, ..
+            adc,
+            pots,
+            supply_pin,
+            battery_gauge,
+            ambient_sensor,
+            expander,
+            accelerometer,
+            touch_pads,
+            delay,
+            ble_link,
+            wifi_link,
+            espnow_link,
+            boot_button,
+            ota_button,
+            profile_button,
+            host_button,
+            feedback,
+            power,
+            ..
         } = cx.local;
 
         let idle::SharedResources {
             mut raw_input_values,
+            mut output_values,
             mut display,
+            mut ota_request,
+            mut host_mutes,
+            mut host_link,
+            mut active_host,
+            mut protocol_mode,
+            mut status,
+            mut events,
+            mut muted,
+            mut settings,
+            mut calibrate,
+            mut watchdog,
+            mut sample_period,
+            mut sample_due,
             ..
         } = cx.shared;
 
         let mut volumes = [0; INPUT_COUNT];
-        loop {
-            for (idx, input) in pots.iter_mut().enumerate() {
-                let new_val = input.read_multi_sample(adc, 128);
-                raw_input_values.lock(|r| r[idx] = new_val);
-                volumes[idx] = scale_analog_input_to_100(new_val);
+        // Set by the sampling with `supply-monitor`, shown by `publish`
+        let supply_low = Cell::new(false);
+        // Charge of the battery with `battery`, read along with the pots like the supply
+        let battery = Cell::new(None);
+        // Contrast for the ambient light with `ambient-light`
+        let ambient_contrast = Cell::new(None);
+        // New rotation of the display with `auto-rotate`, taken by `publish`
+        let rotation = Cell::new(None);
+        // Change of the [LinkState] to publish, taken by `publish`
+        let link_event = Cell::new(None);
+        let mut page_gestures =
+            GestureDetector::new(GESTURE_TIMING.for_actions(&PAGE_BUTTON_ACTIONS));
+        // Positions published as [Event::InputChanged], none at first so every channel is
+        // published with the first sample
+        let mut last_positions = [u16::MAX; INPUT_COUNT];
+        // Follows the published positions, the display zooms on the focused channel
+        let mut activity = ChannelActivity::new();
+        // Makes new output values (0-1023) visible to the serial task and the display, along with
+        // the pot positions they were made from by the channel roles and the profile switched to
+        let mut publish = |outputs: &[u16; OUTPUT_COUNT],
+                           positions: &[u16; INPUT_COUNT],
+                           raw_values: &[u16; INPUT_COUNT],
+                           disconnected: &[bool; INPUT_COUNT],
+                           profile: Option<&'static rust_deej::profiles::Profile>,
+                           status: Option<&str>| {
+            watchdog.lock(|(tasks, wdt)| {
+                if tasks.check_in(WatchedTask::Sampling) {
+                    wdt.feed();
+                }
+            });
+            let muted = muted.lock(|m| *m);
+            // The profile index stays, so the host keeps the mappings of the profile
+            let mut outputs = *outputs;
+            if muted {
+                outputs[..OUTPUT_COUNT - cfg!(feature = "profiles") as usize].fill(0);
+            }
+            let outputs = &outputs;
+            output_values.lock(|o| *o = *outputs);
+            let values = rust_deej::roles::channel_values(&CHANNEL_CONFIGS, outputs);
+            for (vol, val) in volumes.iter_mut().zip(values.iter()) {
+                *vol = wire_to_percent(*val);
+            }
+            #[cfg(feature = "feedback")]
+            feedback.update(&volumes, now_ms());
+            #[cfg(not(feature = "feedback"))]
+            let _ = &feedback;
+
+            // Same threshold as the serial gate, so a jittering pot is not reported as moved.
+            // The changes of a sample share a time, so sliders moved together are not focused.
+            let changed_ms = now_ms();
+            for (channel, (value, last)) in positions.iter().zip(&mut last_positions).enumerate() {
+                if value.abs_diff(*last) >= SERIAL_CHANGE_THRESHOLD {
+                    *last = *value;
+                    let event = Event::InputChanged {
+                        channel,
+                        value: *value,
+                    };
+                    activity.handle(&event, changed_ms);
+                    publish_event(&mut events, event);
+                }
+            }
+            if let Some(gesture) = page_gestures.update(boot_button.is_low().unwrap(), now_ms()) {
+                publish_event(&mut events, Event::ButtonPressed(gesture));
+            }
+            if let Some(event) = link_event.take() {
+                publish_event(&mut events, event);
+            }
+
+            #[cfg(feature = "display")]
+            let display_changed = display.lock(|d| {
+                d.set_ambient_contrast(ambient_contrast.get());
+                let profile_changed = match profile {
+                    Some(profile) => d.set_profile(profile),
+                    None => DisplayStatus::NotChanged,
+                };
+                let rotated = match rotation.take() {
+                    Some(rotation) => d.set_rotation(rotation),
+                    None => DisplayStatus::NotChanged,
+                };
+                // The host sets the volumes of a display slave, it has no pots to focus either
+                let volumes_changed = if cfg!(feature = "display-slave") {
+                    DisplayStatus::NotChanged
+                } else {
+                    d.set_volumes(&volumes)
+                        .or(d.set_focus(activity.focused(now_ms())))
+                };
+                d.set_status(if muted { Some("MUTE") } else { status })
+                    .or(volumes_changed)
+                    .or(d.set_positions(&positions.map(wire_to_percent)))
+                    .or(d.set_raw_values(raw_values))
+                    .or(d.set_disconnected(disconnected))
+                    .or(d.set_supply_low(supply_low.get()))
+                    .or(d.set_battery(battery.get()))
+                    .or(d.tick(now_ms()))
+                    .or(profile_changed)
+                    .or(rotated)
+            });
+            #[cfg(feature = "display")]
+            if let DisplayStatus::Changed = display_changed {
+                redraw();
+            }
+            #[cfg(not(feature = "display"))]
+            let _ = (
+                &mut display,
+                &volumes,
+                positions,
+                raw_values,
+                disconnected,
+                profile,
+                status,
+                &supply_low,
+                &battery,
+                &ambient_contrast,
+                &rotation,
+            );
+        };
+
+        // Dongle has no pots, it only forwards what the remote sends
+        #[cfg(feature = "espnow-dongle")]
+        {
+            let _ = (
+                adc,
+                pots,
+                supply_pin,
+                battery_gauge,
+                ambient_sensor,
+                expander,
+                accelerometer,
+                touch_pads,
+                delay,
+                ble_link,
+                wifi_link,
+                ota_button,
+                profile_button,
+                host_button,
+                power,
+            );
+            let _ = (
+                &mut raw_input_values,
+                &mut ota_request,
+                &mut host_mutes,
+                &mut host_link,
+                &mut active_host,
+                &mut protocol_mode,
+                &mut status,
+                &mut settings,
+                &mut calibrate,
+                &mut sample_period,
+                &mut sample_due,
+            );
+            espnow_link.run_dongle(|values| {
+                publish(
+                    values,
+                    &rust_deej::roles::channel_values(&CHANNEL_CONFIGS, values),
+                    &[0; INPUT_COUNT],
+                    &[false; INPUT_COUNT],
+                    None,
+                    None,
+                )
+            })
+        }
+
+        // Display slave has no pots either, it shows what the host streams
+        #[cfg(feature = "display-slave")]
+        {
+            let _ = (
+                adc,
+                pots,
+                supply_pin,
+                battery_gauge,
+                ambient_sensor,
+                expander,
+                accelerometer,
+                touch_pads,
+                delay,
+                ble_link,
+                wifi_link,
+                espnow_link,
+                ota_button,
+                profile_button,
+                host_button,
+                power,
+            );
+            let _ = (
+                &mut raw_input_values,
+                &mut ota_request,
+                &mut host_mutes,
+                &mut active_host,
+                &mut protocol_mode,
+                &mut settings,
+                &mut calibrate,
+                &mut sample_period,
+                &mut status,
+            );
+            let mut previous_state = LinkState::Unknown;
+            // Wakes the loop for the page button and the timeouts of the display
+            pace_sampling::spawn().ok();
+            loop {
+                let state = host_link.lock(|l| l.state(now_ms()));
+                if let Some(event) = state.link_event(previous_state) {
+                    link_event.set(Some(event));
+                }
+                previous_state = state;
+                publish(
+                    &[0; OUTPUT_COUNT],
+                    &[0; INPUT_COUNT],
+                    &[0; INPUT_COUNT],
+                    &[false; INPUT_COUNT],
+                    None,
+                    state.label(),
+                );
+                while !take_or_sleep(&mut sample_due) {}
+            }
+        }
+
+        #[cfg(not(any(feature = "espnow-dongle", feature = "display-slave")))]
+        {
+            // Profile button is only read along with the pots
+            #[cfg(feature = "profiles")]
+            let (mut profiles, mut profile_press) = (
+                Profiles::new(PROFILES),
+                rust_deej::buttons::Debouncer::new(),
+            );
+            // Each profile sends deej frames or MIDI
+            #[cfg(feature = "profiles")]
+            protocol_mode.lock(|m| *m = profiles.active().protocol_mode());
+            #[cfg(not(feature = "profiles"))]
+            let _ = (profile_button, &mut protocol_mode);
+            #[cfg(not(feature = "oversampling"))]
+            let mut sampler = Sampler::new(&CHANNEL_CONFIGS);
+            // Zero cutoff of the pots that are at zero at boot, from their noise
+            #[cfg(not(feature = "oversampling"))]
+            {
+                let mut noise_floor = NoiseFloor::new();
+                for _ in 0..NOISE_FLOOR_SAMPLES {
+                    #[cfg(feature = "adc-dma")]
+                    {
+                        noise_floor.add(&adc.averages().map(|raw| Ok(Reading::steady(raw))));
+                        delay.delay_ms(SAMPLE_PERIOD_MOVING);
+                    }
+                    #[cfg(not(feature = "adc-dma"))]
+                    noise_floor.add(&sampler.read(&mut Pots { adc, pins: pots }));
+                }
+                sampler.set_zero_cutoffs(&noise_floor.cutoffs());
             }
+            // Zero cutoffs measured again over the next samples, see [CliCommand::Calibrate]
+            #[cfg(not(feature = "oversampling"))]
+            let mut recalibration: Option<NoiseFloor> = None;
+            #[cfg(feature = "oversampling")]
+            let _ = &mut calibrate;
+            #[cfg(feature = "oversampling")]
+            let mut oversampled = [0; INPUT_COUNT];
+            #[cfg(feature = "supply-monitor")]
+            let (mut supply, mut next_supply_sample) = (SupplyMonitor::new(), 0);
+            #[cfg(not(feature = "supply-monitor"))]
+            let _ = supply_pin;
+            #[cfg(feature = "battery")]
+            let (mut battery_monitor, mut next_battery_sample) = (BatteryMonitor::new(), 0);
+            #[cfg(not(feature = "battery"))]
+            let _ = (battery_gauge, &battery);
+            #[cfg(feature = "ambient-light")]
+            let (mut ambient, mut next_ambient_sample) = (AmbientLight::new(), 0);
+            #[cfg(not(feature = "ambient-light"))]
+            let _ = (ambient_sensor, &ambient_contrast);
+            // Levels last written to the LEDs of the expander
+            #[cfg(feature = "expander")]
+            let (mut mute_buttons, mut expander_leds) = {
+                let mute_buttons = MuteButtons::new(EXPANDER_BUTTONS, EXPANDER_LEDS);
+                // A missing expander leaves every channel unmuted
+                expander.configure(mute_buttons.inputs()).ok();
+                (mute_buttons, None)
+            };
+            #[cfg(not(feature = "expander"))]
+            let _ = (expander, &mut host_mutes);
+            #[cfg(feature = "auto-rotate")]
+            let (mut orientation, mut next_orientation_sample) =
+                (OrientationDetector::new(DISPLAY_ROTATION.into()), 0);
+            #[cfg(not(feature = "auto-rotate"))]
+            let _ = (accelerometer, &rotation);
+            #[cfg(not(feature = "touch-sense"))]
+            let _ = &touch_pads;
+            let mut sample = |status: Option<&str>| {
+                #[cfg(feature = "profiles")]
+                let profile = (profile_press.update(profile_button.is_low().unwrap())
+                    == Some(rust_deej::buttons::ButtonEvent::Pressed))
+                .then(|| profiles.cycle());
+                #[cfg(not(feature = "profiles"))]
+                let profile = None;
+                // Tapers of the settings unless the active profile replaces them
+                let current = settings.lock(|s| *s);
+                #[cfg(all(feature = "profiles", not(feature = "oversampling")))]
+                sampler.set_tapers(
+                    &profiles
+                        .active()
+                        .tapers(&current.channel_configs(&CHANNEL_CONFIGS)),
+                );
+                #[cfg(all(not(feature = "profiles"), not(feature = "oversampling")))]
+                sampler.set_tapers(&current.tapers);
+                #[cfg(not(feature = "oversampling"))]
+                sampler.set_inverted(&current.inverted);
+                #[cfg(feature = "profiles")]
+                if let Some(profile) = profile {
+                    protocol_mode.lock(|m| *m = profile.protocol_mode());
+                }
+
+                #[cfg(feature = "adc-dma")]
+                let (raw_values, values) = {
+                    let _ = &pots;
+                    let raw_values = adc.averages();
+                    (raw_values, sampler.process(&raw_values))
+                };
+                #[cfg(feature = "oversampling")]
+                let (raw_values, values) = {
+                    let mut raw_values = [0; INPUT_COUNT];
+                    for (idx, input) in pots.iter_mut().enumerate() {
+                        // A failed read keeps the previous value
+                        if let Ok(reading) = input.read_oversampled(adc, OVERSAMPLING_BITS) {
+                            raw_values[idx] = (reading >> OVERSAMPLING_BITS) as u16;
+                            oversampled[idx] = CHANNEL_CONFIGS[idx]
+                                .snap
+                                .apply(oversampling::scale_to_1023(reading, OVERSAMPLING_BITS));
+                        }
+                    }
+                    (raw_values, oversampled)
+                };
+                #[cfg(not(any(feature = "adc-dma", feature = "oversampling")))]
+                let (raw_values, values) = sampler.sample(&mut Pots { adc, pins: pots });
+                // The oversampled values skip the sampler and its pipelines
+                #[cfg(feature = "oversampling")]
+                let values = current.invert(&values);
+                // A touch wakes the display before the slider moves
+                #[cfg(feature = "touch-sense")]
+                if touch_pads.update() {
+                    redraw();
+                }
+                #[cfg(feature = "motorized-faders")]
+                {
+                    #[cfg(feature = "touch-sense")]
+                    let touched = touch_pads.touched();
+                    #[cfg(not(feature = "touch-sense"))]
+                    let touched = [false; INPUT_COUNT];
+                    move_faders::spawn((values, touched)).ok();
+                }
+                #[cfg(not(feature = "oversampling"))]
+                {
+                    if calibrate.lock(core::mem::take) {
+                        recalibration = Some(NoiseFloor::new());
+                    }
+                    if let Some(noise_floor) = &mut recalibration {
+                        noise_floor.add(&raw_values.map(|raw| Ok(Reading::steady(raw))));
+                        if noise_floor.samples() == NOISE_FLOOR_SAMPLES {
+                            sampler.set_zero_cutoffs(&noise_floor.cutoffs());
+                            recalibration = None;
+                        }
+                    }
+                }
+                #[cfg(feature = "supply-monitor")]
+                if now_ms() >= next_supply_sample {
+                    next_supply_sample = now_ms() + SUPPLY_SAMPLE_PERIOD;
+                    // A failed read keeps the previous state
+                    if let Ok(reading) = supply_pin.read_multi_sample(adc, SUPPLY_SAMPLES) {
+                        if supply.update(supply::millivolts(reading.average)) {
+                            supply_low.set(supply.is_low());
+                        }
+                    }
+                }
+                #[cfg(feature = "battery")]
+                if now_ms() >= next_battery_sample {
+                    next_battery_sample = now_ms() + BATTERY_SAMPLE_PERIOD;
+                    // A failed read keeps the previous charge
+                    if let Ok(reading) = battery_gauge.read_battery(adc) {
+                        battery.set(Some(reading.percent));
+                        if battery_monitor.is_empty(reading) {
+                            battery_empty::spawn().ok();
+                        }
+                    }
+                }
+                #[cfg(feature = "ambient-light")]
+                if now_ms() >= next_ambient_sample {
+                    next_ambient_sample = now_ms() + AMBIENT_SAMPLE_PERIOD;
+                    if let Some(contrast) = ambient_sensor
+                        .read_lux()
+                        .ok()
+                        .and_then(|lux| ambient.update(lux))
+                    {
+                        ambient_contrast.set(Some(contrast));
+                    }
+                }
+                #[cfg(feature = "auto-rotate")]
+                if now_ms() >= next_orientation_sample {
+                    next_orientation_sample = now_ms() + ORIENTATION_SAMPLE_PERIOD;
+                    if let Some(turned) = accelerometer
+                        .read_gravity()
+                        .ok()
+                        .and_then(|gravity| orientation.update(gravity))
+                    {
+                        rotation.set(Some(turned));
+                    }
+                }
+                #[cfg(not(feature = "oversampling"))]
+                let disconnected = sampler.disconnected();
+                #[cfg(feature = "oversampling")]
+                let disconnected = [false; INPUT_COUNT];
+                raw_input_values.lock(|r| *r = raw_values);
+                #[cfg(feature = "profiles")]
+                let (values, disconnected) = (
+                    profiles.active().apply(&values),
+                    profiles.active().remap(&disconnected),
+                );
+                #[cfg(feature = "expander")]
+                let values = {
+                    // A failed read keeps the mutes
+                    if let Ok(levels) = expander.read_pins() {
+                        mute_buttons.update(levels);
+                    }
+                    let leds = mute_buttons.leds(&host_mutes.lock(|m| *m));
+                    if expander_leds != Some(leds) && expander.write_outputs(leds).is_ok() {
+                        expander_leds = Some(leds);
+                    }
+                    let mut values = values;
+                    mute_buttons.apply(&mut values);
+                    values
+                };
+                let outputs = rust_deej::roles::apply(&CHANNEL_CONFIGS, VIRTUAL_CHANNELS, &values);
+                // Sent last so the host can switch its mappings along
+                #[cfg(feature = "profiles")]
+                let outputs = profiles.with_index(outputs);
+                publish(
+                    &outputs,
+                    &values,
+                    &raw_values,
+                    &disconnected,
+                    profile,
+                    status,
+                );
+                (values, outputs)
+            };
 
-            let display_changed = display.lock(|d| d.set_volumes(&volumes));
-            match display_changed {
-                DisplayStatus::Changed => update_display::spawn().unwrap(),
-                DisplayStatus::NotChanged => (),
+            // Wireless stacks have to be polled after every event so they drive the sampling as well
+            #[cfg(any(feature = "ble", feature = "wifi", feature = "espnow-remote"))]
+            let mut poll = {
+                let _ = (
+                    delay,
+                    &ble_link,
+                    &wifi_link,
+                    &espnow_link,
+                    &power,
+                    &mut host_link,
+                    &host_button,
+                    &mut active_host,
+                );
+                let mut gate = Gate::new(
+                    SERIAL_CHANGE_THRESHOLD,
+                    SERIAL_KEEP_ALIVE_PERIOD / SAMPLE_PERIOD,
+                );
+                sample_period.lock(|p| *p = SAMPLE_PERIOD);
+                pace_sampling::spawn().ok();
+                // Every radio event comes with an interrupt, so the stack is polled again after it
+                move |status: Option<&str>| {
+                    if !take_or_sleep(&mut sample_due) {
+                        return None;
+                    }
+                    let (_, outputs) = sample(status);
+                    gate.should_send(&outputs).then_some(outputs)
+                }
             };
 
-            delay.delay_ms(50u32);
+            #[cfg(feature = "ble")]
+            ble_link.run(
+                || poll(None).map(|values| protocol::encode(ProtocolMode::Plain, &values)),
+                |event| status.lock(|s| s.handle(event, now_ms())),
+            );
+
+            #[cfg(feature = "wifi")]
+            let mut previous_state = WifiState::Disconnected;
+            #[cfg(feature = "wifi")]
+            wifi_link.run(
+                |state: WifiState| {
+                    if let Some(event) = state.status_event(previous_state) {
+                        status.lock(|s| s.handle(event, now_ms()));
+                    }
+                    previous_state = state;
+                    poll(Some(&state.label()))
+                },
+                || {
+                    #[cfg(feature = "ota")]
+                    {
+                        if ota_button
+                            .update(boot_button.is_low().unwrap(), esp_wifi::current_millis())
+                        {
+                            return true;
+                        }
+                    }
+                    ota_request.lock(core::mem::take)
+                },
+            );
+
+            #[cfg(feature = "espnow-remote")]
+            {
+                let _ = &mut status;
+                espnow_link.run_remote(|| poll(None));
+            }
+
+            #[cfg(not(any(feature = "ble", feature = "wifi", feature = "espnow-remote")))]
+            {
+                let _ = (
+                    ble_link,
+                    wifi_link,
+                    espnow_link,
+                    ota_button,
+                    &mut ota_request,
+                    &mut status,
+                );
+                // Sampling slows down while the pots are not moved
+                #[cfg(not(feature = "light-sleep"))]
+                let mut motion = rust_deej::motion::MotionDetector::new();
+                let mut previous_state = LinkState::Unknown;
+                #[cfg(feature = "host-switch")]
+                let mut host_press = rust_deej::buttons::Debouncer::new();
+                #[cfg(not(feature = "host-switch"))]
+                let _ = (host_button, &mut active_host);
+                #[cfg(not(feature = "light-sleep"))]
+                pace_sampling::spawn().ok();
+                #[cfg(feature = "light-sleep")]
+                let _ = (&mut sample_period, &mut sample_due);
+                loop {
+                    let state = host_link.lock(|l| l.state(now_ms()));
+                    if let Some(event) = state.link_event(previous_state) {
+                        link_event.set(Some(event));
+                    }
+                    previous_state = state;
+                    // The PC getting the stream is shown unless the link is down
+                    #[cfg(feature = "host-switch")]
+                    let label = {
+                        if host_press.update(host_button.is_low().unwrap())
+                            == Some(rust_deej::buttons::ButtonEvent::Pressed)
+                        {
+                            active_host.lock(|a| *a = a.toggle());
+                        }
+                        state.label().or(Some(active_host.lock(|a| a.label())))
+                    };
+                    #[cfg(not(feature = "host-switch"))]
+                    let label = state.label();
+                    let (values, _) = sample(label);
+                    #[cfg(feature = "light-sleep")]
+                    power.wait(&values, now_ms(), delay);
+                    #[cfg(not(feature = "light-sleep"))]
+                    {
+                        let _ = (&power, &delay);
+                        motion.update(&values, now_ms());
+                        sample_period.lock(|p| *p = motion.sample_period(now_ms()));
+                        while !take_or_sleep(&mut sample_due) {}
+                    }
+                }
+            }
         }
     }
 
-    #[task(priority=2, shared=[display, timer0, &display_on_time])]
+    #[cfg(feature = "display")]
+    #[task(priority=2, shared=[display, display_off_at, settings, animation_alarm])]
     async fn update_display(cx: update_display::Context) {
         let update_display::SharedResources {
             mut display,
-            mut timer0,
-            display_on_time,
+            mut display_off_at,
+            mut settings,
+            mut animation_alarm,
             ..
         } = cx.shared;
 
-        display.lock(|d| d.draw()).unwrap();
-        timer0.lock(|t| t.start(display_on_time.secs()));
+        let timeout = settings.lock(|s| s.display_timeout);
+        if display.lock(|d| d.keep_off(timeout)) {
+            return;
+        }
+        display.lock(|d| d.draw_frame()).unwrap();
+        // A name may have started or stopped scrolling with this frame
+        let animated = display.lock(|d| d.is_animated());
+        animation_alarm.lock(|a| a.enable_interrupt(animated));
+        flush_display::spawn().ok();
+        if let DisplayTimeout::After(secs) = timeout {
+            display_off_at.lock(|t| *t = Some(Mono::now() + mono::Duration::secs(secs as u64)));
+            // Already waiting for the previous time, it waits on for this one
+            turn_display_off::spawn().ok();
+        }
+    }
+
+    /// Sends the frame drawn by `update_display` a page at a time. At the priority of
+    /// `send_to_serial`, which gets its turn between the pages, so a frame never holds it up.
+    /// Already sending, it sends the pages drawn in the meantime as well.
+    #[cfg(feature = "display")]
+    #[task(priority=1, shared=[display])]
+    async fn flush_display(mut cx: flush_display::Context) {
+        while !cx.shared.display.lock(|d| d.flush_chunk()) {
+            mono::yield_now().await;
+        }
+        // Whatever took it offline since the last frame, this is where the recovery starts
+        if cx.shared.display.lock(|d| d.is_offline()) {
+            recover_display::spawn().ok();
+        }
+    }
+
+    /// Brings the display back after a transfer to it failed, e.g. the cable glitched: clears the
+    /// I2C bus and sets the display up again, [DISPLAY_RETRY_DELAY] after the failure and twice as
+    /// long after every attempt it does not answer. The host is told with
+    /// [protocol::encode_display] and the status LED blinks the error, the values go on to the
+    /// host in the meantime.
+    #[cfg(feature = "display")]
+    #[task(priority=1, shared=[display, host, status], local=[clocks])]
+    async fn recover_display(mut cx: recover_display::Context) {
+        cx.shared
+            .host
+            .lock(|h| h.send(protocol::encode_display(false).as_bytes()));
+        let mut delay = DISPLAY_RETRY_DELAY;
+        loop {
+            // Blinks on until the display is back
+            cx.shared
+                .status
+                .lock(|s| s.handle(StatusEvent::Error, now_ms()));
+            Mono::delay(mono::Duration::millis(delay as u64)).await;
+            #[cfg(not(feature = "display-spi"))]
+            crate::reset_i2c_bus(cx.local.clocks);
+            #[cfg(feature = "display-spi")]
+            let _ = cx.local.clocks;
+            if cx.shared.display.lock(|d| d.reinit()) {
+                break;
+            }
+            delay = (delay * 2).min(DISPLAY_RETRY_MAX_DELAY);
+        }
+        cx.shared
+            .host
+            .lock(|h| h.send(protocol::encode_display(true).as_bytes()));
+        update_display::spawn().ok();
     }
 
-    /// Turn the display off after the timer has expired
-    #[task(binds=TG0_T0_LEVEL,shared=[display, timer0] )]
-    fn turn_display_off(mut cx: turn_display_off::Context) {
-        cx.shared.timer0.lock(|t| t.clear_interrupt());
+    /// Turns the display off and puts the chip into deep sleep once idle found the battery empty
+    #[cfg(feature = "battery")]
+    #[task(priority=2, shared=[display])]
+    async fn battery_empty(mut cx: battery_empty::Context) {
+        #[cfg(feature = "display")]
         cx.shared.display.lock(|d| d.turn_off());
+        #[cfg(not(feature = "display"))]
+        let _ = &mut cx;
+        crate::power_off();
     }
 
-    #[task(binds=TG1_T0_LEVEL,shared =[raw_input_values], local=[timer1])]
-    fn send_to_serial(mut cx: send_to_serial::Context) {
-        cx.local.timer1.clear_interrupt();
+    /// Queues `event` for the consumers and wakes `handle_events`, the others read it on their next
+    /// period
+    fn publish_event(events: &mut impl rtic::Mutex<T = Events>, event: Event) {
+        events.lock(|e| e.publish(event));
+        handle_events::spawn().ok();
+    }
 
-        let mut values: [u16; INPUT_COUNT] = Default::default();
+    /// Reads the events for the display: carries out the [PAGE_BUTTON_ACTIONS] of the gestures
+    /// and shows the host commands
+    #[task(priority=2, shared=[events, display, muted])]
+    async fn handle_events(cx: handle_events::Context) {
+        let handle_events::SharedResources {
+            mut events,
+            mut display,
+            mut muted,
+            ..
+        } = cx.shared;
+
+        #[cfg(not(feature = "display"))]
+        let _ = &mut display;
+        let mut changed = DisplayStatus::NotChanged;
+        while let Some(event) = events.lock(|e| e.next(Consumer::Display)) {
+            let gesture = match event {
+                Event::ButtonPressed(gesture) => gesture,
+                // A display slave has no volumes of its own, the bars show these. Channels
+                // without a session are empty
+                #[cfg(feature = "display-slave")]
+                Event::HostMessage(HostCommand::HostVolumes(volumes)) => {
+                    changed = changed
+                        .or(display
+                            .lock(|d| d.set_volumes(&volumes.map(|volume| volume.unwrap_or(0)))));
+                    continue;
+                }
+                #[cfg(feature = "display")]
+                Event::HostMessage(command) => {
+                    changed = changed.or(display.lock(|d| d.handle_host_command(command)));
+                    continue;
+                }
+                _ => continue,
+            };
+            changed = changed.or(match PAGE_BUTTON_ACTIONS.action(gesture) {
+                #[cfg(feature = "display")]
+                Some(ButtonAction::NextPage) => display.lock(|d| d.next_screen()),
+                #[cfg(feature = "display")]
+                Some(ButtonAction::ShowScreen(screen)) => display.lock(|d| d.show_screen(screen)),
+                #[cfg(not(feature = "display"))]
+                Some(ButtonAction::NextPage | ButtonAction::ShowScreen(_)) => {
+                    DisplayStatus::NotChanged
+                }
+                // Shown by idle along with the next sample
+                Some(ButtonAction::ToggleMute) => {
+                    muted.lock(|m| *m = !*m);
+                    DisplayStatus::NotChanged
+                }
+                None => DisplayStatus::NotChanged,
+            });
+        }
+        if let DisplayStatus::Changed = changed {
+            redraw();
+        }
+    }
+
+    /// Opens the settings menu on a push of the encoder, then moves through it and edits the values.
+    /// Changes apply right away and are written to flash when the menu is closed.
+    #[cfg(feature = "settings")]
+    #[task(binds=GPIO, shared=[display, settings], local=[encoder, decoder: QuadratureDecoder = QuadratureDecoder::new(), cursor: MenuCursor = MenuCursor::new(), push_edge_at: u64 = 0])]
+    fn read_encoder(mut cx: read_encoder::Context) {
+        let (a, b, push) = cx.local.encoder;
+        let now = now_ms();
+        // Every edge of the switch restarts the debounce, so only the first one of a press counts
+        let mut pushed = false;
+        if push.is_interrupt_set() {
+            pushed = push.is_low().unwrap()
+                && now.saturating_sub(*cx.local.push_edge_at) >= ENCODER_PUSH_DEBOUNCE;
+            *cx.local.push_edge_at = now;
+        }
+        a.clear_interrupt();
+        b.clear_interrupt();
+        push.clear_interrupt();
+        let detents = cx
+            .local
+            .decoder
+            .update(a.is_high().unwrap(), b.is_high().unwrap());
+
+        let cursor = cx.local.cursor;
+        let mut settings = cx.shared.settings.lock(|s| *s);
+        let open = cx.shared.display.lock(|d| d.screen() == Screen::Settings);
+        let event = match (open, pushed) {
+            (false, false) => return,
+            (false, true) => None,
+            (true, true) => Some(cursor.push(&mut settings)),
+            (true, false) => match cursor.turn(&mut settings, detents) {
+                Some(event) => Some(event),
+                None => return,
+            },
+        };
+        match event {
+            Some(MenuEvent::Changed(_)) => {
+                cx.shared.settings.lock(|s| *s = settings);
+                cx.shared.display.lock(|d| {
+                    d.set_on_contrast(settings.contrast);
+                    d.set_theme(settings.theme.theme());
+                });
+            }
+            // Blocks everything for the few tens of ms the sector takes to erase and write. A failed
+            // write boots with the previously saved settings
+            Some(MenuEvent::Exit) => {
+                rust_deej::settings::save(&mut esp_storage::FlashStorage::new(), &settings).ok();
+            }
+            Some(MenuEvent::Moved) | None => (),
+        }
 
-        cx.shared.raw_input_values.lock(|r| {
-            r.iter()
-                .enumerate()
-                .for_each(|(idx, val)| values[idx] = scale_analog_input_to_1023(*val))
+        let view = cursor.view(&settings);
+        cx.shared.display.lock(|d| {
+            d.set_menu(view);
+            d.show_screen(if event == Some(MenuEvent::Exit) {
+                Screen::Volumes
+            } else {
+                Screen::Settings
+            });
         });
-        println!("{}|{}|{}|{}\r", values[0], values[1], values[2], values[3]);
-        cx.local.timer1.start(SERIAL_UPDATE_PERIOD.millis())
+        update_display::spawn().ok();
+    }
+
+    /// Lets idle take a sample every [Shared::sample_period] ms, so the samples keep their pace
+    /// however long one of them took. Idle sleeps in between.
+    #[task(priority=1, shared=[sample_period, sample_due])]
+    async fn pace_sampling(mut cx: pace_sampling::Context) {
+        let mut next = Mono::now();
+        loop {
+            next += mono::Duration::millis(cx.shared.sample_period.lock(|p| *p) as u64);
+            Mono::delay_until(next).await;
+            cx.shared.sample_due.lock(|d| *d = true);
+        }
+    }
+
+    /// Draws the next frame of the bar animation or a scrolling name
+    #[cfg(feature = "display")]
+    #[task(binds=SYSTIMER_TARGET1, shared=[display, animation_alarm])]
+    fn animate_display(mut cx: animate_display::Context) {
+        cx.shared.animation_alarm.lock(|a| a.clear_interrupt());
+        if let DisplayStatus::Changed = cx.shared.display.lock(|d| d.animate()) {
+            // Already pending redraw draws the latest frame as well
+            update_display::spawn().ok();
+        }
+    }
+
+    /// Mirrors the positions of the pots on the LED strip, the channel moved last highlighted, and
+    /// shows the status on the status LED, every [LED_UPDATE_PERIOD] ms. Both follow the events
+    /// read since the previous update.
+    #[task(priority=1, shared=[events, status], local=[led_bar, status_led])]
+    async fn update_leds(mut cx: update_leds::Context) {
+        let mut activity = ChannelActivity::new();
+        let mut host_mutes = [false; INPUT_COUNT];
+        let mut next = Mono::now();
+        loop {
+            while let Some(event) = cx.shared.events.lock(|e| e.next(Consumer::Leds)) {
+                match event {
+                    Event::InputChanged { .. } => activity.handle(&event, now_ms()),
+                    Event::HostMessage(HostCommand::HostMutes(mutes)) => host_mutes = mutes,
+                    event => {
+                        if let Some(event) = event.status_event() {
+                            cx.shared.status.lock(|s| s.handle(event, now_ms()));
+                        }
+                    }
+                }
+            }
+            let color = cx.shared.status.lock(|s| s.color(now_ms()));
+
+            #[cfg(feature = "leds")]
+            cx.local.led_bar.show(
+                &activity.positions(),
+                &host_mutes,
+                activity.focused(now_ms()),
+            );
+            #[cfg(not(feature = "leds"))]
+            let _ = (&activity, host_mutes, &cx.local.led_bar);
+
+            #[cfg(feature = "status-led")]
+            cx.local.status_led.show(color);
+            #[cfg(not(feature = "status-led"))]
+            let _ = (color, &cx.local.status_led);
+
+            next += mono::Duration::millis(LED_UPDATE_PERIOD as u64);
+            Mono::delay_until(next).await;
+        }
+    }
+
+    /// Wakes the tasks waiting on [Mono], above everything else so they wake on time
+    #[task(binds=SYSTIMER_TARGET2, priority=15)]
+    fn tick_monotonic(_: tick_monotonic::Context) {
+        // Bound to the alarm of the monotonic
+        unsafe { mono::on_interrupt() }
+    }
+
+    /// Runs the loops of the motorized faders on the positions (0-1023) of a sample and the
+    /// sliders touched at the time. Above the display tasks, so a flush does not hold up the motors
+    #[task(priority=3, shared=[fader_targets], local=[faders])]
+    async fn move_faders(
+        mut cx: move_faders::Context,
+        sample: ([u16; INPUT_COUNT], [bool; INPUT_COUNT]),
+    ) {
+        let (positions, touched) = sample;
+        let targets = cx.shared.fader_targets.lock(|t| t.positions());
+        #[cfg(feature = "motorized-faders")]
+        cx.local
+            .faders
+            .update(&targets, &positions, &touched, now_ms());
+        #[cfg(not(feature = "motorized-faders"))]
+        let _ = (targets, positions, touched, cx.local.faders);
+    }
+
+    /// Dim the display once [Shared::display_off_at] has passed and turn it off after
+    /// [DISPLAY_OFF_DELAY]. Shows the now playing page instead while the host reports a track.
+    #[cfg(feature = "display")]
+    #[task(priority=2, shared=[display, display_off_at, settings])]
+    async fn turn_display_off(mut cx: turn_display_off::Context) {
+        // A draw in the meantime pushes the time back
+        while let Some(off_at) = cx.shared.display_off_at.lock(|t| *t) {
+            if Mono::now() < off_at {
+                Mono::delay_until(off_at).await;
+                continue;
+            }
+            cx.shared.display_off_at.lock(|t| *t = None);
+            // Set before the timeout was changed
+            if !matches!(
+                cx.shared.settings.lock(|s| s.display_timeout),
+                DisplayTimeout::After(_)
+            ) {
+                return;
+            }
+            match cx.shared.display.lock(|d| d.dim_or_turn_off()) {
+                DisplayPower::Dimmed => cx.shared.display_off_at.lock(|t| {
+                    *t = Some(Mono::now() + mono::Duration::secs(DISPLAY_OFF_DELAY as u64))
+                }),
+                // Drawing the page sets the time again
+                DisplayPower::On => {
+                    update_display::spawn().ok();
+                }
+                DisplayPower::Off => (),
+            }
+        }
+    }
+
+    /// Sends the values to the host every serial period when they have changed or the keep-alive
+    /// period has passed. A host that talks back gets no keep-alives while nobody touches a
+    /// channel, it gets the values again when it reconnects.
+    #[task(priority=1, shared =[output_values, protocol_mode, host_link, host, active_host, settings, watchdog, events], local=[serial_gate])]
+    async fn send_to_serial(mut cx: send_to_serial::Context) {
+        let mut activity = ChannelActivity::new();
+        // Time (ms) of the latest frame
+        let mut sent_at = 0;
+        let mut next = Mono::now();
+        loop {
+            let period = cx.shared.settings.lock(|s| s.serial_period);
+            next += mono::Duration::millis(period as u64);
+            Mono::delay_until(next).await;
+
+            cx.shared.watchdog.lock(|(tasks, wdt)| {
+                if tasks.check_in(WatchedTask::Serial) {
+                    wdt.feed();
+                }
+            });
+
+            while let Some(event) = cx.shared.events.lock(|e| e.next(Consumer::Serial)) {
+                match event {
+                    // A host that just connected gets the values right away
+                    Event::LinkUp => cx.local.serial_gate.resend(),
+                    event => activity.handle(&event, now_ms()),
+                }
+            }
+            let values = cx.shared.output_values.lock(|o| *o);
+            // A display slave has no values of its own, it only checks in
+            let send = !cfg!(feature = "display-slave")
+                && cx.shared.host_link.lock(|l| l.should_send(now_ms()));
+            // A host that never talks back, e.g. plain deej, keeps getting keep-alives to pick the
+            // values up after a restart
+            let touched = activity.changed_since(sent_at)
+                || cx.shared.host_link.lock(|l| l.state(now_ms())) != LinkState::Connected;
+            #[cfg(not(feature = "host-switch"))]
+            if send && cx.local.serial_gate.should_send_touched(&values, touched) {
+                sent_at = now_ms();
+                let mode = cx.shared.protocol_mode.lock(|m| *m);
+                let frame = protocol::encode(mode, &values);
+                cx.shared.host.lock(|h| h.send(frame.as_bytes()));
+            }
+            #[cfg(feature = "host-switch")]
+            if send {
+                let active = cx.shared.active_host.lock(|a| *a);
+                let [first, second] = cx.local.serial_gate.frames(active, &values, touched);
+                if first.is_some() || second.is_some() {
+                    sent_at = now_ms();
+                }
+                let mode = cx.shared.protocol_mode.lock(|m| *m);
+                cx.shared.host.lock(|h| {
+                    if let Some(values) = first {
+                        h.0.send(protocol::encode(mode, &values).as_bytes());
+                    }
+                    if let Some(values) = second {
+                        h.1.send(protocol::encode(mode, &values).as_bytes());
+                    }
+                });
+            }
+        }
+    }
+
+    /// Handles commands sent by the host on UART0
+    #[cfg(not(feature = "host-uart1"))]
+    #[task(binds=UART0, shared=[host, protocol_mode, ota_request, display, host_mutes, fader_targets, host_link, status, settings, raw_input_values, calibrate, events], local=[line_reader])]
+    fn receive_from_serial(mut cx: receive_from_serial::Context) {
+        receive_from_host!(cx, |h| h.0.read(), line_reader);
+        cx.shared.host.lock(|h| h.0.reset_rx_fifo_full_interrupt());
+    }
+
+    /// Handles commands sent by the host on UART1
+    #[cfg(feature = "host-uart1")]
+    #[task(binds=UART1, shared=[host, protocol_mode, ota_request, display, host_mutes, fader_targets, host_link, status, settings, raw_input_values, calibrate, events], local=[line_reader])]
+    fn receive_from_serial1(mut cx: receive_from_serial1::Context) {
+        receive_from_host!(cx, |h| h.0.read(), line_reader);
+        cx.shared.host.lock(|h| h.0.reset_rx_fifo_full_interrupt());
+    }
+
+    /// Handles commands sent by the host on the USB Serial/JTAG port
+    #[cfg(feature = "usb-serial-jtag")]
+    #[task(binds=USB_DEVICE, shared=[host, protocol_mode, ota_request, display, host_mutes, fader_targets, host_link, status, settings, raw_input_values, calibrate, events], local=[usb_line_reader])]
+    fn receive_from_usb(mut cx: receive_from_usb::Context) {
+        receive_from_host!(cx, |h| h.1.read_byte(), usb_line_reader);
+        cx.shared
+            .host
+            .lock(|h| h.1.reset_rx_packet_recv_interrupt());
     }
 }
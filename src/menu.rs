@@ -0,0 +1,146 @@
+//! List of items edited with the encoder. Each item is a toggle, a number or a choice between names
+//! and its value is kept as an `i32` by the [Menu] that owns it, e.g. [crate::settings::Settings].
+//! Turning moves the selection, pushing starts and ends editing the selected value.
+
+use heapless::{String, Vec};
+
+use crate::text::format_truncated;
+
+pub type Label = String<12>;
+pub type ValueText = String<10>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ItemKind {
+    /// Off (0) or on (1), flipped by a push without editing
+    Toggle,
+    /// `min..=max` in steps of `step` per detent, shown with `unit`
+    Number {
+        min: i32,
+        max: i32,
+        step: i32,
+        unit: &'static str,
+    },
+    /// Index of one of the names
+    Choice(&'static [&'static str]),
+    /// Closes the menu when pushed, has no value
+    Exit,
+}
+
+impl ItemKind {
+    /// Value after turning by `detents`. Numbers stop at their ends, toggles and choices wrap around
+    pub fn step(self, value: i32, detents: i32) -> i32 {
+        match self {
+            ItemKind::Toggle => (value + detents).rem_euclid(2),
+            ItemKind::Number { min, max, step, .. } => (value + detents * step).clamp(min, max),
+            ItemKind::Choice(names) => (value + detents).rem_euclid(names.len() as i32),
+            ItemKind::Exit => value,
+        }
+    }
+
+    pub fn format(self, value: i32) -> ValueText {
+        match self {
+            ItemKind::Toggle => {
+                format_truncated(format_args!("{}", if value != 0 { "On" } else { "Off" }))
+            }
+            ItemKind::Number { unit, .. } => format_truncated(format_args!("{}{}", value, unit)),
+            ItemKind::Choice(names) => format_truncated(format_args!(
+                "{}",
+                names.get(value as usize).unwrap_or(&"?")
+            )),
+            ItemKind::Exit => ValueText::new(),
+        }
+    }
+}
+
+/// Items edited through the menu
+pub trait Menu {
+    fn item_count(&self) -> usize;
+    fn label(&self, item: usize) -> Label;
+    fn kind(&self, item: usize) -> ItemKind;
+    /// Current value of `item`, 0 for [ItemKind::Exit]
+    fn value(&self, item: usize) -> i32;
+    /// Only called with values [ItemKind::step] returned
+    fn set_value(&mut self, item: usize, value: i32);
+}
+
+/// What a turn or push did, the menu has to be redrawn after each of them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuEvent {
+    /// Other item selected or editing started or ended
+    Moved,
+    /// Value of the item changed, apply it right away
+    Changed(usize),
+    /// [ItemKind::Exit] pushed
+    Exit,
+}
+
+/// Selected item and whether its value is being edited
+#[derive(Default)]
+pub struct MenuCursor {
+    selected: usize,
+    editing: bool,
+}
+
+impl MenuCursor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the selection, or changes the value while editing
+    pub fn turn<M: Menu>(&mut self, menu: &mut M, detents: i32) -> Option<MenuEvent> {
+        if self.editing {
+            let value = menu.value(self.selected);
+            let stepped = menu.kind(self.selected).step(value, detents);
+            if stepped == value {
+                return None;
+            }
+            menu.set_value(self.selected, stepped);
+            return Some(MenuEvent::Changed(self.selected));
+        }
+        let selected =
+            (self.selected as i32 + detents).clamp(0, menu.item_count() as i32 - 1) as usize;
+        if selected == self.selected {
+            return None;
+        }
+        self.selected = selected;
+        Some(MenuEvent::Moved)
+    }
+
+    pub fn push<M: Menu>(&mut self, menu: &mut M) -> MenuEvent {
+        match menu.kind(self.selected) {
+            ItemKind::Toggle => {
+                let value = menu.value(self.selected);
+                menu.set_value(self.selected, ItemKind::Toggle.step(value, 1));
+                MenuEvent::Changed(self.selected)
+            }
+            ItemKind::Exit => {
+                *self = Self::new();
+                MenuEvent::Exit
+            }
+            ItemKind::Number { .. } | ItemKind::Choice(_) => {
+                self.editing = !self.editing;
+                MenuEvent::Moved
+            }
+        }
+    }
+
+    /// Rows of `menu` as shown by [crate::pages::MenuPage], at most `N` of them
+    pub fn view<M: Menu, const N: usize>(&self, menu: &M) -> MenuView<N> {
+        MenuView {
+            rows: (0..menu.item_count().min(N))
+                .map(|item| (menu.label(item), menu.kind(item).format(menu.value(item))))
+                .collect(),
+            selected: self.selected,
+            editing: self.editing,
+        }
+    }
+}
+
+/// Label and value text of each item
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MenuView<const N: usize> {
+    pub rows: Vec<(Label, ValueText), N>,
+    pub selected: usize,
+    /// Value of the selected item is being edited
+    pub editing: bool,
+}
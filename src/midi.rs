@@ -0,0 +1,104 @@
+//! MIDI Control Change output, so the box can drive a DAW directly instead of the deej host.
+//! Each channel of the frame is sent as one CC message. Over a UART the messages go out as they are,
+//! e.g. on a 5-pin DIN port at 31250 baud, over USB they are wrapped in USB-MIDI event packets.
+
+use crate::globals::{MIDI_CHANNEL, MIDI_CONTROLLERS, OUTPUT_COUNT};
+
+/// Status nibble of a Control Change message
+pub const CONTROL_CHANGE: u8 = 0xB0;
+/// Code Index Number of a Control Change in a USB-MIDI event packet, on virtual cable 0
+pub const USB_CONTROL_CHANGE: u8 = 0x0B;
+/// Status, controller and value
+pub const MESSAGE_LEN: usize = 3;
+
+/// One Control Change message per channel of the frame
+pub type MidiMessages = [u8; OUTPUT_COUNT * MESSAGE_LEN];
+/// USB-MIDI event packet of one message
+pub type UsbMidiPacket = [u8; 4];
+
+/// Controllers `first`, `first + 1`, ... for each channel of the frame, for [MIDI_CONTROLLERS]
+pub const fn consecutive_controllers(first: u8) -> [u8; OUTPUT_COUNT] {
+    let mut controllers = [0; OUTPUT_COUNT];
+    let mut idx = 0;
+    while idx < OUTPUT_COUNT {
+        controllers[idx] = first + idx as u8;
+        idx += 1;
+    }
+    controllers
+}
+
+/// Channel value (0-1023) as a 7 bit controller value
+pub fn controller_value(value: u16) -> u8 {
+    (value.min(1023) >> 3) as u8
+}
+
+/// Control Change message setting `controller` (0-119) to `value` (0-127) on `channel` (0-15)
+pub fn control_change(channel: u8, controller: u8, value: u8) -> [u8; MESSAGE_LEN] {
+    [
+        CONTROL_CHANGE | channel & 0x0F,
+        controller & 0x7F,
+        value & 0x7F,
+    ]
+}
+
+/// Control Change messages for the values of every channel, on [MIDI_CHANNEL] with the controllers
+/// of [MIDI_CONTROLLERS]
+pub fn encode(values: &[u16; OUTPUT_COUNT]) -> MidiMessages {
+    let mut messages = [0; OUTPUT_COUNT * MESSAGE_LEN];
+    for ((message, value), controller) in messages
+        .chunks_exact_mut(MESSAGE_LEN)
+        .zip(values)
+        .zip(MIDI_CONTROLLERS)
+    {
+        message.copy_from_slice(&control_change(
+            MIDI_CHANNEL,
+            controller,
+            controller_value(*value),
+        ));
+    }
+    messages
+}
+
+/// USB-MIDI event packets of the messages made by [encode]
+pub fn usb_packets(messages: &[u8]) -> impl Iterator<Item = UsbMidiPacket> + '_ {
+    messages
+        .chunks_exact(MESSAGE_LEN)
+        .map(|message| [USB_CONTROL_CHANGE, message[0], message[1], message[2]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_become_control_changes() {
+        let values = core::array::from_fn(|idx| [0, 1023, 512, 8][idx % 4]);
+        let messages = encode(&values);
+        assert_eq!(messages.len(), OUTPUT_COUNT * MESSAGE_LEN);
+        assert_eq!(
+            messages[..MESSAGE_LEN * 2],
+            [
+                CONTROL_CHANGE | MIDI_CHANNEL,
+                MIDI_CONTROLLERS[0],
+                0,
+                CONTROL_CHANGE | MIDI_CHANNEL,
+                MIDI_CONTROLLERS[1],
+                127
+            ]
+        );
+        assert_eq!(controller_value(512), 64);
+        assert_eq!(controller_value(8), 1);
+
+        let packet = usb_packets(&messages).nth(1).unwrap();
+        assert_eq!(
+            packet,
+            [
+                USB_CONTROL_CHANGE,
+                CONTROL_CHANGE | MIDI_CHANNEL,
+                MIDI_CONTROLLERS[1],
+                127
+            ]
+        );
+        assert_eq!(usb_packets(&messages).count(), OUTPUT_COUNT);
+    }
+}
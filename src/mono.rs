@@ -0,0 +1,104 @@
+//! Monotonic of the RTIC app, the tasks wait on it with `Mono::delay_until` instead of owning a
+//! hardware timer each. It counts on SYSTIMER unit 0 like [SystemTimer::now] and wakes the tasks
+//! through alarm 2. The SYSTIMER monotonic that comes with rtic-monotonics is not used, it takes
+//! alarm 0, which belongs to the radio. [yield_now] is for the tasks that split up their work.
+
+use core::{cell::RefCell, future::poll_fn, task::Poll};
+
+use critical_section::Mutex;
+use esp_hal::systimer::{Alarm, SystemTimer, Target};
+use rtic_monotonics::{fugit, TimerQueue, TimerQueueBackend, TimerQueueBasedMonotonic};
+
+/// SYSTIMER ticks per second, 16 MHz
+const TICK_RATE: u32 = SystemTimer::TICKS_PER_SECOND as u32;
+
+pub type Instant = fugit::Instant<u64, 1, TICK_RATE>;
+pub type Duration = fugit::Duration<u64, 1, TICK_RATE>;
+
+static TIMER_QUEUE: TimerQueue<SystimerBackend> = TimerQueue::new();
+static ALARM: Mutex<RefCell<Option<Alarm<Target, 2>>>> = Mutex::new(RefCell::new(None));
+
+/// Monotonic of the RTIC app, running once [start] was called
+pub struct Mono;
+
+impl TimerQueueBasedMonotonic for Mono {
+    type Backend = SystimerBackend;
+    type Instant = Instant;
+    type Duration = Duration;
+}
+
+pub struct SystimerBackend;
+
+impl SystimerBackend {
+    fn with_alarm(f: impl FnOnce(&Alarm<Target, 2>)) {
+        critical_section::with(|cs| {
+            f(ALARM
+                .borrow_ref(cs)
+                .as_ref()
+                .expect("Monotonic used before it was started"))
+        })
+    }
+}
+
+impl TimerQueueBackend for SystimerBackend {
+    type Ticks = u64;
+
+    fn now() -> u64 {
+        SystemTimer::now()
+    }
+
+    fn set_compare(instant: u64) {
+        Self::with_alarm(|alarm| alarm.set_target(instant));
+    }
+
+    fn clear_compare_flag() {
+        Self::with_alarm(|alarm| alarm.clear_interrupt());
+    }
+
+    fn pend_interrupt() {
+        // A SYSTIMER interrupt can not be pended from software. Runs the handler right away
+        // instead, with the interrupts off as if it had the top priority
+        critical_section::with(|_| unsafe { TIMER_QUEUE.on_monotonic_interrupt() });
+    }
+
+    fn max_timer_value() -> u64 {
+        // Unit 0 is 52 bits wide, it wraps after 8.9 years
+        (1 << 52) - 1
+    }
+
+    fn timer_queue() -> &'static TimerQueue<Self> {
+        &TIMER_QUEUE
+    }
+}
+
+/// Starts [Mono] on `alarm`, before anything waits on it. The SYSTIMER_TARGET2 interrupt has to
+/// call [on_interrupt].
+pub fn start(alarm: Alarm<Target, 2>) {
+    alarm.clear_interrupt();
+    alarm.enable_interrupt(true);
+    critical_section::with(|cs| ALARM.borrow_ref_mut(cs).replace(alarm));
+    TIMER_QUEUE.initialize(SystimerBackend);
+}
+
+/// Lets the other tasks of the same priority run before the task goes on
+pub async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+/// Wakes the tasks whose time has come
+///
+/// # Safety
+///
+/// Only call from the task bound to SYSTIMER_TARGET2
+pub unsafe fn on_interrupt() {
+    TIMER_QUEUE.on_monotonic_interrupt();
+}
@@ -0,0 +1,54 @@
+use crate::globals::{
+    INPUT_COUNT, MOTION_SETTLE_TIME, MOTION_THRESHOLD, SAMPLE_PERIOD_IDLE, SAMPLE_PERIOD_MOVING,
+};
+
+/// Tells whether the pots are being moved, so sampling can slow down when they are not
+pub struct MotionDetector {
+    /// Values at the latest change, 0-1023
+    values: [u16; INPUT_COUNT],
+    last_change: u64,
+}
+
+impl Default for MotionDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MotionDetector {
+    pub fn new() -> Self {
+        Self {
+            values: [0; INPUT_COUNT],
+            last_change: 0,
+        }
+    }
+
+    /// Call after every sample with the values in range 0-1023. Changes smaller than
+    /// [MOTION_THRESHOLD] are noise.
+    pub fn update(&mut self, values: &[u16; INPUT_COUNT], now_ms: u64) {
+        let changed = self
+            .values
+            .iter()
+            .zip(values)
+            .any(|(old, new)| old.abs_diff(*new) >= MOTION_THRESHOLD);
+        if changed {
+            self.values = *values;
+            self.last_change = now_ms;
+        }
+    }
+
+    /// Time (ms) since the latest change
+    pub fn still_for(&self, now_ms: u64) -> u64 {
+        now_ms.saturating_sub(self.last_change)
+    }
+
+    /// [SAMPLE_PERIOD_MOVING] until nothing has changed for [MOTION_SETTLE_TIME], then
+    /// [SAMPLE_PERIOD_IDLE]
+    pub fn sample_period(&self, now_ms: u64) -> u32 {
+        if self.still_for(now_ms) < MOTION_SETTLE_TIME {
+            SAMPLE_PERIOD_MOVING
+        } else {
+            SAMPLE_PERIOD_IDLE
+        }
+    }
+}
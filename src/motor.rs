@@ -0,0 +1,372 @@
+//! Motorized faders that move to the volumes the host reports with `VOLUMES`, see `motor` in
+//! board.toml. Each motor is driven by an H-bridge in phase/enable mode (DRV8835, DRV8871, ...)
+//! with the PWM of the LEDC on the enable pin, and a PID loop holds the fader on its target.
+//!
+//! A hand is noticed right away by the cap of the slider with `touch-sense`, see [crate::touch].
+//! Without it the fader moving while the motor is off or not getting closer while the motor pushes
+//! gives the hand away. The motor is let go then until the fader was let go and stood still for
+//! [MOTOR_RELEASE_TIME], and the fader stays where the hand left it.
+
+#[cfg(feature = "hal")]
+use embedded_hal_027::digital::v2::OutputPin;
+#[cfg(feature = "hal")]
+use esp_hal::{
+    gpio::{AnyPin, Output, PushPull},
+    ledc::{
+        channel::{self, Channel, ChannelIFace},
+        timer::{self, Timer, TimerIFace},
+        LSGlobalClkSource, LowSpeed, LEDC,
+    },
+    prelude::*,
+};
+
+#[cfg(feature = "hal")]
+use crate::globals::MOTOR_PWM_FREQUENCY;
+use crate::{
+    globals::{
+        INPUT_COUNT, MOTOR_DEADBAND, MOTOR_GAINS, MOTOR_INTEGRAL_LIMIT, MOTOR_MIN_DUTY,
+        MOTOR_RELEASE_TIME, MOTOR_STALL_TIME,
+    },
+    log::debug,
+    scale_to_range,
+};
+
+/// Motor moving a fader
+pub trait Motor {
+    /// `duty` is -100-100 % of the supply, positive moves the fader up and 0 lets it coast
+    fn drive(&mut self, duty: i8);
+}
+
+/// H-bridge in phase/enable mode, PWM on the enable pin and the direction on the phase pin
+#[cfg(feature = "hal")]
+pub struct HBridge {
+    enable: Channel<'static, LowSpeed, AnyPin<Output<PushPull>>>,
+    phase: AnyPin<Output<PushPull>>,
+}
+
+#[cfg(feature = "hal")]
+impl Motor for HBridge {
+    fn drive(&mut self, duty: i8) {
+        if duty >= 0 {
+            self.phase.set_high().ok();
+        } else {
+            self.phase.set_low().ok();
+        }
+        self.enable.set_duty(duty.unsigned_abs().min(100)).ok();
+    }
+}
+
+/// Sets up the low speed `timer` of `ledc` at [MOTOR_PWM_FREQUENCY] and a channel of it on each
+/// of the `enable` pins. The ESP32-C3 has 6 channels, build.rs allows at most that many motors.
+#[cfg(feature = "hal")]
+pub fn h_bridges(
+    ledc: &'static mut LEDC<'static>,
+    timer: &'static mut Option<Timer<'static, LowSpeed>>,
+    enable: [AnyPin<Output<PushPull>>; INPUT_COUNT],
+    phase: [AnyPin<Output<PushPull>>; INPUT_COUNT],
+) -> [HBridge; INPUT_COUNT] {
+    const CHANNELS: [channel::Number; 6] = [
+        channel::Number::Channel0,
+        channel::Number::Channel1,
+        channel::Number::Channel2,
+        channel::Number::Channel3,
+        channel::Number::Channel4,
+        channel::Number::Channel5,
+    ];
+    ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    let ledc: &'static LEDC<'static> = ledc;
+    let timer = timer.insert(ledc.get_timer::<LowSpeed>(timer::Number::Timer0));
+    timer
+        .configure(timer::config::Config {
+            duty: timer::config::Duty::Duty10Bit,
+            clock_source: timer::LSClockSource::APBClk,
+            frequency: MOTOR_PWM_FREQUENCY.kHz(),
+        })
+        .unwrap();
+    let timer: &'static Timer<'static, LowSpeed> = timer;
+    let mut pins = enable.into_iter().zip(phase);
+    core::array::from_fn(|motor| {
+        let (enable, mut phase) = pins.next().unwrap();
+        phase.set_low().ok();
+        let mut enable = ledc.get_channel(CHANNELS[motor], enable);
+        enable
+            .configure(channel::config::Config {
+                timer,
+                duty_pct: 0,
+                pin_config: channel::config::PinConfig::PushPull,
+            })
+            .unwrap();
+        HBridge { enable, phase }
+    })
+}
+
+/// PID loop of one fader, the error is in the 0-1023 range of the positions
+#[derive(Default)]
+pub struct PositionController {
+    integral: i32,
+    previous_error: Option<i32>,
+}
+
+impl PositionController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets the history, for a new move
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Motor duty (%) that moves the fader by `error` towards its target
+    pub fn update(&mut self, error: i32) -> i8 {
+        let (kp, ki, kd) = MOTOR_GAINS;
+        let derivative = self.previous_error.map_or(0, |previous| error - previous);
+        self.previous_error = Some(error);
+        self.integral = (self.integral + error).clamp(-MOTOR_INTEGRAL_LIMIT, MOTOR_INTEGRAL_LIMIT);
+        let duty = (kp * error + ki * self.integral + kd * derivative) / 100;
+        // A motor below MOTOR_MIN_DUTY does not get over the friction of the fader
+        (duty.abs().clamp(MOTOR_MIN_DUTY, 100) * duty.signum()) as i8
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FaderState {
+    /// On its target or without one, the motor is off
+    Resting,
+    /// Driven to the target. `closest` is the smallest error so far, reached at `closest_at`
+    Moving { closest: u16, closest_at: u64 },
+    /// Held by a hand since the fader was at `position` at `moved_at`, the motor is off
+    Grabbed { position: u16, moved_at: u64 },
+}
+
+/// Target and state of one motorized fader
+pub struct Fader {
+    /// Position (0-1023) to move to
+    target: Option<u16>,
+    state: FaderState,
+    controller: PositionController,
+}
+
+impl Default for Fader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fader {
+    pub fn new() -> Self {
+        Self {
+            target: None,
+            state: FaderState::Resting,
+            controller: PositionController::new(),
+        }
+    }
+
+    /// Moves the fader to `target` (0-1023) unless a hand holds it, `None` leaves it where it is
+    pub fn set_target(&mut self, target: Option<u16>, now_ms: u64) {
+        if self.target == target || matches!(self.state, FaderState::Grabbed { .. }) {
+            return;
+        }
+        self.target = target;
+        self.controller.reset();
+        self.state = match target {
+            Some(_) => FaderState::Moving {
+                closest: u16::MAX,
+                closest_at: now_ms,
+            },
+            None => FaderState::Resting,
+        };
+    }
+
+    /// Call with every sample of the fader and whether its slider is touched, returns the duty for
+    /// its [Motor]
+    pub fn update(&mut self, position: u16, touched: bool, now_ms: u64) -> i8 {
+        if let FaderState::Grabbed {
+            position: held,
+            moved_at,
+        } = self.state
+        {
+            if touched || position.abs_diff(held) > MOTOR_DEADBAND {
+                self.state = FaderState::Grabbed {
+                    position,
+                    moved_at: now_ms,
+                };
+            } else if now_ms >= moved_at + MOTOR_RELEASE_TIME {
+                debug!("Fader let go at {}", position);
+                // Stays where the hand left it, the host follows it
+                self.target = None;
+                self.state = FaderState::Resting;
+            }
+            return 0;
+        }
+        if touched {
+            return self.grab(position, now_ms);
+        }
+        let Some(target) = self.target else {
+            return 0;
+        };
+        let error = target.abs_diff(position);
+        if error <= MOTOR_DEADBAND {
+            self.state = FaderState::Resting;
+            return 0;
+        }
+        match self.state {
+            FaderState::Moving {
+                closest,
+                closest_at,
+            } => {
+                if error + MOTOR_DEADBAND <= closest {
+                    self.state = FaderState::Moving {
+                        closest: error,
+                        closest_at: now_ms,
+                    };
+                } else if now_ms >= closest_at + MOTOR_STALL_TIME {
+                    // Held back by a hand or by the end of the track
+                    return self.grab(position, now_ms);
+                }
+            }
+            // Pushed off the target while the motor was off
+            _ => return self.grab(position, now_ms),
+        }
+        self.controller.update(target as i32 - position as i32)
+    }
+
+    fn grab(&mut self, position: u16, now_ms: u64) -> i8 {
+        debug!("Fader grabbed at {}", position);
+        self.controller.reset();
+        self.state = FaderState::Grabbed {
+            position,
+            moved_at: now_ms,
+        };
+        0
+    }
+}
+
+/// Volumes the host asked the faders to move to, kept by the serial tasks for the loop
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FaderTargets {
+    /// 0-100, see [crate::protocol::HostCommand::HostVolumes]
+    volumes: [Option<u16>; INPUT_COUNT],
+    /// See [crate::protocol::HostCommand::Faders]
+    enabled: bool,
+}
+
+impl Default for FaderTargets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FaderTargets {
+    pub const fn new() -> Self {
+        Self {
+            volumes: [None; INPUT_COUNT],
+            enabled: true,
+        }
+    }
+
+    pub fn set_volumes(&mut self, volumes: &[Option<u16>; INPUT_COUNT]) {
+        self.volumes = *volumes;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Target position (0-1023) of each fader, `None` for all of them while the motors are off.
+    /// A fader lands on the volume of the host unless a profile or a channel role maps it to
+    /// something else.
+    pub fn positions(&self) -> [Option<u16>; INPUT_COUNT] {
+        self.volumes.map(|volume| {
+            volume
+                .filter(|_| self.enabled)
+                .map(|volume| scale_to_range(volume, 0, 100, 0, 1023))
+        })
+    }
+}
+
+/// Motors and the loops of all faders
+pub struct Faders<M> {
+    motors: [M; INPUT_COUNT],
+    faders: [Fader; INPUT_COUNT],
+    /// Duty last given to each motor
+    duties: [i8; INPUT_COUNT],
+}
+
+impl<M: Motor> Faders<M> {
+    pub fn new(mut motors: [M; INPUT_COUNT]) -> Self {
+        for motor in &mut motors {
+            motor.drive(0);
+        }
+        Self {
+            motors,
+            faders: core::array::from_fn(|_| Fader::new()),
+            duties: [0; INPUT_COUNT],
+        }
+    }
+
+    /// Runs the loops on the `positions` (0-1023) of a sample and the sliders `touched` at the
+    /// time. `targets` are the positions to move to, `None` for the channels the host has no
+    /// volume for.
+    pub fn update(
+        &mut self,
+        targets: &[Option<u16>; INPUT_COUNT],
+        positions: &[u16; INPUT_COUNT],
+        touched: &[bool; INPUT_COUNT],
+        now_ms: u64,
+    ) {
+        for channel in 0..INPUT_COUNT {
+            let fader = &mut self.faders[channel];
+            fader.set_target(targets[channel], now_ms);
+            let duty = fader.update(positions[channel], touched[channel], now_ms);
+            if duty != self.duties[channel] {
+                self.motors[channel].drive(duty);
+                self.duties[channel] = duty;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_to_the_target_and_lets_go_of_a_hand() {
+        let mut fader = Fader::new();
+        assert_eq!(fader.update(100, false, 0), 0);
+        fader.set_target(Some(800), 0);
+        let duty = fader.update(100, false, 10);
+        assert!(duty >= MOTOR_MIN_DUTY as i8);
+        // Getting closer keeps the motor going, down when past the target
+        assert!(fader.update(500, false, 20) > 0);
+        assert!(fader.update(900, false, 30) < 0);
+        assert_eq!(fader.update(800, false, 40), 0);
+
+        // Pushed away while resting, the new position is kept once the hand is gone
+        assert_eq!(fader.update(600, false, 50), 0);
+        fader.set_target(Some(800), 60);
+        assert_eq!(fader.update(400, false, 60), 0);
+        assert_eq!(fader.update(400, false, 60 + MOTOR_RELEASE_TIME), 0);
+        fader.set_target(Some(400), 70 + MOTOR_RELEASE_TIME);
+        assert_eq!(fader.update(400, false, 80 + MOTOR_RELEASE_TIME), 0);
+
+        // Held back while moving
+        let start = 100 + MOTOR_RELEASE_TIME;
+        fader.set_target(Some(1000), start);
+        assert!(fader.update(400, false, start) > 0);
+        assert!(fader.update(402, false, start + MOTOR_STALL_TIME / 2) > 0);
+        assert_eq!(fader.update(403, false, start + MOTOR_STALL_TIME), 0);
+
+        // A touch holds it right away, until it was let go for MOTOR_RELEASE_TIME
+        let start = start + MOTOR_STALL_TIME + MOTOR_RELEASE_TIME;
+        assert_eq!(fader.update(403, false, start), 0);
+        fader.set_target(Some(0), start);
+        assert_eq!(fader.update(403, true, start), 0);
+        assert_eq!(fader.update(403, true, start + MOTOR_RELEASE_TIME), 0);
+        assert_eq!(fader.update(403, false, start + MOTOR_RELEASE_TIME), 0);
+        assert_eq!(fader.update(403, false, start + 2 * MOTOR_RELEASE_TIME), 0);
+        fader.set_target(Some(0), start + 2 * MOTOR_RELEASE_TIME);
+        assert!(fader.update(403, false, start + 2 * MOTOR_RELEASE_TIME) < 0);
+    }
+}
@@ -0,0 +1,95 @@
+use heapless::{String, Vec};
+
+use crate::text::write_truncated;
+
+pub const MQTT_CLIENT_ID: &str = "rust-deej";
+/// Channel N is published to `deej/chN`
+pub const MQTT_TOPIC_PREFIX: &str = "deej/ch";
+
+const CONNECT: u8 = 0x10;
+const PUBLISH: u8 = 0x30;
+const PUBLISH_RETAIN: u8 = 0x01;
+const PROTOCOL_LEVEL_3_1_1: u8 = 4;
+const CONNECT_CLEAN_SESSION: u8 = 0x02;
+
+/// Room for the prefix and a channel index of up to 9 digits
+const TOPIC_LEN: usize = 16;
+/// A 0-100 value
+const PAYLOAD_LEN: usize = 3;
+/// Protocol name, level, flags, keep-alive and the client id
+const CONNECT_LEN: usize = 2 + 4 + 2 + 2 + 2 + MQTT_CLIENT_ID.len();
+/// Topic and payload
+const PUBLISH_LEN: usize = 2 + TOPIC_LEN + PAYLOAD_LEN;
+/// The longer of the bodies after the fixed header, whose remaining length takes a byte below 128
+const PACKET_LEN: usize = 2 + if CONNECT_LEN > PUBLISH_LEN {
+    CONNECT_LEN
+} else {
+    PUBLISH_LEN
+};
+
+/// Large enough for every packet this client sends
+pub type Packet = Vec<u8, PACKET_LEN>;
+pub type Topic = String<TOPIC_LEN>;
+pub type Payload = String<PAYLOAD_LEN>;
+
+pub fn channel_topic(idx: usize) -> Topic {
+    let mut topic = Topic::new();
+    write_truncated(&mut topic, format_args!("{}{}", MQTT_TOPIC_PREFIX, idx)).ok();
+    topic
+}
+
+/// MQTT 3.1.1 CONNECT as [MQTT_CLIENT_ID] with clean session and no credentials.
+/// `keep_alive_secs` 0 disables the keep-alive.
+pub fn connect_packet(keep_alive_secs: u16) -> Packet {
+    let mut body = Packet::new();
+    push_str(&mut body, "MQTT");
+    push(&mut body, &[PROTOCOL_LEVEL_3_1_1, CONNECT_CLEAN_SESSION]);
+    push(&mut body, &keep_alive_secs.to_be_bytes());
+    push_str(&mut body, MQTT_CLIENT_ID);
+    finish(CONNECT, &body)
+}
+
+/// QoS 0 PUBLISH
+pub fn publish_packet(topic: &Topic, payload: &Payload, retain: bool) -> Packet {
+    let mut body = Packet::new();
+    push_str(&mut body, topic);
+    push(&mut body, payload.as_bytes());
+    let header = if retain {
+        PUBLISH | PUBLISH_RETAIN
+    } else {
+        PUBLISH
+    };
+    finish(header, &body)
+}
+
+/// Prepends the fixed header to `body`
+fn finish(header: u8, body: &[u8]) -> Packet {
+    let mut packet = Packet::new();
+    push(&mut packet, &[header]);
+
+    // Remaining length is a variable length integer, 7 bits per byte
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        push(&mut packet, &[byte]);
+        if len == 0 {
+            break;
+        }
+    }
+    push(&mut packet, body);
+    packet
+}
+
+fn push_str(packet: &mut Packet, s: &str) {
+    push(packet, &(s.len() as u16).to_be_bytes());
+    push(packet, s.as_bytes());
+}
+
+/// Always fits, see [PACKET_LEN]
+fn push(packet: &mut Packet, bytes: &[u8]) {
+    packet.extend_from_slice(bytes).ok();
+}
@@ -0,0 +1,137 @@
+//! Integer mapping between ranges, e.g. ADC readings to the 0-1023 sent to the host and those to
+//! the 0-100 shown on the display. The math is done in `u64`, so no intermediate product of the
+//! integers below can overflow unnoticed.
+
+use core::ops::RangeInclusive;
+
+/// Largest value sent to the host, the deej protocol is 0-1023
+pub const WIRE_MAX: u16 = 1023;
+
+/// How [map_range] rounds a value that falls between two integers of the new range
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rounding {
+    /// Toward the start of the new range, e.g. for pixels that are only lit once reached
+    Down,
+    /// To the closest, halves away from the start of the new range. Values reach the end of the
+    /// new range as soon as they are closer to it than to the one before.
+    Nearest,
+}
+
+/// Unsigned integers [map_range] works on
+pub trait Unsigned: Copy + Into<u64> + TryFrom<u64> {}
+
+impl Unsigned for u8 {}
+impl Unsigned for u16 {}
+impl Unsigned for u32 {}
+
+/// Maps `value` from `from` to `to`. Values outside `from` are clamped to it first. `to` may run
+/// backwards, e.g. `1023..=0` flips the value. `None` when `from` is empty or the math overflowed.
+pub fn map_range<T: Unsigned>(
+    value: T,
+    from: RangeInclusive<T>,
+    to: RangeInclusive<T>,
+    rounding: Rounding,
+) -> Option<T> {
+    let (from_start, from_end): (u64, u64) = ((*from.start()).into(), (*from.end()).into());
+    let (to_start, to_end): (u64, u64) = ((*to.start()).into(), (*to.end()).into());
+    if from_start > from_end {
+        return None;
+    }
+    let from_span = from_end - from_start;
+    if from_span == 0 {
+        return Some(*to.start());
+    }
+    let value: u64 = value.into();
+    let value = value.clamp(from_start, from_end);
+    let scaled = (value - from_start).checked_mul(to_start.abs_diff(to_end))?;
+    let offset = match rounding {
+        Rounding::Down => scaled / from_span,
+        Rounding::Nearest => scaled.checked_add(from_span / 2)? / from_span,
+    };
+    let mapped = if to_start <= to_end {
+        to_start + offset
+    } else {
+        to_start - offset
+    };
+    T::try_from(mapped).ok()
+}
+
+/// Value sent to the host (0-1023) as a percentage, rounded to the nearest
+pub fn wire_to_percent(value: u16) -> u16 {
+    map_range(value, 0..=WIRE_MAX, 0..=100, Rounding::Nearest).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FROM: [(u16, u16); 5] = [(0, 1023), (0, 770), (100, 900), (3, 4), (0, 65535)];
+    const TO: [(u16, u16); 5] = [(0, 100), (0, 1023), (1023, 0), (20, 30), (0, 65535)];
+
+    /// Every value of every pair of ranges
+    fn each_mapping(mut check: impl FnMut(u16, (u16, u16), (u16, u16))) {
+        for from in FROM {
+            for to in TO {
+                // The widest range is sampled, the others are walked through
+                let step = ((from.1 - from.0) / 4096).max(1) as usize;
+                for value in (from.0..=from.1).step_by(step) {
+                    check(value, from, to);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ends_map_to_ends() {
+        for from in FROM {
+            for to in TO {
+                for rounding in [Rounding::Down, Rounding::Nearest] {
+                    let map = |value| map_range(value, from.0..=from.1, to.0..=to.1, rounding);
+                    assert_eq!(map(from.0), Some(to.0));
+                    assert_eq!(map(from.1), Some(to.1));
+                    // Outside the range is clamped, below the start as well
+                    assert_eq!(map(from.0.saturating_sub(1)), Some(to.0));
+                    assert_eq!(map(from.1.saturating_add(1)), Some(to.1));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn monotonic_and_within_half_a_step() {
+        each_mapping(|value, from, to| {
+            let nearest =
+                map_range(value, from.0..=from.1, to.0..=to.1, Rounding::Nearest).unwrap();
+            let down = map_range(value, from.0..=from.1, to.0..=to.1, Rounding::Down).unwrap();
+            let (low, high) = (to.0.min(to.1), to.0.max(to.1));
+            assert!((low..=high).contains(&nearest) && (low..=high).contains(&down));
+
+            // Exact offset from the start of `to` is scaled / span
+            let span = (from.1 - from.0) as u64;
+            let scaled = (value - from.0) as u64 * to.0.abs_diff(to.1) as u64;
+            let offset = |mapped: u16| mapped.abs_diff(to.0) as u64;
+            assert!(offset(down) * span <= scaled && scaled < (offset(down) + 1) * span);
+            assert!((2 * offset(nearest) * span).abs_diff(2 * scaled) <= span);
+
+            if value > from.0 {
+                let previous =
+                    map_range(value - 1, from.0..=from.1, to.0..=to.1, Rounding::Nearest).unwrap();
+                assert!(offset(previous) <= offset(nearest));
+            }
+        });
+    }
+
+    #[test]
+    fn empty_ranges_and_wide_integers() {
+        let empty = RangeInclusive::new(10u16, 0);
+        assert_eq!(map_range(5, empty, 0..=100, Rounding::Down), None);
+        assert_eq!(map_range(5u16, 7..=7, 0..=100, Rounding::Down), Some(0));
+        assert_eq!(
+            map_range(u32::MAX, 0..=u32::MAX, 0..=u32::MAX, Rounding::Nearest),
+            Some(u32::MAX)
+        );
+        assert_eq!(map_range(128u8, 0..=255, 0..=1, Rounding::Nearest), Some(1));
+        assert_eq!(wire_to_percent(1018), 100);
+        assert_eq!(wire_to_percent(1017), 99);
+    }
+}
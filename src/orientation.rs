@@ -0,0 +1,207 @@
+//! Display rotation that follows the enclosure, measured by an MPU6050 or a LIS3DH on
+//! [crate::i2c_bus], see `accelerometer` in board.toml. One firmware works on a desk and in a panel
+//! mount: standing up, the picture is turned so its top is up, lying flat it keeps its rotation.
+//!
+//! The sensor has to be mounted with its X axis pointing to the right edge of the display and its
+//! Y axis to the top edge, as seen without rotation.
+
+use embedded_hal_027::blocking::i2c::{Write, WriteRead};
+
+use crate::{
+    globals::{ORIENTATION_READINGS, ORIENTATION_THRESHOLD_MG},
+    log::debug,
+};
+
+/// The sensor did not acknowledge
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AccelerometerError;
+
+/// Gravity along X, Y and Z in mg, positive on the axis pointing up
+pub type Gravity = [i32; 3];
+
+/// Both sensors read ±2 g as the full i16 range by default
+fn to_mg(raw: i16) -> i32 {
+    raw as i32 * 1000 / 16384
+}
+
+/// Accelerometer of `accelerometer` in board.toml
+pub trait ReadGravity {
+    /// Wakes the sensor up, it measures continuously afterwards
+    fn start(&mut self) -> Result<(), AccelerometerError>;
+    fn read_gravity(&mut self) -> Result<Gravity, AccelerometerError>;
+}
+
+/// MPU6050, `address` is 0x68 with AD0 low and 0x69 with it high
+pub struct Mpu6050<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: Write + WriteRead> Mpu6050<I> {
+    const PWR_MGMT_1: u8 = 0x6b;
+    /// X, Y and Z, high byte first
+    const ACCEL_XOUT_H: u8 = 0x3b;
+
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I: Write + WriteRead> ReadGravity for Mpu6050<I> {
+    fn start(&mut self) -> Result<(), AccelerometerError> {
+        // Out of sleep on the internal oscillator
+        self.i2c
+            .write(self.address, &[Self::PWR_MGMT_1, 0x00])
+            .map_err(|_| AccelerometerError)
+    }
+
+    fn read_gravity(&mut self) -> Result<Gravity, AccelerometerError> {
+        let mut values = [0; 6];
+        self.i2c
+            .write_read(self.address, &[Self::ACCEL_XOUT_H], &mut values)
+            .map_err(|_| AccelerometerError)?;
+        Ok(core::array::from_fn(|axis| {
+            to_mg(i16::from_be_bytes([values[2 * axis], values[2 * axis + 1]]))
+        }))
+    }
+}
+
+/// LIS3DH, `address` is 0x18 with SA0 low and 0x19 with it high
+pub struct Lis3dh<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: Write + WriteRead> Lis3dh<I> {
+    const CTRL_REG1: u8 = 0x20;
+    /// 100 Hz, X, Y and Z enabled
+    const NORMAL_100HZ: u8 = 0x57;
+    /// X, Y and Z, low byte first
+    const OUT_X_L: u8 = 0x28;
+    /// Reads the following registers along
+    const AUTO_INCREMENT: u8 = 0x80;
+
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I: Write + WriteRead> ReadGravity for Lis3dh<I> {
+    fn start(&mut self) -> Result<(), AccelerometerError> {
+        self.i2c
+            .write(self.address, &[Self::CTRL_REG1, Self::NORMAL_100HZ])
+            .map_err(|_| AccelerometerError)
+    }
+
+    fn read_gravity(&mut self) -> Result<Gravity, AccelerometerError> {
+        let mut values = [0; 6];
+        self.i2c
+            .write_read(
+                self.address,
+                &[Self::OUT_X_L | Self::AUTO_INCREMENT],
+                &mut values,
+            )
+            .map_err(|_| AccelerometerError)?;
+        // Left aligned, the low bits are 0
+        Ok(core::array::from_fn(|axis| {
+            to_mg(i16::from_le_bytes([values[2 * axis], values[2 * axis + 1]]))
+        }))
+    }
+}
+
+/// Rotation of the picture, converts to and from the `DisplayRotation` of ssd1306, which can not be
+/// compared
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rotation {
+    Rotate0,
+    /// Clockwise
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+/// Rotation that puts the top of the picture up, `None` while the display lies flat or is tilted
+/// halfway between two edges
+pub fn rotation_of(gravity: Gravity) -> Option<Rotation> {
+    let [x, y, _] = gravity;
+    if y >= ORIENTATION_THRESHOLD_MG {
+        Some(Rotation::Rotate0)
+    } else if y <= -ORIENTATION_THRESHOLD_MG {
+        Some(Rotation::Rotate180)
+    } else if x >= ORIENTATION_THRESHOLD_MG {
+        // Right edge up, the picture is turned clockwise
+        Some(Rotation::Rotate90)
+    } else if x <= -ORIENTATION_THRESHOLD_MG {
+        Some(Rotation::Rotate270)
+    } else {
+        None
+    }
+}
+
+/// Decides when the display was turned, a bump or a tilt in passing does not turn the picture
+pub struct OrientationDetector {
+    rotation: Rotation,
+    /// Rotation of the latest readings and how many in a row there were
+    candidate: Option<(Rotation, u8)>,
+}
+
+impl OrientationDetector {
+    /// Starts in `rotation`, the one the display was set up with
+    pub fn new(rotation: Rotation) -> Self {
+        Self {
+            rotation,
+            candidate: None,
+        }
+    }
+
+    /// Returns the new rotation once [ORIENTATION_READINGS] readings in a row agreed on it
+    pub fn update(&mut self, gravity: Gravity) -> Option<Rotation> {
+        let rotation = match rotation_of(gravity) {
+            Some(rotation) if rotation != self.rotation => rotation,
+            // Lying flat keeps the rotation
+            _ => {
+                self.candidate = None;
+                return None;
+            }
+        };
+        let readings = match self.candidate {
+            Some((candidate, readings)) if candidate == rotation => readings + 1,
+            _ => 1,
+        };
+        if readings < ORIENTATION_READINGS {
+            self.candidate = Some((rotation, readings));
+            return None;
+        }
+        debug!("Display turned, gravity {} mg", gravity);
+        self.rotation = rotation;
+        self.candidate = None;
+        Some(rotation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_once_the_readings_agree() {
+        let mut detector = OrientationDetector::new(Rotation::Rotate0);
+        let flat = [0, 0, 1000];
+        let right_edge_up = [990, 50, 80];
+        assert_eq!(detector.update(flat), None);
+        for _ in 1..ORIENTATION_READINGS {
+            assert_eq!(detector.update(right_edge_up), None);
+        }
+        // Bumped halfway, starts over
+        assert_eq!(detector.update([600, 600, 0]), None);
+        for _ in 1..ORIENTATION_READINGS {
+            assert_eq!(detector.update(right_edge_up), None);
+        }
+        assert_eq!(detector.update(right_edge_up), Some(Rotation::Rotate90));
+        assert_eq!(detector.update(right_edge_up), None);
+        assert_eq!(detector.update(flat), None);
+        assert_eq!(rotation_of([0, -1000, 0]), Some(Rotation::Rotate180));
+    }
+}
@@ -0,0 +1,65 @@
+use heapless::{String, Vec};
+
+use crate::text::write_truncated;
+
+/// Channel N is sent to `/deej/ch/N`
+pub const OSC_ADDRESS_PREFIX: &str = "/deej/ch/";
+/// UDP port the messages are sent from
+pub const OSC_LOCAL_PORT: u16 = 9001;
+
+const FLOAT_TYPE_TAG: &str = ",f";
+
+/// Room for the prefix and a channel index of up to 7 digits
+const ADDRESS_LEN: usize = 16;
+/// The padded address, the padded type tag and the float, so every [channel_message] fits
+const MESSAGE_LEN: usize = (ADDRESS_LEN / 4 + 1) * 4 + 4 + 4;
+
+pub type Message = Vec<u8, MESSAGE_LEN>;
+pub type Address = String<ADDRESS_LEN>;
+
+pub fn channel_address(idx: usize) -> Address {
+    let mut address = Address::new();
+    write_truncated(&mut address, format_args!("{}{}", OSC_ADDRESS_PREFIX, idx)).ok();
+    address
+}
+
+/// OSC 1.0 message to [channel_address] with the value (0-1023) as a single float argument, 0.0-1.0
+pub fn channel_message(idx: usize, value: u16) -> Message {
+    let mut message = Message::new();
+    push_str(&mut message, &channel_address(idx));
+    push_str(&mut message, FLOAT_TYPE_TAG);
+    push(
+        &mut message,
+        &(value.min(1023) as f32 / 1023.0).to_be_bytes(),
+    );
+    message
+}
+
+/// Strings are null terminated and padded with nulls to a multiple of 4 bytes
+fn push_str(message: &mut Message, s: &str) {
+    push(message, s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    push(message, &[0; 4][..padding]);
+}
+
+/// Always fits, see [MESSAGE_LEN]
+fn push(message: &mut Message, bytes: &[u8]) {
+    message.extend_from_slice(bytes).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_message_is_padded() {
+        let message = channel_message(2, 1023);
+        assert_eq!(&message[..12], b"/deej/ch/2\0\0");
+        assert_eq!(&message[12..16], b",f\0\0");
+        assert_eq!(&message[16..], 1.0f32.to_be_bytes());
+
+        assert_eq!(&channel_message(10, 0)[..16], b"/deej/ch/10\0,f\0\0");
+        // Address of 12 bytes still gets its terminating null
+        assert_eq!(channel_message(100, 0).len(), 24);
+    }
+}
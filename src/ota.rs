@@ -0,0 +1,316 @@
+use core::str::{from_utf8, FromStr};
+
+use embedded_io::{Read, Write};
+use embedded_storage::{ReadStorage, Storage};
+use esp_storage::FlashStorage;
+use esp_wifi::{wifi::WifiStaDevice, wifi_interface::WifiStack};
+use smoltcp::wire::IpAddress;
+
+use crate::wifi::parse_ipv4;
+
+/// Firmware image is downloaded from `http://DEEJ_OTA_HOST:DEEJ_OTA_PORT/DEEJ_OTA_PATH`, set at build time
+pub const OTA_HOST: &str = env!("DEEJ_OTA_HOST");
+pub const OTA_PORT: &str = env!("DEEJ_OTA_PORT");
+pub const OTA_PATH: &str = env!("DEEJ_OTA_PATH");
+
+/// Offsets must match `partitions.csv`
+pub const OTADATA_OFFSET: u32 = 0xd000;
+pub const OTA_SLOT_OFFSETS: [u32; 2] = [0x10000, 0x1f0000];
+pub const OTA_SLOT_SIZE: u32 = 0x1e0000;
+
+const SECTOR_SIZE: usize = 4096;
+/// First byte of every ESP application image
+const IMAGE_MAGIC: u8 = 0xe9;
+const OTA_SELECT_ENTRY_LEN: usize = 32;
+
+#[derive(Debug)]
+pub enum OtaError {
+    /// Could not open the connection to [OTA_HOST]
+    Connect,
+    /// Response was not `200 OK` with a Content-Length
+    Http,
+    /// Connection closed before the whole image was received
+    Network,
+    TooLarge,
+    /// Downloaded data is not an ESP application image
+    InvalidImage,
+    Flash,
+}
+
+/// `esp_ota_img_states_t` of ESP-IDF. The bootloader only marks images [OtaState::PendingVerify]
+/// when rollback is enabled, in that case the new image must call [mark_valid] or the previous one is booted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum OtaState {
+    New = 0,
+    PendingVerify = 1,
+    Valid = 2,
+    Invalid = 3,
+    Aborted = 4,
+    Undefined = u32::MAX,
+}
+
+/// `esp_ota_select_entry_t` stored at the start of both otadata sectors
+#[derive(Clone, Copy)]
+struct OtaSelectEntry {
+    seq: u32,
+    state: u32,
+    crc: u32,
+}
+
+impl OtaSelectEntry {
+    fn new(seq: u32, state: OtaState) -> Self {
+        Self {
+            seq,
+            state: state as u32,
+            crc: crc32_le(u32::MAX, &seq.to_le_bytes()),
+        }
+    }
+
+    fn read(flash: &mut FlashStorage, sector: usize) -> Result<Self, OtaError> {
+        let mut bytes = [0u8; OTA_SELECT_ENTRY_LEN];
+        flash
+            .read(otadata_sector_offset(sector), &mut bytes)
+            .map_err(|_| OtaError::Flash)?;
+        let word =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        // 20 bytes of sequence label between the sequence number and state are unused
+        Ok(Self {
+            seq: word(0),
+            state: word(24),
+            crc: word(28),
+        })
+    }
+
+    fn write(&self, flash: &mut FlashStorage, sector: usize) -> Result<(), OtaError> {
+        let mut bytes = [0xffu8; OTA_SELECT_ENTRY_LEN];
+        bytes[0..4].copy_from_slice(&self.seq.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.state.to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.crc.to_le_bytes());
+        flash
+            .write(otadata_sector_offset(sector), &bytes)
+            .map_err(|_| OtaError::Flash)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.seq != u32::MAX
+            && self.crc == crc32_le(u32::MAX, &self.seq.to_le_bytes())
+            && self.state != OtaState::Invalid as u32
+            && self.state != OtaState::Aborted as u32
+    }
+
+    fn slot(&self) -> usize {
+        (self.seq.wrapping_sub(1) as usize) % OTA_SLOT_OFFSETS.len()
+    }
+}
+
+fn otadata_sector_offset(sector: usize) -> u32 {
+    OTADATA_OFFSET + (sector * SECTOR_SIZE) as u32
+}
+
+/// Returns the otadata sector holding the newest valid entry, `None` when booting ota_0 without otadata
+fn newest_entry(flash: &mut FlashStorage) -> Result<Option<(usize, OtaSelectEntry)>, OtaError> {
+    let mut newest: Option<(usize, OtaSelectEntry)> = None;
+    for sector in 0..2 {
+        let entry = OtaSelectEntry::read(flash, sector)?;
+        if entry.is_valid() && newest.map_or(true, |(_, n)| entry.seq > n.seq) {
+            newest = Some((sector, entry));
+        }
+    }
+    Ok(newest)
+}
+
+/// Index of the OTA slot the running firmware was booted from
+pub fn running_slot(flash: &mut FlashStorage) -> Result<usize, OtaError> {
+    Ok(newest_entry(flash)?.map_or(0, |(_, entry)| entry.slot()))
+}
+
+/// Confirms that the running image works so the bootloader does not roll back to the previous one
+pub fn mark_valid(flash: &mut FlashStorage) -> Result<(), OtaError> {
+    match newest_entry(flash)? {
+        Some((sector, mut entry)) if entry.state == OtaState::PendingVerify as u32 => {
+            entry.state = OtaState::Valid as u32;
+            entry.write(flash, sector)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Makes the bootloader boot `slot` on the next reset. The entry of the running image is kept for rollback.
+fn activate(flash: &mut FlashStorage, slot: usize) -> Result<(), OtaError> {
+    let newest = newest_entry(flash)?;
+    let mut seq = newest.map_or(0, |(_, entry)| entry.seq) + 1;
+    if (seq - 1) as usize % OTA_SLOT_OFFSETS.len() != slot {
+        seq += 1;
+    }
+    let sector = newest.map_or(0, |(sector, _)| 1 - sector);
+    OtaSelectEntry::new(seq, OtaState::New).write(flash, sector)
+}
+
+/// Buffers the image so every flash sector is erased and written only once
+struct SlotWriter<'a> {
+    flash: &'a mut FlashStorage,
+    offset: u32,
+    sector: [u8; SECTOR_SIZE],
+    fill: usize,
+    written: usize,
+}
+
+impl<'a> SlotWriter<'a> {
+    fn new(flash: &'a mut FlashStorage, slot: usize) -> Self {
+        Self {
+            flash,
+            offset: OTA_SLOT_OFFSETS[slot],
+            sector: [0xff; SECTOR_SIZE],
+            fill: 0,
+            written: 0,
+        }
+    }
+
+    fn write(&mut self, mut data: &[u8]) -> Result<(), OtaError> {
+        if self.written + data.len() > OTA_SLOT_SIZE as usize {
+            return Err(OtaError::TooLarge);
+        }
+        if self.written == 0 && data.first().is_some_and(|b| *b != IMAGE_MAGIC) {
+            return Err(OtaError::InvalidImage);
+        }
+        self.written += data.len();
+
+        while !data.is_empty() {
+            let len = data.len().min(SECTOR_SIZE - self.fill);
+            self.sector[self.fill..self.fill + len].copy_from_slice(&data[..len]);
+            self.fill += len;
+            data = &data[len..];
+            if self.fill == SECTOR_SIZE {
+                self.flush_sector()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), OtaError> {
+        if self.fill > 0 {
+            self.sector[self.fill..].fill(0xff);
+            self.flush_sector()?;
+        }
+        Ok(())
+    }
+
+    fn flush_sector(&mut self) -> Result<(), OtaError> {
+        self.flash
+            .write(self.offset, &self.sector)
+            .map_err(|_| OtaError::Flash)?;
+        self.offset += SECTOR_SIZE as u32;
+        self.fill = 0;
+        Ok(())
+    }
+}
+
+/// Downloads the image into the inactive slot and activates it. Reset the chip afterwards to boot it.
+///
+/// `on_progress` is called with the percentage every time it changes.
+pub fn update(
+    stack: &WifiStack<'_, WifiStaDevice>,
+    mut on_progress: impl FnMut(u8),
+) -> Result<(), OtaError> {
+    let host = parse_ipv4(OTA_HOST).expect("DEEJ_OTA_HOST is not a valid IPv4 address");
+    let port = u16::from_str(OTA_PORT).expect("DEEJ_OTA_PORT is not a valid port");
+
+    let mut flash = FlashStorage::new();
+    let slot = 1 - running_slot(&mut flash)?;
+
+    let mut rx_buffer = [0u8; 1536];
+    let mut tx_buffer = [0u8; 256];
+    let mut socket = stack.get_socket(&mut rx_buffer, &mut tx_buffer);
+    socket
+        .open(IpAddress::Ipv4(host), port)
+        .map_err(|_| OtaError::Connect)?;
+
+    let result = (|| {
+        for part in [
+            "GET /",
+            OTA_PATH,
+            " HTTP/1.0\r\nHost: ",
+            OTA_HOST,
+            "\r\n\r\n",
+        ] {
+            socket
+                .write_all(part.as_bytes())
+                .map_err(|_| OtaError::Network)?;
+        }
+        socket.flush().map_err(|_| OtaError::Network)?;
+
+        let mut buf = [0u8; 1024];
+        let mut len = 0;
+        let (body_start, content_length) = loop {
+            if len == buf.len() {
+                return Err(OtaError::Http);
+            }
+            match socket.read(&mut buf[len..]) {
+                Ok(0) | Err(_) => return Err(OtaError::Network),
+                Ok(n) => len += n,
+            }
+            if let Some(end) = buf[..len].windows(4).position(|w| w == b"\r\n\r\n") {
+                break (
+                    end + 4,
+                    parse_http_header(&buf[..end]).ok_or(OtaError::Http)?,
+                );
+            }
+        };
+        if content_length > OTA_SLOT_SIZE as usize {
+            return Err(OtaError::TooLarge);
+        }
+
+        let mut writer = SlotWriter::new(&mut flash, slot);
+        writer.write(&buf[body_start..len])?;
+        let mut percent = 0;
+        while writer.written < content_length {
+            match socket.read(&mut buf) {
+                Ok(0) | Err(_) => return Err(OtaError::Network),
+                Ok(n) => writer.write(&buf[..n.min(content_length - writer.written)])?,
+            }
+            let new_percent = (writer.written * 100 / content_length) as u8;
+            if new_percent != percent {
+                percent = new_percent;
+                on_progress(percent);
+            }
+        }
+        writer.finish()
+    })();
+    socket.disconnect();
+    result?;
+
+    activate(&mut flash, slot)
+}
+
+/// Returns the Content-Length of a `200` response header
+fn parse_http_header(header: &[u8]) -> Option<usize> {
+    let header = from_utf8(header).ok()?;
+    let mut lines = header.split("\r\n");
+
+    let status = lines.next()?;
+    if !status.starts_with("HTTP/1.") || status.split(' ').nth(1)? != "200" {
+        return None;
+    }
+
+    lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-length")
+            .then(|| usize::from_str(value.trim()).ok())?
+    })
+}
+
+/// Same as `esp_rom_crc32_le` used by the bootloader for the otadata entries
+fn crc32_le(crc: u32, data: &[u8]) -> u32 {
+    let crc = data.iter().fold(!crc, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    });
+    !crc
+}
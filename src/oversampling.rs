@@ -0,0 +1,23 @@
+use crate::globals::{MAX_ANALOG_VALUE, ZERO_CUTOFF};
+
+/// Sums 4^`extra_bits` readings and shifts the sum right by `extra_bits`. The noise of the ADC
+/// dithers the readings, so the result is the average with `extra_bits` more bits of resolution.
+/// Stops at the first failed reading.
+pub fn oversample<E>(extra_bits: u32, mut read: impl FnMut() -> Result<u16, E>) -> Result<u32, E> {
+    let sum: u32 = (0..4u32.pow(extra_bits))
+        .map(|_| read().map(u32::from))
+        .sum::<Result<u32, E>>()?;
+    Ok(sum >> extra_bits)
+}
+
+/// Maps an [oversample]d reading onto 0-1023.
+///
+/// Readings below [ZERO_CUTOFF] are 0 and the travel from there to [MAX_ANALOG_VALUE] is spread
+/// over the whole range, so the ends do not jump and the extra bits are kept until the last step.
+pub fn scale_to_1023(value: u32, extra_bits: u32) -> u16 {
+    let min = (ZERO_CUTOFF as u32) << extra_bits;
+    let max = (MAX_ANALOG_VALUE as u32) << extra_bits;
+    let range = max - min;
+    let value = value.clamp(min, max) - min;
+    ((value * 1023 + range / 2) / range) as u16
+}
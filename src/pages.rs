@@ -0,0 +1,588 @@
+use embedded_graphics::{
+    image::{Image, ImageRaw},
+    mono_font::MonoTextStyle,
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Arc, Circle, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use heapless::String;
+
+use crate::{
+    assets::logo,
+    diagnostics::{self, ChannelStats},
+    globals::INPUT_COUNT,
+    layout::BarOrientation,
+    menu::MenuView,
+    reset::{BootInfo, ResetReason},
+    self_test::{self, SelfTest},
+    style::{
+        Theme, FILL_RECT_STYLE, TEXT_STYLE, TEXT_STYLE_BOLD, TEXT_STYLE_INVERTED, TEXT_STYLE_SMALL,
+    },
+    text::{format_truncated, write_truncated},
+    widgets::{text_width, Label, Overflow, VolumeBar},
+};
+
+/// Height of a line of [TEXT_STYLE] text
+const LINE_HEIGHT: u32 = 10;
+
+/// Firmware version and short commit hash of the build
+pub const VERSION: &str = concat!("v", env!("CARGO_PKG_VERSION"), " ", env!("DEEJ_BUILD_HASH"));
+
+/// Screens cycled through with the page button
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Screen {
+    /// Logo and version shown at boot, covers the whole display
+    Splash,
+    /// Volume bars, drawn by [crate::DisplayState] itself so only changed rows are redrawn
+    #[default]
+    Volumes,
+    Diagnostics,
+    Info,
+    /// Track playing on the PC, also shown instead of turning the display off
+    NowPlaying,
+    /// Menu of [crate::settings::Settings], opened with the encoder instead of the page button
+    Settings,
+    /// Checklist of the boot self-test, shown once after [Screen::Splash]. Covers the whole display
+    SelfTest,
+}
+
+impl Screen {
+    pub fn next(self) -> Self {
+        match self {
+            Screen::Splash | Screen::Volumes | Screen::Settings | Screen::SelfTest => {
+                Screen::Diagnostics
+            }
+            Screen::Diagnostics => Screen::Info,
+            Screen::Info => Screen::NowPlaying,
+            Screen::NowPlaying => Screen::Volumes,
+        }
+    }
+}
+
+/// Content of a screen below the title
+pub trait Page<D: DrawTarget<Color = BinaryColor>> {
+    fn draw(&self, display: &mut D, area: Rectangle) -> Result<(), D::Error>;
+}
+
+/// Logo centered at the top with the version below it
+pub struct SplashPage {
+    /// Its [ResetReason::banner] is shown above the version, which is left out if there is no room
+    /// for both
+    pub reset: Option<ResetReason>,
+}
+
+impl<D: DrawTarget<Color = BinaryColor>> Page<D> for SplashPage {
+    fn draw(&self, display: &mut D, area: Rectangle) -> Result<(), D::Error> {
+        let logo = logo();
+        let logo_top_left =
+            area.top_left + Point::new((area.size.width - logo.size().width) as i32 / 2, 2);
+        Image::new(&logo, logo_top_left).draw(display)?;
+
+        let banner = self.reset.and_then(ResetReason::banner);
+        let mut baseline = logo_top_left.y + (logo.size().height + LINE_HEIGHT) as i32;
+        let bottom = area.top_left.y + area.size.height as i32;
+        for line in banner.into_iter().chain([VERSION]) {
+            if baseline > bottom {
+                break;
+            }
+            Text::with_alignment(
+                line,
+                Point::new(area.center().x, baseline),
+                TEXT_STYLE,
+                Alignment::Center,
+            )
+            .draw(display)?;
+            baseline += LINE_HEIGHT as i32;
+        }
+        Ok(())
+    }
+}
+
+/// Longest channel name kept from [crate::protocol::HostCommand::Name], in bytes
+pub const CHANNEL_NAME_LEN: usize = 12;
+pub type ChannelName = String<CHANNEL_NAME_LEN>;
+
+/// A single channel with a large value and a full width bar
+pub struct ZoomPage<'a> {
+    pub channel: usize,
+    /// Formatted value, e.g. `42` or `-8dB`
+    pub label: &'a str,
+    /// Bar fill, 0-100
+    pub fill: u16,
+    /// Shown instead of the channel index when set
+    pub icon: Option<ImageRaw<'a, BinaryColor>>,
+    /// Shown instead of the channel index when set and there is no icon, in the room left of
+    /// the value
+    pub name: Option<&'a str>,
+    /// Pixels a name too long for its room has scrolled by, it is cut instead when `None`
+    pub name_scroll: Option<u32>,
+    /// Font of the value and style of the bar
+    pub theme: Theme,
+}
+
+impl<'a> ZoomPage<'a> {
+    /// Label of [ZoomPage::name] in `area` of the page, unless it is not shown
+    pub fn name_label(&self, area: Rectangle) -> Option<Label<'a>> {
+        let name = self.name.filter(|_| self.icon.is_none())?;
+        let (value_style, middle_y) = self.value_position(area);
+        let value_width = text_width(self.label, &value_style);
+        let room = (area.size.width / 2).saturating_sub(value_width / 2 + 4);
+        let height = TEXT_STYLE.font.character_size.height;
+        let mut label = Label::new(
+            name,
+            Rectangle::new(
+                Point::new(area.top_left.x + 2, middle_y - height as i32 / 2),
+                Size::new(room, height),
+            ),
+            TEXT_STYLE,
+        );
+        if let Some(offset) = self.name_scroll {
+            label.overflow = Overflow::Scroll(offset);
+        }
+        Some(label)
+    }
+
+    /// Style of the value, the one of the theme if it fits above the bar, and the middle of the
+    /// text line
+    fn value_position(&self, area: Rectangle) -> (MonoTextStyle<'static, BinaryColor>, i32) {
+        let text_height = area.size.height - Self::bar_height(area) - 4;
+        let value_style = if text_height >= self.theme.value_style.font.character_size.height {
+            self.theme.value_style
+        } else {
+            TEXT_STYLE_BOLD
+        };
+        (value_style, area.top_left.y + text_height as i32 / 2)
+    }
+
+    fn bar_height(area: Rectangle) -> u32 {
+        (area.size.height / 4).clamp(4, 10)
+    }
+}
+
+impl<'a, D: DrawTarget<Color = BinaryColor>> Page<D> for ZoomPage<'a> {
+    fn draw(&self, display: &mut D, area: Rectangle) -> Result<(), D::Error> {
+        let bar_height = Self::bar_height(area);
+        let mut bar = VolumeBar::new(
+            area.top_left + Point::new(2, (area.size.height - bar_height - 2) as i32),
+            Size::new(area.size.width - 4, bar_height),
+            BarOrientation::Horizontal,
+            self.fill,
+        );
+        bar.style = self.theme.bar_style;
+        bar.draw(display)?;
+
+        // Channel on the left at the same height as the value
+        let (value_style, middle_y) = self.value_position(area);
+        Text::with_text_style(
+            self.label,
+            Point::new(area.center().x, middle_y),
+            value_style,
+            TextStyleBuilder::new()
+                .alignment(Alignment::Center)
+                .baseline(Baseline::Middle)
+                .build(),
+        )
+        .draw(display)?;
+
+        if let Some(icon) = &self.icon {
+            let top_left = Point::new(
+                area.top_left.x + 2,
+                middle_y - icon.size().height as i32 / 2,
+            );
+            Image::new(icon, top_left).draw(display)?;
+        } else if let Some(label) = self.name_label(area) {
+            label.draw(display)?;
+        } else {
+            let s_buf: String<8> = format_truncated(format_args!("CH{}", self.channel));
+            Text::with_text_style(
+                &s_buf,
+                Point::new(area.top_left.x + 2, middle_y),
+                TEXT_STYLE,
+                TextStyleBuilder::new().baseline(Baseline::Middle).build(),
+            )
+            .draw(display)?;
+        }
+        Ok(())
+    }
+}
+
+/// Raw ADC readings of every channel with their voltage, range since boot and noise, useful for
+/// calibrating [crate::globals::MAX_ANALOG_VALUE] and the zero cutoffs. A row per channel in
+/// [TEXT_STYLE_SMALL], below a header if there is room for it. Channels beyond the last row are
+/// left out.
+pub struct DiagnosticsPage<'a> {
+    pub stats: &'a [ChannelStats; INPUT_COUNT],
+}
+
+impl<'a> DiagnosticsPage<'a> {
+    const HEADER: &'static str = "CH  raw   mV  min  max  n";
+    const ROW_HEIGHT: u32 = 8;
+}
+
+impl<'a, D: DrawTarget<Color = BinaryColor>> Page<D> for DiagnosticsPage<'a> {
+    fn draw(&self, display: &mut D, area: Rectangle) -> Result<(), D::Error> {
+        let rows = (area.size.height / Self::ROW_HEIGHT) as usize;
+        let header = rows > INPUT_COUNT;
+
+        let mut s_buf: String<32> = String::new();
+        let lines = header.then_some(None).into_iter().chain(
+            self.stats
+                .iter()
+                .enumerate()
+                .map(Some)
+                .take(rows - header as usize),
+        );
+        for (row, line) in lines.enumerate() {
+            s_buf.clear();
+            match line {
+                None => s_buf.push_str(Self::HEADER).unwrap(),
+                Some((idx, stats)) => {
+                    write_truncated(
+                        &mut s_buf,
+                        format_args!(
+                            "{:<2}{:>5}{:>5}{:>5}{:>5}{:>3}",
+                            idx,
+                            stats.raw,
+                            diagnostics::millivolts(idx, stats.raw),
+                            stats.min,
+                            stats.max,
+                            stats.noise().min(99)
+                        ),
+                    )
+                    .ok();
+                }
+            }
+            Text::with_baseline(
+                &s_buf,
+                area.top_left + Point::new(1, (row as u32 * Self::ROW_HEIGHT) as i32),
+                TEXT_STYLE_SMALL,
+                Baseline::Top,
+            )
+            .draw(display)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks of the boot self-test below the overall result, in as many columns as they need
+pub struct SelfTestPage<'a> {
+    pub test: &'a SelfTest,
+}
+
+impl<'a> SelfTestPage<'a> {
+    const ROW_HEIGHT: u32 = 8;
+}
+
+impl<'a, D: DrawTarget<Color = BinaryColor>> Page<D> for SelfTestPage<'a> {
+    fn draw(&self, display: &mut D, area: Rectangle) -> Result<(), D::Error> {
+        let mut s_buf: String<16> = format_truncated(format_args!(
+            "Self-test {}",
+            if self.test.passed() { "PASS" } else { "FAIL" }
+        ));
+        Text::with_baseline(&s_buf, area.top_left, TEXT_STYLE_SMALL, Baseline::Top)
+            .draw(display)?;
+
+        let rows = (area.size.height / Self::ROW_HEIGHT)
+            .saturating_sub(1)
+            .max(1) as usize;
+        let columns = (2 + INPUT_COUNT).div_ceil(rows);
+        let column_width = (area.size.width / columns as u32) as i32;
+        let checks = [
+            ("Display", self_test::label(self.test.display)),
+            ("Timer", self_test::label(self.test.timer)),
+        ]
+        .into_iter()
+        .map(|(name, result)| (None, name, result))
+        .chain(
+            self.test
+                .channels
+                .iter()
+                .enumerate()
+                .map(|(idx, check)| (Some(idx), "ADC", check.label())),
+        );
+        for (item, (channel, name, result)) in checks.enumerate() {
+            s_buf.clear();
+            match channel {
+                Some(idx) => {
+                    write_truncated(&mut s_buf, format_args!("{}{} {}", name, idx, result))
+                }
+                None => write_truncated(&mut s_buf, format_args!("{} {}", name, result)),
+            }
+            .ok();
+            let position = Point::new(
+                (item / rows) as i32 * column_width,
+                ((item % rows + 1) as u32 * Self::ROW_HEIGHT) as i32,
+            );
+            Text::with_baseline(
+                &s_buf,
+                area.top_left + position,
+                TEXT_STYLE_SMALL,
+                Baseline::Top,
+            )
+            .draw(display)?;
+        }
+        Ok(())
+    }
+}
+
+/// Firmware version and build configuration
+pub struct InfoPage {
+    /// Adds the reason of the latest reset and the count since power-on
+    pub boot: Option<BootInfo>,
+}
+
+impl InfoPage {
+    const LINK: &'static str = if cfg!(feature = "ble") {
+        "BLE"
+    } else if cfg!(feature = "wifi") {
+        "Wi-Fi"
+    } else if cfg!(feature = "espnow-remote") || cfg!(feature = "espnow-dongle") {
+        "ESP-NOW"
+    } else {
+        "USB"
+    };
+}
+
+impl<D: DrawTarget<Color = BinaryColor>> Page<D> for InfoPage {
+    fn draw(&self, display: &mut D, area: Rectangle) -> Result<(), D::Error> {
+        let config: String<24> =
+            format_truncated(format_args!("{} ch, {}", INPUT_COUNT, Self::LINK));
+        let mut reset: String<24> = String::new();
+        let mut count: String<24> = String::new();
+        if let Some(boot) = self.boot {
+            write_truncated(&mut reset, format_args!("Reset: {}", boot.reason.text())).ok();
+            write_truncated(
+                &mut count,
+                format_args!("Resets {} BOD {}", boot.count.resets, boot.count.brown_outs),
+            )
+            .ok();
+        }
+
+        // Lines that do not fit are left out, e.g. on 128x32 displays
+        let rows = (area.size.height / LINE_HEIGHT) as usize;
+        for (row, line) in [VERSION, config.as_str(), reset.as_str(), count.as_str()]
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .take(rows)
+            .enumerate()
+        {
+            Text::with_alignment(
+                line,
+                area.top_left + Point::new(2, (row as u32 * LINE_HEIGHT + LINE_HEIGHT - 2) as i32),
+                TEXT_STYLE,
+                Alignment::Left,
+            )
+            .draw(display)?;
+        }
+        Ok(())
+    }
+}
+
+/// Items of a menu, the selected one is drawn inverted and its value is in brackets while edited.
+/// Scrolls so the selected item is always shown.
+pub struct MenuPage<'a, const N: usize> {
+    pub view: &'a MenuView<N>,
+}
+
+impl<'a, const N: usize, D: DrawTarget<Color = BinaryColor>> Page<D> for MenuPage<'a, N> {
+    fn draw(&self, display: &mut D, area: Rectangle) -> Result<(), D::Error> {
+        let rows = (area.size.height / LINE_HEIGHT).max(1) as usize;
+        let first = (self.view.selected + 1).saturating_sub(rows);
+        let mut value: String<12> = String::new();
+        for (row, (idx, (label, text))) in self
+            .view
+            .rows
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(rows)
+            .enumerate()
+        {
+            let top_left = area.top_left + Point::new(0, (row as u32 * LINE_HEIGHT) as i32);
+            let baseline = LINE_HEIGHT as i32 - 2;
+            let style = if idx == self.view.selected {
+                Rectangle::new(top_left, Size::new(area.size.width, LINE_HEIGHT))
+                    .into_styled(FILL_RECT_STYLE)
+                    .draw(display)?;
+                TEXT_STYLE_INVERTED
+            } else {
+                TEXT_STYLE
+            };
+            Text::new(label, top_left + Point::new(2, baseline), style).draw(display)?;
+
+            value.clear();
+            if idx == self.view.selected && self.view.editing {
+                write_truncated(&mut value, format_args!("<{}>", text))
+            } else {
+                write_truncated(&mut value, format_args!("{}", text))
+            }
+            .ok();
+            Text::with_alignment(
+                &value,
+                top_left + Point::new(area.size.width as i32 - 2, baseline),
+                style,
+                Alignment::Right,
+            )
+            .draw(display)?;
+        }
+        Ok(())
+    }
+}
+
+/// Longest track title or artist kept from [crate::protocol::HostCommand::NowPlaying], in bytes
+pub const TRACK_TEXT_LEN: usize = 40;
+pub type TrackText = String<TRACK_TEXT_LEN>;
+
+/// Track playing on the PC, reported by a host helper
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NowPlaying {
+    pub title: TrackText,
+    pub artist: TrackText,
+}
+
+impl NowPlaying {
+    /// Texts longer than [TRACK_TEXT_LEN] are cut
+    pub fn new(title: &str, artist: &str) -> Self {
+        let truncated = |text: &str| {
+            let mut truncated = TrackText::new();
+            for c in text.trim().chars() {
+                if truncated.push(c).is_err() {
+                    break;
+                }
+            }
+            truncated
+        };
+        Self {
+            title: truncated(title),
+            artist: truncated(artist),
+        }
+    }
+}
+
+/// Title and artist of the track playing on the PC, lines wider than the display scroll
+pub struct NowPlayingPage<'a> {
+    pub track: Option<&'a NowPlaying>,
+    /// Pixels the wide lines have scrolled by, grows with time
+    pub scroll: u32,
+}
+
+impl NowPlayingPage<'_> {
+    const NOTHING_PLAYING: &'static str = "Nothing playing";
+
+    fn lines(&self) -> [(&str, MonoTextStyle<'static, BinaryColor>); 2] {
+        match self.track {
+            Some(track) => [
+                (track.title.as_str(), TEXT_STYLE_BOLD),
+                (track.artist.as_str(), TEXT_STYLE),
+            ],
+            None => [(Self::NOTHING_PLAYING, TEXT_STYLE), ("", TEXT_STYLE)],
+        }
+    }
+
+    /// Whether a line is wider than `width`, so the page changes with [NowPlayingPage::scroll]
+    pub fn scrolls(&self, width: u32) -> bool {
+        self.lines()
+            .iter()
+            .any(|(line, style)| text_width(line, style) > width)
+    }
+}
+
+impl<D: DrawTarget<Color = BinaryColor>> Page<D> for NowPlayingPage<'_> {
+    fn draw(&self, display: &mut D, area: Rectangle) -> Result<(), D::Error> {
+        let mut top = area.top_left.y + 2;
+        for (line, style) in self.lines() {
+            let height = style.font.character_size.height;
+            let mut label = Label::new(
+                line,
+                Rectangle::new(
+                    Point::new(area.top_left.x, top),
+                    Size::new(area.size.width, height),
+                ),
+                style,
+            );
+            label.alignment = Alignment::Center;
+            label.overflow = Overflow::Scroll(self.scroll);
+            label.draw(display)?;
+            top += height as i32 + 2;
+        }
+        Ok(())
+    }
+}
+
+/// Frowny face and as much of the panic message as fits, drawn by the panic handler
+pub struct PanicPage<'a> {
+    pub message: &'a str,
+}
+
+impl PanicPage<'_> {
+    const FACE_DIAMETER: u32 = 20;
+}
+
+impl<'a, D: DrawTarget<Color = BinaryColor>> Page<D> for PanicPage<'a> {
+    fn draw(&self, display: &mut D, area: Rectangle) -> Result<(), D::Error> {
+        let face_top_left = area.top_left
+            + Point::new(
+                2,
+                (area.size.height.saturating_sub(Self::FACE_DIAMETER) / 2) as i32,
+            );
+        let stroke = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+        Circle::new(face_top_left, Self::FACE_DIAMETER)
+            .into_styled(stroke)
+            .draw(display)?;
+        for eye_x in [6, 12] {
+            Rectangle::new(face_top_left + Point::new(eye_x, 6), Size::new(2, 2))
+                .into_styled(FILL_RECT_STYLE)
+                .draw(display)?;
+        }
+        // Upper half of a small circle below the eyes
+        Arc::new(
+            face_top_left + Point::new(5, 12),
+            10,
+            200.0.deg(),
+            140.0.deg(),
+        )
+        .into_styled(stroke)
+        .draw(display)?;
+
+        let text_x = Self::FACE_DIAMETER as i32 + 6;
+        let char_size = TEXT_STYLE_SMALL.font.character_size;
+        let chars_per_line = ((area.size.width as i32 - text_x) / char_size.width as i32).max(1);
+        let rows = (area.size.height / char_size.height) as usize;
+        for (row, line) in self
+            .message
+            .split('\n')
+            .flat_map(|line| wrap(line, chars_per_line as usize))
+            .take(rows)
+            .enumerate()
+        {
+            Text::with_baseline(
+                line,
+                area.top_left + Point::new(text_x, (row as u32 * char_size.height) as i32),
+                TEXT_STYLE_SMALL,
+                Baseline::Top,
+            )
+            .draw(display)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `text` into lines of at most `width` characters
+fn wrap(text: &str, width: usize) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    core::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest
+            .char_indices()
+            .nth(width)
+            .map_or(rest.len(), |(idx, _)| idx);
+        let (line, tail) = rest.split_at(end);
+        rest = tail;
+        Some(line)
+    })
+}
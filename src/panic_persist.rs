@@ -0,0 +1,48 @@
+use core::ptr::addr_of_mut;
+
+use esp_hal::macros::ram;
+
+use crate::{log::info, PanicMessage, PANIC_MESSAGE_LEN};
+
+/// Marks [PersistedPanic] as written. RTC RAM holds garbage after power-on, but keeps its
+/// contents over a software reset.
+const MAGIC: u32 = 0xdee7_dead;
+
+#[repr(C)]
+struct PersistedPanic {
+    magic: u32,
+    len: usize,
+    message: [u8; PANIC_MESSAGE_LEN],
+}
+
+#[ram(rtc_fast, uninitialized)]
+static mut PERSISTED_PANIC: PersistedPanic = PersistedPanic {
+    magic: 0,
+    len: 0,
+    message: [0; PANIC_MESSAGE_LEN],
+};
+
+/// Keeps `message` over the reset after the panic
+pub fn store(message: &str) {
+    let len = message.len().min(PANIC_MESSAGE_LEN);
+    // Only called from the panic handler, nothing else runs at the same time
+    let persisted = unsafe { &mut *addr_of_mut!(PERSISTED_PANIC) };
+    persisted.message[..len].copy_from_slice(&message.as_bytes()[..len]);
+    persisted.len = len;
+    persisted.magic = MAGIC;
+}
+
+/// Message of the panic before the latest reset. Cleared, so it is only reported once.
+pub fn take() -> Option<PanicMessage> {
+    // Only called from init, before the panic handler can run
+    let persisted = unsafe { &mut *addr_of_mut!(PERSISTED_PANIC) };
+    if persisted.magic != MAGIC {
+        return None;
+    }
+    persisted.magic = 0;
+    info!("Previous boot panicked");
+    let len = persisted.len.min(PANIC_MESSAGE_LEN);
+    core::str::from_utf8(&persisted.message[..len])
+        .ok()
+        .and_then(|message| PanicMessage::try_from(message).ok())
+}
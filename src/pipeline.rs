@@ -0,0 +1,243 @@
+//! Steps a reading of a pot goes through on its way to the host. Each channel has its own
+//! [ChannelPipeline] with the [Stage]s of its [ChannelConfig] in order, so a feature is a stage
+//! that can be added, replaced or left out per channel instead of a step every value takes.
+
+use core::mem::discriminant;
+
+use heapless::Vec;
+
+use crate::{
+    fixed::Q16,
+    numerics::{wire_to_percent, WIRE_MAX},
+    sampling::{ChannelConfig, Filter, Snap, Taper},
+    scale_analog_input_to_1023,
+};
+
+/// Kinds of [Stage], a pipeline has each of them once at most
+const STAGE_KINDS: usize = 7;
+
+/// A step of a [ChannelPipeline]. The stages before [Stage::Scale] work on the raw reading
+/// (0-[crate::globals::MAX_ANALOG_VALUE]), the ones after it on the value sent to the host
+/// (0-1023).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    /// Readings below the cutoff are 0, see [ChannelConfig::zero_cutoff]
+    ZeroCutoff(u16),
+    Filter(Filter),
+    /// Raw reading to 0-1023
+    Scale,
+    Taper(Taper),
+    Snap(Snap),
+    /// Changes smaller than this are dropped, see [ChannelConfig::deadband]
+    Deadband(u16),
+    /// Flips the value, for pots mounted the other way around
+    Invert,
+}
+
+/// Output of a [ChannelPipeline]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ChannelValue {
+    /// Sent to the host, 0-1023
+    pub wire: u16,
+    /// Shown on the display, 0-100
+    pub display: u16,
+}
+
+impl ChannelValue {
+    pub fn new(wire: u16) -> Self {
+        Self {
+            wire,
+            display: wire_to_percent(wire),
+        }
+    }
+}
+
+/// Turns the raw readings of a channel into [ChannelValue]s, stage by stage. Keeps the state of
+/// the stages that need one, e.g. the average of [Stage::Filter].
+pub struct ChannelPipeline {
+    stages: Vec<Stage, STAGE_KINDS>,
+    /// [Filter::Ema] value, with the fraction the integer samples lose
+    average: Option<Q16>,
+    /// Previous samples for [Filter::Median3]
+    history: Option<[u16; 2]>,
+    /// Latest value passed on by [Stage::Deadband]
+    deadband_value: Option<u16>,
+    output: Option<ChannelValue>,
+}
+
+impl ChannelPipeline {
+    /// Runs `stages` in order. A stage given twice replaces the earlier one of the same kind.
+    pub fn new(stages: &[Stage]) -> Self {
+        let mut pipeline = Self {
+            stages: Vec::new(),
+            average: None,
+            history: None,
+            deadband_value: None,
+            output: None,
+        };
+        for stage in stages {
+            pipeline.set_stage(*stage);
+        }
+        pipeline
+    }
+
+    /// Same steps as before the stages were configurable: cutoff, filter, scale, taper, snap and
+    /// deadband
+    pub fn from_config(config: &ChannelConfig) -> Self {
+        Self::new(&[
+            Stage::ZeroCutoff(config.zero_cutoff),
+            Stage::Filter(config.filter),
+            Stage::Scale,
+            Stage::Taper(config.taper),
+            Stage::Snap(config.snap),
+            Stage::Deadband(config.deadband),
+        ])
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Replaces the stage of the same kind in place, or adds `stage` at the end. The state of the
+    /// stage starts over if it changed.
+    pub fn set_stage(&mut self, stage: Stage) {
+        match self
+            .stages
+            .iter_mut()
+            .find(|existing| discriminant(*existing) == discriminant(&stage))
+        {
+            Some(existing) if *existing == stage => return,
+            Some(existing) => *existing = stage,
+            // One stage per kind, there is room for all of them
+            None => {
+                self.stages.push(stage).ok();
+            }
+        }
+        self.reset(stage);
+    }
+
+    /// Drops the stage of the same kind as `stage`, whatever its settings
+    pub fn remove_stage(&mut self, stage: Stage) {
+        self.stages
+            .retain(|existing| discriminant(existing) != discriminant(&stage));
+        self.reset(stage);
+    }
+
+    fn reset(&mut self, stage: Stage) {
+        match stage {
+            Stage::Filter(_) => (self.average, self.history) = (None, None),
+            Stage::Deadband(_) => self.deadband_value = None,
+            _ => {}
+        }
+    }
+
+    /// Runs `raw` through every stage
+    pub fn process(&mut self, raw: u16) -> ChannelValue {
+        let mut value = raw;
+        for index in 0..self.stages.len() {
+            value = self.run(self.stages[index], value);
+        }
+        let output = ChannelValue::new(value.min(WIRE_MAX));
+        self.output = Some(output);
+        output
+    }
+
+    /// Latest [ChannelPipeline::process]ed value, `None` before the first one
+    pub fn output(&self) -> Option<ChannelValue> {
+        self.output
+    }
+
+    fn run(&mut self, stage: Stage, value: u16) -> u16 {
+        match stage {
+            Stage::ZeroCutoff(cutoff) if value < cutoff => 0,
+            Stage::ZeroCutoff(_) => value,
+            Stage::Filter(filter) => self.filter(filter, value),
+            Stage::Scale => scale_analog_input_to_1023(value),
+            Stage::Taper(taper) => taper.straighten(value),
+            Stage::Snap(snap) => snap.apply(value),
+            Stage::Deadband(deadband) => self.apply_deadband(deadband, value),
+            Stage::Invert => WIRE_MAX - value.min(WIRE_MAX),
+        }
+    }
+
+    fn filter(&mut self, filter: Filter, raw: u16) -> u16 {
+        match filter {
+            Filter::None => raw,
+            Filter::Ema(shift) => {
+                let sample = Q16::from_int(raw);
+                let average = match self.average {
+                    Some(average) => average.approach(sample, shift),
+                    None => sample,
+                };
+                self.average = Some(average);
+                average.floor() as u16
+            }
+            Filter::Median3 => {
+                let [a, b] = self.history.unwrap_or([raw; 2]);
+                self.history = Some([b, raw]);
+                a.max(b).min(a.min(b).max(raw))
+            }
+        }
+    }
+
+    fn apply_deadband(&mut self, deadband: u16, value: u16) -> u16 {
+        let value = match self.deadband_value {
+            Some(previous)
+                if value.abs_diff(previous) < deadband && value != 0 && value != WIRE_MAX =>
+            {
+                previous
+            }
+            _ => value,
+        };
+        self.deadband_value = Some(value);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::MAX_ANALOG_VALUE;
+
+    #[test]
+    fn stages_run_in_order() {
+        let mut pipeline = ChannelPipeline::from_config(&ChannelConfig::DEFAULT);
+        assert_eq!(pipeline.output(), None);
+        assert_eq!(pipeline.process(MAX_ANALOG_VALUE).wire, 1023);
+        assert_eq!(pipeline.process(0), ChannelValue::default());
+
+        // Inverted after the snap, the snap zone at the start of the travel reads 1023
+        pipeline.set_stage(Stage::Snap(Snap { low: 10, high: 100 }));
+        pipeline.set_stage(Stage::Invert);
+        assert_eq!(pipeline.stages().last(), Some(&Stage::Invert));
+        let value = pipeline.process(MAX_ANALOG_VALUE / 20);
+        assert_eq!(value, ChannelValue::new(1023));
+        assert_eq!(value.display, 100);
+
+        pipeline.remove_stage(Stage::Invert);
+        assert_eq!(pipeline.process(MAX_ANALOG_VALUE / 20).wire, 0);
+        assert_eq!(pipeline.stages().len(), 6);
+    }
+
+    #[test]
+    fn changed_stages_start_over() {
+        let mut pipeline =
+            ChannelPipeline::new(&[Stage::Deadband(50), Stage::Filter(Filter::None)]);
+        assert_eq!(
+            pipeline.stages(),
+            [Stage::Deadband(50), Stage::Filter(Filter::None)]
+        );
+        assert_eq!(pipeline.process(500).wire, 500);
+        assert_eq!(pipeline.process(520).wire, 500);
+        // Same settings keep the state
+        pipeline.set_stage(Stage::Deadband(50));
+        assert_eq!(pipeline.process(530).wire, 500);
+        pipeline.set_stage(Stage::Deadband(10));
+        assert_eq!(pipeline.stages()[0], Stage::Deadband(10));
+        assert_eq!(pipeline.process(530).wire, 530);
+
+        pipeline.set_stage(Stage::Filter(Filter::Ema(1)));
+        assert_eq!(pipeline.process(800).wire, 800);
+        assert_eq!(pipeline.process(600).wire, 700);
+    }
+}
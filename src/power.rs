@@ -0,0 +1,42 @@
+use core::time::Duration;
+
+use esp_hal::{
+    prelude::*,
+    rtc_cntl::{sleep::TimerWakeupSource, Rtc},
+    Delay,
+};
+
+use crate::{
+    globals::{INPUT_COUNT, SLEEP_AFTER, SLEEP_WAKE_PERIOD},
+    motion::MotionDetector,
+};
+
+/// Samples at [MotionDetector::sample_period] while the pots are moved and drops into light sleep
+/// between samples once nothing has changed for [SLEEP_AFTER].
+///
+/// Only the timer wakes the chip. The pots can not trigger a wake-up, so movement is noticed on
+/// the next wake-up after at most [SLEEP_WAKE_PERIOD].
+pub struct PowerManager {
+    rtc: Rtc<'static>,
+    motion: MotionDetector,
+}
+
+impl PowerManager {
+    pub fn new(rtc: Rtc<'static>) -> Self {
+        Self {
+            rtc,
+            motion: MotionDetector::new(),
+        }
+    }
+
+    /// Call after every sample with the values in range 0-1023, returns when the next sample is due
+    pub fn wait(&mut self, values: &[u16; INPUT_COUNT], now_ms: u64, delay: &mut Delay) {
+        self.motion.update(values, now_ms);
+        if self.motion.still_for(now_ms) < SLEEP_AFTER {
+            delay.delay_ms(self.motion.sample_period(now_ms));
+            return;
+        }
+        let timer = TimerWakeupSource::new(Duration::from_millis(SLEEP_WAKE_PERIOD));
+        self.rtc.sleep_light(&[&timer], delay);
+    }
+}
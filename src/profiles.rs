@@ -0,0 +1,181 @@
+//! Sets of channel mappings cycled with the profile button, e.g. one for work and one for games.
+//! The index of the active profile is sent last in every frame, so the host can switch its
+//! mappings along with it.
+
+use crate::{
+    assets::Icon,
+    globals::INPUT_COUNT,
+    protocol::ProtocolMode,
+    sampling::{ChannelConfig, Taper},
+};
+
+/// How the pots are mapped to the channels of the frame while the profile is active
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Profile {
+    /// Shown as the title of the display
+    pub name: &'static str,
+    /// Pot sent in each channel of the frame
+    pub order: [usize; INPUT_COUNT],
+    /// Shown left of the bar of each channel, `None` shows the index
+    pub icons: [Option<Icon>; INPUT_COUNT],
+    /// Taper of each pot, `None` keeps the one of its [ChannelConfig]. Not used with `oversampling`
+    pub tapers: [Option<Taper>; INPUT_COUNT],
+    /// Channels that are sent as 0 whatever their pot is at
+    pub muted: [bool; INPUT_COUNT],
+    /// Send MIDI Control Changes instead of deej frames, e.g. for a DAW
+    pub midi: bool,
+}
+
+impl Profile {
+    /// Pots in their own channels, nothing muted
+    pub const fn named(name: &'static str) -> Self {
+        let mut order = [0; INPUT_COUNT];
+        let mut idx = 0;
+        while idx < INPUT_COUNT {
+            order[idx] = idx;
+            idx += 1;
+        }
+        Self {
+            name,
+            order,
+            icons: [None; INPUT_COUNT],
+            tapers: [None; INPUT_COUNT],
+            muted: [false; INPUT_COUNT],
+            midi: false,
+        }
+    }
+
+    /// Sends `pot` in `channel`
+    pub const fn with_pot(mut self, channel: usize, pot: usize) -> Self {
+        self.order[channel] = pot;
+        self
+    }
+
+    pub const fn with_icon(mut self, channel: usize, icon: Icon) -> Self {
+        self.icons[channel] = Some(icon);
+        self
+    }
+
+    pub const fn with_taper(mut self, pot: usize, taper: Taper) -> Self {
+        self.tapers[pot] = Some(taper);
+        self
+    }
+
+    pub const fn with_muted(mut self, channel: usize) -> Self {
+        self.muted[channel] = true;
+        self
+    }
+
+    pub const fn with_midi(mut self) -> Self {
+        self.midi = true;
+        self
+    }
+
+    /// Mode the frames are sent in while the profile is active. Selecting the profile replaces the
+    /// mode set with the `MODE` command
+    pub fn protocol_mode(&self) -> ProtocolMode {
+        if self.midi {
+            ProtocolMode::Midi
+        } else {
+            ProtocolMode::default()
+        }
+    }
+
+    /// Taper of each pot, for [crate::sampling::Sampler::set_tapers]
+    pub fn tapers(&self, configs: &[ChannelConfig; INPUT_COUNT]) -> [Taper; INPUT_COUNT] {
+        core::array::from_fn(|pot| self.tapers[pot].unwrap_or(configs[pot].taper))
+    }
+
+    /// Puts the state of each pot in its channel, e.g. the disconnected flags
+    pub fn remap<T: Copy>(&self, pots: &[T; INPUT_COUNT]) -> [T; INPUT_COUNT] {
+        self.order.map(|pot| pots[pot])
+    }
+
+    /// Channel values (0-1023) for the sampled pot `values`, muted channels are 0
+    pub fn apply(&self, values: &[u16; INPUT_COUNT]) -> [u16; INPUT_COUNT] {
+        let mut values = self.remap(values);
+        for (value, muted) in values.iter_mut().zip(self.muted) {
+            if muted {
+                *value = 0;
+            }
+        }
+        values
+    }
+}
+
+/// The active one of the profiles, the first is active at boot
+pub struct Profiles {
+    profiles: &'static [Profile],
+    active: usize,
+}
+
+impl Profiles {
+    pub fn new(profiles: &'static [Profile]) -> Self {
+        assert!(!profiles.is_empty(), "No profiles");
+        Self {
+            profiles,
+            active: 0,
+        }
+    }
+
+    pub fn active(&self) -> &'static Profile {
+        &self.profiles[self.active]
+    }
+
+    /// Index of the active profile, sent last in every frame
+    pub fn index(&self) -> u16 {
+        self.active as u16
+    }
+
+    /// `outputs` with the [Profiles::index] in the last value, which
+    /// [crate::globals::OUTPUT_COUNT] keeps for it with `profiles`
+    #[cfg(feature = "profiles")]
+    pub fn with_index<const N: usize>(&self, mut outputs: [u16; N]) -> [u16; N] {
+        outputs[N - 1] = self.index();
+        outputs
+    }
+
+    /// Switches to the next profile, wrapping around after the last one
+    pub fn cycle(&mut self) -> &'static Profile {
+        self.active = (self.active + 1) % self.profiles.len();
+        self.active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_remaps_and_mutes_channels() {
+        static PROFILES: [Profile; 2] = [
+            Profile::named("Work"),
+            Profile::named("Game")
+                .with_pot(0, 1)
+                .with_pot(1, 0)
+                .with_muted(2)
+                .with_taper(3, Taper::Audio)
+                .with_midi(),
+        ];
+        let mut profiles = Profiles::new(&PROFILES);
+        let values = [100, 200, 300, 400];
+        assert_eq!(profiles.active().apply(&values), values);
+        assert_eq!(profiles.active().protocol_mode(), ProtocolMode::default());
+
+        let game = profiles.cycle();
+        assert_eq!(profiles.index(), 1);
+        assert_eq!(game.apply(&values), [200, 100, 0, 400]);
+        assert_eq!(game.protocol_mode(), ProtocolMode::Midi);
+        assert_eq!(
+            game.remap(&[true, false, false, false]),
+            [false, true, false, false]
+        );
+        assert_eq!(
+            game.tapers(&[ChannelConfig::DEFAULT; INPUT_COUNT]),
+            [Taper::Linear, Taper::Linear, Taper::Linear, Taper::Audio]
+        );
+
+        assert_eq!(profiles.cycle().name, "Work");
+        assert_eq!(profiles.index(), 0);
+    }
+}
@@ -0,0 +1,656 @@
+use core::fmt::{self, Display, Write};
+use heapless::String;
+
+use crate::{
+    assets::IconBitmap,
+    globals::{INPUT_COUNT, OUTPUT_COUNT},
+    log::{debug, info, trace},
+    midi::{self, MidiMessages},
+    pages::{ChannelName, NowPlaying},
+    reset::{BootInfo, ResetReason},
+    self_test::{self, SelfTest},
+    settings::DisplayTimeout,
+    text::{end_line, write_truncated},
+    units::Units,
+    PANIC_MESSAGE_LEN,
+};
+
+/// First byte of every framed message so the host can resynchronize after a corrupted frame
+pub const FRAME_START: u8 = b'>';
+/// Separates the values from the checksum in a framed message
+pub const FRAME_CRC_SEPARATOR: u8 = b'*';
+/// CRC-8/SMBUS polynomial
+pub const CRC8_POLY: u8 = 0x07;
+
+/// Header of every binary frame. Either byte can be the low byte of a value, but a 10 bit value has
+/// a high byte of at most 0x03, so the pair never starts at a value boundary. A receiver that
+/// resyncs takes the pair as a header only when a whole [BINARY_FRAME_LEN] frame follows it.
+pub const BINARY_SYNC: [u8; 2] = [0xDE, 0xE7];
+/// Sync header followed by OUTPUT_COUNT little-endian u16 values
+pub const BINARY_FRAME_LEN: usize = BINARY_SYNC.len() + OUTPUT_COUNT * 2;
+
+/// Version of the serial protocol reported in the handshake. Bump when the wire format changes
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Capability flags reported in the handshake
+///
+/// Buttons change what is sent: the profile button with `profiles` or the mute buttons with
+/// `expander`
+pub const CAP_BUTTONS: u8 = 1 << 0;
+/// The volumes are shown on a display, with `display`
+pub const CAP_DISPLAY: u8 = 1 << 1;
+/// Channels are muted here and sent as 0, by the mute buttons of the `expander`
+pub const CAP_MUTE: u8 = 1 << 2;
+/// The display draws the icons the host sends with [HostCommand::Icon], with `display`
+pub const CAP_ICONS: u8 = 1 << 3;
+/// The last value of every frame is the index of the active profile
+pub const CAP_PROFILES: u8 = 1 << 4;
+/// The host can report the actual volumes with [HostCommand::HostVolumes]
+pub const CAP_HOST_VOLUMES: u8 = 1 << 5;
+/// The host can report the muted sessions with [HostCommand::HostMutes]
+pub const CAP_HOST_MUTES: u8 = 1 << 6;
+/// The host can stream the audio levels with [HostCommand::Levels]
+pub const CAP_LEVELS: u8 = 1 << 7;
+/// Capabilities of this firmware build
+pub const CAPABILITIES: u8 = (if cfg!(feature = "display") {
+    CAP_DISPLAY | CAP_ICONS
+} else {
+    0
+}) | CAP_HOST_VOLUMES
+    | CAP_HOST_MUTES
+    | CAP_LEVELS
+    | (cfg!(any(feature = "profiles", feature = "expander")) as u8 * CAP_BUTTONS)
+    | (cfg!(feature = "expander") as u8 * CAP_MUTE)
+    | (cfg!(feature = "profiles") as u8 * CAP_PROFILES);
+
+/// Digits of the largest u16
+const VALUE_DIGITS: usize = 5;
+/// Room for OUTPUT_COUNT values of any u16 with their separators, the framing and the line ending,
+/// so a frame is never cut
+pub type FrameBuffer = String<{ OUTPUT_COUNT * (VALUE_DIGITS + 1) + 6 }>;
+pub type BinaryFrame = [u8; BINARY_FRAME_LEN];
+
+/// Format used when sending values to the host.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProtocolMode {
+    /// Plain deej format `a|b|c|d`
+    Plain,
+    /// `>a|b|c|d*XX` where XX is the CRC8 of `a|b|c|d` in hex
+    Framed,
+    /// [BINARY_SYNC] followed by the values as little-endian u16
+    Binary,
+    /// A MIDI Control Change message per channel, see [crate::midi]
+    Midi,
+}
+
+impl Default for ProtocolMode {
+    fn default() -> Self {
+        if cfg!(feature = "midi-protocol") {
+            ProtocolMode::Midi
+        } else if cfg!(feature = "binary-protocol") {
+            ProtocolMode::Binary
+        } else if cfg!(feature = "framed-protocol") {
+            ProtocolMode::Framed
+        } else {
+            ProtocolMode::Plain
+        }
+    }
+}
+
+/// Commands the host can send, one per line.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HostCommand {
+    /// `HELLO`, answered with [encode_hello]
+    Hello,
+    /// `MODE PLAIN`, `MODE FRAMED`, `MODE BINARY` or `MODE MIDI`
+    SetMode(ProtocolMode),
+    /// `OTA`, start a firmware update over Wi-Fi
+    Ota,
+    /// `DFU`, reset into the ROM download mode so the host can flash the firmware over the same
+    /// port without the BOOT button. Ignored on the ESP32, it only enters it with GPIO0 held low.
+    Bootloader,
+    /// `ICON <channel> <bitmap>` where bitmap is an [IconBitmap] in hex, e.g. `ICON 1 3C5AFF9999FF5A3C`.
+    /// Without the bitmap the icon is cleared and the channel index is shown again.
+    Icon(usize, Option<IconBitmap>),
+    /// `NAME <channel> <name>`, e.g. `NAME 0 Spotify`, shown when the channel is zoomed. Without
+    /// the name the channel index is shown again. Names longer than
+    /// [crate::pages::CHANNEL_NAME_LEN] are cut.
+    Name(usize, Option<ChannelName>),
+    /// `UNITS PERCENT` or `UNITS DB`, how the values are shown on the display
+    SetUnits(Units),
+    /// `VOLUMES a|b|c|d`, the actual volume (0-100) of the sessions mapped to each channel.
+    /// Channels without a session are `-`, e.g. `VOLUMES 50|-|100|0`.
+    HostVolumes([Option<u16>; INPUT_COUNT]),
+    /// `MUTE a|b|c|d`, 1 for each channel whose sessions are muted on the PC, e.g. `MUTE 0|1|0|0`
+    HostMutes([bool; INPUT_COUNT]),
+    /// `LEVELS a|b|c|d`, the peak audio level (0-100) of the sessions mapped to each channel since
+    /// the previous report. Streamed at about 10 Hz, e.g. `LEVELS 12|0|87|40`.
+    Levels([u16; INPUT_COUNT]),
+    /// `PLAYING <title>|<artist>`, the track playing on the PC. Without the text nothing is playing.
+    NowPlaying(Option<NowPlaying>),
+    /// `TIMEOUT <seconds>`, `TIMEOUT NEVER` or `TIMEOUT OFF`, how long the display stays on without
+    /// changes. Kept over resets with the settings menu.
+    DisplayTimeout(DisplayTimeout),
+    /// `FADERS ON` or `FADERS OFF`, whether the motorized faders move to the volumes of
+    /// [HostCommand::HostVolumes]. On at boot, ignored without motors.
+    Faders(bool),
+}
+
+pub fn parse_command(line: &str) -> Option<HostCommand> {
+    let command = parse_words(line);
+    match &command {
+        // Too frequent for the debug log
+        Some(command @ HostCommand::Levels(_)) => trace!("Host command {}", command),
+        Some(command) => debug!("Host command {}", command),
+        None => info!("Unknown host command {}", line),
+    }
+    command
+}
+
+fn parse_words(line: &str) -> Option<HostCommand> {
+    // Title and artist have spaces of their own
+    match line.split_once(' ') {
+        Some(("PLAYING", track)) => return Some(HostCommand::NowPlaying(parse_now_playing(track))),
+        None if line == "PLAYING" => return Some(HostCommand::NowPlaying(None)),
+        Some(("NAME", name)) => return parse_name(name),
+        _ => (),
+    }
+    let mut words = line.split_ascii_whitespace();
+    let command = match (words.next()?, words.next()) {
+        ("HELLO", None) => HostCommand::Hello,
+        ("OTA", None) => HostCommand::Ota,
+        ("DFU", None) => HostCommand::Bootloader,
+        ("MODE", Some("PLAIN")) => HostCommand::SetMode(ProtocolMode::Plain),
+        ("MODE", Some("FRAMED")) => HostCommand::SetMode(ProtocolMode::Framed),
+        ("MODE", Some("BINARY")) => HostCommand::SetMode(ProtocolMode::Binary),
+        ("MODE", Some("MIDI")) => HostCommand::SetMode(ProtocolMode::Midi),
+        ("UNITS", Some("PERCENT")) => HostCommand::SetUnits(Units::Percent),
+        ("UNITS", Some("DB")) => HostCommand::SetUnits(Units::Decibel),
+        ("VOLUMES", Some(list)) => HostCommand::HostVolumes(parse_host_volumes(list)?),
+        ("MUTE", Some(list)) => HostCommand::HostMutes(parse_host_mutes(list)?),
+        ("LEVELS", Some(list)) => HostCommand::Levels(parse_levels(list)?),
+        ("FADERS", Some("ON")) => HostCommand::Faders(true),
+        ("FADERS", Some("OFF")) => HostCommand::Faders(false),
+        ("TIMEOUT", Some(timeout)) => HostCommand::DisplayTimeout(DisplayTimeout::parse(timeout)?),
+        ("ICON", Some(channel)) => {
+            let channel = channel.parse().ok().filter(|c| *c < INPUT_COUNT)?;
+            let bitmap = match words.next() {
+                Some(hex) => Some(parse_icon_bitmap(hex)?),
+                None => None,
+            };
+            HostCommand::Icon(channel, bitmap)
+        }
+        _ => return None,
+    };
+    if words.next().is_some() {
+        return None;
+    }
+    Some(command)
+}
+
+fn parse_host_volumes(list: &str) -> Option<[Option<u16>; INPUT_COUNT]> {
+    let mut volumes = [None; INPUT_COUNT];
+    let mut items = list.split('|');
+    for volume in volumes.iter_mut() {
+        *volume = match items.next()? {
+            "-" => None,
+            item => Some(item.parse().ok().filter(|volume| *volume <= 100)?),
+        };
+    }
+    items.next().is_none().then_some(volumes)
+}
+
+fn parse_levels(list: &str) -> Option<[u16; INPUT_COUNT]> {
+    let mut levels = [0; INPUT_COUNT];
+    let mut items = list.split('|');
+    for level in levels.iter_mut() {
+        *level = items.next()?.parse().ok().filter(|level| *level <= 100)?;
+    }
+    items.next().is_none().then_some(levels)
+}
+
+fn parse_now_playing(track: &str) -> Option<NowPlaying> {
+    let (title, artist) = track.split_once('|').unwrap_or((track, ""));
+    let track = NowPlaying::new(title, artist);
+    (!track.title.is_empty() || !track.artist.is_empty()).then_some(track)
+}
+
+/// `<channel> <name>`, the name has spaces of its own
+fn parse_name(text: &str) -> Option<HostCommand> {
+    let text = text.trim();
+    let (channel, name) = text.split_once(' ').unwrap_or((text, ""));
+    let channel = channel.parse().ok().filter(|c| *c < INPUT_COUNT)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Some(HostCommand::Name(channel, None));
+    }
+    let mut truncated = ChannelName::new();
+    for c in name.chars() {
+        if truncated.push(c).is_err() {
+            break;
+        }
+    }
+    Some(HostCommand::Name(channel, Some(truncated)))
+}
+
+fn parse_host_mutes(list: &str) -> Option<[bool; INPUT_COUNT]> {
+    let mut mutes = [false; INPUT_COUNT];
+    let mut items = list.split('|');
+    for muted in mutes.iter_mut() {
+        *muted = match items.next()? {
+            "0" => false,
+            "1" => true,
+            _ => return None,
+        };
+    }
+    items.next().is_none().then_some(mutes)
+}
+
+fn parse_icon_bitmap(hex: &str) -> Option<IconBitmap> {
+    let mut bitmap = IconBitmap::default();
+    if hex.len() != bitmap.len() * 2 {
+        return None;
+    }
+    let mut digits = hex.chars().map(|c| c.to_digit(16));
+    for byte in bitmap.iter_mut() {
+        *byte = (digits.next()?? << 4 | digits.next()??) as u8;
+    }
+    Some(bitmap)
+}
+
+/// Text with its line breaks replaced by spaces
+struct OneLine<'a>(&'a str);
+
+impl Display for OneLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            f.write_char(if c == '\r' || c == '\n' { ' ' } else { c })?;
+        }
+        Ok(())
+    }
+}
+
+/// Sent once at boot when the previous boot ended in a panic: `PANIC <message>\r\n`.
+/// Line breaks in the message are replaced with spaces, one longer than [PANIC_MESSAGE_LEN] is
+/// cut.
+pub fn encode_panic(message: &str) -> String<{ PANIC_MESSAGE_LEN + 8 }> {
+    let mut buf = String::new();
+    write_truncated(&mut buf, format_args!("PANIC {}", OneLine(message))).ok();
+    end_line(&mut buf);
+    buf
+}
+
+/// Sent once at boot: `RESET <reason> RESETS:<count> BROWNOUTS:<count>\r\n`, where the counts are
+/// since power-on. The reason is a [ResetReason::label], followed by the watchdog or the code of
+/// the ROM, e.g. `WATCHDOG:TASK` or `OTHER:21`.
+pub fn encode_reset(boot: &BootInfo) -> String<64> {
+    let mut buf = String::new();
+    write_truncated(&mut buf, format_args!("RESET {}", boot.reason.label())).ok();
+    match boot.reason {
+        ResetReason::Watchdog(watchdog) => {
+            write_truncated(&mut buf, format_args!(":{}", watchdog.label()))
+        }
+        ResetReason::Other(code) => write_truncated(&mut buf, format_args!(":{}", code)),
+        _ => Ok(()),
+    }
+    .ok();
+    write_truncated(
+        &mut buf,
+        format_args!(
+            " RESETS:{} BROWNOUTS:{}",
+            boot.count.resets, boot.count.brown_outs
+        ),
+    )
+    .ok();
+    end_line(&mut buf);
+    buf
+}
+
+/// Result of the boot self-test, sent once at boot:
+/// `SELFTEST <PASS|FAIL> DISPLAY:<OK|FAIL> TIMER:<OK|FAIL> ADC0:<OK|NOADC|RAIL> ...\r\n`
+pub fn encode_self_test(test: &SelfTest) -> String<{ 40 + 12 * INPUT_COUNT }> {
+    let mut buf = String::new();
+    write_truncated(
+        &mut buf,
+        format_args!(
+            "SELFTEST {} DISPLAY:{} TIMER:{}",
+            if test.passed() { "PASS" } else { "FAIL" },
+            self_test::label(test.display),
+            self_test::label(test.timer)
+        ),
+    )
+    .ok();
+    for (idx, check) in test.channels.iter().enumerate() {
+        write_truncated(&mut buf, format_args!(" ADC{}:{}", idx, check.label())).ok();
+    }
+    end_line(&mut buf);
+    buf
+}
+
+/// Sent when a transfer to the display failed and again once it answers:
+/// `DISPLAY <OFFLINE|ONLINE>\r\n`. The values are sent on in between.
+pub fn encode_display(online: bool) -> &'static str {
+    if online {
+        "DISPLAY ONLINE\r\n"
+    } else {
+        "DISPLAY OFFLINE\r\n"
+    }
+}
+
+/// Reply to [HostCommand::Hello]: `HELLO <protocol version> <value count> <capability flags in hex>\r\n`
+pub fn encode_hello(capabilities: u8) -> String<32> {
+    let mut buf = String::new();
+    write_truncated(
+        &mut buf,
+        format_args!(
+            "HELLO {} {} {:02X}",
+            PROTOCOL_VERSION, OUTPUT_COUNT, capabilities
+        ),
+    )
+    .ok();
+    end_line(&mut buf);
+    buf
+}
+
+/// Complete message ready to be written to the serial port
+pub enum Frame {
+    Text(FrameBuffer),
+    Binary(BinaryFrame),
+    Midi(MidiMessages),
+}
+
+impl Frame {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Frame::Text(s) => s.as_bytes(),
+            Frame::Binary(b) => b,
+            Frame::Midi(m) => m,
+        }
+    }
+}
+
+/// Encodes `values` in the given `mode`. Text frames include the `\r\n` line ending.
+pub fn encode(mode: ProtocolMode, values: &[u16; OUTPUT_COUNT]) -> Frame {
+    trace!("{} frame {}", mode, values);
+    let mut buf = FrameBuffer::new();
+    match mode {
+        ProtocolMode::Plain => encode_plain(values, &mut buf),
+        ProtocolMode::Framed => encode_framed(values, &mut buf),
+        ProtocolMode::Binary => return Frame::Binary(encode_binary(values)),
+        ProtocolMode::Midi => return Frame::Midi(midi::encode(values)),
+    }
+    end_line(&mut buf);
+    Frame::Text(buf)
+}
+
+pub fn encode_plain(values: &[u16; OUTPUT_COUNT], buf: &mut FrameBuffer) {
+    buf.clear();
+    for (idx, val) in values.iter().enumerate() {
+        let separator = if idx > 0 { "|" } else { "" };
+        // Always fits, see FrameBuffer
+        write_truncated(buf, format_args!("{}{}", separator, val)).ok();
+    }
+}
+
+pub fn encode_framed(values: &[u16; OUTPUT_COUNT], buf: &mut FrameBuffer) {
+    let mut payload = FrameBuffer::new();
+    encode_plain(values, &mut payload);
+
+    buf.clear();
+    write_truncated(
+        buf,
+        format_args!(
+            "{}{}{}{:02X}",
+            FRAME_START as char,
+            payload,
+            FRAME_CRC_SEPARATOR as char,
+            crc8(payload.as_bytes())
+        ),
+    )
+    .ok();
+}
+
+pub fn encode_binary(values: &[u16; OUTPUT_COUNT]) -> BinaryFrame {
+    let mut frame = [0u8; BINARY_FRAME_LEN];
+    frame[..BINARY_SYNC.len()].copy_from_slice(&BINARY_SYNC);
+    for (chunk, val) in frame[BINARY_SYNC.len()..]
+        .chunks_exact_mut(2)
+        .zip(values.iter())
+    {
+        chunk.copy_from_slice(&val.to_le_bytes());
+    }
+    frame
+}
+
+/// Returns the values of a binary frame or `None` if `frame` does not start with [BINARY_SYNC] or has wrong length
+pub fn decode_binary(frame: &[u8]) -> Option<[u16; OUTPUT_COUNT]> {
+    if frame.len() != BINARY_FRAME_LEN || frame[..BINARY_SYNC.len()] != BINARY_SYNC {
+        return None;
+    }
+    let mut values = [0u16; OUTPUT_COUNT];
+    for (val, chunk) in values
+        .iter_mut()
+        .zip(frame[BINARY_SYNC.len()..].chunks_exact(2))
+    {
+        *val = u16::from_le_bytes([chunk[0], chunk[1]]);
+    }
+    Some(values)
+}
+
+/// Returns the values of a framed message without its line ending or `None` if the framing, the
+/// values or the CRC8 do not match
+pub fn decode_framed(line: &str) -> Option<[u16; OUTPUT_COUNT]> {
+    let (payload, crc) = line
+        .strip_prefix(FRAME_START as char)?
+        .split_once(FRAME_CRC_SEPARATOR as char)?;
+    if crc.len() != 2 || u8::from_str_radix(crc, 16).ok()? != crc8(payload.as_bytes()) {
+        return None;
+    }
+    let mut values = [0u16; OUTPUT_COUNT];
+    let mut parts = payload.split('|');
+    for val in values.iter_mut() {
+        *val = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(values)
+}
+
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ CRC8_POLY
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `pattern` repeated over every value of the frame, whatever [OUTPUT_COUNT] the features give
+    fn frame_values(pattern: [u16; 4]) -> [u16; OUTPUT_COUNT] {
+        core::array::from_fn(|idx| pattern[idx % pattern.len()])
+    }
+
+    /// `values` as they are written in a plain frame
+    fn joined(values: &[u16]) -> std::string::String {
+        values
+            .iter()
+            .map(u16::to_string)
+            .collect::<std::vec::Vec<_>>()
+            .join("|")
+    }
+
+    #[test]
+    fn plain_frame() {
+        let values = frame_values([0, 12, 512, 1023]);
+        let frame = encode(ProtocolMode::Plain, &values);
+        assert!(frame.as_bytes().starts_with(b"0|12|512|1023"));
+        assert_eq!(
+            frame.as_bytes(),
+            format!("{}\r\n", joined(&values)).as_bytes()
+        );
+    }
+
+    #[test]
+    fn framed_crc_matches_payload() {
+        let values = frame_values([1, 2, 3, 4]);
+        let frame = encode(ProtocolMode::Framed, &values);
+        let payload = joined(&values);
+        let expected = format!("{:02X}", crc8(payload.as_bytes()));
+        assert_eq!(
+            frame.as_bytes(),
+            format!(">{}*{}\r\n", payload, expected).as_bytes()
+        );
+    }
+
+    #[cfg(feature = "profiles")]
+    #[test]
+    fn profile_index_ends_the_frame() {
+        use crate::{globals::PROFILES, profiles::Profiles};
+
+        let mut profiles = Profiles::new(PROFILES);
+        profiles.cycle();
+        let values = profiles.with_index(frame_values([512; 4]));
+        let frame = encode(ProtocolMode::Plain, &values);
+        assert!(frame.as_bytes().ends_with(b"|512|1\r\n"));
+        assert_eq!(decode_binary(&encode_binary(&values)), Some(values));
+    }
+
+    #[test]
+    fn framed_round_trip() {
+        let values = frame_values([0, 7, 512, 1023]);
+        let mut buf = FrameBuffer::new();
+        encode_framed(&values, &mut buf);
+        assert_eq!(decode_framed(&buf), Some(values));
+
+        // A flipped digit no longer matches the checksum
+        let corrupted = buf.replacen("512", "513", 1);
+        assert_eq!(decode_framed(&corrupted), None);
+        assert_eq!(decode_framed(buf.trim_start_matches('>')), None);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let values = frame_values([0, 1, 513, 1023]);
+        assert_eq!(decode_binary(&encode_binary(&values)), Some(values));
+        assert_eq!(decode_binary(&[0; BINARY_FRAME_LEN]), None);
+    }
+
+    #[test]
+    fn self_test_report() {
+        use crate::{
+            globals::RAIL_VALUE,
+            sampling::{AdcError, Reading},
+        };
+
+        let mut readings = [Ok(Reading::steady(100)); INPUT_COUNT];
+        let test = SelfTest::new(true, &readings, true);
+        assert!(encode_self_test(&test).starts_with("SELFTEST PASS DISPLAY:OK TIMER:OK ADC0:OK"));
+
+        readings[1] = Ok(Reading::steady(RAIL_VALUE));
+        readings[2] = Err(AdcError);
+        let report = encode_self_test(&SelfTest::new(true, &readings, true));
+        assert!(
+            report.starts_with("SELFTEST FAIL DISPLAY:OK TIMER:OK ADC0:OK ADC1:RAIL ADC2:NOADC")
+        );
+        assert!(report.ends_with("\r\n"));
+    }
+
+    #[test]
+    fn crc8_check_value() {
+        // CRC-8/SMBUS check value
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc8(b""), 0);
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse_command("HELLO"), Some(HostCommand::Hello));
+        assert_eq!(parse_command("DFU"), Some(HostCommand::Bootloader));
+        assert_eq!(parse_command("DFU NOW"), None);
+        assert_eq!(
+            parse_command("MODE BINARY"),
+            Some(HostCommand::SetMode(ProtocolMode::Binary))
+        );
+        assert_eq!(
+            parse_command("MODE MIDI"),
+            Some(HostCommand::SetMode(ProtocolMode::Midi))
+        );
+        assert_eq!(parse_command("ICON 1"), Some(HostCommand::Icon(1, None)));
+        assert_eq!(
+            parse_command("ICON 1 3C5AFF9999FF5A3C").map(|_| ()),
+            Some(())
+        );
+        assert_eq!(parse_command("ICON 1 3C5A"), None);
+        assert_eq!(
+            parse_command("NAME 2 Discord calls and more"),
+            Some(HostCommand::Name(
+                2,
+                Some("Discord call".try_into().unwrap())
+            ))
+        );
+        assert_eq!(parse_command("NAME 2"), Some(HostCommand::Name(2, None)));
+        assert_eq!(parse_command("NAME 9 Game"), None);
+        assert_eq!(
+            parse_command("VOLUMES 50|-|100|0"),
+            Some(HostCommand::HostVolumes([
+                Some(50),
+                None,
+                Some(100),
+                Some(0)
+            ]))
+        );
+        assert_eq!(parse_command("VOLUMES 50|-|100"), None);
+        assert_eq!(parse_command("VOLUMES 50|-|101|0"), None);
+        assert_eq!(
+            parse_command("MUTE 0|1|0|0"),
+            Some(HostCommand::HostMutes([false, true, false, false]))
+        );
+        assert_eq!(parse_command("MUTE 0|2|0|0"), None);
+        assert_eq!(
+            parse_command("LEVELS 12|0|87|40"),
+            Some(HostCommand::Levels([12, 0, 87, 40]))
+        );
+        assert_eq!(parse_command("LEVELS 12|-|87|40"), None);
+        assert_eq!(
+            parse_command("PLAYING Song 2|Blur"),
+            Some(HostCommand::NowPlaying(Some(NowPlaying::new(
+                "Song 2", "Blur"
+            ))))
+        );
+        assert_eq!(
+            parse_command("PLAYING  |  "),
+            Some(HostCommand::NowPlaying(None))
+        );
+        assert_eq!(
+            parse_command("PLAYING"),
+            Some(HostCommand::NowPlaying(None))
+        );
+        assert_eq!(
+            parse_command("TIMEOUT 30"),
+            Some(HostCommand::DisplayTimeout(DisplayTimeout::After(30)))
+        );
+        assert_eq!(
+            parse_command("TIMEOUT NEVER"),
+            Some(HostCommand::DisplayTimeout(DisplayTimeout::Never))
+        );
+        assert_eq!(
+            parse_command("TIMEOUT OFF"),
+            Some(HostCommand::DisplayTimeout(DisplayTimeout::AlwaysOff))
+        );
+        assert_eq!(parse_command("TIMEOUT 0"), None);
+        assert_eq!(
+            parse_command("FADERS OFF"),
+            Some(HostCommand::Faders(false))
+        );
+        assert_eq!(parse_command("FADERS"), None);
+        assert_eq!(parse_command("HELLO THERE"), None);
+        assert_eq!(parse_command("MODE"), None);
+    }
+}
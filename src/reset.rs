@@ -0,0 +1,186 @@
+//! Why the chip reset and how often it has since power-on, read once at boot. Sent to the host with
+//! [crate::protocol::encode_reset] and shown on [crate::pages::Screen::Info], crashes, watchdog
+//! resets and brown-outs also on [crate::pages::Screen::Splash]. A count that keeps growing without
+//! anyone pressing reset points at a flaky supply.
+
+#[cfg(feature = "hal")]
+use core::ptr::addr_of_mut;
+
+#[cfg(feature = "hal")]
+use esp_hal::macros::ram;
+
+#[cfg(feature = "hal")]
+use crate::log::info;
+use crate::watchdog::WatchdogReset;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetReason {
+    PowerOn,
+    /// Supply dropped below the brown-out level
+    BrownOut,
+    Watchdog(WatchdogReset),
+    /// Reset by the panic handler after [crate::globals::PANIC_RESET_DELAY]
+    Panic,
+    /// Reset by the firmware, e.g. after a firmware update or `!reboot`
+    Software,
+    DeepSleep,
+    /// Any other reset cause of the ROM
+    Other(u8),
+}
+
+impl ResetReason {
+    /// Reason of the reset cause `code` of the ROM, which is numbered the same on every chip. A
+    /// software reset counts as [ResetReason::Panic] when the panic handler did it.
+    pub fn from_code(code: u8, panicked: bool) -> Self {
+        match code {
+            0x01 => ResetReason::PowerOn,
+            0x03 | 0x0c if panicked => ResetReason::Panic,
+            0x03 | 0x0c => ResetReason::Software,
+            0x05 => ResetReason::DeepSleep,
+            0x07 | 0x0b => ResetReason::Watchdog(WatchdogReset::Timer),
+            0x08 | 0x11 => ResetReason::Watchdog(WatchdogReset::Task),
+            0x09 | 0x0d | 0x10 => ResetReason::Watchdog(WatchdogReset::Rtc),
+            0x0f => ResetReason::BrownOut,
+            0x12 => ResetReason::Watchdog(WatchdogReset::Super),
+            code => ResetReason::Other(code),
+        }
+    }
+
+    /// Name of the reason over serial, [ResetReason::Watchdog] is followed by the watchdog
+    pub fn label(self) -> &'static str {
+        match self {
+            ResetReason::PowerOn => "POWER_ON",
+            ResetReason::BrownOut => "BROWN_OUT",
+            ResetReason::Watchdog(_) => "WATCHDOG",
+            ResetReason::Panic => "PANIC",
+            ResetReason::Software => "SOFTWARE",
+            ResetReason::DeepSleep => "DEEP_SLEEP",
+            ResetReason::Other(_) => "OTHER",
+        }
+    }
+
+    /// Name of the reason on [crate::pages::Screen::Info]
+    pub fn text(self) -> &'static str {
+        match self {
+            ResetReason::PowerOn => "Power on",
+            ResetReason::BrownOut => "Brown-out",
+            ResetReason::Watchdog(watchdog) => watchdog.name(),
+            ResetReason::Panic => "Panic",
+            ResetReason::Software => "Software",
+            ResetReason::DeepSleep => "Deep sleep",
+            ResetReason::Other(_) => "Unknown",
+        }
+    }
+
+    /// Banner of [crate::pages::SplashPage] for the resets that should not happen
+    pub fn banner(self) -> Option<&'static str> {
+        match self {
+            ResetReason::Panic => Some("Crashed last time"),
+            ResetReason::BrownOut => Some("Brown-out reset"),
+            ResetReason::Watchdog(watchdog) => Some(watchdog.banner()),
+            _ => None,
+        }
+    }
+}
+
+/// Resets since the chip was powered on, kept in RTC RAM
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RebootCount {
+    pub resets: u16,
+    pub brown_outs: u16,
+}
+
+impl RebootCount {
+    /// Counts a reset for `reason`, a power-on starts over
+    pub fn count(&mut self, reason: ResetReason) {
+        match reason {
+            ResetReason::PowerOn => *self = Self::default(),
+            ResetReason::BrownOut => {
+                self.resets = self.resets.saturating_add(1);
+                self.brown_outs = self.brown_outs.saturating_add(1);
+            }
+            _ => self.resets = self.resets.saturating_add(1),
+        }
+    }
+}
+
+/// Reset of the current boot
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BootInfo {
+    pub reason: ResetReason,
+    pub count: RebootCount,
+}
+
+/// Marks [PersistedCount] as written, RTC RAM holds garbage after power-on
+#[cfg(feature = "hal")]
+const MAGIC: u32 = 0xdee7_b007;
+
+#[cfg(feature = "hal")]
+#[repr(C)]
+struct PersistedCount {
+    magic: u32,
+    count: RebootCount,
+}
+
+#[cfg(feature = "hal")]
+#[ram(rtc_fast, uninitialized)]
+static mut PERSISTED_COUNT: PersistedCount = PersistedCount {
+    magic: 0,
+    count: RebootCount {
+        resets: 0,
+        brown_outs: 0,
+    },
+};
+
+/// Reason of the latest reset and the count including it. Call once at boot, `panicked` is whether
+/// [crate::panic_persist::take] found a panic.
+#[cfg(feature = "hal")]
+pub fn read(panicked: bool) -> BootInfo {
+    let code = esp_hal::reset::get_reset_reason().map_or(0, |reason| reason as u8);
+    let reason = ResetReason::from_code(code, panicked);
+    // Only called from init, nothing else touches the count
+    let persisted = unsafe { &mut *addr_of_mut!(PERSISTED_COUNT) };
+    if persisted.magic != MAGIC {
+        persisted.magic = MAGIC;
+        persisted.count = RebootCount::default();
+    }
+    persisted.count.count(reason);
+    info!(
+        "Reset by {}, {} resets since power-on",
+        reason.label(),
+        persisted.count.resets
+    );
+    BootInfo {
+        reason,
+        count: persisted.count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_resets_since_power_on() {
+        assert_eq!(ResetReason::from_code(0x0c, true), ResetReason::Panic);
+        assert_eq!(
+            ResetReason::from_code(0x08, false),
+            ResetReason::Watchdog(WatchdogReset::Task)
+        );
+
+        let mut count = RebootCount::default();
+        for code in [0x0f, 0x03, 0x0f] {
+            count.count(ResetReason::from_code(code, false));
+        }
+        assert_eq!(
+            count,
+            RebootCount {
+                resets: 3,
+                brown_outs: 2
+            }
+        );
+        count.count(ResetReason::PowerOn);
+        assert_eq!(count, RebootCount::default());
+    }
+}
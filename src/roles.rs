@@ -0,0 +1,207 @@
+//! What each pot controls. The roles are applied to the sampled values before they are sent to the
+//! host, so the pots themselves are still read and filtered the same way. [VirtualChannel]s are
+//! computed from the result and sent after the pots.
+
+use crate::{globals::INPUT_COUNT, sampling::ChannelConfig};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Role {
+    /// Value is sent as is
+    #[default]
+    Volume,
+    /// Scales every other channel by its value (0-100 %). Sent as is itself, so the host can still
+    /// map it to the master volume.
+    Master,
+    /// Crossfades between two outputs, e.g. game and chat. Sends 1023 minus the value and then
+    /// the value, so the frame has one more value than there are pots.
+    Crossfade,
+}
+
+impl Role {
+    /// Values the channel adds to the frame
+    pub const fn outputs(self) -> usize {
+        match self {
+            Role::Volume | Role::Master => 1,
+            Role::Crossfade => 2,
+        }
+    }
+}
+
+/// Output that is a function of the channels, e.g. a "comms" group following the lower of two
+/// sliders. Uses the channel values after the roles, so a master pot scales it too.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VirtualChannel {
+    /// Lower of the two channels
+    Min(usize, usize),
+    /// Higher of the two channels
+    Max(usize, usize),
+    /// Average of the two channels
+    Average(usize, usize),
+    /// 1023 minus the channel
+    Inverted(usize),
+}
+
+impl VirtualChannel {
+    /// Highest channel the value is computed from
+    const fn last_input(self) -> usize {
+        match self {
+            VirtualChannel::Min(a, b)
+            | VirtualChannel::Max(a, b)
+            | VirtualChannel::Average(a, b) => {
+                if a > b {
+                    a
+                } else {
+                    b
+                }
+            }
+            VirtualChannel::Inverted(a) => a,
+        }
+    }
+
+    /// Value (0-1023) for the channel `values` (0-1023)
+    pub fn value(self, values: &[u16; INPUT_COUNT]) -> u16 {
+        match self {
+            VirtualChannel::Min(a, b) => values[a].min(values[b]),
+            VirtualChannel::Max(a, b) => values[a].max(values[b]),
+            VirtualChannel::Average(a, b) => ((values[a] as u32 + values[b] as u32) / 2) as u16,
+            VirtualChannel::Inverted(a) => 1023 - values[a].min(1023),
+        }
+    }
+}
+
+/// Values in the frame for the channels with `configs` and the `virtuals` after them, see
+/// [crate::globals::OUTPUT_COUNT]. Fails to compile when a virtual channel uses a missing channel.
+pub const fn output_count(configs: &[ChannelConfig], virtuals: &[VirtualChannel]) -> usize {
+    let mut count = 0;
+    let mut idx = 0;
+    while idx < configs.len() {
+        count += configs[idx].role.outputs();
+        idx += 1;
+    }
+    idx = 0;
+    while idx < virtuals.len() {
+        assert!(
+            virtuals[idx].last_input() < configs.len(),
+            "virtual channel uses a missing channel"
+        );
+        idx += 1;
+    }
+    count + virtuals.len()
+}
+
+/// Values sent to the host for the sampled `values` (0-1023) of the channels with `configs`,
+/// followed by the `virtuals`. `N` has to be at least the [output_count] of `configs` and
+/// `virtuals`, the values after them are left at 0 for the caller, e.g. the profile index.
+pub fn apply<const N: usize>(
+    configs: &[ChannelConfig; INPUT_COUNT],
+    virtuals: &[VirtualChannel],
+    values: &[u16; INPUT_COUNT],
+) -> [u16; N] {
+    debug_assert!(output_count(configs, virtuals) <= N);
+    let mut outputs = [0; N];
+    let mut output = 0;
+    for (idx, (config, value)) in configs.iter().zip(values).enumerate() {
+        // Product of the masters other than the channel itself
+        let scale = |value: u16| {
+            configs
+                .iter()
+                .zip(values)
+                .enumerate()
+                .filter(|(master, (config, _))| *master != idx && config.role == Role::Master)
+                .fold(value as u32, |value, (_, (_, master))| {
+                    value * *master as u32 / 1023
+                }) as u16
+        };
+        match config.role {
+            Role::Volume | Role::Master => outputs[output] = scale(*value),
+            Role::Crossfade => {
+                outputs[output] = scale(1023 - (*value).min(1023));
+                outputs[output + 1] = scale(*value);
+            }
+        }
+        output += config.role.outputs();
+    }
+    let levels = channel_values(configs, &outputs);
+    for (virtual_channel, value) in virtuals.iter().zip(&mut outputs[output..]) {
+        *value = virtual_channel.value(&levels);
+    }
+    outputs
+}
+
+/// One value per channel of the `outputs` of [apply], the level of the second output for
+/// [Role::Crossfade]. Used by the display, which has a bar per pot and none for the virtual
+/// channels.
+pub fn channel_values<const N: usize>(
+    configs: &[ChannelConfig; INPUT_COUNT],
+    outputs: &[u16; N],
+) -> [u16; INPUT_COUNT] {
+    let mut values = [0; INPUT_COUNT];
+    let mut output = 0;
+    for (value, config) in values.iter_mut().zip(configs) {
+        output += config.role.outputs();
+        *value = outputs[output - 1];
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn master_scales_the_other_channels() {
+        let mut configs = [ChannelConfig::DEFAULT; INPUT_COUNT];
+        assert_eq!(
+            apply(&configs, &[], &[1023, 512, 100, 0]),
+            [1023, 512, 100, 0]
+        );
+
+        configs[1].role = Role::Master;
+        assert_eq!(
+            apply(&configs, &[], &[1023, 512, 100, 0]),
+            [512, 512, 50, 0]
+        );
+        assert_eq!(apply(&configs, &[], &[1023, 0, 100, 1023]), [0, 0, 0, 0]);
+        assert_eq!(
+            apply(&configs, &[], &[1023, 1023, 100, 0]),
+            [1023, 1023, 100, 0]
+        );
+    }
+
+    #[test]
+    fn crossfade_sends_two_complementary_values() {
+        let mut configs = [ChannelConfig::DEFAULT; INPUT_COUNT];
+        configs[0].role = Role::Crossfade;
+        assert_eq!(output_count(&configs, &[]), INPUT_COUNT + 1);
+
+        let outputs: [u16; INPUT_COUNT + 1] = apply(&configs, &[], &[1023, 512, 100, 0]);
+        assert_eq!(outputs, [0, 1023, 512, 100, 0]);
+        assert_eq!(channel_values(&configs, &outputs), [1023, 512, 100, 0]);
+
+        configs[3].role = Role::Master;
+        let outputs: [u16; INPUT_COUNT + 1] = apply(&configs, &[], &[300, 512, 100, 512]);
+        assert_eq!(outputs, [361, 150, 256, 50, 512]);
+        assert_eq!(channel_values(&configs, &outputs), [150, 256, 50, 512]);
+    }
+
+    #[test]
+    fn virtual_channels_follow_the_channels_after_roles() {
+        let mut configs = [ChannelConfig::DEFAULT; INPUT_COUNT];
+        let virtuals = [
+            VirtualChannel::Min(1, 2),
+            VirtualChannel::Max(1, 2),
+            VirtualChannel::Average(0, 3),
+            VirtualChannel::Inverted(1),
+        ];
+        assert_eq!(output_count(&configs, &virtuals), INPUT_COUNT + 4);
+
+        let outputs: [u16; INPUT_COUNT + 4] = apply(&configs, &virtuals, &[1023, 512, 100, 0]);
+        assert_eq!(outputs, [1023, 512, 100, 0, 100, 512, 511, 511]);
+
+        configs[0].role = Role::Master;
+        let outputs: [u16; INPUT_COUNT + 4] = apply(&configs, &virtuals, &[512, 512, 100, 0]);
+        assert_eq!(outputs, [512, 256, 50, 0, 50, 256, 256, 767]);
+    }
+}
@@ -0,0 +1,465 @@
+use crate::{
+    fixed::{Curve, Q16},
+    globals::{
+        DISCONNECT_SAMPLES, FLOATING_SPREAD, INPUT_COUNT, MAX_ZERO_CUTOFF, RAIL_VALUE, ZERO_CUTOFF,
+    },
+    pipeline::{ChannelPipeline, Stage},
+    roles::Role,
+};
+
+/// Value (0-1023) of an audio taper pot at half travel, 15 % of the range
+const AUDIO_TAPER_MIDPOINT: u32 = 153;
+/// Travel of an audio taper pot for its value, both as fractions of the range
+const AUDIO_TAPER: [(Q16, Q16); 3] = [
+    (Q16::ZERO, Q16::ZERO),
+    (
+        Q16::from_ratio(AUDIO_TAPER_MIDPOINT, 1023),
+        Q16::from_ratio(1, 2),
+    ),
+    (Q16::ONE, Q16::ONE),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    /// Each sample is used as is
+    None,
+    /// Exponential moving average, each sample moves the value by 1/2^n of the difference
+    Ema(u8),
+    /// Median of the latest three samples, drops single spikes without adding lag to steady moves
+    Median3,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Taper {
+    /// Linear pot, the value follows the travel
+    Linear,
+    /// Logarithmic (A) pot with 15 % of the value at half travel, straightened out so the volume
+    /// follows the travel like with a linear pot
+    Audio,
+}
+
+impl Taper {
+    /// Travel (0-1023) of a pot with this taper that reads `value` (0-1023)
+    pub fn straighten(self, value: u16) -> u16 {
+        match self {
+            Taper::Linear => value,
+            Taper::Audio => {
+                let travel = Curve::new(&AUDIO_TAPER).eval(Q16::from_ratio(value as u32, 1023));
+                travel.scale(1023) as u16
+            }
+        }
+    }
+}
+
+/// Zones at the ends of the travel that report exactly 0 or 1023, so mute and full volume are easy
+/// to hit. Applies to the serial values and with them to the display.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Snap {
+    /// Values below this percentage report 0
+    pub low: u16,
+    /// Values above this percentage report 1023
+    pub high: u16,
+}
+
+impl Snap {
+    pub const OFF: Self = Self { low: 0, high: 100 };
+
+    /// Snaps a value in range 0-1023
+    pub fn apply(self, value: u16) -> u16 {
+        let percent_x_1023 = value as u32 * 100;
+        if percent_x_1023 < self.low as u32 * 1023 {
+            0
+        } else if percent_x_1023 > self.high as u32 * 1023 {
+            1023
+        } else {
+            value
+        }
+    }
+}
+
+/// How a single channel is read and turned into the 0-1023 value sent to the host
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChannelConfig {
+    /// Readings averaged per sample. Only applies to the blocking reads.
+    pub samples: u32,
+    pub filter: Filter,
+    /// Smallest change (0-1023) that is passed on, smaller ones are treated as noise.
+    /// The ends of the range are always reached.
+    pub deadband: u16,
+    pub taper: Taper,
+    pub snap: Snap,
+    /// What the channel controls, applied after sampling by [crate::roles::apply]
+    pub role: Role,
+    /// Averaged readings below this are 0. Replaced at boot by the one measured by [NoiseFloor]
+    /// when the pot is at zero.
+    pub zero_cutoff: u16,
+}
+
+impl ChannelConfig {
+    /// Same as the fixed read of 128 samples before channels were configurable
+    pub const DEFAULT: Self = Self {
+        samples: 128,
+        filter: Filter::None,
+        deadband: 0,
+        taper: Taper::Linear,
+        snap: Snap::OFF,
+        role: Role::Volume,
+        zero_cutoff: ZERO_CUTOFF,
+    };
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The ADC did not return a reading
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdcError;
+
+/// Averaged readings of a channel, see [AnalogSource::read]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Reading {
+    /// In range 0-[crate::globals::MAX_ANALOG_VALUE]
+    pub average: u16,
+    /// Highest minus lowest of the averaged readings. A pot barely moves during one sample, a
+    /// floating input without a wiper jumps around.
+    pub spread: u16,
+}
+
+impl Reading {
+    /// Reading without a known spread, e.g. from the background scan of `adc-dma`
+    pub const fn steady(average: u16) -> Self {
+        Self { average, spread: 0 }
+    }
+}
+
+/// Whether a channel reads like a pot is connected to it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChannelHealth {
+    Connected,
+    /// Failed reads, readings stuck at the rail or floating for [DISCONNECT_SAMPLES] samples in a
+    /// row. The value is frozen until the channel reads fine for as many samples again.
+    Disconnected,
+}
+
+/// Health and [ChannelPipeline] of a single channel
+struct ChannelSampler {
+    /// [ChannelConfig::samples]
+    samples: u32,
+    health: ChannelHealth,
+    /// Consecutive samples that disagree with [ChannelSampler::health]
+    health_samples: u8,
+    pipeline: ChannelPipeline,
+}
+
+impl ChannelSampler {
+    /// Updates [ChannelSampler::health] with the reading, returns the reading if it can be used
+    fn check(&mut self, reading: Result<Reading, AdcError>) -> Option<u16> {
+        let usable = reading
+            .ok()
+            .filter(|reading| reading.average < RAIL_VALUE && reading.spread < FLOATING_SPREAD);
+        let health = match usable {
+            Some(_) => ChannelHealth::Connected,
+            None => ChannelHealth::Disconnected,
+        };
+        if health == self.health {
+            self.health_samples = 0;
+        } else {
+            self.health_samples += 1;
+            if self.health_samples >= DISCONNECT_SAMPLES {
+                self.health = health;
+                self.health_samples = 0;
+            }
+        }
+        usable
+            .filter(|_| self.health == ChannelHealth::Connected)
+            .map(|reading| reading.average)
+    }
+}
+
+/// Averaged readings of the pots. Implemented by [crate::analog::Pots] on the hardware, tests can
+/// implement it to feed scripted readings through [Sampler::sample].
+pub trait AnalogSource {
+    /// Average and spread of `samples` readings of `channel`
+    fn read(&mut self, channel: usize, samples: u32) -> Result<Reading, AdcError>;
+}
+
+/// Turns the averaged readings of the channels into the values sent to the host, each through
+/// the [ChannelPipeline] of its [ChannelConfig]
+pub struct Sampler {
+    channels: [ChannelSampler; INPUT_COUNT],
+}
+
+impl Sampler {
+    pub fn new(configs: &[ChannelConfig; INPUT_COUNT]) -> Self {
+        Self {
+            channels: configs.map(|config| ChannelSampler {
+                samples: config.samples,
+                health: ChannelHealth::Connected,
+                health_samples: 0,
+                pipeline: ChannelPipeline::from_config(&config),
+            }),
+        }
+    }
+
+    /// Readings to average for the next sample of `channel`
+    pub fn samples(&self, channel: usize) -> u32 {
+        self.channels[channel].samples
+    }
+
+    /// Channels that are currently [ChannelHealth::Disconnected]
+    pub fn disconnected(&self) -> [bool; INPUT_COUNT] {
+        core::array::from_fn(|idx| self.channels[idx].health == ChannelHealth::Disconnected)
+    }
+
+    /// Takes the averaged readings of all channels, see [crate::ReadAnalog::read_multi_sample],
+    /// and returns the values in range 0-1023
+    pub fn process(&mut self, raw_values: &[u16; INPUT_COUNT]) -> [u16; INPUT_COUNT] {
+        self.process_readings(&raw_values.map(|raw| Ok(Reading::steady(raw))))
+    }
+
+    /// Same as [Sampler::process] but also checks the spread of the readings. Channels without a
+    /// usable reading keep their previous value.
+    pub fn process_readings(
+        &mut self,
+        readings: &[Result<Reading, AdcError>; INPUT_COUNT],
+    ) -> [u16; INPUT_COUNT] {
+        let mut values = [0; INPUT_COUNT];
+        for ((value, reading), channel) in values.iter_mut().zip(readings).zip(&mut self.channels) {
+            *value = match channel.check(*reading) {
+                Some(raw) => channel.pipeline.process(raw).wire,
+                None => channel.pipeline.output().unwrap_or_default().wire,
+            };
+        }
+        values
+    }
+
+    /// Reads every channel from `source` with its configured sample count
+    pub fn read<S: AnalogSource>(
+        &self,
+        source: &mut S,
+    ) -> [Result<Reading, AdcError>; INPUT_COUNT] {
+        core::array::from_fn(|idx| source.read(idx, self.samples(idx)))
+    }
+
+    /// [Sampler::read]s every channel and returns the raw readings and the values from
+    /// [Sampler::process_readings]. Failed reads are 0 in the raw readings.
+    pub fn sample<S: AnalogSource>(
+        &mut self,
+        source: &mut S,
+    ) -> ([u16; INPUT_COUNT], [u16; INPUT_COUNT]) {
+        let readings = self.read(source);
+        let raw_values = readings.map(|reading| reading.map_or(0, |reading| reading.average));
+        (raw_values, self.process_readings(&readings))
+    }
+
+    /// Replaces the [Stage::Taper] of every channel, e.g. for [crate::profiles::Profile::tapers]
+    pub fn set_tapers(&mut self, tapers: &[Taper; INPUT_COUNT]) {
+        for (channel, taper) in self.channels.iter_mut().zip(tapers) {
+            channel.pipeline.set_stage(Stage::Taper(*taper));
+        }
+    }
+
+    /// Replaces the [Stage::ZeroCutoff] of the channels [NoiseFloor::cutoffs] measured
+    pub fn set_zero_cutoffs(&mut self, cutoffs: &[Option<u16>; INPUT_COUNT]) {
+        for (channel, cutoff) in self.channels.iter_mut().zip(cutoffs) {
+            if let Some(cutoff) = cutoff {
+                channel.pipeline.set_stage(Stage::ZeroCutoff(*cutoff));
+            }
+        }
+    }
+
+    /// Flips the values of the `inverted` channels with a [Stage::Invert] after the other stages,
+    /// e.g. for [crate::settings::Settings::inverted]
+    pub fn set_inverted(&mut self, inverted: &[bool; INPUT_COUNT]) {
+        for (channel, inverted) in self.channels.iter_mut().zip(inverted) {
+            if *inverted {
+                channel.pipeline.set_stage(Stage::Invert);
+            } else {
+                channel.pipeline.remove_stage(Stage::Invert);
+            }
+        }
+    }
+
+    /// Latest values of the channels as shown on the display (0-100)
+    pub fn positions(&self) -> [u16; INPUT_COUNT] {
+        core::array::from_fn(|idx| {
+            self.channels[idx]
+                .pipeline
+                .output()
+                .unwrap_or_default()
+                .display
+        })
+    }
+}
+
+/// Measures the noise of every channel at boot, so each pot gets a zero cutoff that just clears
+/// its own leakage and noise instead of the [ZERO_CUTOFF] guess
+pub struct NoiseFloor {
+    /// Lowest and highest average and the highest spread seen per channel
+    ranges: [Option<(u16, u16, u16)>; INPUT_COUNT],
+    samples: u32,
+}
+
+impl Default for NoiseFloor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoiseFloor {
+    pub fn new() -> Self {
+        Self {
+            ranges: [None; INPUT_COUNT],
+            samples: 0,
+        }
+    }
+
+    /// Number of [NoiseFloor::add]s so far
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Adds one [Sampler::read] of every channel, failed reads are skipped
+    pub fn add(&mut self, readings: &[Result<Reading, AdcError>; INPUT_COUNT]) {
+        self.samples += 1;
+        for (range, reading) in self.ranges.iter_mut().zip(readings) {
+            let Ok(reading) = reading else {
+                continue;
+            };
+            let (min, max, spread) = range.unwrap_or((reading.average, reading.average, 0));
+            *range = Some((
+                min.min(reading.average),
+                max.max(reading.average),
+                spread.max(reading.spread),
+            ));
+        }
+    }
+
+    /// Cutoff just above the noise of each channel. `None` for channels that were not read or
+    /// whose pot was not at zero, i.e. read above [MAX_ZERO_CUTOFF], those keep their cutoff.
+    pub fn cutoffs(&self) -> [Option<u16>; INPUT_COUNT] {
+        self.ranges.map(|range| {
+            let (min, max, spread) = range?;
+            let cutoff = max + spread.max(max - min) + 1;
+            (cutoff <= MAX_ZERO_CUTOFF).then_some(cutoff)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floating_channel_is_frozen_until_it_settles() {
+        let mut sampler = Sampler::new(&[ChannelConfig::DEFAULT; INPUT_COUNT]);
+        let steady = |average| Ok(Reading::steady(average));
+        let floating = Ok(Reading {
+            average: 0,
+            spread: FLOATING_SPREAD,
+        });
+
+        let mut readings = [steady(RAIL_VALUE / 2); INPUT_COUNT];
+        let value = sampler.process_readings(&readings)[0];
+        readings[0] = floating;
+        for _ in 0..DISCONNECT_SAMPLES {
+            assert_eq!(sampler.process_readings(&readings)[0], value);
+        }
+        assert!(sampler.disconnected()[0]);
+        assert!(!sampler.disconnected()[1]);
+
+        readings[0] = steady(0);
+        for _ in 0..DISCONNECT_SAMPLES - 1 {
+            assert_eq!(sampler.process_readings(&readings)[0], value);
+        }
+        assert_eq!(sampler.process_readings(&readings)[0], 0);
+        assert!(!sampler.disconnected()[0]);
+    }
+
+    #[test]
+    fn noise_floor_sets_cutoff_of_pots_at_zero() {
+        let mut noise_floor = NoiseFloor::new();
+        let mut readings = [Ok(Reading::steady(MAX_ZERO_CUTOFF)); INPUT_COUNT];
+        readings[0] = Ok(Reading {
+            average: 12,
+            spread: 6,
+        });
+        readings[1] = Err(AdcError);
+        noise_floor.add(&readings);
+        readings[0] = Ok(Reading {
+            average: 9,
+            spread: 4,
+        });
+        noise_floor.add(&readings);
+
+        let cutoffs = noise_floor.cutoffs();
+        assert_eq!(cutoffs[0], Some(12 + 6 + 1));
+        assert_eq!(cutoffs[1], None);
+        assert_eq!(cutoffs[2], None);
+
+        let mut sampler = Sampler::new(&[ChannelConfig::DEFAULT; INPUT_COUNT]);
+        sampler.set_zero_cutoffs(&cutoffs);
+        readings[0] = Ok(Reading::steady(18));
+        readings[1] = Ok(Reading::steady(ZERO_CUTOFF));
+        let values = sampler.process_readings(&readings);
+        assert_eq!(values[0], 0);
+        assert!(values[1] > 0);
+    }
+
+    #[test]
+    fn audio_taper_is_straightened() {
+        assert_eq!(Taper::Audio.straighten(0), 0);
+        assert_eq!(Taper::Audio.straighten(AUDIO_TAPER_MIDPOINT as u16), 512);
+        assert_eq!(Taper::Audio.straighten(1023), 1023);
+        for value in 1..=1023 {
+            assert!(Taper::Audio.straighten(value) >= Taper::Audio.straighten(value - 1));
+        }
+        assert_eq!(Taper::Linear.straighten(300), 300);
+    }
+
+    #[test]
+    fn inverted_channels_and_positions() {
+        let mut sampler = Sampler::new(&[ChannelConfig::DEFAULT; INPUT_COUNT]);
+        let mut inverted = [false; INPUT_COUNT];
+        inverted[1] = true;
+        sampler.set_inverted(&inverted);
+        let values = sampler.process(&[0; INPUT_COUNT]);
+        assert_eq!(values[0], 0);
+        assert_eq!(values[1], 1023);
+        assert_eq!(sampler.positions()[1], 100);
+
+        sampler.set_inverted(&[false; INPUT_COUNT]);
+        assert_eq!(sampler.process(&[0; INPUT_COUNT])[1], 0);
+    }
+
+    #[test]
+    fn snap_zones() {
+        let snap = Snap { low: 3, high: 97 };
+        assert_eq!(snap.apply(30), 0);
+        assert_eq!(snap.apply(31), 31);
+        assert_eq!(snap.apply(992), 992);
+        assert_eq!(snap.apply(993), 1023);
+        assert_eq!(Snap::OFF.apply(1), 1);
+        assert_eq!(Snap::OFF.apply(1022), 1022);
+    }
+
+    #[test]
+    fn short_glitches_keep_the_channel_connected() {
+        let mut sampler = Sampler::new(&[ChannelConfig::DEFAULT; INPUT_COUNT]);
+        let mut readings = [Ok(Reading::steady(0)); INPUT_COUNT];
+        for _ in 0..3 {
+            readings[1] = Err(AdcError);
+            sampler.process_readings(&readings);
+            readings[1] = Ok(Reading::steady(RAIL_VALUE));
+            sampler.process_readings(&readings);
+            readings[1] = Ok(Reading::steady(0));
+            sampler.process_readings(&readings);
+        }
+        assert_eq!(sampler.disconnected(), [false; INPUT_COUNT]);
+    }
+}
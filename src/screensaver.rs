@@ -0,0 +1,57 @@
+use embedded_graphics::geometry::Point;
+
+/// Offsets cycled through, the whole layout moves at most 2 px from its original position
+const SHIFTS: [Point; 12] = [
+    Point::new(0, 0),
+    Point::new(1, 0),
+    Point::new(1, 1),
+    Point::new(0, 1),
+    Point::new(-1, 1),
+    Point::new(-1, 0),
+    Point::new(-1, -1),
+    Point::new(0, -1),
+    Point::new(2, 0),
+    Point::new(0, 2),
+    Point::new(-2, 0),
+    Point::new(0, -2),
+];
+
+/// Burn-in protection, moves the layout every `shift_period_ms` and optionally inverts the
+/// display after every full cycle of shifts.
+pub struct Screensaver {
+    shift_period_ms: u64,
+    invert: bool,
+    step: usize,
+    last_shift_ms: u64,
+}
+
+impl Screensaver {
+    pub fn new(shift_period_ms: u64, invert: bool) -> Self {
+        Self {
+            shift_period_ms,
+            invert,
+            step: 0,
+            last_shift_ms: 0,
+        }
+    }
+
+    /// Returns true when the offset or inversion changed and the display should be redrawn
+    pub fn update(&mut self, now_ms: u64) -> bool {
+        if now_ms.saturating_sub(self.last_shift_ms) < self.shift_period_ms {
+            return false;
+        }
+        self.last_shift_ms = now_ms;
+        self.step = (self.step + 1) % (SHIFTS.len() * 2);
+        true
+    }
+
+    /// Offset added to every draw position
+    pub fn offset(&self) -> Point {
+        SHIFTS[self.step % SHIFTS.len()]
+    }
+
+    /// Every other cycle of shifts is drawn inverted when enabled
+    pub fn inverted(&self) -> bool {
+        self.invert && self.step >= SHIFTS.len()
+    }
+}
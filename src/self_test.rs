@@ -0,0 +1,83 @@
+//! Power-on self-test of what the firmware can check on its own: the display answers, no pot is
+//! stuck at the supply rail and the timers run. The results are sent to the host with
+//! [crate::protocol::encode_self_test] and shown as a checklist on [crate::pages::Screen::SelfTest]
+//! before normal operation starts.
+
+use crate::{
+    globals::{INPUT_COUNT, RAIL_VALUE},
+    log::info,
+    sampling::{AdcError, Reading},
+};
+
+/// Result of the check of a single channel
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChannelCheck {
+    Ok,
+    /// The ADC did not return a reading
+    NoReading,
+    /// Reads at [RAIL_VALUE] or above, e.g. a wiper shorted to 3.3 V. A channel at 0 may just be a
+    /// pot turned down, so the low rail is not checked.
+    Rail,
+}
+
+impl ChannelCheck {
+    pub fn of(reading: Result<Reading, AdcError>) -> Self {
+        match reading {
+            Err(AdcError) => ChannelCheck::NoReading,
+            Ok(reading) if reading.average >= RAIL_VALUE => ChannelCheck::Rail,
+            Ok(_) => ChannelCheck::Ok,
+        }
+    }
+
+    /// Text of the check on the display and over serial
+    pub fn label(self) -> &'static str {
+        match self {
+            ChannelCheck::Ok => "OK",
+            ChannelCheck::NoReading => "NOADC",
+            ChannelCheck::Rail => "RAIL",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SelfTest {
+    /// The display acknowledged a command
+    pub display: bool,
+    pub channels: [ChannelCheck; INPUT_COUNT],
+    /// The timer of the serial stream counts
+    pub timer: bool,
+}
+
+impl SelfTest {
+    /// Checks `readings` of every pot, taken at boot
+    pub fn new(
+        display: bool,
+        readings: &[Result<Reading, AdcError>; INPUT_COUNT],
+        timer: bool,
+    ) -> Self {
+        let test = Self {
+            display,
+            channels: readings.map(ChannelCheck::of),
+            timer,
+        };
+        info!(
+            "Self-test {}",
+            if test.passed() { "passed" } else { "failed" }
+        );
+        test
+    }
+
+    pub fn passed(&self) -> bool {
+        self.display && self.timer && self.channels.iter().all(|c| *c == ChannelCheck::Ok)
+    }
+}
+
+/// Text of a check that is either fine or not
+pub fn label(ok: bool) -> &'static str {
+    if ok {
+        "OK"
+    } else {
+        "FAIL"
+    }
+}
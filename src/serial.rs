@@ -0,0 +1,446 @@
+use heapless::String;
+
+use crate::{
+    cli,
+    events::Event,
+    globals::{HOST_TIMEOUT, INPUT_COUNT, OUTPUT_COUNT, STOP_SENDING_WHEN_DISCONNECTED},
+    log::info,
+};
+
+/// Decides whether a serial frame should be sent to the host.
+///
+/// A frame is sent as soon as any channel has moved at least `threshold` from the last sent frame.
+/// If nothing moves a keep-alive frame is sent after `keep_alive_ticks` calls to [SerialGate::should_send].
+pub struct SerialGate {
+    last_sent: [u16; OUTPUT_COUNT],
+    threshold: u16,
+    keep_alive_ticks: u32,
+    ticks_since_send: u32,
+    /// The next call sends whatever the values, see [SerialGate::resend]
+    resend: bool,
+}
+
+impl SerialGate {
+    pub fn new(threshold: u16, keep_alive_ticks: u32) -> Self {
+        Self {
+            last_sent: Default::default(),
+            threshold: threshold.max(1),
+            keep_alive_ticks,
+            ticks_since_send: 0,
+            // First call always sends so the host gets the initial state right away
+            resend: true,
+        }
+    }
+
+    /// Should be called once per serial update period with the values that would be sent.
+    pub fn should_send(&mut self, values: &[u16; OUTPUT_COUNT]) -> bool {
+        self.should_send_touched(values, true)
+    }
+
+    /// Same as [SerialGate::should_send], but the keep-alive waits while `touched` is false: no
+    /// channel has changed since the previous frame, so the host already has every value.
+    pub fn should_send_touched(&mut self, values: &[u16; OUTPUT_COUNT], touched: bool) -> bool {
+        self.ticks_since_send = self.ticks_since_send.saturating_add(1);
+
+        let changed = values
+            .iter()
+            .zip(self.last_sent.iter())
+            .any(|(new, old)| new.abs_diff(*old) >= self.threshold);
+        let keep_alive = touched && self.ticks_since_send >= self.keep_alive_ticks;
+
+        if changed || keep_alive || self.resend {
+            self.last_sent = *values;
+            self.ticks_since_send = 0;
+            self.resend = false;
+            return true;
+        }
+        false
+    }
+
+    /// The next [SerialGate::should_send] sends whether or not anything changed, e.g. for a host
+    /// that just connected
+    pub fn resend(&mut self) {
+        self.resend = true;
+    }
+}
+
+/// Which of two PCs gets the stream with `host-switch`, the first is on the UART and the second on
+/// the USB Serial/JTAG port
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ActiveHost {
+    #[default]
+    First,
+    Second,
+}
+
+impl ActiveHost {
+    pub fn toggle(self) -> Self {
+        match self {
+            ActiveHost::First => ActiveHost::Second,
+            ActiveHost::Second => ActiveHost::First,
+        }
+    }
+
+    /// Status text of the display
+    pub fn label(self) -> &'static str {
+        match self {
+            ActiveHost::First => "PC 1",
+            ActiveHost::Second => "PC 2",
+        }
+    }
+}
+
+/// Streams the values to the [ActiveHost] and keeps the other one alive with the values it got
+/// last, so its volumes stay put while the mixer is switched away
+pub struct HostSwitch {
+    gates: [SerialGate; 2],
+    /// Values each host got last
+    last_sent: [[u16; OUTPUT_COUNT]; 2],
+}
+
+impl HostSwitch {
+    /// Same parameters as [SerialGate::new], each host gets its own gate
+    pub fn new(threshold: u16, keep_alive_ticks: u32) -> Self {
+        Self {
+            gates: [
+                SerialGate::new(threshold, keep_alive_ticks),
+                SerialGate::new(threshold, keep_alive_ticks),
+            ],
+            last_sent: Default::default(),
+        }
+    }
+
+    /// Values to send to the first and the second host on this tick, if any. Should be called once
+    /// per serial update period like [SerialGate::should_send]. `touched` holds back the
+    /// keep-alives of the active host like [SerialGate::should_send_touched], the other one gets
+    /// them to keep its volumes put.
+    pub fn frames(
+        &mut self,
+        active: ActiveHost,
+        values: &[u16; OUTPUT_COUNT],
+        touched: bool,
+    ) -> [Option<[u16; OUTPUT_COUNT]>; 2] {
+        core::array::from_fn(|idx| {
+            let is_active = idx == active as usize;
+            if is_active {
+                self.last_sent[idx] = *values;
+            }
+            let values = self.last_sent[idx];
+            self.gates[idx]
+                .should_send_touched(&values, touched || !is_active)
+                .then_some(values)
+        })
+    }
+
+    /// Same as [SerialGate::resend] for both hosts
+    pub fn resend(&mut self) {
+        for gate in &mut self.gates {
+            gate.resend();
+        }
+    }
+}
+
+/// Port the frames and the replies for the host are written to, e.g. the host UART or the USB
+/// Serial/JTAG port
+pub trait Transport {
+    /// Writes `bytes` or drops them, a host that is not reading must not stall the firmware
+    fn send(&mut self, bytes: &[u8]);
+}
+
+/// Writes everything to both transports, so the same box works on either connector or on two PCs
+pub struct FanOut<A, B>(pub A, pub B);
+
+impl<A: Transport, B: Transport> Transport for FanOut<A, B> {
+    fn send(&mut self, bytes: &[u8]) {
+        self.0.send(bytes);
+        self.1.send(bytes);
+    }
+}
+
+/// Transport of a feature that is disabled
+impl Transport for () {
+    fn send(&mut self, _bytes: &[u8]) {}
+}
+
+#[cfg(feature = "hal")]
+impl<T: esp_hal::uart::Instance> Transport for esp_hal::Uart<'_, T> {
+    fn send(&mut self, bytes: &[u8]) {
+        self.write_bytes(bytes).ok();
+    }
+}
+
+/// The FIFO only drains while a host has the port open, so the rest of the bytes are dropped once
+/// it is full
+#[cfg(feature = "usb-serial-jtag")]
+impl Transport for esp_hal::usb_serial_jtag::UsbSerialJtag<'_> {
+    fn send(&mut self, bytes: &[u8]) {
+        use embedded_hal_027::serial::Write;
+
+        for byte in bytes {
+            if self.write(*byte).is_err() {
+                break;
+            }
+        }
+        self.flush().ok();
+    }
+}
+
+/// Whether a host is reading the serial port, judged from what it sends
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkState {
+    /// Nothing received since boot or since the port was opened
+    #[default]
+    Unknown,
+    Connected,
+    /// Silent for [HOST_TIMEOUT] or the port was closed
+    Disconnected,
+}
+
+impl LinkState {
+    /// Status text of the display
+    pub fn label(self) -> Option<&'static str> {
+        (self == LinkState::Disconnected).then_some("NO PC")
+    }
+
+    /// Event to publish when the state changes from `previous` to this one
+    pub fn link_event(self, previous: LinkState) -> Option<Event> {
+        match (previous, self) {
+            (LinkState::Connected, LinkState::Connected) => None,
+            (_, LinkState::Connected) => Some(Event::LinkUp),
+            (LinkState::Connected, LinkState::Disconnected) => Some(Event::LinkDown),
+            _ => None,
+        }
+    }
+}
+
+/// Tracks the [LinkState] from the time of the latest traffic from the host. Shared by the task
+/// receiving from the host and the ones showing the state and sending the frames.
+pub struct LinkMonitor {
+    state: LinkState,
+    last_received_ms: u64,
+    port_open: bool,
+}
+
+impl LinkMonitor {
+    pub const fn new() -> Self {
+        Self {
+            state: LinkState::Unknown,
+            last_received_ms: 0,
+            port_open: true,
+        }
+    }
+
+    /// Call whenever anything is received from the host
+    pub fn received(&mut self, now_ms: u64) {
+        self.last_received_ms = now_ms;
+        if self.state != LinkState::Connected {
+            info!("Host connected");
+            self.state = LinkState::Connected;
+        }
+    }
+
+    /// Host opened or closed the port, from DTR of the USB CDC port. The UART has no DTR line.
+    pub fn set_port_open(&mut self, open: bool) {
+        if open == self.port_open {
+            return;
+        }
+        self.port_open = open;
+        self.state = if open {
+            LinkState::Unknown
+        } else {
+            info!("Host closed the port");
+            LinkState::Disconnected
+        };
+    }
+
+    /// State at `now_ms`, a connected host is disconnected after [HOST_TIMEOUT] without traffic
+    pub fn state(&mut self, now_ms: u64) -> LinkState {
+        if self.state == LinkState::Connected && now_ms >= self.last_received_ms + HOST_TIMEOUT {
+            info!("Host silent for {} ms", HOST_TIMEOUT);
+            self.state = LinkState::Disconnected;
+        }
+        self.state
+    }
+
+    /// False while the port is closed and, with [STOP_SENDING_WHEN_DISCONNECTED], while the host is
+    /// gone
+    pub fn should_send(&mut self, now_ms: u64) -> bool {
+        let gone = STOP_SENDING_WHEN_DISCONNECTED && self.state(now_ms) == LinkState::Disconnected;
+        self.port_open && !gone
+    }
+}
+
+impl Default for LinkMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Longest line accepted from the host, at least room for `PLAYING` with a title and artist of
+/// [crate::pages::TRACK_TEXT_LEN] and for `VOLUMES` with every channel at 100. Longer lines are
+/// discarded.
+pub const MAX_LINE_LEN: usize = if INPUT_COUNT * 4 + 8 > 128 {
+    INPUT_COUNT * 4 + 8
+} else {
+    128
+};
+
+pub type Line = String<MAX_LINE_LEN>;
+
+/// Collects bytes received from the host into `\n` or `\r` terminated lines. The editing keys
+/// of a terminal are applied with [crate::cli::edit].
+#[derive(Default)]
+pub struct LineReader {
+    buf: Line,
+    overflow: bool,
+}
+
+impl LineReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the line once its terminator is received. Empty and too long lines are never returned.
+    pub fn push(&mut self, byte: u8) -> Option<Line> {
+        if cli::edit(&mut self.buf, byte) {
+            return None;
+        }
+        match byte {
+            b'\r' | b'\n' => {
+                let overflow = core::mem::replace(&mut self.overflow, false);
+                let line = core::mem::take(&mut self.buf);
+                if overflow || line.is_empty() {
+                    return None;
+                }
+                Some(line)
+            }
+            byte if byte.is_ascii() && !self.overflow => {
+                if self.buf.push(byte as char).is_err() {
+                    self.overflow = true;
+                    self.buf.clear();
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values of a frame with `value` in the first channel and 0 in the others
+    fn first(value: u16) -> [u16; OUTPUT_COUNT] {
+        let mut values = [0; OUTPUT_COUNT];
+        values[0] = value;
+        values
+    }
+
+    #[test]
+    fn gate_sends_first_then_changes_and_keep_alive() {
+        let mut gate = SerialGate::new(2, 3);
+        assert!(gate.should_send(&[0; OUTPUT_COUNT]));
+        assert!(!gate.should_send(&first(1)));
+        assert!(gate.should_send(&first(2)));
+        assert!(!gate.should_send(&first(2)));
+        assert!(!gate.should_send(&first(2)));
+        assert!(gate.should_send(&first(2)));
+        gate.resend();
+        assert!(gate.should_send(&first(2)));
+        assert!(!gate.should_send(&first(2)));
+    }
+
+    #[test]
+    fn keep_alive_waits_for_a_touch() {
+        let mut gate = SerialGate::new(2, 2);
+        assert!(gate.should_send_touched(&[0; OUTPUT_COUNT], false));
+        for _ in 0..5 {
+            assert!(!gate.should_send_touched(&[0; OUTPUT_COUNT], false));
+        }
+        // Moved less than the threshold, the keep-alive carries it
+        assert!(gate.should_send_touched(&first(1), true));
+        assert!(!gate.should_send_touched(&first(1), false));
+        // Changes and resends go out untouched
+        assert!(gate.should_send_touched(&first(3), false));
+        gate.resend();
+        assert!(gate.should_send_touched(&first(3), false));
+    }
+
+    #[test]
+    fn inactive_host_only_gets_keep_alives() {
+        let mut switch = HostSwitch::new(2, 3);
+        let moved = first(500);
+        assert_eq!(
+            switch.frames(ActiveHost::First, &moved, true),
+            [Some(moved), Some([0; OUTPUT_COUNT])]
+        );
+        let moved_again = first(600);
+        assert_eq!(
+            switch.frames(ActiveHost::First, &moved_again, true),
+            [Some(moved_again), None]
+        );
+        assert_eq!(
+            switch.frames(ActiveHost::First, &moved_again, true),
+            [None, None]
+        );
+        assert_eq!(
+            switch.frames(ActiveHost::First, &moved_again, true),
+            [None, Some([0; OUTPUT_COUNT])]
+        );
+
+        let after_switch = first(100);
+        assert_eq!(
+            switch.frames(ActiveHost::First.toggle(), &after_switch, true),
+            [Some(moved_again), Some(after_switch)]
+        );
+    }
+
+    #[test]
+    fn link_times_out_and_comes_back() {
+        let mut link = LinkMonitor::new();
+        assert_eq!(link.state(HOST_TIMEOUT * 2), LinkState::Unknown);
+
+        link.received(100);
+        assert_eq!(link.state(100 + HOST_TIMEOUT - 1), LinkState::Connected);
+        assert_eq!(link.state(100 + HOST_TIMEOUT), LinkState::Disconnected);
+        assert_eq!(
+            LinkState::Disconnected.link_event(LinkState::Connected),
+            Some(Event::LinkDown)
+        );
+        assert_eq!(LinkState::Disconnected.label(), Some("NO PC"));
+
+        link.received(200 + HOST_TIMEOUT);
+        assert_eq!(link.state(200 + HOST_TIMEOUT), LinkState::Connected);
+
+        link.set_port_open(false);
+        assert_eq!(link.state(200 + HOST_TIMEOUT), LinkState::Disconnected);
+        assert!(!link.should_send(200 + HOST_TIMEOUT));
+        link.set_port_open(true);
+        assert_eq!(link.state(200 + HOST_TIMEOUT), LinkState::Unknown);
+    }
+
+    #[test]
+    fn line_reader() {
+        let mut reader = LineReader::new();
+        let lines: Vec<Line> = b"\r\nHELLO\r\n"
+            .iter()
+            .filter_map(|b| reader.push(*b))
+            .collect();
+        assert_eq!(lines, ["HELLO"]);
+
+        let long = [b'A'; MAX_LINE_LEN + 1];
+        assert!(long.iter().all(|b| reader.push(*b).is_none()));
+        assert_eq!(reader.push(b'\n'), None);
+        assert_eq!(
+            b"OK\n"
+                .iter()
+                .filter_map(|b| reader.push(*b))
+                .last()
+                .unwrap(),
+            "OK"
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! Settings that can be changed on the device with the encoder and the [crate::menu], kept in
+//! flash with `settings`. Without the feature [Settings::DEFAULT] is used, made from the globals.
+
+use core::ops::RangeInclusive;
+
+use crate::{
+    globals::{
+        CHANNEL_CONFIGS, DISPLAY_CONTRAST, DISPLAY_ON_TIME, INPUT_COUNT, SERIAL_UPDATE_PERIOD,
+    },
+    menu::{ItemKind, Label, Menu, MenuView},
+    protocol::crc8,
+    sampling::{ChannelConfig, Taper},
+    style::ThemeName,
+    text::format_truncated,
+};
+
+/// Offset of the `settings` partition, must match `partitions.csv`
+pub const SETTINGS_OFFSET: u32 = 0x3d0000;
+/// Marks a sector that holds [Settings], erased flash reads as `0xff`
+const MAGIC: [u8; 2] = [0xde, 0xe1];
+/// Magic, channel count, timeout, contrast, serial period, theme, a flag byte per channel and the
+/// CRC
+pub const STORED_LEN: usize = 2 + 1 + 2 + 1 + 2 + 1 + INPUT_COUNT + 1;
+/// Where the flag bytes start
+const FLAGS_OFFSET: usize = 9;
+
+const FLAG_INVERTED: u8 = 1 << 0;
+const FLAG_AUDIO_TAPER: u8 = 1 << 1;
+
+/// Display timeout, contrast and serial period, inversion and taper of each channel, the theme and
+/// Exit
+pub const ITEM_COUNT: usize = 3 + 2 * INPUT_COUNT + 1 + 1;
+pub type SettingsView = MenuView<ITEM_COUNT>;
+
+const TAPER_NAMES: &[&str] = &["Linear", "Audio"];
+/// Periods (ms) the serial stream can be set to
+pub const SERIAL_PERIOD_RANGE: RangeInclusive<u32> = 10..=200;
+/// Display timeouts the menu steps through, the host can set any other with `TIMEOUT`
+const TIMEOUT_CHOICES: [DisplayTimeout; 7] = [
+    DisplayTimeout::AlwaysOff,
+    DisplayTimeout::After(5),
+    DisplayTimeout::After(10),
+    DisplayTimeout::After(30),
+    DisplayTimeout::After(60),
+    DisplayTimeout::After(300),
+    DisplayTimeout::Never,
+];
+const TIMEOUT_NAMES: &[&str] = &["Off", "5s", "10s", "30s", "1min", "5min", "Never"];
+/// Stored in place of the seconds of [DisplayTimeout::After]
+const STORED_NEVER: u16 = 0;
+const STORED_ALWAYS_OFF: u16 = u16::MAX;
+
+/// How long the display stays on without changes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DisplayTimeout {
+    /// Dimmed after this many seconds, then turned off after [crate::globals::DISPLAY_OFF_DELAY]
+    After(u32),
+    /// Stays on at full contrast
+    Never,
+    /// Stays off, only the settings menu is still shown so the timeout can be changed back
+    AlwaysOff,
+}
+
+impl DisplayTimeout {
+    /// Longest timeout that can be stored, in seconds
+    pub const MAX_SECONDS: u32 = STORED_ALWAYS_OFF as u32 - 1;
+
+    /// Seconds, `NEVER` or `OFF`, ignoring case
+    pub fn parse(word: &str) -> Option<Self> {
+        if word.eq_ignore_ascii_case("NEVER") {
+            return Some(DisplayTimeout::Never);
+        }
+        if word.eq_ignore_ascii_case("OFF") {
+            return Some(DisplayTimeout::AlwaysOff);
+        }
+        let secs = word.parse().ok()?;
+        (1..=Self::MAX_SECONDS)
+            .contains(&secs)
+            .then_some(DisplayTimeout::After(secs))
+    }
+
+    fn to_stored(self) -> u16 {
+        match self {
+            DisplayTimeout::After(secs) => secs.clamp(1, Self::MAX_SECONDS) as u16,
+            DisplayTimeout::Never => STORED_NEVER,
+            DisplayTimeout::AlwaysOff => STORED_ALWAYS_OFF,
+        }
+    }
+
+    fn from_stored(stored: u16) -> Self {
+        match stored {
+            STORED_NEVER => DisplayTimeout::Never,
+            STORED_ALWAYS_OFF => DisplayTimeout::AlwaysOff,
+            secs => DisplayTimeout::After(secs as u32),
+        }
+    }
+
+    /// Index in [TIMEOUT_CHOICES], other timeouts count as the next longer choice
+    fn choice(self) -> usize {
+        TIMEOUT_CHOICES
+            .iter()
+            .position(|choice| match (self, *choice) {
+                (DisplayTimeout::After(secs), DisplayTimeout::After(choice)) => secs <= choice,
+                (timeout, choice) => timeout == choice,
+            })
+            .unwrap_or(TIMEOUT_CHOICES.len() - 1)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Settings {
+    pub display_timeout: DisplayTimeout,
+    /// Contrast of the display while it is on
+    pub contrast: u8,
+    /// How often (ms) the serial task checks whether a new frame needs to be sent
+    pub serial_period: u32,
+    /// Pots that read 1023 at the bottom, e.g. when mounted upside down
+    pub inverted: [bool; INPUT_COUNT],
+    /// Replaces [ChannelConfig::taper] of each pot. Not used with `oversampling`
+    pub tapers: [Taper; INPUT_COUNT],
+    pub theme: ThemeName,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = {
+        let mut tapers = [Taper::Linear; INPUT_COUNT];
+        let mut idx = 0;
+        while idx < INPUT_COUNT {
+            tapers[idx] = CHANNEL_CONFIGS[idx].taper;
+            idx += 1;
+        }
+        Self {
+            display_timeout: DisplayTimeout::After(DISPLAY_ON_TIME),
+            contrast: DISPLAY_CONTRAST,
+            serial_period: SERIAL_UPDATE_PERIOD,
+            inverted: [false; INPUT_COUNT],
+            tapers,
+            theme: ThemeName::Dark,
+        }
+    };
+
+    /// Flips the values (0-1023) of the [Settings::inverted] pots
+    pub fn invert(&self, values: &[u16; INPUT_COUNT]) -> [u16; INPUT_COUNT] {
+        core::array::from_fn(|idx| {
+            if self.inverted[idx] {
+                1023 - values[idx].min(1023)
+            } else {
+                values[idx]
+            }
+        })
+    }
+
+    /// `configs` with the [Settings::tapers], e.g. for [crate::profiles::Profile::tapers]
+    pub fn channel_configs(
+        &self,
+        configs: &[ChannelConfig; INPUT_COUNT],
+    ) -> [ChannelConfig; INPUT_COUNT] {
+        core::array::from_fn(|idx| ChannelConfig {
+            taper: self.tapers[idx],
+            ..configs[idx]
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; STORED_LEN] {
+        let mut bytes = [0; STORED_LEN];
+        bytes[0..2].copy_from_slice(&MAGIC);
+        bytes[2] = INPUT_COUNT as u8;
+        bytes[3..5].copy_from_slice(&self.display_timeout.to_stored().to_le_bytes());
+        bytes[5] = self.contrast;
+        bytes[6..8].copy_from_slice(&(self.serial_period as u16).to_le_bytes());
+        bytes[8] = self.theme as u8;
+        for (idx, flags) in bytes[FLAGS_OFFSET..FLAGS_OFFSET + INPUT_COUNT]
+            .iter_mut()
+            .enumerate()
+        {
+            *flags = if self.inverted[idx] { FLAG_INVERTED } else { 0 }
+                | if self.tapers[idx] == Taper::Audio {
+                    FLAG_AUDIO_TAPER
+                } else {
+                    0
+                };
+        }
+        bytes[STORED_LEN - 1] = crc8(&bytes[..STORED_LEN - 1]);
+        bytes
+    }
+
+    /// `None` for erased flash, a different channel count or a broken CRC
+    pub fn from_bytes(bytes: &[u8; STORED_LEN]) -> Option<Self> {
+        if bytes[0..2] != MAGIC
+            || bytes[2] != INPUT_COUNT as u8
+            || bytes[STORED_LEN - 1] != crc8(&bytes[..STORED_LEN - 1])
+        {
+            return None;
+        }
+        let flags = &bytes[FLAGS_OFFSET..FLAGS_OFFSET + INPUT_COUNT];
+        Some(Self {
+            display_timeout: DisplayTimeout::from_stored(u16::from_le_bytes([bytes[3], bytes[4]])),
+            contrast: bytes[5],
+            serial_period: u16::from_le_bytes([bytes[6], bytes[7]]) as u32,
+            inverted: core::array::from_fn(|idx| flags[idx] & FLAG_INVERTED != 0),
+            tapers: core::array::from_fn(|idx| {
+                if flags[idx] & FLAG_AUDIO_TAPER != 0 {
+                    Taper::Audio
+                } else {
+                    Taper::Linear
+                }
+            }),
+            // A theme of a newer firmware is the default one
+            theme: ThemeName::ALL
+                .get(bytes[8] as usize)
+                .copied()
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Item of the menu at each index
+enum Item {
+    DisplayTimeout,
+    Contrast,
+    SerialPeriod,
+    Inverted(usize),
+    Taper(usize),
+    Theme,
+    Exit,
+}
+
+impl Item {
+    fn at(idx: usize) -> Self {
+        match idx {
+            0 => Item::DisplayTimeout,
+            1 => Item::Contrast,
+            2 => Item::SerialPeriod,
+            idx if idx < 3 + INPUT_COUNT => Item::Inverted(idx - 3),
+            idx if idx < 3 + 2 * INPUT_COUNT => Item::Taper(idx - 3 - INPUT_COUNT),
+            idx if idx < ITEM_COUNT - 1 => Item::Theme,
+            _ => Item::Exit,
+        }
+    }
+}
+
+impl Menu for Settings {
+    fn item_count(&self) -> usize {
+        ITEM_COUNT
+    }
+
+    fn label(&self, item: usize) -> Label {
+        match Item::at(item) {
+            Item::DisplayTimeout => format_truncated(format_args!("Timeout")),
+            Item::Contrast => format_truncated(format_args!("Contrast")),
+            Item::SerialPeriod => format_truncated(format_args!("Serial")),
+            Item::Inverted(channel) => format_truncated(format_args!("Invert CH{}", channel)),
+            Item::Taper(channel) => format_truncated(format_args!("Taper CH{}", channel)),
+            Item::Theme => format_truncated(format_args!("Theme")),
+            Item::Exit => format_truncated(format_args!("Save & exit")),
+        }
+    }
+
+    fn kind(&self, item: usize) -> ItemKind {
+        match Item::at(item) {
+            Item::DisplayTimeout => ItemKind::Choice(TIMEOUT_NAMES),
+            Item::Contrast => ItemKind::Number {
+                min: 0,
+                max: 255,
+                step: 16,
+                unit: "",
+            },
+            Item::SerialPeriod => ItemKind::Number {
+                min: *SERIAL_PERIOD_RANGE.start() as i32,
+                max: *SERIAL_PERIOD_RANGE.end() as i32,
+                step: 10,
+                unit: "ms",
+            },
+            Item::Inverted(_) => ItemKind::Toggle,
+            Item::Taper(_) => ItemKind::Choice(TAPER_NAMES),
+            Item::Theme => ItemKind::Choice(ThemeName::NAMES),
+            Item::Exit => ItemKind::Exit,
+        }
+    }
+
+    fn value(&self, item: usize) -> i32 {
+        match Item::at(item) {
+            Item::DisplayTimeout => self.display_timeout.choice() as i32,
+            Item::Contrast => self.contrast as i32,
+            Item::SerialPeriod => self.serial_period as i32,
+            Item::Inverted(channel) => self.inverted[channel] as i32,
+            Item::Taper(channel) => (self.tapers[channel] == Taper::Audio) as i32,
+            Item::Theme => self.theme as i32,
+            Item::Exit => 0,
+        }
+    }
+
+    fn set_value(&mut self, item: usize, value: i32) {
+        match Item::at(item) {
+            Item::DisplayTimeout => self.display_timeout = TIMEOUT_CHOICES[value as usize],
+            Item::Contrast => self.contrast = value as u8,
+            Item::SerialPeriod => self.serial_period = value as u32,
+            Item::Inverted(channel) => self.inverted[channel] = value != 0,
+            Item::Taper(channel) => {
+                self.tapers[channel] = if value != 0 {
+                    Taper::Audio
+                } else {
+                    Taper::Linear
+                }
+            }
+            Item::Theme => self.theme = ThemeName::ALL[value as usize],
+            Item::Exit => (),
+        }
+    }
+}
+
+/// Settings saved by the latest [save], `None` when nothing valid was saved yet
+#[cfg(feature = "settings")]
+pub fn load(flash: &mut esp_storage::FlashStorage) -> Option<Settings> {
+    use embedded_storage::ReadStorage;
+
+    let mut bytes = [0; STORED_LEN];
+    flash.read(SETTINGS_OFFSET, &mut bytes).ok()?;
+    Settings::from_bytes(&bytes)
+}
+
+/// Blocks while the sector is erased and written, tens of milliseconds
+#[cfg(feature = "settings")]
+pub fn save(
+    flash: &mut esp_storage::FlashStorage,
+    settings: &Settings,
+) -> Result<(), esp_storage::FlashStorageError> {
+    use embedded_storage::Storage;
+
+    flash.write(SETTINGS_OFFSET, &settings.to_bytes())?;
+    crate::log::info!("Settings saved");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::menu::{MenuCursor, MenuEvent};
+
+    #[test]
+    fn menu_edits_settings_that_survive_flash() {
+        let mut settings = Settings::DEFAULT;
+        let mut cursor = MenuCursor::new();
+
+        // Contrast up by two steps
+        assert_eq!(cursor.turn(&mut settings, 1), Some(MenuEvent::Moved));
+        assert_eq!(cursor.push(&mut settings), MenuEvent::Moved);
+        assert_eq!(cursor.turn(&mut settings, 2), Some(MenuEvent::Changed(1)));
+        assert_eq!(settings.contrast, DISPLAY_CONTRAST + 32);
+        assert_eq!(cursor.push(&mut settings), MenuEvent::Moved);
+
+        // Toggles flip without editing, the selection stops at the last item
+        assert_eq!(cursor.turn(&mut settings, 2), Some(MenuEvent::Moved));
+        assert_eq!(cursor.push(&mut settings), MenuEvent::Changed(3));
+        assert!(settings.inverted[0]);
+        assert_eq!(settings.invert(&[1023; INPUT_COUNT])[..2], [0, 1023]);
+        let view: SettingsView = cursor.view(&settings);
+        assert_eq!(view.rows[3].0, "Invert CH0");
+        assert_eq!(view.rows[3].1, "On");
+
+        assert_eq!(cursor.turn(&mut settings, 100), Some(MenuEvent::Moved));
+        assert_eq!(cursor.turn(&mut settings, 1), None);
+        assert_eq!(cursor.push(&mut settings), MenuEvent::Exit);
+        settings.set_value(ITEM_COUNT - 2, 1);
+        assert_eq!(settings.theme, ThemeName::Light);
+
+        let bytes = settings.to_bytes();
+        assert_eq!(Settings::from_bytes(&bytes), Some(settings));
+        assert_eq!(Settings::from_bytes(&[0xff; STORED_LEN]), None);
+        settings.display_timeout = DisplayTimeout::After(20);
+        assert_eq!(settings.value(0), 3);
+        for timeout in [DisplayTimeout::Never, DisplayTimeout::AlwaysOff] {
+            settings.display_timeout = timeout;
+            assert_eq!(Settings::from_bytes(&settings.to_bytes()), Some(settings));
+        }
+
+        let mut corrupted = bytes;
+        corrupted[5] ^= 1;
+        assert_eq!(Settings::from_bytes(&corrupted), None);
+    }
+}
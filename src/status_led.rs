@@ -0,0 +1,120 @@
+#[cfg(feature = "status-led")]
+use esp_hal::rmt::Channel;
+#[cfg(feature = "status-led")]
+use esp_hal_smartled::SmartLedsAdapter;
+#[cfg(feature = "status-led")]
+use smart_leds::{brightness, SmartLedsWrite, RGB8};
+
+#[cfg(feature = "status-led")]
+use crate::globals::LED_BRIGHTNESS;
+use crate::{
+    globals::{STATUS_BLINK_PERIOD, STATUS_BREATHE_PERIOD, STATUS_ERROR_TIME},
+    log::debug,
+};
+
+/// Red, green and blue, 0-255
+pub type Rgb = [u8; 3];
+
+const CONNECTED_COLOR: Rgb = [0, 255, 0];
+const PAIRING_COLOR: Rgb = [0, 0, 255];
+const ERROR_COLOR: Rgb = [255, 0, 0];
+const OFF: Rgb = [0, 0, 0];
+
+/// Reported by the other tasks
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StatusEvent {
+    /// Host said hello over serial or a wireless link was established
+    HostConnected,
+    HostDisconnected,
+    /// Waiting for a BLE central to connect
+    Pairing,
+    /// Something failed, shown for [STATUS_ERROR_TIME] on top of the other states
+    Error,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum Status {
+    /// Breathing white
+    #[default]
+    Idle,
+    /// Solid green
+    Connected,
+    /// Solid blue
+    Pairing,
+}
+
+/// Turns [StatusEvent]s into the color of the status LED
+#[derive(Default)]
+pub struct StatusIndicator {
+    status: Status,
+    /// Red blinking is shown until this time
+    error_until: Option<u64>,
+}
+
+impl StatusIndicator {
+    pub fn handle(&mut self, event: StatusEvent, now_ms: u64) {
+        debug!("Status event {}", event);
+        match event {
+            StatusEvent::HostConnected => self.status = Status::Connected,
+            StatusEvent::HostDisconnected => self.status = Status::Idle,
+            StatusEvent::Pairing => self.status = Status::Pairing,
+            StatusEvent::Error => self.error_until = Some(now_ms + STATUS_ERROR_TIME),
+        }
+    }
+
+    pub fn color(&self, now_ms: u64) -> Rgb {
+        if self.error_until.is_some_and(|until_ms| now_ms < until_ms) {
+            let on = now_ms % STATUS_BLINK_PERIOD < STATUS_BLINK_PERIOD / 2;
+            return if on { ERROR_COLOR } else { OFF };
+        }
+        match self.status {
+            Status::Idle => {
+                // Triangle wave from off to full and back over one period
+                let half = STATUS_BREATHE_PERIOD / 2;
+                let phase = now_ms % STATUS_BREATHE_PERIOD;
+                let level = if phase < half {
+                    phase * 255 / half
+                } else {
+                    (STATUS_BREATHE_PERIOD - phase) * 255 / half
+                } as u8;
+                [level; 3]
+            }
+            Status::Connected => CONNECTED_COLOR,
+            Status::Pairing => PAIRING_COLOR,
+        }
+    }
+}
+
+/// RMT pulses needed for a single LED, 24 and an end marker
+#[cfg(feature = "status-led")]
+pub const STATUS_LED_BUFFER_SIZE: usize = 24 + 1;
+
+#[cfg(feature = "status-led")]
+pub type StatusLedStrip = SmartLedsAdapter<Channel<0>, STATUS_LED_BUFFER_SIZE>;
+
+/// Single WS2812 LED, e.g. the one on GPIO8 of the ESP32-C3 DevKits
+#[cfg(feature = "status-led")]
+pub struct StatusLed {
+    strip: StatusLedStrip,
+    /// The LED is only written when the color changes
+    shown: Option<Rgb>,
+}
+
+#[cfg(feature = "status-led")]
+impl StatusLed {
+    pub fn new(strip: StatusLedStrip) -> Self {
+        Self { strip, shown: None }
+    }
+
+    pub fn show(&mut self, color: Rgb) {
+        if self.shown == Some(color) {
+            return;
+        }
+        let [r, g, b] = color;
+        self.strip
+            .write(brightness([RGB8::new(r, g, b)].into_iter(), LED_BRIGHTNESS))
+            .unwrap(); // TODO propagate error?
+        self.shown = Some(color);
+    }
+}
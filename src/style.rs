@@ -1,18 +1,32 @@
 use embedded_graphics::{
-    mono_font::{ascii::{FONT_6X10, FONT_8X13}, MonoTextStyle, MonoTextStyleBuilder},
+    mono_font::{ascii::{FONT_10X20, FONT_5X8, FONT_6X10, FONT_8X13}, MonoTextStyle, MonoTextStyleBuilder},
     pixelcolor::BinaryColor, primitives::{PrimitiveStyle, PrimitiveStyleBuilder},
 };
 
+use crate::widgets::BarStyle;
+
 pub const TEXT_STYLE: MonoTextStyle<'static, BinaryColor> = MonoTextStyleBuilder::new()
     .font(&FONT_6X10)
     .text_color(BinaryColor::On)
     .build();
 
+/// Used when the channels do not fit on the display with [TEXT_STYLE]
+pub const TEXT_STYLE_SMALL: MonoTextStyle<'static, BinaryColor> = MonoTextStyleBuilder::new()
+    .font(&FONT_5X8)
+    .text_color(BinaryColor::On)
+    .build();
+
 pub const TEXT_STYLE_BOLD: MonoTextStyle<'static, BinaryColor> = MonoTextStyleBuilder::new()
     .font(&FONT_8X13)
     .text_color(BinaryColor::On)
     .build();
 
+/// Value of the zoomed channel
+pub const TEXT_STYLE_LARGE: MonoTextStyle<'static, BinaryColor> = MonoTextStyleBuilder::new()
+    .font(&FONT_10X20)
+    .text_color(BinaryColor::On)
+    .build();
+
 pub const OUTER_RECT_STYLE: PrimitiveStyle<BinaryColor> = PrimitiveStyleBuilder::new()
     .stroke_color(BinaryColor::On)
     .stroke_width(1)
@@ -22,3 +36,103 @@ pub const OUTER_RECT_STYLE: PrimitiveStyle<BinaryColor> = PrimitiveStyleBuilder:
 pub const FILL_RECT_STYLE: PrimitiveStyle<BinaryColor> = PrimitiveStyleBuilder::new()
     .fill_color(BinaryColor::On)
     .build();
+
+/// Thin lines, e.g. the cross over a muted bar
+pub const LINE_STYLE: PrimitiveStyle<BinaryColor> = PrimitiveStyleBuilder::new()
+    .stroke_color(BinaryColor::On)
+    .stroke_width(1)
+    .build();
+
+/// Cuts into a [FILL_RECT_STYLE] area, e.g. a marker inside the filled part of a bar
+pub const CLEAR_RECT_STYLE: PrimitiveStyle<BinaryColor> = PrimitiveStyleBuilder::new()
+    .fill_color(BinaryColor::Off)
+    .build();
+
+/// [TEXT_STYLE] on a [FILL_RECT_STYLE] area, e.g. the selected item of the menu
+pub const TEXT_STYLE_INVERTED: MonoTextStyle<'static, BinaryColor> = MonoTextStyleBuilder::new()
+    .font(&FONT_6X10)
+    .text_color(BinaryColor::Off)
+    .build();
+
+/// Looks of the display that can change at runtime, picked with [ThemeName]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Theme {
+    /// Title at the top of the display
+    pub title_style: MonoTextStyle<'static, BinaryColor>,
+    /// Value of the zoomed channel, [TEXT_STYLE_BOLD] when the page is too low for it
+    pub value_style: MonoTextStyle<'static, BinaryColor>,
+    /// How the volume bars are drawn, muted channels are [BarStyle::CrossedOut] in every theme
+    pub bar_style: BarStyle,
+    /// Dark on a lit display, the panel inverts the whole frame. The screensaver still inverts it
+    /// every other cycle.
+    pub inverted: bool,
+}
+
+impl Theme {
+    pub const DARK: Self = Self {
+        title_style: TEXT_STYLE_BOLD,
+        value_style: TEXT_STYLE_LARGE,
+        bar_style: BarStyle::Filled,
+        inverted: false,
+    };
+    pub const LIGHT: Self = Self { inverted: true, ..Self::DARK };
+    /// Bars in segments with tick marks, small differences are easier to read
+    pub const SEGMENTED: Self = Self {
+        bar_style: BarStyle::Segmented,
+        ..Self::DARK
+    };
+    /// Smaller text, for displays that are read from up close
+    pub const COMPACT: Self = Self {
+        title_style: TEXT_STYLE,
+        value_style: TEXT_STYLE_BOLD,
+        ..Self::DARK
+    };
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::DARK
+    }
+}
+
+/// Themes of the settings menu and `!set theme`, kept in the settings
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    Compact,
+    Segmented,
+}
+
+impl ThemeName {
+    pub const ALL: [ThemeName; 4] = [
+        ThemeName::Dark,
+        ThemeName::Light,
+        ThemeName::Compact,
+        ThemeName::Segmented,
+    ];
+    /// In the order of [ThemeName::ALL]
+    pub const NAMES: &'static [&'static str] = &["Dark", "Light", "Compact", "Segmented"];
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    /// Name of the theme, ignoring case
+    pub fn parse(word: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|theme| theme.name().eq_ignore_ascii_case(word))
+    }
+
+    pub fn theme(self) -> Theme {
+        match self {
+            ThemeName::Dark => Theme::DARK,
+            ThemeName::Light => Theme::LIGHT,
+            ThemeName::Compact => Theme::COMPACT,
+            ThemeName::Segmented => Theme::SEGMENTED,
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Supply voltage read through a divider on a spare ADC1 pin, see `supply` in board.toml. While it
+//! sags below [SUPPLY_LOW_MV] the display shows LOW V and runs at [crate::globals::SUPPLY_LOW_CONTRAST],
+//! which helps on long USB cables and battery builds where a bright OLED pulls the supply down further.
+
+use crate::{
+    globals::{SUPPLY_FULL_SCALE_MV, SUPPLY_HYSTERESIS_MV, SUPPLY_LOW_MV},
+    log::info,
+};
+
+/// Supply voltage (mV) of a `raw` reading of the supply pin
+pub fn millivolts(raw: u16) -> u32 {
+    raw as u32 * SUPPLY_FULL_SCALE_MV / 4095
+}
+
+#[derive(Default)]
+pub struct SupplyMonitor {
+    low: bool,
+}
+
+impl SupplyMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compares the latest supply voltage with [SUPPLY_LOW_MV]. Returns true when the supply went
+    /// low or recovered by [SUPPLY_HYSTERESIS_MV], the display has to be updated then.
+    pub fn update(&mut self, millivolts: u32) -> bool {
+        let low = if self.low {
+            millivolts < SUPPLY_LOW_MV + SUPPLY_HYSTERESIS_MV
+        } else {
+            millivolts < SUPPLY_LOW_MV
+        };
+        if low == self.low {
+            return false;
+        }
+        self.low = low;
+        info!(
+            "Supply {} at {} mV",
+            if low { "low" } else { "recovered" },
+            millivolts
+        );
+        true
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_with_hysteresis() {
+        let mut monitor = SupplyMonitor::new();
+        assert!(!monitor.update(SUPPLY_LOW_MV));
+        assert!(monitor.update(SUPPLY_LOW_MV - 1));
+        assert!(monitor.is_low());
+        assert!(!monitor.update(SUPPLY_LOW_MV + 1));
+        assert!(monitor.update(SUPPLY_LOW_MV + SUPPLY_HYSTERESIS_MV));
+        assert!(!monitor.is_low());
+    }
+}
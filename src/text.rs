@@ -0,0 +1,86 @@
+//! Formatting into the fixed size strings of the display and the serial replies without panicking.
+//! Text that does not fit is cut and ends in [ELLIPSIS], so it still shows that something is
+//! missing.
+
+use core::fmt::{self, Write};
+
+use heapless::String;
+
+use crate::error::DeejError;
+
+/// Ends a cut text, ASCII like the fonts of [crate::style]
+pub const ELLIPSIS: &str = "...";
+/// Ends every text line of the serial protocol
+const LINE_END: &str = "\r\n";
+
+/// Keeps the start of what is written and drops the rest
+struct Truncated<'a, const N: usize>(&'a mut String<N>);
+
+impl<const N: usize> Write for Truncated<'_, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.0.push(c).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends `args` to `text`, e.g. `write_truncated(&mut text, format_args!("CH{}", idx))`. When
+/// it does not fit, as much as fits before [ELLIPSIS] is kept and [DeejError::Format] returned.
+pub fn write_truncated<const N: usize>(
+    text: &mut String<N>,
+    args: fmt::Arguments,
+) -> Result<(), DeejError> {
+    if Truncated(text).write_fmt(args).is_ok() {
+        return Ok(());
+    }
+    while text.len() + ELLIPSIS.len() > N && text.pop().is_some() {}
+    text.push_str(&ELLIPSIS[..ELLIPSIS.len().min(N)]).ok();
+    Err(DeejError::Format)
+}
+
+/// Ends a line sent to the host with `\r\n`. When there is no room left the end of the text gives
+/// way, so the host still gets a whole line.
+pub fn end_line<const N: usize>(text: &mut String<N>) {
+    while text.len() + LINE_END.len() > N && text.pop().is_some() {}
+    text.push_str(&LINE_END[..LINE_END.len().min(N)]).ok();
+}
+
+/// `args` in a new string, cut like [write_truncated] when it does not fit
+pub fn format_truncated<const N: usize>(args: fmt::Arguments) -> String<N> {
+    let mut text = String::new();
+    write_truncated(&mut text, args).ok();
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_with_an_ellipsis() {
+        let mut text: String<8> = String::new();
+        assert_eq!(write_truncated(&mut text, format_args!("CH{}", 3)), Ok(()));
+        assert_eq!(text, "CH3");
+        assert_eq!(
+            write_truncated(&mut text, format_args!(" {}", 123456)),
+            Err(DeejError::Format)
+        );
+        assert_eq!(text, "CH3 1...");
+
+        let text: String<2> = format_truncated(format_args!("{}", 100));
+        assert_eq!(text, "..");
+    }
+
+    #[test]
+    fn line_end_makes_room() {
+        let mut text: String<6> = String::new();
+        write_truncated(&mut text, format_args!("OK")).unwrap();
+        end_line(&mut text);
+        assert_eq!(text, "OK\r\n");
+
+        let mut text: String<6> = format_truncated(format_args!("{}", 123456789));
+        end_line(&mut text);
+        assert_eq!(text, "123.\r\n");
+    }
+}
@@ -0,0 +1,131 @@
+//! Touch sensing on the slider caps, so the firmware knows a hand is on a slider before it moves.
+//! The ESP32-C3 has no touch peripheral, each cap is timed instead: it is wired to a spare GPIO,
+//! see `touch` in board.toml, and through a 1 MOhm resistor to 3.3 V. The pin discharges the cap,
+//! lets go and counts how long the resistor takes to charge it up again. A finger adds
+//! capacitance and so time.
+//!
+//! A touch wakes the display right away and holds the motor of a motorized fader.
+
+// InputPin is behind the `unproven` feature of embedded-hal, which esp-hal enables
+#[cfg(feature = "hal")]
+use embedded_hal_027::digital::v2::{InputPin, OutputPin};
+
+use crate::globals::TOUCH_THRESHOLD_PERCENT;
+#[cfg(feature = "hal")]
+use crate::{
+    globals::{INPUT_COUNT, TOUCH_MAX_COUNT, TOUCH_SAMPLES},
+    log::debug,
+};
+
+/// Decides from the charge times of a cap whether it is touched
+#[derive(Default)]
+pub struct TouchDetector {
+    /// Charge time of the cap without a finger, follows the slow drift with temperature and
+    /// humidity
+    baseline: Option<u32>,
+    touched: bool,
+}
+
+impl TouchDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give the charge time of a reading, returns whether the cap is touched. The first reading
+    /// is taken as untouched.
+    pub fn update(&mut self, count: u32) -> bool {
+        let baseline = *self.baseline.get_or_insert(count);
+        let threshold = (baseline * TOUCH_THRESHOLD_PERCENT / 100).max(1);
+        // Let go at half the threshold, so a finger right at it does not flicker
+        self.touched = if self.touched {
+            count > baseline + threshold / 2
+        } else {
+            count >= baseline + threshold
+        };
+        if !self.touched {
+            self.baseline = Some((baseline * 15 + count) / 16);
+        }
+        self.touched
+    }
+
+    pub fn is_touched(&self) -> bool {
+        self.touched
+    }
+}
+
+/// Caps of the sliders, one per channel. The pins are open-drain outputs, driven low to discharge
+/// and released to let the resistor charge them, and read back while they charge.
+#[cfg(feature = "hal")]
+pub struct TouchPads<P> {
+    pins: [P; INPUT_COUNT],
+    detectors: [TouchDetector; INPUT_COUNT],
+}
+
+#[cfg(feature = "hal")]
+impl<P: InputPin + OutputPin> TouchPads<P> {
+    pub fn new(pins: [P; INPUT_COUNT]) -> Self {
+        Self {
+            pins,
+            detectors: core::array::from_fn(|_| TouchDetector::new()),
+        }
+    }
+
+    /// Reads every cap, returns true when one of them was just touched
+    pub fn update(&mut self) -> bool {
+        let mut new_touch = false;
+        for (channel, (pin, detector)) in self.pins.iter_mut().zip(&mut self.detectors).enumerate()
+        {
+            let count = (0..TOUCH_SAMPLES).map(|_| charge_time(pin)).sum();
+            let was_touched = detector.is_touched();
+            if detector.update(count) && !was_touched {
+                debug!("Slider {} touched", channel);
+                new_touch = true;
+            }
+        }
+        new_touch
+    }
+
+    /// Whether each slider is touched, as of the last [TouchPads::update]
+    pub fn touched(&self) -> [bool; INPUT_COUNT] {
+        core::array::from_fn(|channel| self.detectors[channel].is_touched())
+    }
+}
+
+/// Polls of `pin` until its cap charged up to a high level, [TOUCH_MAX_COUNT] at most. Timed
+/// with the interrupts off, an interrupt in between would read as a touch.
+#[cfg(feature = "hal")]
+fn charge_time<P: InputPin + OutputPin>(pin: &mut P) -> u32 {
+    critical_section::with(|_| {
+        pin.set_low().ok();
+        // The pin empties the few pF of a cap in nanoseconds, this is plenty
+        for _ in 0..8 {
+            core::hint::spin_loop();
+        }
+        pin.set_high().ok();
+        let mut count = 0;
+        while count < TOUCH_MAX_COUNT && pin.is_low().unwrap_or(false) {
+            count += 1;
+        }
+        count
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touch_over_the_threshold() {
+        let mut detector = TouchDetector::new();
+        assert!(!detector.update(100));
+        // Slow drift is followed
+        for _ in 0..50 {
+            assert!(!detector.update(110));
+        }
+        let touch = 110 + 110 * TOUCH_THRESHOLD_PERCENT / 100 + 5;
+        assert!(detector.update(touch));
+        // Held until well below the threshold
+        assert!(detector.update(touch - 10));
+        assert!(!detector.update(110));
+    }
+}
@@ -0,0 +1,59 @@
+use heapless::String;
+
+use crate::text::format_truncated;
+
+/// `20 * log10(v / 100)` rounded, for volumes 1-100
+#[rustfmt::skip]
+const DB_TABLE: [i8; 100] = [
+    -40, -34, -30, -28, -26, -24, -23, -22, -21, -20,
+    -19, -18, -18, -17, -16, -16, -15, -15, -14, -14,
+    -14, -13, -13, -12, -12, -12, -11, -11, -11, -10,
+    -10, -10, -10,  -9,  -9,  -9,  -9,  -8,  -8,  -8,
+     -8,  -8,  -7,  -7,  -7,  -7,  -7,  -6,  -6,  -6,
+     -6,  -6,  -6,  -5,  -5,  -5,  -5,  -5,  -5,  -4,
+     -4,  -4,  -4,  -4,  -4,  -4,  -3,  -3,  -3,  -3,
+     -3,  -3,  -3,  -3,  -2,  -2,  -2,  -2,  -2,  -2,
+     -2,  -2,  -2,  -2,  -1,  -1,  -1,  -1,  -1,  -1,
+     -1,  -1,  -1,  -1,   0,   0,   0,   0,   0,   0,
+];
+
+/// Text of a single value label, long enough for every [Units]
+pub type ValueLabel = String<8>;
+
+/// How the volume next to each bar is shown
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Units {
+    /// 0-100
+    #[default]
+    Percent,
+    /// Approximate attenuation, `-inf` at 0 and `0dB` at 100
+    Decibel,
+}
+
+impl Units {
+    /// Characters in the longest label, the layout reserves room for it
+    pub fn max_label_len(self) -> usize {
+        match self {
+            Units::Percent => 3,
+            Units::Decibel => 5,
+        }
+    }
+
+    /// `value` in range 0-100
+    pub fn format(self, value: u16) -> ValueLabel {
+        match (self, to_db(value)) {
+            (Units::Percent, _) => format_truncated(format_args!("{}", value)),
+            (Units::Decibel, Some(db)) => format_truncated(format_args!("{}dB", db)),
+            (Units::Decibel, None) => format_truncated(format_args!("-inf")),
+        }
+    }
+}
+
+/// Attenuation in dB of a volume in range 0-100, `None` is silence
+pub fn to_db(value: u16) -> Option<i8> {
+    match value {
+        0 => None,
+        v => Some(DB_TABLE[v.min(100) as usize - 1]),
+    }
+}
@@ -0,0 +1,109 @@
+//! Task watchdog in the manner of the one of ESP-IDF: the hardware watchdog is only fed once every
+//! supervised task has checked in, so a single stuck task resets the chip as well. A reset by any
+//! of the watchdogs is reported on the next boot as a [crate::reset::ResetReason::Watchdog].
+
+/// Task that has to check in within [crate::globals::WATCHDOG_TIMEOUT]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WatchedTask {
+    /// Idle loop reading the pots
+    Sampling,
+    /// Task sending the frames to the host
+    Serial,
+}
+
+impl WatchedTask {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+pub struct TaskWatchdog {
+    watched: u8,
+    checked_in: u8,
+}
+
+impl TaskWatchdog {
+    pub fn new(tasks: &[WatchedTask]) -> Self {
+        Self {
+            watched: tasks.iter().fold(0, |bits, task| bits | task.bit()),
+            checked_in: 0,
+        }
+    }
+
+    /// Records that `task` still runs. Returns true once every watched task has checked in since
+    /// the previous time, the hardware watchdog has to be fed then. Unwatched tasks are ignored.
+    pub fn check_in(&mut self, task: WatchedTask) -> bool {
+        self.checked_in |= task.bit() & self.watched;
+        if self.checked_in != self.watched {
+            return false;
+        }
+        self.checked_in = 0;
+        true
+    }
+}
+
+/// Watchdog that reset the chip
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WatchdogReset {
+    /// TIMG1 watchdog fed through [TaskWatchdog], a watched task stopped checking in
+    Task,
+    /// TIMG0 watchdog
+    Timer,
+    /// RTC watchdog, e.g. armed by the bootloader
+    Rtc,
+    /// Super watchdog of the RTC
+    Super,
+}
+
+impl WatchdogReset {
+    /// Name of the watchdog on [crate::pages::Screen::Info]
+    pub fn name(self) -> &'static str {
+        match self {
+            WatchdogReset::Task => "Task watchdog",
+            WatchdogReset::Timer => "Timer watchdog",
+            WatchdogReset::Rtc => "RTC watchdog",
+            WatchdogReset::Super => "Super watchdog",
+        }
+    }
+
+    /// Name of the watchdog over serial
+    pub fn label(self) -> &'static str {
+        match self {
+            WatchdogReset::Task => "TASK",
+            WatchdogReset::Timer => "TIMER",
+            WatchdogReset::Rtc => "RTC",
+            WatchdogReset::Super => "SUPER",
+        }
+    }
+
+    /// Banner of [crate::pages::SplashPage], see [crate::reset::ResetReason::banner]
+    pub fn banner(self) -> &'static str {
+        match self {
+            WatchdogReset::Task => "Task watchdog reset",
+            WatchdogReset::Timer => "Timer watchdog reset",
+            WatchdogReset::Rtc => "RTC watchdog reset",
+            WatchdogReset::Super => "Super watchdog reset",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feeds_once_every_task_checked_in() {
+        let mut watchdog = TaskWatchdog::new(&[WatchedTask::Sampling, WatchedTask::Serial]);
+        assert!(!watchdog.check_in(WatchedTask::Sampling));
+        assert!(!watchdog.check_in(WatchedTask::Sampling));
+        assert!(watchdog.check_in(WatchedTask::Serial));
+        // Starts over after feeding
+        assert!(!watchdog.check_in(WatchedTask::Serial));
+
+        let mut watchdog = TaskWatchdog::new(&[WatchedTask::Serial]);
+        assert!(!watchdog.check_in(WatchedTask::Sampling));
+        assert!(watchdog.check_in(WatchedTask::Serial));
+    }
+}
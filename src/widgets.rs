@@ -0,0 +1,444 @@
+//! Parts of the pages that are drawn the same way in several places. Each widget knows its own
+//! geometry, so where it puts its pixels can be tested without a display.
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, Rectangle},
+    text::{Alignment, Baseline, Text},
+};
+use heapless::Vec;
+
+use crate::{
+    layout::BarOrientation,
+    scale_to_range,
+    style::{CLEAR_RECT_STYLE, FILL_RECT_STYLE, LINE_STYLE, OUTER_RECT_STYLE},
+};
+
+/// Most markers a [VolumeBar] carries, the pot position and the volume of the host
+pub const MAX_MARKERS: usize = 2;
+/// Space (px) between the end of a scrolling [Label] and its repeat
+pub const SCROLL_GAP: u32 = 24;
+/// Values with a tick mark next to a [BarStyle::Segmented] bar
+pub const TICKS: [u16; 3] = [0, 50, 100];
+/// Length (px) of a segment of [BarStyle::Segmented] along the bar
+const SEGMENT_LENGTH: u32 = 3;
+/// Space (px) between two segments, and between the segments and the outline
+const SEGMENT_GAP: u32 = 1;
+/// Strip (px) below a horizontal [BarStyle::Segmented] bar, left of a vertical one, that holds
+/// the tick marks
+const TICK_LENGTH: u32 = 2;
+
+/// How a [VolumeBar] shows its value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BarStyle {
+    /// Filled from the start up to the value
+    Filled,
+    /// Crossed out instead of filled, e.g. a channel muted on the PC
+    CrossedOut,
+    /// Discrete segments up to the value, with tick marks at [TICKS] outside the outline. Easier
+    /// to tell small differences apart than a solid fill.
+    Segmented,
+}
+
+/// Outlined bar filled up to a value (0-100), left to right or bottom to top. Markers are lines
+/// across the bar, cut into the fill or drawn past it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct VolumeBar {
+    pub area: Rectangle,
+    /// Anything but [BarOrientation::Vertical] fills left to right
+    pub orientation: BarOrientation,
+    pub value: u16,
+    pub style: BarStyle,
+    pub markers: Vec<u16, MAX_MARKERS>,
+}
+
+impl VolumeBar {
+    pub fn new(top_left: Point, size: Size, orientation: BarOrientation, value: u16) -> Self {
+        Self {
+            area: Rectangle::new(top_left, size),
+            orientation,
+            value,
+            style: BarStyle::Filled,
+            markers: Vec::new(),
+        }
+    }
+
+    /// Adds a marker at `value`, dropped when there are [MAX_MARKERS] already
+    pub fn add_marker(&mut self, value: u16) {
+        self.markers.push(value).ok();
+    }
+
+    /// Outline of the bar, all of [VolumeBar::area] but the strip of the tick marks
+    pub fn outline(&self) -> Rectangle {
+        let Rectangle { top_left, size } = self.area;
+        match (self.style, self.orientation) {
+            (BarStyle::Segmented, BarOrientation::Vertical) => Rectangle::new(
+                top_left + Point::new(TICK_LENGTH as i32, 0),
+                size.saturating_sub(Size::new(TICK_LENGTH, 0)),
+            ),
+            (BarStyle::Segmented, _) => {
+                Rectangle::new(top_left, size.saturating_sub(Size::new(0, TICK_LENGTH)))
+            }
+            _ => self.area,
+        }
+    }
+
+    /// Filled part of the bar
+    pub fn fill_area(&self) -> Rectangle {
+        let Rectangle { top_left, size } = self.outline();
+        match self.orientation {
+            BarOrientation::Vertical => {
+                let fill = scale_to_range(self.value, 0, 100, 0, size.height as u16) as u32;
+                Rectangle::new(
+                    top_left + Point::new(0, (size.height - fill) as i32),
+                    Size::new(size.width, fill),
+                )
+            }
+            _ => {
+                let fill = scale_to_range(self.value, 0, 100, 0, size.width as u16) as u32;
+                Rectangle::new(top_left, Size::new(fill, size.height))
+            }
+        }
+    }
+
+    /// Line across the bar at `value`, 0 on the first row or column and 100 on the last
+    pub fn marker_area(&self, value: u16) -> Rectangle {
+        let Rectangle { top_left, size } = self.outline();
+        match self.orientation {
+            BarOrientation::Vertical => {
+                let y = scale_to_range(value, 0, 100, 0, size.height as u16 - 1) as u32;
+                Rectangle::new(
+                    top_left + Point::new(0, (size.height - 1 - y) as i32),
+                    Size::new(size.width, 1),
+                )
+            }
+            _ => {
+                let x = scale_to_range(value, 0, 100, 0, size.width as u16 - 1);
+                Rectangle::new(
+                    top_left + Point::new(x as i32, 0),
+                    Size::new(1, size.height),
+                )
+            }
+        }
+    }
+
+    /// Lit segments of [BarStyle::Segmented], a segment is lit once the value reaches into it
+    pub fn segments(&self) -> impl Iterator<Item = Rectangle> {
+        let outline = self.outline();
+        let inset = SEGMENT_GAP + 1;
+        let top_left = outline.top_left + Point::new(inset as i32, inset as i32);
+        let size = outline.size.saturating_sub(Size::new(2 * inset, 2 * inset));
+        let vertical = self.orientation == BarOrientation::Vertical;
+        let length = if vertical { size.height } else { size.width };
+        let filled = scale_to_range(self.value, 0, 100, 0, length as u16) as u32;
+        (0..filled)
+            .step_by((SEGMENT_LENGTH + SEGMENT_GAP) as usize)
+            .map(move |start| {
+                let segment = SEGMENT_LENGTH.min(length - start);
+                if vertical {
+                    Rectangle::new(
+                        top_left + Point::new(0, (size.height - start - segment) as i32),
+                        Size::new(size.width, segment),
+                    )
+                } else {
+                    Rectangle::new(
+                        top_left + Point::new(start as i32, 0),
+                        Size::new(segment, size.height),
+                    )
+                }
+            })
+    }
+
+    /// Tick marks of [BarStyle::Segmented] at [TICKS], in line with [VolumeBar::marker_area]
+    pub fn ticks(&self) -> [Rectangle; TICKS.len()] {
+        let outline = self.outline();
+        TICKS.map(|tick| {
+            let marker = self.marker_area(tick);
+            if self.orientation == BarOrientation::Vertical {
+                Rectangle::new(
+                    Point::new(self.area.top_left.x, marker.top_left.y),
+                    Size::new(TICK_LENGTH, 1),
+                )
+            } else {
+                Rectangle::new(
+                    Point::new(
+                        marker.top_left.x,
+                        outline.top_left.y + outline.size.height as i32,
+                    ),
+                    Size::new(1, TICK_LENGTH),
+                )
+            }
+        })
+    }
+
+    /// Diagonals of [BarStyle::CrossedOut]
+    pub fn cross(&self) -> [Line; 2] {
+        let top_left = self.area.top_left;
+        let bottom_right = top_left + self.area.size - Size::new(1, 1);
+        [
+            Line::new(top_left, bottom_right),
+            Line::new(
+                Point::new(top_left.x, bottom_right.y),
+                Point::new(bottom_right.x, top_left.y),
+            ),
+        ]
+    }
+}
+
+impl Drawable for VolumeBar {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D: DrawTarget<Color = BinaryColor>>(&self, target: &mut D) -> Result<(), D::Error> {
+        self.outline().into_styled(OUTER_RECT_STYLE).draw(target)?;
+        match self.style {
+            BarStyle::CrossedOut => {
+                for line in self.cross() {
+                    line.into_styled(LINE_STYLE).draw(target)?;
+                }
+                return Ok(());
+            }
+            BarStyle::Filled => {
+                self.fill_area().into_styled(FILL_RECT_STYLE).draw(target)?;
+            }
+            BarStyle::Segmented => {
+                for area in self.segments().chain(self.ticks()) {
+                    area.into_styled(FILL_RECT_STYLE).draw(target)?;
+                }
+            }
+        }
+        for &marker in &self.markers {
+            // A gap in the fill, a line past it
+            let style = if marker < self.value {
+                CLEAR_RECT_STYLE
+            } else {
+                FILL_RECT_STYLE
+            };
+            self.marker_area(marker).into_styled(style).draw(target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Width (px) of `text` in the monospaced `style`
+pub fn text_width(text: &str, style: &MonoTextStyle<'static, BinaryColor>) -> u32 {
+    text.chars().count() as u32 * style.font.character_size.width
+}
+
+/// What a [Label] does with text wider than its area
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Overflow {
+    /// Cut after the last character that fits
+    Truncate,
+    /// Moved left by this many pixels, wrapping around with a repeat [SCROLL_GAP] after the end
+    Scroll(u32),
+}
+
+/// Line of text kept inside an area, placed by the alignment when it fits
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Label<'a> {
+    pub text: &'a str,
+    /// Text is drawn from the top of the area
+    pub area: Rectangle,
+    pub style: MonoTextStyle<'static, BinaryColor>,
+    /// [Alignment::Left] or [Alignment::Center], text that does not fit starts on the left
+    pub alignment: Alignment,
+    pub overflow: Overflow,
+}
+
+impl<'a> Label<'a> {
+    pub fn new(text: &'a str, area: Rectangle, style: MonoTextStyle<'static, BinaryColor>) -> Self {
+        Self {
+            text,
+            area,
+            style,
+            alignment: Alignment::Left,
+            overflow: Overflow::Truncate,
+        }
+    }
+
+    pub fn fits(&self) -> bool {
+        text_width(self.text, &self.style) <= self.area.size.width
+    }
+
+    /// Whether the label changes with the offset of [Overflow::Scroll]
+    pub fn scrolls(&self) -> bool {
+        matches!(self.overflow, Overflow::Scroll(_)) && !self.fits()
+    }
+
+    /// Part of the text that is drawn, the characters that fit unless it scrolls
+    pub fn visible_text(&self) -> &'a str {
+        if self.fits() || self.scrolls() {
+            return self.text;
+        }
+        let chars = (self.area.size.width / self.style.font.character_size.width) as usize;
+        self.text
+            .char_indices()
+            .nth(chars)
+            .map_or(self.text, |(end, _)| &self.text[..end])
+    }
+
+    /// Left edge of the text, of its first copy when it scrolls
+    pub fn text_x(&self) -> i32 {
+        let left = self.area.top_left.x;
+        match self.overflow {
+            Overflow::Scroll(offset) if self.scrolls() => {
+                let period = text_width(self.text, &self.style) + SCROLL_GAP;
+                left - (offset % period) as i32
+            }
+            _ if self.alignment == Alignment::Center && self.fits() => {
+                let width = text_width(self.text, &self.style);
+                left + ((self.area.size.width - width) / 2) as i32
+            }
+            _ => left,
+        }
+    }
+}
+
+impl Drawable for Label<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D: DrawTarget<Color = BinaryColor>>(&self, target: &mut D) -> Result<(), D::Error> {
+        let mut target = target.clipped(&self.area);
+        let text = self.visible_text();
+        let x = self.text_x();
+        // Second copy follows the first after a gap, so the text wraps around
+        let copies = if self.scrolls() { 2 } else { 1 };
+        let period = (text_width(text, &self.style) + SCROLL_GAP) as i32;
+        for copy in 0..copies {
+            Text::with_baseline(
+                text,
+                Point::new(x + copy * period, self.area.top_left.y),
+                self.style,
+                Baseline::Top,
+            )
+            .draw(&mut target)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::TEXT_STYLE;
+
+    #[test]
+    fn volume_bar_geometry() {
+        let mut bar = VolumeBar::new(
+            Point::new(20, 4),
+            Size::new(101, 7),
+            BarOrientation::Horizontal,
+            50,
+        );
+        assert_eq!(
+            bar.fill_area(),
+            Rectangle::new(Point::new(20, 4), Size::new(50, 7))
+        );
+        assert_eq!(
+            bar.marker_area(100),
+            Rectangle::new(Point::new(120, 4), Size::new(1, 7))
+        );
+        bar.add_marker(10);
+        bar.add_marker(60);
+        // Full already
+        bar.add_marker(80);
+        assert_eq!(bar.markers, [10, 60]);
+
+        // Grows up from the bottom
+        let bar = VolumeBar::new(
+            Point::new(0, 10),
+            Size::new(8, 40),
+            BarOrientation::Vertical,
+            25,
+        );
+        assert_eq!(
+            bar.fill_area(),
+            Rectangle::new(Point::new(0, 40), Size::new(8, 10))
+        );
+        assert_eq!(
+            bar.marker_area(0),
+            Rectangle::new(Point::new(0, 49), Size::new(8, 1))
+        );
+        assert_eq!(
+            bar.cross()[1],
+            Line::new(Point::new(0, 49), Point::new(7, 10))
+        );
+    }
+
+    #[test]
+    fn segmented_bar_geometry() {
+        // Outline 23x6 above the ticks, segments inside it 19 px long
+        let mut bar = VolumeBar::new(
+            Point::new(0, 0),
+            Size::new(23, 8),
+            BarOrientation::Horizontal,
+            50,
+        );
+        bar.style = BarStyle::Segmented;
+        assert_eq!(
+            bar.outline(),
+            Rectangle::new(Point::new(0, 0), Size::new(23, 6))
+        );
+        let segments: std::vec::Vec<_> = bar.segments().collect();
+        assert_eq!(
+            segments,
+            [
+                Rectangle::new(Point::new(2, 2), Size::new(3, 2)),
+                Rectangle::new(Point::new(6, 2), Size::new(3, 2)),
+                Rectangle::new(Point::new(10, 2), Size::new(3, 2)),
+            ]
+        );
+        assert_eq!(
+            bar.ticks().map(|tick| tick.top_left),
+            [Point::new(0, 6), Point::new(11, 6), Point::new(22, 6)]
+        );
+        bar.value = 100;
+        assert_eq!(
+            bar.segments().last(),
+            Some(Rectangle::new(Point::new(18, 2), Size::new(3, 2)))
+        );
+
+        // Segments from the bottom, ticks on the left
+        let mut bar = VolumeBar::new(
+            Point::new(0, 0),
+            Size::new(10, 24),
+            BarOrientation::Vertical,
+            10,
+        );
+        bar.style = BarStyle::Segmented;
+        assert_eq!(
+            bar.segments().collect::<std::vec::Vec<_>>(),
+            [Rectangle::new(Point::new(4, 19), Size::new(4, 3))]
+        );
+        assert_eq!(
+            bar.ticks()[0],
+            Rectangle::new(Point::new(0, 23), Size::new(2, 1))
+        );
+    }
+
+    #[test]
+    fn label_overflow() {
+        // 6 px per character, room for 5
+        let area = Rectangle::new(Point::new(10, 0), Size::new(32, 10));
+        let mut label = Label::new("Discord", area, TEXT_STYLE);
+        assert!(!label.fits());
+        assert_eq!(label.visible_text(), "Disco");
+        assert_eq!(label.text_x(), 10);
+
+        // 42 px of text and the gap make a period of 66 px
+        label.overflow = Overflow::Scroll(70);
+        assert!(label.scrolls());
+        assert_eq!(label.visible_text(), "Discord");
+        assert_eq!(label.text_x(), 6);
+
+        let mut label = Label::new("Game", area, TEXT_STYLE);
+        label.overflow = Overflow::Scroll(70);
+        label.alignment = Alignment::Center;
+        assert!(!label.scrolls());
+        assert_eq!(label.text_x(), 14);
+    }
+}
@@ -0,0 +1,336 @@
+use core::{fmt::Write as _, str::FromStr};
+
+#[cfg(not(feature = "osc"))]
+use embedded_io::Write;
+use esp_hal::peripherals::WIFI;
+#[cfg(feature = "osc")]
+use esp_wifi::wifi_interface::{UdpSocket, WifiStackError};
+use esp_wifi::{
+    current_millis,
+    wifi::{utils::create_network_interface, ClientConfiguration, Configuration, WifiStaDevice},
+    wifi_interface::WifiStack,
+    EspWifiInitialization,
+};
+#[cfg(feature = "osc")]
+use smoltcp::socket::udp::PacketMetadata;
+use smoltcp::{
+    iface::SocketStorage,
+    wire::{IpAddress, Ipv4Address},
+};
+
+#[cfg(feature = "mqtt")]
+use crate::mqtt;
+#[cfg(feature = "osc")]
+use crate::osc;
+#[cfg(not(any(feature = "mqtt", feature = "osc")))]
+use crate::protocol::{self, ProtocolMode};
+use crate::{globals::OUTPUT_COUNT, status_led::StatusEvent, StatusText};
+
+/// Network settings are given at build time, e.g. `DEEJ_WIFI_SSID=home cargo build --features wifi`
+pub const WIFI_SSID: &str = env!("DEEJ_WIFI_SSID");
+pub const WIFI_PASSWORD: &str = env!("DEEJ_WIFI_PASSWORD");
+/// IPv4 address of the host receiving the frames (or of the MQTT broker or the OSC receiver), e.g.
+/// `192.168.1.10`
+pub const WIFI_HOST: &str = env!("DEEJ_WIFI_HOST");
+pub const WIFI_PORT: &str = env!("DEEJ_WIFI_PORT");
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WifiState {
+    /// Not associated with the access point
+    Disconnected,
+    /// Associated, waiting for DHCP or for the host to accept the TCP connection. OSC only waits
+    /// for DHCP
+    Connecting,
+    /// Frames are being streamed to the host
+    Connected,
+    /// Downloading a firmware update, value is the progress in percent
+    Updating(u8),
+}
+
+impl WifiState {
+    /// Short label for the display status corner
+    pub fn label(&self) -> StatusText {
+        let mut label = StatusText::new();
+        match self {
+            WifiState::Disconnected => label.push_str("W--").unwrap(),
+            WifiState::Connecting => label.push_str("W..").unwrap(),
+            WifiState::Connected => label.push_str("WiFi").unwrap(),
+            WifiState::Updating(percent) => write!(label, "U{}%", percent).unwrap(),
+        }
+        label
+    }
+
+    /// Event for the status LED when the state changes from `previous` to this one
+    pub fn status_event(self, previous: WifiState) -> Option<StatusEvent> {
+        match (previous, self) {
+            (WifiState::Updating(_), WifiState::Updating(_)) => None,
+            // A successful update resets the chip, so leaving the update means it failed
+            (WifiState::Updating(_), _) => Some(StatusEvent::Error),
+            (WifiState::Connected, WifiState::Connected) => None,
+            (_, WifiState::Connected) => Some(StatusEvent::HostConnected),
+            (WifiState::Connected, _) => Some(StatusEvent::HostDisconnected),
+            _ => None,
+        }
+    }
+}
+
+/// Everything needed to bring up the Wi-Fi stack. Created in init and consumed by the idle task.
+pub struct WifiLink {
+    pub init: EspWifiInitialization,
+    pub wifi: WIFI,
+}
+
+impl WifiLink {
+    /// Connects to [WIFI_SSID] and streams values to [WIFI_HOST]:[WIFI_PORT] over TCP (UDP with `osc`)
+    /// forever, reconnecting when needed.
+    ///
+    /// `poll` is called continuously with the current connection state. Values it returns are sent when connected.
+    /// When `take_ota_request` returns true a firmware update is downloaded and the chip is reset to boot it.
+    pub fn run(
+        &mut self,
+        mut poll: impl FnMut(WifiState) -> Option<[u16; OUTPUT_COUNT]>,
+        mut take_ota_request: impl FnMut() -> bool,
+    ) -> ! {
+        let host = parse_ipv4(WIFI_HOST).expect("DEEJ_WIFI_HOST is not a valid IPv4 address");
+        let port = u16::from_str(WIFI_PORT).expect("DEEJ_WIFI_PORT is not a valid port");
+
+        let mut socket_set_entries: [SocketStorage; 3] = Default::default();
+        let (iface, device, mut controller, sockets) = create_network_interface(
+            &self.init,
+            &mut self.wifi,
+            WifiStaDevice,
+            &mut socket_set_entries,
+        )
+        .unwrap();
+        let stack = WifiStack::new(iface, device, sockets, current_millis);
+
+        controller
+            .set_configuration(&Configuration::Client(ClientConfiguration {
+                ssid: WIFI_SSID.try_into().expect("DEEJ_WIFI_SSID too long"),
+                password: WIFI_PASSWORD
+                    .try_into()
+                    .expect("DEEJ_WIFI_PASSWORD too long"),
+                ..Default::default()
+            }))
+            .unwrap();
+        controller.start().unwrap();
+
+        let mut rx_buffer = [0u8; 128];
+        let mut tx_buffer = [0u8; 512];
+        #[cfg(not(feature = "osc"))]
+        let mut socket = stack.get_socket(&mut rx_buffer, &mut tx_buffer);
+        #[cfg(feature = "osc")]
+        let (mut rx_meta, mut tx_meta) = (
+            [PacketMetadata::EMPTY; 4],
+            [PacketMetadata::EMPTY; OUTPUT_COUNT],
+        );
+        #[cfg(feature = "osc")]
+        let mut socket = OscSocket::new(stack.get_udp_socket(
+            &mut rx_meta,
+            &mut rx_buffer,
+            &mut tx_meta,
+            &mut tx_buffer,
+        ));
+
+        let mut state = WifiState::Disconnected;
+        let mut last_sent = None;
+        loop {
+            socket.work();
+
+            state = match (state, controller.is_connected()) {
+                (WifiState::Disconnected, _) => match controller.connect() {
+                    Ok(()) => WifiState::Connecting,
+                    Err(_) => WifiState::Disconnected,
+                },
+                // Association failed or was lost
+                (WifiState::Connecting, Err(_)) | (WifiState::Connected, Err(_) | Ok(false)) => {
+                    socket.disconnect();
+                    WifiState::Disconnected
+                }
+                (WifiState::Connecting, Ok(false)) => WifiState::Connecting,
+                (WifiState::Connecting, Ok(true)) => {
+                    if stack.is_iface_up()
+                        && socket.open(IpAddress::Ipv4(host), port).is_ok()
+                        && start_session(&mut socket).is_ok()
+                    {
+                        last_sent = None;
+                        WifiState::Connected
+                    } else {
+                        WifiState::Connecting
+                    }
+                }
+                (WifiState::Connected, Ok(true)) => WifiState::Connected,
+                (WifiState::Updating(_), _) => WifiState::Connecting,
+            };
+
+            if cfg!(feature = "ota") && take_ota_request() && stack.is_iface_up() {
+                // Only returns if the update failed
+                socket.disconnect();
+                update_firmware(&stack, &mut poll);
+                state = WifiState::Connecting;
+            }
+
+            let Some(values) = poll(state) else {
+                continue;
+            };
+            if state == WifiState::Connected
+                && (send_values(&mut socket, &values, &mut last_sent).is_err()
+                    || socket.flush().is_err())
+            {
+                // Host went away, try to open the connection again
+                socket.disconnect();
+                state = WifiState::Connecting;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ota")]
+fn update_firmware(
+    stack: &WifiStack<'_, WifiStaDevice>,
+    poll: &mut impl FnMut(WifiState) -> Option<[u16; OUTPUT_COUNT]>,
+) {
+    poll(WifiState::Updating(0));
+    if crate::ota::update(stack, |percent| {
+        poll(WifiState::Updating(percent));
+    })
+    .is_ok()
+    {
+        esp_hal::reset::software_reset();
+    }
+}
+
+#[cfg(not(feature = "ota"))]
+fn update_firmware(
+    _stack: &WifiStack<'_, WifiStaDevice>,
+    _poll: &mut impl FnMut(WifiState) -> Option<[u16; OUTPUT_COUNT]>,
+) {
+}
+
+/// UDP socket sending to a fixed destination, with the calls of the TCP socket the run loop makes
+#[cfg(feature = "osc")]
+struct OscSocket<'s, 'n> {
+    socket: UdpSocket<'s, 'n, WifiStaDevice>,
+    bound: bool,
+    destination: Option<(IpAddress, u16)>,
+}
+
+#[cfg(feature = "osc")]
+impl<'s, 'n> OscSocket<'s, 'n> {
+    fn new(socket: UdpSocket<'s, 'n, WifiStaDevice>) -> Self {
+        Self {
+            socket,
+            bound: false,
+            destination: None,
+        }
+    }
+
+    fn work(&mut self) {
+        self.socket.work();
+    }
+
+    /// Nothing to connect, only remembers where the messages go
+    fn open(&mut self, host: IpAddress, port: u16) -> Result<(), WifiStackError> {
+        if !self.bound {
+            self.socket.bind(osc::OSC_LOCAL_PORT)?;
+            self.bound = true;
+        }
+        self.destination = Some((host, port));
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        self.destination = None;
+    }
+
+    fn send(&mut self, message: &[u8]) -> Result<(), WifiStackError> {
+        let Some((host, port)) = self.destination else {
+            return Ok(());
+        };
+        self.socket.send(host, port, message)
+    }
+
+    /// Every message is sent as a datagram of its own right away
+    fn flush(&mut self) -> Result<(), WifiStackError> {
+        Ok(())
+    }
+}
+
+/// Plain TCP stream needs no handshake
+#[cfg(not(any(feature = "mqtt", feature = "osc")))]
+fn start_session<W: Write>(_socket: &mut W) -> Result<(), W::Error> {
+    Ok(())
+}
+
+#[cfg(feature = "mqtt")]
+fn start_session<W: Write>(socket: &mut W) -> Result<(), W::Error> {
+    // Keep-alive disabled, a dead connection is noticed when publishing fails
+    socket.write_all(&mqtt::connect_packet(0))
+}
+
+/// OSC has no session, the receiver takes whatever arrives
+#[cfg(feature = "osc")]
+fn start_session(_socket: &mut OscSocket) -> Result<(), WifiStackError> {
+    Ok(())
+}
+
+/// Sends the same pipe-delimited frame as the serial port
+#[cfg(not(any(feature = "mqtt", feature = "osc")))]
+fn send_values<W: Write>(
+    socket: &mut W,
+    values: &[u16; OUTPUT_COUNT],
+    _last_sent: &mut Option<[u16; OUTPUT_COUNT]>,
+) -> Result<(), W::Error> {
+    socket.write_all(protocol::encode(ProtocolMode::Plain, values).as_bytes())
+}
+
+/// Publishes every channel that differs from `last_sent` as a retained 0-100 value
+#[cfg(feature = "mqtt")]
+fn send_values<W: Write>(
+    socket: &mut W,
+    values: &[u16; OUTPUT_COUNT],
+    last_sent: &mut Option<[u16; OUTPUT_COUNT]>,
+) -> Result<(), W::Error> {
+    for (idx, val) in values.iter().enumerate() {
+        if last_sent.is_some_and(|last| last[idx] == *val) {
+            continue;
+        }
+        let payload: mqtt::Payload = crate::text::format_truncated(format_args!(
+            "{}",
+            crate::numerics::wire_to_percent(*val)
+        ));
+        socket.write_all(&mqtt::publish_packet(
+            &mqtt::channel_topic(idx),
+            &payload,
+            true,
+        ))?;
+    }
+    *last_sent = Some(*values);
+    Ok(())
+}
+
+/// Sends every channel as a 0.0-1.0 float to its OSC address. Datagrams can be lost, so unchanged
+/// channels are sent again too
+#[cfg(feature = "osc")]
+fn send_values(
+    socket: &mut OscSocket,
+    values: &[u16; OUTPUT_COUNT],
+    _last_sent: &mut Option<[u16; OUTPUT_COUNT]>,
+) -> Result<(), WifiStackError> {
+    for (idx, val) in values.iter().enumerate() {
+        socket.send(&osc::channel_message(idx, *val))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn parse_ipv4(s: &str) -> Option<Ipv4Address> {
+    let mut octets = [0u8; 4];
+    let mut parts = s.split('.');
+    for octet in octets.iter_mut() {
+        *octet = u8::from_str(parts.next()?).ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Ipv4Address::from_bytes(&octets))
+}
@@ -0,0 +1,250 @@
+//! Scripted pot readings through the sampling, serial and display code, without the hardware.
+//! Run with `cargo test-host`.
+
+use std::{cell::Cell, convert::Infallible};
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use rust_deej::{
+    globals::{
+        DISCONNECT_SAMPLES, FLOATING_SPREAD, INPUT_COUNT, MAX_ANALOG_VALUE, OUTPUT_COUNT,
+        VIRTUAL_CHANNELS,
+    },
+    numerics::wire_to_percent,
+    protocol::{self, ProtocolMode},
+    roles,
+    sampling::{AdcError, AnalogSource, ChannelConfig, Reading, Sampler},
+    serial::SerialGate,
+    DisplayFlush, DisplayState, DisplayStatus,
+};
+use ssd1306::prelude::DisplayRotation;
+
+/// Returns the readings of the current step for every channel, [ScriptedAdc::next] moves on
+struct ScriptedAdc {
+    steps: Vec<[u16; INPUT_COUNT]>,
+    /// Spread of every reading, e.g. [FLOATING_SPREAD] for channels without a wiper
+    spreads: [u16; INPUT_COUNT],
+    step: usize,
+    /// Sample counts asked for, per channel
+    samples: Vec<(usize, u32)>,
+}
+
+impl ScriptedAdc {
+    fn new(steps: &[[u16; INPUT_COUNT]]) -> Self {
+        Self {
+            steps: steps.to_vec(),
+            spreads: [0; INPUT_COUNT],
+            step: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    fn next(&mut self) {
+        self.step += 1;
+    }
+}
+
+impl AnalogSource for ScriptedAdc {
+    fn read(&mut self, channel: usize, samples: u32) -> Result<Reading, AdcError> {
+        self.samples.push((channel, samples));
+        Ok(Reading {
+            average: self.steps[self.step][channel],
+            spread: self.spreads[channel],
+        })
+    }
+}
+
+/// Counts what [DisplayState] does to the panel
+#[derive(Default)]
+struct RecordingDisplay {
+    pixels_on: usize,
+    flushes: usize,
+    on: Option<bool>,
+    /// Every transfer fails while set, like a glitching cable
+    unplugged: Cell<bool>,
+}
+
+impl RecordingDisplay {
+    fn transfer(&self) -> Result<(), ()> {
+        if self.unplugged.get() {
+            return Err(());
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for RecordingDisplay {
+    fn size(&self) -> Size {
+        Size::new(128, 64)
+    }
+}
+
+impl DrawTarget for RecordingDisplay {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.pixels_on += pixels
+            .into_iter()
+            .filter(|Pixel(_, color)| color.is_on())
+            .count();
+        Ok(())
+    }
+}
+
+impl DisplayFlush for RecordingDisplay {
+    type Error = ();
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.transfer()?;
+        self.flushes += 1;
+        Ok(())
+    }
+
+    fn set_display_on(&mut self, on: bool) -> Result<(), Self::Error> {
+        self.transfer()?;
+        self.on = Some(on);
+        Ok(())
+    }
+
+    fn set_invert(&mut self, _invert: bool) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_contrast(&mut self, _contrast: u8) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_rotation(&mut self, _rotation: DisplayRotation) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[test]
+fn scripted_readings_produce_serial_frames() {
+    let mut adc = ScriptedAdc::new(&[
+        [0, MAX_ANALOG_VALUE / 2, MAX_ANALOG_VALUE, 0],
+        [0, MAX_ANALOG_VALUE / 2, MAX_ANALOG_VALUE, 0],
+        [MAX_ANALOG_VALUE, MAX_ANALOG_VALUE / 2, MAX_ANALOG_VALUE, 0],
+    ]);
+    let configs = [ChannelConfig::DEFAULT; INPUT_COUNT];
+    let mut sampler = Sampler::new(&configs);
+    let mut gate = SerialGate::new(2, 100);
+
+    let mut frames = Vec::new();
+    for _ in 0..3 {
+        let (_, values) = sampler.sample(&mut adc);
+        let outputs = roles::apply(&configs, VIRTUAL_CHANNELS, &values);
+        if gate.should_send(&outputs) {
+            frames.push(protocol::encode(ProtocolMode::Plain, &outputs));
+        }
+        adc.next();
+    }
+
+    // Whatever the features and VIRTUAL_CHANNELS add to the frame follows the pots
+    let expected = |values: [u16; INPUT_COUNT]| {
+        let outputs: Vec<_> = roles::apply::<OUTPUT_COUNT>(&configs, VIRTUAL_CHANNELS, &values)
+            .iter()
+            .map(u16::to_string)
+            .collect();
+        format!("{}\r\n", outputs.join("|"))
+    };
+    // The unchanged second sample is not sent. Half way is 511.5, rounded up
+    assert_eq!(frames.len(), 2);
+    assert!(frames[0].as_bytes().starts_with(b"0|512|1023|0"));
+    assert_eq!(frames[0].as_bytes(), expected([0, 512, 1023, 0]).as_bytes());
+    assert!(frames[1].as_bytes().starts_with(b"1023|512|1023|0"));
+    assert_eq!(
+        frames[1].as_bytes(),
+        expected([1023, 512, 1023, 0]).as_bytes()
+    );
+    assert!(adc
+        .samples
+        .iter()
+        .all(|(_, samples)| *samples == ChannelConfig::DEFAULT.samples));
+}
+
+#[test]
+fn moved_pot_is_drawn_and_flushed() {
+    let mut adc = ScriptedAdc::new(&[[0; INPUT_COUNT], [MAX_ANALOG_VALUE, 0, 0, 0]]);
+    let mut sampler = Sampler::new(&[ChannelConfig::DEFAULT; INPUT_COUNT]);
+    let mut display = DisplayState::new(RecordingDisplay::default());
+    display.ready();
+    display.draw().unwrap();
+
+    let (_, values) = sampler.sample(&mut adc);
+    let volumes = values.map(wire_to_percent);
+    assert!(matches!(
+        display.set_volumes(&volumes),
+        DisplayStatus::NotChanged
+    ));
+
+    adc.next();
+    let (_, values) = sampler.sample(&mut adc);
+    let volumes = values.map(wire_to_percent);
+    assert!(matches!(
+        display.set_volumes(&volumes),
+        DisplayStatus::Changed
+    ));
+    display.draw().unwrap();
+
+    let panel = display.display();
+    assert_eq!(panel.flushes, 2);
+    assert_eq!(panel.on, Some(true));
+    assert!(panel.pixels_on > 0);
+}
+
+#[test]
+fn unplugged_pot_is_frozen_and_shown_disconnected() {
+    let mut adc = ScriptedAdc::new(&[[MAX_ANALOG_VALUE / 2; INPUT_COUNT]]);
+    let mut sampler = Sampler::new(&[ChannelConfig::DEFAULT; INPUT_COUNT]);
+    let mut display = DisplayState::new(RecordingDisplay::default());
+    display.ready();
+    display.draw().unwrap();
+
+    let (_, before) = sampler.sample(&mut adc);
+    adc.spreads[2] = FLOATING_SPREAD * 2;
+    let mut values = before;
+    for _ in 0..DISCONNECT_SAMPLES {
+        values = sampler.sample(&mut adc).1;
+    }
+    // Serial keeps the last good value instead of the noise
+    assert_eq!(values, before);
+    assert!(sampler.disconnected()[2]);
+    assert!(matches!(
+        display.set_disconnected(&sampler.disconnected()),
+        DisplayStatus::Changed
+    ));
+    display.draw().unwrap();
+    assert_eq!(display.display().flushes, 2);
+}
+
+#[test]
+fn glitching_display_goes_offline_and_comes_back() {
+    let mut display = DisplayState::new(RecordingDisplay::default());
+    display.ready();
+    display.draw().unwrap();
+
+    display.display().unplugged.set(true);
+    assert!(matches!(
+        display.set_volumes(&[100; INPUT_COUNT]),
+        DisplayStatus::Changed
+    ));
+    // No panic, the drawing is skipped from then on
+    display.draw().unwrap();
+    assert!(display.is_offline());
+    assert!(matches!(
+        display.set_volumes(&[50; INPUT_COUNT]),
+        DisplayStatus::Changed
+    ));
+    display.draw().unwrap();
+    assert!(!display.reinit());
+
+    display.display().unplugged.set(false);
+    assert!(display.reinit());
+    assert!(!display.is_offline());
+    display.draw().unwrap();
+    assert_eq!(display.display().flushes, 2);
+}